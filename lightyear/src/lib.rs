@@ -30,6 +30,11 @@ pub mod webtransport {
     pub use lightyear_webtransport::*;
 }
 
+#[cfg(feature = "websocket")]
+pub mod websocket {
+    pub use lightyear_websocket::*;
+}
+
 #[cfg(any(feature = "input_native", feature = "leafwing"))]
 pub mod input {
     pub use lightyear_inputs::*;
@@ -52,13 +57,19 @@ pub mod utils {
     pub use lightyear_utils::*;
 }
 
+pub mod serde {
+    pub use lightyear_serde::*;
+}
+
 pub mod prelude {
     pub use lightyear_connection::prelude::*;
     pub use lightyear_core::prelude::*;
     pub use lightyear_link::prelude::*;
+    pub use lightyear_macros::ToBytes;
     pub use lightyear_messages::prelude::*;
     #[cfg(feature = "replication")]
     pub use lightyear_replication::prelude::*;
+    pub use lightyear_serde::prelude::*;
     pub use lightyear_sync::prelude::*;
     pub use lightyear_transport::prelude::*;
 
@@ -69,6 +80,10 @@ pub mod prelude {
     #[cfg(feature = "webtransport")]
     pub use lightyear_webtransport::prelude::*;
 
+    #[allow(unused_imports)]
+    #[cfg(feature = "websocket")]
+    pub use lightyear_websocket::prelude::*;
+
     #[cfg(feature = "netcode")]
     pub use lightyear_netcode::prelude::*;
 
@@ -102,6 +117,8 @@ pub mod prelude {
         pub use lightyear_netcode::prelude::client::*;
         #[cfg(feature = "webtransport")]
         pub use lightyear_webtransport::prelude::client::*;
+        #[cfg(feature = "websocket")]
+        pub use lightyear_websocket::prelude::client::*;
 
         #[cfg(any(feature = "input_native", feature = "leafwing"))]
         pub mod input {
@@ -122,6 +139,8 @@ pub mod prelude {
         pub use lightyear_netcode::prelude::server::*;
         #[cfg(feature = "webtransport")]
         pub use lightyear_webtransport::prelude::server::*;
+        #[cfg(feature = "websocket")]
+        pub use lightyear_websocket::prelude::server::*;
 
         #[cfg(any(feature = "input_native", feature = "leafwing"))]
         pub mod input {
@@ -50,6 +50,8 @@ impl PluginGroup for ServerPlugins {
         let builder = builder.add(lightyear_udp::server::ServerUdpPlugin);
         #[cfg(feature = "webtransport")]
         let builder = builder.add(lightyear_webtransport::server::WebTransportServerPlugin);
+        #[cfg(feature = "websocket")]
+        let builder = builder.add(lightyear_websocket::server::WebSocketServerPlugin);
 
         // CONNECTION
         #[cfg(feature = "netcode")]
@@ -1,3 +1,4 @@
+use crate::client_of::ClientOf;
 #[cfg(not(feature = "std"))]
 use alloc::{format, string::String};
 use bevy::app::{App, Plugin};
@@ -56,6 +57,24 @@ pub struct Connect;
 #[derive(Event)]
 pub struct Disconnect;
 
+/// Disconnect the peer connected to `entity` because of `reason`.
+///
+/// The [`Disconnect`] trigger lets the connection layer notify the remote peer (e.g. netcode disconnect packets).
+/// On a client, [`Disconnected`] is then inserted with the `reason`. On a server, the [`ClientOf`] entity
+/// stays [`Disconnecting`] until the remote peer has been notified, and is then disconnected by the connection layer.
+pub fn disconnect_with_reason(commands: &mut Commands, entity: Entity, reason: String) {
+    commands.trigger_targets(Disconnect, entity);
+    commands
+        .entity(entity)
+        .queue(move |mut entity: EntityWorldMut| {
+            if !entity.contains::<ClientOf>() {
+                entity.insert(Disconnected {
+                    reason: Some(reason),
+                });
+            }
+        });
+}
+
 // TODO: it looks like in some cases, we want Connected.peer_id to return the local peer_id (when client connects to server)
 //  and in some cases we want it to return the remote peer_id (when server's ClientOf gets connected)
 //  We should decide on a rule.
@@ -6,6 +6,18 @@ pub enum NetworkDirection {
     Bidirectional,
 }
 
+impl NetworkDirection {
+    /// Identifier of the direction that does not depend on the platform, used to compare
+    /// the protocols of two peers
+    pub fn protocol_id(&self) -> u8 {
+        match self {
+            NetworkDirection::ClientToServer => 1,
+            NetworkDirection::ServerToClient => 2,
+            NetworkDirection::Bidirectional => 3,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -34,6 +34,7 @@ impl<S: ActionStateSequence + MapEntities> Plugin for InputPlugin<S> {
             send_frequency: Duration::default(),
             // we always want to include the inputs in the packet
             priority: f32::INFINITY,
+            ..Default::default()
         })
         // bidirectional in case of rebroadcasting inputs
         .add_direction(NetworkDirection::Bidirectional);
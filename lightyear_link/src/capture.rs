@@ -0,0 +1,340 @@
+//! Contains the `LinkCapture` component which records the payloads going through a [`Link`](crate::Link)
+//!
+//! The capture is written in a simple binary format so that it can be decoded offline:
+//! - the file starts with [`CAPTURE_MAGIC`] followed by the format version (u8)
+//! - then each payload is written as a record:
+//!   - time elapsed since the start of the capture, in microseconds (u64, little-endian)
+//!   - the [`CaptureDirection`] (u8)
+//!   - the length of the payload (u32, little-endian), at most [`MAX_CAPTURE_RECORD_SIZE`]
+//!   - the payload bytes
+//!
+//! Use [`CaptureReader`] to read the records back.
+use crate::{LinkReceiver, LinkSender};
+#[cfg(not(feature = "test_utils"))]
+use bevy::platform::time::Instant;
+use bevy::prelude::Component;
+use bytes::Bytes;
+use core::time::Duration;
+#[cfg(feature = "test_utils")]
+use mock_instant::global::Instant;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Bytes written at the start of every capture file
+pub const CAPTURE_MAGIC: &[u8; 6] = b"LYCAPT";
+
+/// Version of the capture format
+pub const CAPTURE_VERSION: u8 = 1;
+
+/// Maximum size of a recorded payload, well above the size of any packet sent through a `Link`.
+///
+/// It prevents a corrupted capture from making the [`CaptureReader`] allocate a huge buffer.
+pub const MAX_CAPTURE_RECORD_SIZE: usize = 1 << 20;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CaptureError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("the file is not a lightyear capture")]
+    InvalidMagic,
+    #[error("unsupported capture version {0}")]
+    UnsupportedVersion(u8),
+    #[error("invalid capture direction {0}")]
+    InvalidDirection(u8),
+}
+
+/// Whether the payload was sent or received by the `Link`
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Send = 0,
+    Recv = 1,
+}
+
+impl TryFrom<u8> for CaptureDirection {
+    type Error = CaptureError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CaptureDirection::Send),
+            1 => Ok(CaptureDirection::Recv),
+            _ => Err(CaptureError::InvalidDirection(value)),
+        }
+    }
+}
+
+/// A single payload that was recorded by a [`LinkCapture`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureRecord {
+    /// Time elapsed between the start of the capture and the moment the payload was recorded
+    pub elapsed: Duration,
+    pub direction: CaptureDirection,
+    pub payload: Bytes,
+}
+
+/// Add this component on an entity with a [`Link`](crate::Link) to record every payload
+/// that is sent or received through the link.
+///
+/// The payloads are recorded by the transport layer, so they are the unencrypted packets
+/// (before the connection layer encrypts them on the send side, and after it has decrypted them
+/// on the receive side).
+#[derive(Component)]
+pub struct LinkCapture {
+    writer: Box<dyn Write + Send + Sync>,
+    start: Instant,
+}
+
+impl LinkCapture {
+    /// Create a new capture that writes the records to `writer`
+    pub fn new(writer: impl Write + Send + Sync + 'static) -> Result<Self, CaptureError> {
+        let mut writer: Box<dyn Write + Send + Sync> = Box::new(writer);
+        writer.write_all(CAPTURE_MAGIC)?;
+        writer.write_all(&[CAPTURE_VERSION])?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    /// Create a new capture that writes the records to the file at `path`.
+    ///
+    /// The file will be created or truncated.
+    pub fn to_file(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        let file = File::create(path)?;
+        Self::new(BufWriter::new(file))
+    }
+
+    /// Write a record for the payload
+    pub fn record(
+        &mut self,
+        direction: CaptureDirection,
+        payload: &[u8],
+    ) -> Result<(), CaptureError> {
+        if payload.len() > MAX_CAPTURE_RECORD_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "payload of {} bytes exceeds the maximum record size of {MAX_CAPTURE_RECORD_SIZE} bytes",
+                    payload.len()
+                ),
+            )
+            .into());
+        }
+        let elapsed = self.start.elapsed().as_micros() as u64;
+        self.writer.write_all(&elapsed.to_le_bytes())?;
+        self.writer.write_all(&[direction as u8])?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(payload)?;
+        Ok(())
+    }
+
+    /// Write a record for every payload currently buffered in the [`LinkReceiver`]
+    pub fn record_recv(&mut self, recv: &LinkReceiver) -> Result<(), CaptureError> {
+        recv.buffer
+            .iter()
+            .try_for_each(|(payload, _)| self.record(CaptureDirection::Recv, payload))
+    }
+
+    /// Write a record for every payload currently buffered in the [`LinkSender`]
+    pub fn record_send(&mut self, send: &LinkSender) -> Result<(), CaptureError> {
+        send.buffer
+            .iter()
+            .try_for_each(|payload| self.record(CaptureDirection::Send, payload))
+    }
+
+    /// Flush the records to the underlying writer
+    pub fn flush(&mut self) -> Result<(), CaptureError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the [`CaptureRecord`]s written by a [`LinkCapture`]
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Open the capture file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        let file = File::open(path)?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Create a new reader; this checks that the capture header is valid
+    pub fn new(mut reader: R) -> Result<Self, CaptureError> {
+        let mut magic = [0; CAPTURE_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            return Err(CaptureError::InvalidMagic);
+        }
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != CAPTURE_VERSION {
+            return Err(CaptureError::UnsupportedVersion(version[0]));
+        }
+        Ok(Self { reader })
+    }
+
+    /// Read the next record. Returns `None` if we reached the end of the capture.
+    pub fn read_record(&mut self) -> Result<Option<CaptureRecord>, CaptureError> {
+        let mut elapsed = [0; 8];
+        // the capture only ends cleanly at a record boundary; a truncated record is an error
+        if !self.read_exact_or_eof(&mut elapsed)? {
+            return Ok(None);
+        }
+        let mut direction = [0; 1];
+        self.reader.read_exact(&mut direction)?;
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_CAPTURE_RECORD_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "record of {len} bytes exceeds the maximum record size of {MAX_CAPTURE_RECORD_SIZE} bytes"
+                ),
+            )
+            .into());
+        }
+        let mut payload = vec![0; len];
+        self.reader.read_exact(&mut payload)?;
+        Ok(Some(CaptureRecord {
+            elapsed: Duration::from_micros(u64::from_le_bytes(elapsed)),
+            direction: CaptureDirection::try_from(direction[0])?,
+            payload: Bytes::from(payload),
+        }))
+    }
+
+    /// Fill `buf` from the reader. Returns `false` if the reader was already at the end of the
+    /// stream, and an `UnexpectedEof` error if the stream ends before `buf` is filled.
+    fn read_exact_or_eof(&mut self, buf: &mut [u8]) -> Result<bool, std::io::Error> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Writer that can be inspected after being moved into a [`LinkCapture`]
+#[cfg(any(test, feature = "test_utils"))]
+#[derive(Clone, Default)]
+pub struct SharedBuffer(alloc::sync::Arc<std::sync::Mutex<alloc::vec::Vec<u8>>>);
+
+#[cfg(any(test, feature = "test_utils"))]
+impl SharedBuffer {
+    /// Returns a copy of the bytes written so far
+    pub fn contents(&self) -> alloc::vec::Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(any(test, feature = "test_utils"))]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_capture_roundtrip() {
+        let buffer = SharedBuffer::default();
+        let mut capture = LinkCapture::new(buffer.clone()).unwrap();
+        capture.record(CaptureDirection::Send, &[1, 2, 3]).unwrap();
+        capture.record(CaptureDirection::Recv, &[]).unwrap();
+        capture.flush().unwrap();
+
+        let bytes = buffer.contents();
+        let records = CaptureReader::new(Cursor::new(bytes))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, CaptureDirection::Send);
+        assert_eq!(records[0].payload, Bytes::from_static(&[1, 2, 3]));
+        assert_eq!(records[1].direction, CaptureDirection::Recv);
+        assert!(records[1].elapsed >= records[0].elapsed);
+        assert!(records[1].payload.is_empty());
+    }
+
+    #[test]
+    fn test_capture_invalid_header() {
+        assert!(matches!(
+            CaptureReader::new(Cursor::new(b"NOTCAPT".to_vec())),
+            Err(CaptureError::InvalidMagic)
+        ));
+    }
+
+    fn capture_bytes(payload: &[u8]) -> Vec<u8> {
+        let buffer = SharedBuffer::default();
+        let mut capture = LinkCapture::new(buffer.clone()).unwrap();
+        capture.record(CaptureDirection::Send, payload).unwrap();
+        capture.flush().unwrap();
+        buffer.contents()
+    }
+
+    #[test]
+    fn test_capture_truncated_record() {
+        let bytes = capture_bytes(&[1, 2, 3]);
+        let header_len = CAPTURE_MAGIC.len() + 1;
+        // the capture ends in the middle of the record header or of the payload
+        for len in [header_len + 4, bytes.len() - 1] {
+            let mut reader = CaptureReader::new(Cursor::new(bytes[..len].to_vec())).unwrap();
+            assert!(matches!(
+                reader.read_record(),
+                Err(CaptureError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+            ));
+        }
+        // the capture ends at a record boundary
+        let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+        assert!(reader.read_record().unwrap().is_some());
+        assert!(reader.read_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_capture_record_too_large() {
+        let mut bytes = capture_bytes(&[]);
+        let len_offset = CAPTURE_MAGIC.len() + 1 + 8 + 1;
+        bytes[len_offset..len_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(
+            reader.read_record(),
+            Err(CaptureError::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData
+        ));
+
+        let mut capture = LinkCapture::new(SharedBuffer::default()).unwrap();
+        assert!(
+            capture
+                .record(CaptureDirection::Send, &vec![0; MAX_CAPTURE_RECORD_SIZE + 1])
+                .is_err()
+        );
+    }
+}
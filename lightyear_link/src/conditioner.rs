@@ -1,4 +1,6 @@
 //! Contains the `LinkConditioner` struct which can be used to simulate network conditions
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
 #[cfg(not(feature = "test_utils"))]
 use bevy::platform::time::Instant;
 use bevy::reflect::Reflect;
@@ -6,7 +8,10 @@ use core::time::Duration;
 use lightyear_utils::ready_buffer::ReadyBuffer;
 #[cfg(feature = "test_utils")]
 use mock_instant::global::Instant;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::info;
 
 /// Contains configuration required to initialize a LinkConditioner
 #[derive(Clone, Debug, Default, Reflect)]
@@ -20,40 +25,406 @@ pub struct LinkConditionerConfig {
     /// The % chance that an incoming packet will be dropped.
     /// Represented as a value between 0 and 1
     pub incoming_loss: f32,
+    /// Delay to send outgoing messages in milliseconds (half the RTT)
+    pub outgoing_latency: Duration,
+    /// The maximum additional random latency to delay sent outgoing
+    /// messages in milliseconds. This may be added OR subtracted from the
+    /// latency determined in the `outgoing_latency` property above
+    pub outgoing_jitter: Duration,
+    /// The % chance that an outgoing packet will be dropped.
+    /// Represented as a value between 0 and 1
+    pub outgoing_loss: f32,
+    /// Optional Gilbert-Elliott model to simulate packet loss happening in bursts.
+    /// If set, it replaces the uniform `incoming_loss`/`outgoing_loss`
+    pub burst_loss: Option<BurstLossConfig>,
+    /// The % chance that a packet will be duplicated.
+    /// Represented as a value between 0 and 1
+    pub duplication: f32,
+    /// The % chance that a packet will be held back by an extra `reorder_delay`,
+    /// so that packets sent after it arrive first.
+    /// Represented as a value between 0 and 1
+    pub reorder: f32,
+    /// Extra delay applied to the packets that are reordered
+    pub reorder_delay: Duration,
+    /// Optional bandwidth bottleneck. Packets are queued until the bottleneck
+    /// is free to transmit them, which adds a queueing delay when the link is saturated
+    pub bandwidth: Option<BandwidthConfig>,
+    /// Seed used to initialize the random number generator of the conditioner.
+    /// If None, a random seed is picked when the conditioner is created. The seed is logged, and
+    /// printed to stderr with the `test_utils` feature so that it shows up in the test output.
+    /// Setting a seed makes the conditioning reproducible (for example in tests)
+    pub seed: Option<u64>,
+    /// Optional recorded trace of network conditions to replay.
+    /// If set, it replaces the latency/jitter/loss parameters (uniform or burst loss)
+    pub trace: Option<LinkConditionerTrace>,
+}
+
+/// Parameters of the Gilbert-Elliott model used to simulate bursty packet loss.
+///
+/// The link alternates between a `Good` state and a `Bad` state; each state has
+/// its own loss probability and the state can change every packet.
+/// See: <https://en.wikipedia.org/wiki/Burst_error>
+#[derive(Clone, Debug, Default, Reflect)]
+pub struct BurstLossConfig {
+    /// The % chance to go from the `Good` state to the `Bad` state on every packet
+    pub good_to_bad: f32,
+    /// The % chance to go from the `Bad` state to the `Good` state on every packet.
+    /// The average length of a burst is `1 / bad_to_good` packets
+    pub bad_to_good: f32,
+    /// The % chance that a packet will be dropped while in the `Good` state
+    pub good_loss: f32,
+    /// The % chance that a packet will be dropped while in the `Bad` state
+    pub bad_loss: f32,
+}
+
+/// Simulates a bottleneck with a limited throughput and a finite queue.
+#[derive(Clone, Debug, Reflect)]
+pub struct BandwidthConfig {
+    /// Maximum number of bytes that can be transmitted per second
+    pub bytes_per_second: u32,
+    /// Maximum time that a packet can wait in the queue before being transmitted.
+    /// Packets that would have to wait longer are dropped
+    pub max_queue_delay: Duration,
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            bytes_per_second: 125_000,
+            max_queue_delay: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A single sample of a recorded network trace.
+///
+/// When (de)serialized, durations are represented as a number of milliseconds, with microsecond
+/// precision (the same as [`LinkConditionerTrace::from_csv`]).
+#[derive(Clone, Debug, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct TraceSample {
+    /// Time elapsed since the start of the trace
+    #[serde(with = "duration_millis")]
+    pub timestamp: Duration,
+    /// One-way latency experienced by packets from this point in the trace
+    #[serde(with = "duration_millis")]
+    pub latency: Duration,
+    /// Whether packets are dropped from this point in the trace
+    pub dropped: bool,
+}
+
+/// A recorded time series of network conditions (for example captured from a real player session)
+/// that the [`LinkConditioner`] can replay.
+///
+/// Each packet uses the most recent sample whose `timestamp` is lower or equal to the time elapsed
+/// since the first packet was conditioned.
+#[derive(Clone, Debug, Default, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct LinkConditionerTrace {
+    /// Samples of the trace, sorted by timestamp
+    pub samples: Vec<TraceSample>,
+    /// If true, the trace is replayed from the start once its end (see [`duration`](Self::duration))
+    /// is reached. Otherwise the last sample is used for the rest of the session
+    pub looping: bool,
+}
+
+/// Errors that can occur when parsing a [`LinkConditionerTrace`]
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TraceParseError {
+    #[error("line {line}: expected 3 columns (timestamp, latency, dropped)")]
+    MissingColumn { line: usize },
+    #[error("line {line}: invalid value '{value}'")]
+    InvalidValue { line: usize, value: String },
+}
+
+impl LinkConditionerTrace {
+    /// Creates a new trace from a list of samples. The samples will be sorted by timestamp
+    pub fn new(mut samples: Vec<TraceSample>) -> Self {
+        samples.sort_by_key(|sample| sample.timestamp);
+        Self {
+            samples,
+            looping: false,
+        }
+    }
+
+    /// Replay the trace from the start once its end is reached
+    pub fn looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+
+    /// Header row that can start the CSV data parsed by [`from_csv`](Self::from_csv)
+    pub const CSV_HEADER: [&'static str; 3] = ["timestamp", "latency", "dropped"];
+
+    /// Parse a trace from CSV data with the columns `timestamp,latency,dropped`.
+    ///
+    /// Timestamps and latencies are in milliseconds, with microsecond precision, and `dropped` is
+    /// either `0`/`1` or `true`/`false`.
+    /// Empty lines and lines starting with `#` are ignored. The first other row is skipped if it is
+    /// the [`CSV_HEADER`](Self::CSV_HEADER).
+    pub fn from_csv(data: &str) -> Result<Self, TraceParseError> {
+        let mut samples = Vec::new();
+        let mut first_row = true;
+        for (i, row) in data.lines().enumerate() {
+            let line = i + 1;
+            let row = row.trim();
+            if row.is_empty() || row.starts_with('#') {
+                continue;
+            }
+            let mut columns = row.split(',').map(str::trim);
+            let mut next_column = || {
+                columns
+                    .next()
+                    .ok_or(TraceParseError::MissingColumn { line })
+            };
+            let (timestamp, latency, dropped) = (next_column()?, next_column()?, next_column()?);
+            let is_header = core::mem::take(&mut first_row)
+                && [timestamp, latency, dropped] == Self::CSV_HEADER;
+            if is_header {
+                continue;
+            }
+            let invalid = |value: &str| TraceParseError::InvalidValue {
+                line,
+                value: value.into(),
+            };
+            let parse_millis = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(duration_millis::from_millis)
+                    .ok_or_else(|| invalid(value))
+            };
+            let dropped = match dropped {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                other => return Err(invalid(other)),
+            };
+            samples.push(TraceSample {
+                timestamp: parse_millis(timestamp)?,
+                latency: parse_millis(latency)?,
+                dropped,
+            });
+        }
+        Ok(Self::new(samples))
+    }
+
+    /// Total duration of the trace.
+    ///
+    /// The last sample lasts as long as the interval between the last two samples, so that it is
+    /// also replayed when the trace is looping.
+    pub fn duration(&self) -> Duration {
+        match self.samples.as_slice() {
+            [.., previous, last] => last.timestamp + (last.timestamp - previous.timestamp),
+            [last] => last.timestamp,
+            [] => Duration::ZERO,
+        }
+    }
+
+    /// Returns the sample that applies at `elapsed` time since the start of the trace
+    pub fn sample(&self, elapsed: Duration) -> Option<&TraceSample> {
+        let duration = self.duration();
+        let elapsed = if self.looping && duration > Duration::ZERO {
+            Duration::from_nanos((elapsed.as_nanos() % duration.as_nanos()) as u64)
+        } else {
+            elapsed
+        };
+        let index = self
+            .samples
+            .partition_point(|sample| sample.timestamp <= elapsed);
+        self.samples.get(index.saturating_sub(1))
+    }
+}
+
+/// Serialize a `Duration` as a number of milliseconds, with microsecond precision
+mod duration_millis {
+    use core::time::Duration;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Convert a number of milliseconds to a `Duration`, rounded to the microsecond.
+    ///
+    /// Returns None for negative, infinite and NaN values.
+    pub fn from_millis(millis: f64) -> Option<Duration> {
+        let micros = (millis * 1000.0).round();
+        (micros >= 0.0 && micros < u64::MAX as f64).then(|| Duration::from_micros(micros as u64))
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_micros() as f64 / 1000.0)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let millis = f64::deserialize(deserializer)?;
+        from_millis(millis)
+            .ok_or_else(|| D::Error::custom(format_args!("invalid duration {millis}")))
+    }
+}
+
+/// State of the Gilbert-Elliott model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum BurstState {
+    #[default]
+    Good,
+    Bad,
+}
+
+/// Which side of the [`Link`](crate::Link) a [`LinkConditioner`] is applied to.
+///
+/// This determines whether the `incoming_*` or the `outgoing_*` parameters of the
+/// [`LinkConditionerConfig`] are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConditionerDirection {
+    Incoming,
+    Outgoing,
 }
 
 #[derive(Debug, Clone)]
 pub struct LinkConditioner<P: Eq> {
     config: LinkConditionerConfig,
     pub time_queue: ReadyBuffer<Instant, P>,
+    /// Current state of the burst loss model
+    burst_state: BurstState,
+    /// Instant at which the bandwidth bottleneck will be done transmitting the queued packets
+    bandwidth_free_at: Option<Instant>,
+    /// Instant at which the first packet was conditioned, used as the start of the trace
+    trace_start: Option<Instant>,
+    /// Seed that was used to initialize `rng`
+    seed: u64,
+    rng: StdRng,
 }
 
-impl<P: Eq> LinkConditioner<P> {
+impl<P: Eq + Clone + AsRef<[u8]>> LinkConditioner<P> {
     pub fn new(config: LinkConditionerConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(|| rand::rng().random());
+        info!(?seed, "Creating LinkConditioner");
+        // test harnesses hide the logs but show the stderr of failing tests, so the seed can
+        // be used to replay the failure
+        #[cfg(all(feature = "std", feature = "test_utils"))]
+        std::eprintln!("LinkConditioner seed: {seed}");
         LinkConditioner {
             config,
             time_queue: ReadyBuffer::new(),
+            burst_state: BurstState::default(),
+            bandwidth_free_at: None,
+            trace_start: None,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
-    /// Add latency/jitter/loss to a packet
+    /// Returns the seed used by the random number generator of the conditioner.
     ///
-    /// `elapsed`: Duration since app start
-    pub(crate) fn condition_packet(&mut self, packet: P, instant: Instant) {
-        let mut rng = rand::rng();
-        if rng.random_range(0.0..1.0) <= self.config.incoming_loss {
-            return;
-        }
-        let mut latency: i32 = self.config.incoming_latency.as_millis() as i32;
+    /// Set it as [`LinkConditionerConfig::seed`] to replay the same network conditions.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Add latency/jitter/loss/duplication/reordering/bandwidth limits to a packet
+    ///
+    /// `instant`: the time at which the packet was received (or sent)
+    /// `direction`: whether the incoming or outgoing parameters of the config should be used
+    pub(crate) fn condition_packet(
+        &mut self,
+        packet: P,
+        instant: Instant,
+        direction: ConditionerDirection,
+    ) {
+        let (latency, jitter) = if let Some(trace) = &self.config.trace {
+            let start = *self.trace_start.get_or_insert(instant);
+            match trace.sample(instant.duration_since(start)) {
+                Some(sample) if sample.dropped => return,
+                Some(sample) => (sample.latency, Duration::default()),
+                None => (Duration::default(), Duration::default()),
+            }
+        } else {
+            let (latency, jitter, loss) = self.config.params(direction);
+            if self.is_lost(loss) {
+                return;
+            }
+            (latency, jitter)
+        };
         let mut packet_timestamp = instant;
-        if self.config.incoming_jitter > Duration::default() {
-            let jitter: i32 = self.config.incoming_jitter.as_millis() as i32;
+        if let Some(bandwidth) = &self.config.bandwidth {
+            let Some(departure) = Self::bandwidth_departure(
+                &mut self.bandwidth_free_at,
+                bandwidth,
+                instant,
+                packet.as_ref().len(),
+            ) else {
+                // the bottleneck queue is full
+                return;
+            };
+            packet_timestamp = departure;
+        }
+        if self.config.duplication > 0.0
+            && self.rng.random_range(0.0..1.0) < self.config.duplication
+        {
+            let duplicate_timestamp = Self::delay(&mut self.rng, packet_timestamp, latency, jitter);
+            self.time_queue.push(duplicate_timestamp, packet.clone());
+        }
+        packet_timestamp = Self::delay(&mut self.rng, packet_timestamp, latency, jitter);
+        if self.config.reorder > 0.0 && self.rng.random_range(0.0..1.0) < self.config.reorder {
+            packet_timestamp += self.config.reorder_delay;
+        }
+        self.time_queue.push(packet_timestamp, packet);
+    }
+
+    /// Returns true if the packet should be dropped, using either the uniform loss
+    /// or the Gilbert-Elliott model
+    fn is_lost(&mut self, loss: f32) -> bool {
+        let rng = &mut self.rng;
+        let Some(burst_loss) = &self.config.burst_loss else {
+            return rng.random_range(0.0..1.0) <= loss;
+        };
+        let transition = match self.burst_state {
+            BurstState::Good => burst_loss.good_to_bad,
+            BurstState::Bad => burst_loss.bad_to_good,
+        };
+        if rng.random_range(0.0..1.0) < transition {
+            self.burst_state = match self.burst_state {
+                BurstState::Good => BurstState::Bad,
+                BurstState::Bad => BurstState::Good,
+            };
+        }
+        let loss = match self.burst_state {
+            BurstState::Good => burst_loss.good_loss,
+            BurstState::Bad => burst_loss.bad_loss,
+        };
+        rng.random_range(0.0..1.0) < loss
+    }
+
+    /// Returns the instant at which the packet leaves the bandwidth bottleneck,
+    /// or None if the packet is dropped because the queue is full
+    fn bandwidth_departure(
+        bandwidth_free_at: &mut Option<Instant>,
+        bandwidth: &BandwidthConfig,
+        instant: Instant,
+        len: usize,
+    ) -> Option<Instant> {
+        let start = match *bandwidth_free_at {
+            Some(free_at) if free_at > instant => free_at,
+            _ => instant,
+        };
+        if start.duration_since(instant) > bandwidth.max_queue_delay {
+            return None;
+        }
+        let transmission =
+            Duration::from_secs_f64(len as f64 / bandwidth.bytes_per_second.max(1) as f64);
+        let departure = start + transmission;
+        *bandwidth_free_at = Some(departure);
+        Some(departure)
+    }
+
+    /// Apply latency and jitter to the instant
+    fn delay(rng: &mut impl Rng, instant: Instant, latency: Duration, jitter: Duration) -> Instant {
+        let mut latency: i32 = latency.as_millis() as i32;
+        if jitter > Duration::default() {
+            let jitter: i32 = jitter.as_millis() as i32;
             latency += rng.random_range(-jitter..jitter);
         }
         if latency > 0 {
-            packet_timestamp += Duration::from_millis(latency as u64);
+            instant + Duration::from_millis(latency as u64)
+        } else {
+            instant
         }
-        self.time_queue.push(packet_timestamp, packet);
     }
 
     /// Check if a packet is ready to be returned
@@ -63,12 +434,80 @@ impl<P: Eq> LinkConditioner<P> {
 }
 
 impl LinkConditionerConfig {
-    /// Creates a new LinkConditionerConfig
+    /// Creates a new LinkConditionerConfig that only conditions incoming packets
     pub fn new(incoming_latency: Duration, incoming_jitter: Duration, incoming_loss: f32) -> Self {
         LinkConditionerConfig {
             incoming_latency,
             incoming_jitter,
             incoming_loss,
+            ..Default::default()
+        }
+    }
+
+    /// Set the latency/jitter/loss applied to outgoing packets
+    pub fn with_outgoing(
+        mut self,
+        outgoing_latency: Duration,
+        outgoing_jitter: Duration,
+        outgoing_loss: f32,
+    ) -> Self {
+        self.outgoing_latency = outgoing_latency;
+        self.outgoing_jitter = outgoing_jitter;
+        self.outgoing_loss = outgoing_loss;
+        self
+    }
+
+    /// Use a Gilbert-Elliott model to drop packets in bursts
+    pub fn with_burst_loss(mut self, burst_loss: BurstLossConfig) -> Self {
+        self.burst_loss = Some(burst_loss);
+        self
+    }
+
+    /// Duplicate packets with the given probability
+    pub fn with_duplication(mut self, duplication: f32) -> Self {
+        self.duplication = duplication;
+        self
+    }
+
+    /// Hold back packets by `reorder_delay` with the given probability, so that they
+    /// arrive after packets that were sent later
+    pub fn with_reordering(mut self, reorder: f32, reorder_delay: Duration) -> Self {
+        self.reorder = reorder;
+        self.reorder_delay = reorder_delay;
+        self
+    }
+
+    /// Limit the throughput of the link
+    pub fn with_bandwidth(mut self, bandwidth: BandwidthConfig) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Replay a recorded trace instead of using the latency/jitter/loss parameters
+    pub fn with_trace(mut self, trace: LinkConditionerTrace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Use a fixed seed for the random number generator, to make the conditioning reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Returns the (latency, jitter, loss) to apply for the given direction
+    fn params(&self, direction: ConditionerDirection) -> (Duration, Duration, f32) {
+        match direction {
+            ConditionerDirection::Incoming => (
+                self.incoming_latency,
+                self.incoming_jitter,
+                self.incoming_loss,
+            ),
+            ConditionerDirection::Outgoing => (
+                self.outgoing_latency,
+                self.outgoing_jitter,
+                self.outgoing_loss,
+            ),
         }
     }
 
@@ -79,6 +518,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(40),
             incoming_jitter: Duration::from_millis(6),
             incoming_loss: 0.002,
+            ..Default::default()
         }
     }
 
@@ -89,6 +529,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(100),
             incoming_jitter: Duration::from_millis(15),
             incoming_loss: 0.02,
+            ..Default::default()
         }
     }
 
@@ -99,6 +540,273 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(200),
             incoming_jitter: Duration::from_millis(30),
             incoming_loss: 0.04,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_outgoing_conditioner() {
+        let config = LinkConditionerConfig::default().with_outgoing(
+            Duration::from_millis(100),
+            Duration::default(),
+            0.0,
+        );
+        let mut conditioner = LinkConditioner::<Bytes>::new(config);
+        let now = Instant::now();
+
+        // the incoming parameters are not set, so the packet is available immediately
+        conditioner.condition_packet(
+            Bytes::from_static(&[0]),
+            now,
+            ConditionerDirection::Incoming,
+        );
+        assert_eq!(conditioner.pop_packet(now), Some(Bytes::from_static(&[0])));
+
+        // the outgoing latency is applied
+        conditioner.condition_packet(
+            Bytes::from_static(&[1]),
+            now,
+            ConditionerDirection::Outgoing,
+        );
+        assert_eq!(conditioner.pop_packet(now), None);
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(100)),
+            Some(Bytes::from_static(&[1]))
+        );
+    }
+
+    #[test]
+    fn test_seeded_conditioner() {
+        let config =
+            LinkConditionerConfig::new(Duration::from_millis(50), Duration::from_millis(20), 0.3)
+                .with_duplication(0.2)
+                .with_seed(42);
+        let now = Instant::now();
+        let run = |config: LinkConditionerConfig| {
+            let mut conditioner = LinkConditioner::<Bytes>::new(config);
+            assert_eq!(conditioner.seed(), 42);
+            for i in 0..100 {
+                conditioner.condition_packet(
+                    Bytes::from(vec![i]),
+                    now,
+                    ConditionerDirection::Incoming,
+                );
+            }
+            let mut packets = vec![];
+            while let Some(packet) = conditioner.pop_packet(now + Duration::from_secs(1)) {
+                packets.push(packet);
+            }
+            packets
+        };
+        // the same seed produces the same losses, duplicates and delays
+        assert_eq!(run(config.clone()), run(config));
+    }
+
+    #[test]
+    fn test_trace_csv() {
+        let trace = LinkConditionerTrace::from_csv(
+            "timestamp,latency,dropped\n0,50,0\n100,80,1\n\n# comment\n200, 30, false\n",
+        )
+        .unwrap();
+        assert_eq!(trace.samples.len(), 3);
+        assert_eq!(trace.duration(), Duration::from_millis(300));
+        assert_eq!(
+            trace.sample(Duration::from_millis(150)),
+            Some(&TraceSample {
+                timestamp: Duration::from_millis(100),
+                latency: Duration::from_millis(80),
+                dropped: true,
+            })
+        );
+        // only the first row can be a header, and only if it names the columns
+        assert_eq!(
+            LinkConditionerTrace::from_csv("0,50,0\ntimestamp,latency,dropped"),
+            Err(TraceParseError::InvalidValue {
+                line: 2,
+                value: "dropped".into()
+            })
+        );
+        assert_eq!(
+            LinkConditionerTrace::from_csv("time,latency,dropped\n0,50,0"),
+            Err(TraceParseError::InvalidValue {
+                line: 1,
+                value: "dropped".into()
+            })
+        );
+        // the header can follow comments
+        let trace =
+            LinkConditionerTrace::from_csv("# session 1\ntimestamp,latency,dropped\n0.5,1.25,0")
+                .unwrap();
+        assert_eq!(
+            trace.samples,
+            vec![TraceSample {
+                timestamp: Duration::from_micros(500),
+                latency: Duration::from_micros(1250),
+                dropped: false,
+            }]
+        );
+        assert_eq!(
+            LinkConditionerTrace::from_csv("0,50"),
+            Err(TraceParseError::MissingColumn { line: 1 })
+        );
+        assert_eq!(
+            LinkConditionerTrace::from_csv("0,inf,0"),
+            Err(TraceParseError::InvalidValue {
+                line: 1,
+                value: "inf".into()
+            })
+        );
+        assert_eq!(
+            LinkConditionerTrace::from_csv("NaN,50,0"),
+            Err(TraceParseError::InvalidValue {
+                line: 1,
+                value: "NaN".into()
+            })
+        );
+        assert_eq!(
+            LinkConditionerTrace::from_csv("0,50,maybe"),
+            Err(TraceParseError::InvalidValue {
+                line: 1,
+                value: "maybe".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_trace_conditioner() {
+        let trace = LinkConditionerTrace::new(vec![
+            TraceSample {
+                timestamp: Duration::ZERO,
+                latency: Duration::from_millis(50),
+                dropped: false,
+            },
+            TraceSample {
+                timestamp: Duration::from_millis(100),
+                latency: Duration::ZERO,
+                dropped: true,
+            },
+            TraceSample {
+                timestamp: Duration::from_millis(200),
+                latency: Duration::from_millis(50),
+                dropped: false,
+            },
+        ])
+        .looping();
+        let config = LinkConditionerConfig::default().with_trace(trace);
+        let mut conditioner = LinkConditioner::<Bytes>::new(config);
+        let now = Instant::now();
+
+        conditioner.condition_packet(
+            Bytes::from_static(&[0]),
+            now,
+            ConditionerDirection::Incoming,
+        );
+        // dropped
+        conditioner.condition_packet(
+            Bytes::from_static(&[1]),
+            now + Duration::from_millis(150),
+            ConditionerDirection::Incoming,
+        );
+        // the last sample is also replayed
+        conditioner.condition_packet(
+            Bytes::from_static(&[2]),
+            now + Duration::from_millis(250),
+            ConditionerDirection::Incoming,
+        );
+        // the trace loops back to the first sample
+        conditioner.condition_packet(
+            Bytes::from_static(&[3]),
+            now + Duration::from_millis(310),
+            ConditionerDirection::Incoming,
+        );
+        assert_eq!(conditioner.pop_packet(now), None);
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(50)),
+            Some(Bytes::from_static(&[0]))
+        );
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(300)),
+            Some(Bytes::from_static(&[2]))
+        );
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(350)),
+            None
+        );
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(360)),
+            Some(Bytes::from_static(&[3]))
+        );
+    }
+
+    #[test]
+    fn test_burst_loss() {
+        // always lose packets in the Bad state, and never leave it
+        let config = LinkConditionerConfig::default().with_burst_loss(BurstLossConfig {
+            good_to_bad: 1.0,
+            bad_to_good: 0.0,
+            good_loss: 0.0,
+            bad_loss: 1.0,
+        });
+        let mut conditioner = LinkConditioner::<Bytes>::new(config);
+        let now = Instant::now();
+        for i in 0..10 {
+            conditioner.condition_packet(Bytes::from(vec![i]), now, ConditionerDirection::Incoming);
+        }
+        assert_eq!(conditioner.pop_packet(now), None);
+        assert_eq!(conditioner.burst_state, BurstState::Bad);
+    }
+
+    #[test]
+    fn test_duplication() {
+        let config = LinkConditionerConfig::default().with_duplication(1.0);
+        let mut conditioner = LinkConditioner::<Bytes>::new(config);
+        let now = Instant::now();
+        conditioner.condition_packet(
+            Bytes::from_static(&[0]),
+            now,
+            ConditionerDirection::Incoming,
+        );
+        assert_eq!(conditioner.pop_packet(now), Some(Bytes::from_static(&[0])));
+        assert_eq!(conditioner.pop_packet(now), Some(Bytes::from_static(&[0])));
+        assert_eq!(conditioner.pop_packet(now), None);
+    }
+
+    #[test]
+    fn test_bandwidth() {
+        // 1000 bytes per second: a 100-byte packet takes 100ms to transmit
+        let config = LinkConditionerConfig::default().with_bandwidth(BandwidthConfig {
+            bytes_per_second: 1000,
+            max_queue_delay: Duration::from_millis(150),
+        });
+        let mut conditioner = LinkConditioner::<Bytes>::new(config);
+        let now = Instant::now();
+        for i in 0..3 {
+            conditioner.condition_packet(
+                Bytes::from(vec![i; 100]),
+                now,
+                ConditionerDirection::Incoming,
+            );
         }
+        // the third packet would have to wait 200ms in the queue, so it is dropped
+        assert_eq!(conditioner.pop_packet(now), None);
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(100)),
+            Some(Bytes::from(vec![0; 100]))
+        );
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(150)),
+            None
+        );
+        assert_eq!(
+            conditioner.pop_packet(now + Duration::from_millis(200)),
+            Some(Bytes::from(vec![1; 100]))
+        );
+        assert_eq!(conditioner.pop_packet(now + Duration::from_secs(1)), None);
     }
 }
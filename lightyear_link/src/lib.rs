@@ -10,6 +10,7 @@
 //! - Link conditioning (simulating latency, jitter, packet loss) via `LinkConditioner`.
 //! - Link state management (`Linked`, `Linking`, `Unlinked`).
 //! - Basic link statistics (`LinkStats`).
+//! - Packet capture to a file via `LinkCapture`, to inspect the traffic offline.
 #![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate alloc;
@@ -18,6 +19,8 @@ use bevy::platform::time::Instant;
 #[cfg(feature = "test_utils")]
 use mock_instant::global::Instant;
 
+#[cfg(feature = "std")]
+pub mod capture;
 mod conditioner;
 
 mod id;
@@ -25,7 +28,7 @@ pub mod server;
 
 use alloc::collections::vec_deque::Drain;
 
-use crate::conditioner::LinkConditioner;
+use crate::conditioner::{ConditionerDirection, LinkConditioner};
 use alloc::collections::VecDeque;
 use bevy::ecs::component::HookContext;
 use bevy::ecs::world::DeferredWorld;
@@ -36,13 +39,19 @@ use core::time::Duration;
 
 /// Commonly used items from the `lightyear_link` crate.
 pub mod prelude {
-    pub use crate::conditioner::LinkConditionerConfig;
+    #[cfg(feature = "std")]
+    pub use crate::capture::{CaptureReader, LinkCapture};
+    pub use crate::conditioner::{
+        BandwidthConfig, BurstLossConfig, LinkConditionerConfig, LinkConditionerTrace, TraceSample,
+    };
     pub use crate::server::{LinkOf, Server};
     pub use crate::{
-        Link, LinkSet, LinkStart, LinkStats, Linked, Linking, RecvLinkConditioner, Unlinked,
+        Link, LinkSet, LinkStart, LinkStats, Linked, Linking, RecvLinkConditioner,
+        SendLinkConditioner, Unlinked,
     };
 
     pub mod server {
+        pub use crate::LinkMigrated;
         pub use crate::server::{LinkOf, Server};
     }
 }
@@ -88,6 +97,12 @@ pub struct Link {
 /// on incoming packets.
 pub type RecvLinkConditioner = LinkConditioner<RecvPayload>;
 
+/// Type alias for a `LinkConditioner` specifically for sending `SendPayload`.
+///
+/// This is used to simulate network conditions (latency, jitter, packet loss)
+/// on outgoing packets, using the `outgoing_*` parameters of the `LinkConditionerConfig`.
+pub type SendLinkConditioner = LinkConditioner<SendPayload>;
+
 impl Link {
     /// Creates a new Link with the given remote address.
     pub fn new(remote_addr: SocketAddr, recv_conditioner: Option<RecvLinkConditioner>) -> Self {
@@ -103,37 +118,72 @@ impl Link {
             remote_addr: Some(remote_addr),
         }
     }
+
+    /// Simulate network conditions on the payloads sent on this link, using the `outgoing_*`
+    /// parameters of the [`LinkConditionerConfig`](prelude::LinkConditionerConfig).
+    pub fn with_send_conditioner(mut self, conditioner: SendLinkConditioner) -> Self {
+        self.send.set_conditioner(Some(conditioner));
+        self
+    }
 }
 
 /// Handles receiving and buffering incoming payloads for a `Link`.
 ///
 /// It contains a buffer for payloads and an optional `LinkConditioner`
 /// to simulate network conditions on received data.
+///
+/// Each payload is stored with the instant at which it was received by the IO layer (or released
+/// by the `LinkConditioner`), so that network timings can be measured independently of when the
+/// payloads are processed in the frame.
 #[derive(Default)]
 pub struct LinkReceiver {
-    buffer: VecDeque<RecvPayload>,
+    buffer: VecDeque<(RecvPayload, Instant)>,
     pub conditioner: Option<LinkConditioner<RecvPayload>>,
 }
 
 impl LinkReceiver {
-    pub fn drain(&mut self) -> Drain<RecvPayload> {
-        self.buffer.drain(..)
+    pub fn drain(&mut self) -> impl Iterator<Item = RecvPayload> + '_ {
+        self.buffer.drain(..).map(|(payload, _)| payload)
+    }
+
+    /// Drain the payloads along with the time elapsed since each of them was received
+    pub fn drain_with_elapsed(&mut self) -> impl Iterator<Item = (RecvPayload, Duration)> + '_ {
+        self.buffer
+            .drain(..)
+            .map(|(payload, instant)| (payload, instant.elapsed()))
     }
 
     pub fn pop(&mut self) -> Option<RecvPayload> {
+        self.buffer.pop_front().map(|(payload, _)| payload)
+    }
+
+    /// Pop the next payload along with the instant at which it was received
+    pub fn pop_with_instant(&mut self) -> Option<(RecvPayload, Instant)> {
         self.buffer.pop_front()
     }
 
     /// Push the payload directly to the buffer with no conditioning
     pub fn push_raw(&mut self, value: RecvPayload) {
-        self.buffer.push_back(value);
+        self.push_raw_at(value, Instant::now());
     }
 
+    /// Push a payload that was received at `instant` directly to the buffer with no conditioning.
+    ///
+    /// This can be used by layers that process the payloads (for example to decrypt them) to
+    /// preserve the time at which they were originally received.
+    pub fn push_raw_at(&mut self, value: RecvPayload, instant: Instant) {
+        self.buffer.push_back((value, instant));
+    }
+
+    /// Buffer a payload that was received by the IO layer at `instant`.
+    ///
+    /// IO layers that read from the network on a separate thread should provide the time at which
+    /// the payload arrived, rather than the time at which it was pushed to the `Link`.
     pub fn push(&mut self, value: RecvPayload, instant: Instant) {
         if let Some(conditioner) = &mut self.conditioner {
-            conditioner.condition_packet(value, instant);
+            conditioner.condition_packet(value, instant, ConditionerDirection::Incoming);
         } else {
-            self.push_raw(value);
+            self.push_raw_at(value, instant);
         }
     }
 
@@ -142,37 +192,56 @@ impl LinkReceiver {
     }
 
     #[cfg(any(test, feature = "test_utils"))]
-    pub fn iter(&self) -> impl Iterator<Item = &SendPayload> {
-        self.buffer.iter()
+    pub fn iter(&self) -> impl Iterator<Item = &RecvPayload> {
+        self.buffer.iter().map(|(payload, _)| payload)
     }
 }
 /// Handles buffering outgoing payloads for a `Link`.
 ///
 /// It contains a buffer for payloads that are ready to be sent by the
-/// underlying IO transport.
+/// underlying IO transport, and an optional `LinkConditioner`
+/// to simulate network conditions on sent data.
 #[derive(Default)]
-pub struct LinkSender(VecDeque<SendPayload>);
+pub struct LinkSender {
+    buffer: VecDeque<SendPayload>,
+    conditioner: Option<LinkConditioner<SendPayload>>,
+}
 
 impl LinkSender {
-    pub fn drain(&mut self) -> Drain<SendPayload> {
-        self.0.drain(..)
+    /// Set or remove the conditioner applied to the outgoing payloads.
+    ///
+    /// Payloads already held by the previous conditioner are dropped.
+    pub fn set_conditioner(&mut self, conditioner: Option<SendLinkConditioner>) {
+        self.conditioner = conditioner;
+    }
+
+    pub fn conditioner(&self) -> Option<&SendLinkConditioner> {
+        self.conditioner.as_ref()
+    }
+
+    pub fn drain(&mut self) -> Drain<'_, SendPayload> {
+        self.buffer.drain(..)
     }
 
     pub fn pop(&mut self) -> Option<SendPayload> {
-        self.0.pop_front()
+        self.buffer.pop_front()
     }
 
+    /// Buffer the payload to be sent.
+    ///
+    /// The outgoing conditioner (if any) is only applied in [`LinkSet::ApplyConditioner`],
+    /// after every layer (transport, connection) has written its payloads to the link.
     pub fn push(&mut self, value: SendPayload) {
-        self.0.push_back(value)
+        self.buffer.push_back(value)
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.buffer.len()
     }
 
     #[cfg(any(test, feature = "test_utils"))]
     pub fn iter(&self) -> impl Iterator<Item = &SendPayload> {
-        self.0.iter()
+        self.buffer.iter()
     }
 }
 
@@ -205,7 +274,8 @@ pub enum LinkSet {
     // PRE UPDATE
     /// Receive bytes from the IO and buffer them into the Link
     Receive,
-    /// Apply Link Conditioner on the receive side
+    /// Apply Link Conditioner on the receive side (in PreUpdate)
+    /// or on the send side (in PostUpdate)
     ApplyConditioner,
 
     // PostUpdate
@@ -229,6 +299,19 @@ pub struct Unlink {
     pub reason: String,
 }
 
+/// Event triggered on a [`LinkOf`](server::LinkOf) entity when the remote peer started sending
+/// packets from a different address (for example after a NAT rebinding, or when switching from
+/// Wi-Fi to cellular).
+///
+/// It is triggered by the connection layer once it has authenticated a packet received from the new
+/// address, after updating the `remote_addr` of the [`Link`]. Server IOs that route packets by
+/// address should route the packets from `new_addr` to this entity.
+#[derive(Event, Clone, Debug)]
+pub struct LinkMigrated {
+    pub old_addr: Option<SocketAddr>,
+    pub new_addr: SocketAddr,
+}
+
 #[derive(Component, Default, Debug)]
 #[component(on_insert = Linking::on_insert)]
 pub struct Linking;
@@ -286,7 +369,7 @@ impl Unlinked {
 /// - Applying link conditioning (`LinkSet::ApplyConditioner`).
 /// - Sending data from `Link` buffers (`LinkSet::Send`).
 ///
-/// It also includes a system to apply the `LinkConditioner` if present on a `Link`.
+/// It also includes systems to apply the `LinkConditioner`s if present on a `Link`.
 pub struct LinkPlugin;
 
 impl LinkPlugin {
@@ -295,9 +378,26 @@ impl LinkPlugin {
             // enable split borrows
             let recv = &mut link.recv;
             if let Some(conditioner) = &mut recv.conditioner {
-                while let Some(packet) = conditioner.pop_packet(Instant::now()) {
+                let now = Instant::now();
+                while let Some(packet) = conditioner.pop_packet(now) {
                     // cannot use push_raw() because of partial borrows issue
-                    recv.buffer.push_back(packet);
+                    recv.buffer.push_back((packet, now));
+                }
+            }
+        });
+    }
+
+    pub fn apply_send_link_conditioner(mut query: Query<&mut Link>) {
+        query.par_iter_mut().for_each(|mut link| {
+            // enable split borrows
+            let send = &mut link.send;
+            if let Some(conditioner) = &mut send.conditioner {
+                let now = Instant::now();
+                send.buffer.drain(..).for_each(|packet| {
+                    conditioner.condition_packet(packet, now, ConditionerDirection::Outgoing);
+                });
+                while let Some(packet) = conditioner.pop_packet(now) {
+                    send.buffer.push_back(packet);
                 }
             }
         });
@@ -323,8 +423,55 @@ impl Plugin for LinkPlugin {
             PreUpdate,
             (LinkSet::Receive, LinkSet::ApplyConditioner).chain(),
         );
-        app.configure_sets(PostUpdate, LinkSet::Send);
+        app.add_systems(
+            PostUpdate,
+            Self::apply_send_link_conditioner.in_set(LinkSet::ApplyConditioner),
+        );
+        app.configure_sets(
+            PostUpdate,
+            (LinkSet::ApplyConditioner, LinkSet::Send).chain(),
+        );
 
         app.add_observer(Self::unlink);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::LinkConditionerConfig;
+
+    fn advance(duration: Duration) {
+        #[cfg(feature = "test_utils")]
+        mock_instant::global::MockClock::advance(duration);
+        #[cfg(not(feature = "test_utils"))]
+        std::thread::sleep(duration);
+    }
+
+    /// The outgoing conditioner holds the payloads sent on the link until their latency elapsed
+    #[test]
+    fn test_send_conditioner() {
+        let mut app = App::new();
+        app.add_plugins(LinkPlugin);
+        let config = LinkConditionerConfig::default().with_outgoing(
+            Duration::from_millis(20),
+            Duration::default(),
+            0.0,
+        );
+        let link = Link::new(SocketAddr::from(([127, 0, 0, 1], 0)), None)
+            .with_send_conditioner(SendLinkConditioner::new(config));
+        let entity = app.world_mut().spawn(link).id();
+
+        app.world_mut()
+            .get_mut::<Link>(entity)
+            .unwrap()
+            .send(Bytes::from_static(&[1]));
+        app.update();
+        assert_eq!(app.world().get::<Link>(entity).unwrap().send.len(), 0);
+
+        advance(Duration::from_millis(30));
+        app.update();
+        let mut link = app.world_mut().get_mut::<Link>(entity).unwrap();
+        assert_eq!(link.send.pop(), Some(Bytes::from_static(&[1])));
+    }
+}
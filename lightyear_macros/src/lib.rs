@@ -9,9 +9,11 @@ use quote::{format_ident, quote};
 use syn::{ItemEnum, parse_macro_input};
 
 use channel::channel_impl;
+use to_bytes::to_bytes_impl;
 
 mod channel;
 mod shared;
+mod to_bytes;
 
 // Channel
 #[doc(hidden)]
@@ -27,3 +29,42 @@ pub fn channel_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     let shared_crate_name = quote! { lightyear };
     channel_impl(input, shared_crate_name)
 }
+
+// ToBytes
+#[doc(hidden)]
+#[proc_macro_derive(ToBytesInternal, attributes(lightyear))]
+pub fn to_bytes_derive_internal(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let shared_crate_name = quote! { lightyear_serde };
+    to_bytes_impl(input, shared_crate_name)
+}
+
+/// Derives the `ToBytes` trait for a struct or an enum.
+///
+/// Fields are serialized in order using their own `ToBytes` implementation. Enums first write the index
+/// of the variant as a varint.
+///
+/// The encoding of a field can be changed with an attribute:
+/// - `#[lightyear(varint)]`: write an unsigned integer (`u8`, `u16`, `u32`, `u64` or `usize`) as a
+///   variable-length integer. Other types are rejected.
+/// - `#[lightyear(quantize(min = -100.0, max = 100.0, bits = 16))]`: write a `f32` in the range `[min, max]`
+///   with `bits` bits. Consecutive quantized fields are bit-packed together.
+/// - `#[lightyear(skip)]`: do not serialize the field; it is set to `Default::default()` when deserializing
+///
+/// ```rust,ignore
+/// #[derive(ToBytes)]
+/// struct PlayerState {
+///     #[lightyear(varint)]
+///     id: u64,
+///     #[lightyear(quantize(min = -1000.0, max = 1000.0, bits = 20))]
+///     x: f32,
+///     #[lightyear(quantize(min = -1000.0, max = 1000.0, bits = 20))]
+///     y: f32,
+///     #[lightyear(skip)]
+///     cached_speed: f32,
+/// }
+/// ```
+#[proc_macro_derive(ToBytes, attributes(lightyear))]
+pub fn to_bytes_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let shared_crate_name = quote! { lightyear::serde };
+    to_bytes_impl(input, shared_crate_name)
+}
@@ -0,0 +1,435 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    Data, DeriveInput, Expr, Field, Fields, Ident, LitInt, Member, Type, parse_macro_input,
+    parse_quote,
+};
+
+/// How a single field is encoded
+enum Encoding {
+    /// Use the `ToBytes` implementation of the field type
+    Default,
+    /// Encode an unsigned integer as a variable-length integer
+    Varint,
+    /// Encode a `f32` in the range `[min, max]` with `bits` bits
+    Quantize {
+        min: Box<Expr>,
+        max: Box<Expr>,
+        bits: u32,
+    },
+    /// Do not serialize the field; it is set to `Default::default()` on deserialization
+    Skip,
+}
+
+struct FieldInfo {
+    member: Member,
+    /// Name of the local variable bound to the field
+    binding: Ident,
+    ty: Type,
+    encoding: Encoding,
+}
+
+/// Consecutive fields that are serialized together
+enum Segment<'a> {
+    Single(&'a FieldInfo),
+    /// Consecutive quantized fields are packed together with a `BitWriter`
+    Bits(Vec<&'a FieldInfo>),
+}
+
+pub fn to_bytes_impl(
+    input: proc_macro::TokenStream,
+    shared_crate_name: TokenStream,
+) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input, &shared_crate_name) {
+        Ok(tokens) => proc_macro::TokenStream::from(tokens),
+        Err(err) => proc_macro::TokenStream::from(err.to_compile_error()),
+    }
+}
+
+fn expand(input: DeriveInput, krate: &TokenStream) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+
+    let mut generics = input.generics.clone();
+    let type_params: Vec<Ident> = generics.type_params().map(|p| p.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in type_params {
+        where_clause
+            .predicates
+            .push(parse_quote! { #param: #krate::ToBytes });
+    }
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let (bytes_len, to_bytes, from_bytes) = match &input.data {
+        Data::Struct(data) => {
+            let fields = parse_fields(&data.fields)?;
+            let pattern = pattern(quote! { Self }, &data.fields, &fields);
+            let bytes_len = bytes_len_expr(&fields, krate);
+            let to_bytes = to_bytes_stmts(&fields, krate);
+            let read = from_bytes_stmts(&fields, krate);
+            let construct = pattern_construct(quote! { Self }, &data.fields, &fields);
+            (
+                quote! {
+                    let #pattern = self;
+                    #bytes_len
+                },
+                quote! {
+                    let #pattern = self;
+                    #to_bytes
+                    Ok(())
+                },
+                quote! {
+                    #read
+                    Ok(#construct)
+                },
+            )
+        }
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Cannot derive ToBytes on an enum without variants",
+                ));
+            }
+            let mut len_arms = Vec::new();
+            let mut write_arms = Vec::new();
+            let mut read_arms = Vec::new();
+            for (index, variant) in data.variants.iter().enumerate() {
+                let index = index as u64;
+                let ident = &variant.ident;
+                let fields = parse_fields(&variant.fields)?;
+                let pattern = pattern(quote! { Self::#ident }, &variant.fields, &fields);
+                let bytes_len = bytes_len_expr(&fields, krate);
+                let to_bytes = to_bytes_stmts(&fields, krate);
+                let read = from_bytes_stmts(&fields, krate);
+                let construct =
+                    pattern_construct(quote! { Self::#ident }, &variant.fields, &fields);
+                len_arms.push(quote! {
+                    #pattern => #krate::varint::varint_len(#index) + #bytes_len
+                });
+                write_arms.push(quote! {
+                    #pattern => {
+                        #krate::writer::WriteInteger::write_varint(buffer, #index)?;
+                        #to_bytes
+                    }
+                });
+                read_arms.push(quote! {
+                    #index => {
+                        #read
+                        Ok(#construct)
+                    }
+                });
+            }
+            (
+                quote! {
+                    match self {
+                        #(#len_arms,)*
+                    }
+                },
+                quote! {
+                    match self {
+                        #(#write_arms)*
+                    }
+                    Ok(())
+                },
+                quote! {
+                    match #krate::reader::ReadVarInt::read_varint(buffer)? {
+                        #(#read_arms)*
+                        _ => Err(#krate::SerializationError::InvalidValue),
+                    }
+                },
+            )
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Cannot derive ToBytes on a union",
+            ));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #krate::ToBytes for #name #type_generics #where_clause {
+            #[allow(unused_variables)]
+            fn bytes_len(&self) -> usize {
+                #bytes_len
+            }
+
+            fn to_bytes(
+                &self,
+                buffer: &mut impl #krate::writer::WriteInteger,
+            ) -> ::core::result::Result<(), #krate::SerializationError> {
+                #to_bytes
+            }
+
+            fn from_bytes(
+                buffer: &mut #krate::reader::Reader,
+            ) -> ::core::result::Result<Self, #krate::SerializationError>
+            where
+                Self: Sized,
+            {
+                #from_bytes
+            }
+        }
+    })
+}
+
+fn parse_fields(fields: &Fields) -> syn::Result<Vec<FieldInfo>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(i.into()),
+            };
+            Ok(FieldInfo {
+                member,
+                binding: format_ident!("__field{}", i),
+                ty: field.ty.clone(),
+                encoding: parse_encoding(field)?,
+            })
+        })
+        .collect()
+}
+
+/// Whether the type is one of the unsigned integers that can be encoded as a varint.
+///
+/// Signed integers are rejected because casting them to `u64` would encode negative values
+/// with the maximum length.
+fn is_unsigned_integer(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
+    };
+    path.qself.is_none()
+        && ["u8", "u16", "u32", "u64", "usize"]
+            .iter()
+            .any(|name| path.path.is_ident(name))
+}
+
+/// Parse the `#[lightyear(...)]` attributes of a field
+fn parse_encoding(field: &Field) -> syn::Result<Encoding> {
+    let mut encoding = None;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("lightyear") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            let parsed = if meta.path.is_ident("varint") {
+                if !is_unsigned_integer(&field.ty) {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        "`varint` can only be used on unsigned integers (`u8`, `u16`, `u32`, `u64` or `usize`)",
+                    ));
+                }
+                Encoding::Varint
+            } else if meta.path.is_ident("skip") {
+                Encoding::Skip
+            } else if meta.path.is_ident("quantize") {
+                let mut min = None;
+                let mut max = None;
+                let mut bits = None;
+                meta.parse_nested_meta(|inner| {
+                    if inner.path.is_ident("min") {
+                        min = Some(Box::new(inner.value()?.parse::<Expr>()?));
+                    } else if inner.path.is_ident("max") {
+                        max = Some(Box::new(inner.value()?.parse::<Expr>()?));
+                    } else if inner.path.is_ident("bits") {
+                        let lit = inner.value()?.parse::<LitInt>()?;
+                        let value = lit.base10_parse::<u32>()?;
+                        if value == 0 || value > 32 {
+                            return Err(syn::Error::new_spanned(
+                                lit,
+                                "`bits` must be between 1 and 32",
+                            ));
+                        }
+                        bits = Some(value);
+                    } else {
+                        return Err(inner.error("expected `min`, `max` or `bits`"));
+                    }
+                    Ok(())
+                })?;
+                match (min, max, bits) {
+                    (Some(min), Some(max), Some(bits)) => Encoding::Quantize { min, max, bits },
+                    _ => {
+                        return Err(meta.error(
+                            "`quantize` requires `min`, `max` and `bits`, e.g. `quantize(min = -1.0, max = 1.0, bits = 16)`",
+                        ));
+                    }
+                }
+            } else {
+                return Err(meta.error("expected `varint`, `quantize(...)` or `skip`"));
+            };
+            if encoding.is_some() {
+                return Err(meta.error("only one encoding attribute can be specified per field"));
+            }
+            encoding = Some(parsed);
+            Ok(())
+        })?;
+    }
+    Ok(encoding.unwrap_or(Encoding::Default))
+}
+
+/// Pattern that binds every field to a local variable, e.g. `Self { a: __field0, b: _ }`
+fn pattern(path: TokenStream, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+    let bindings = infos.iter().map(|info| {
+        let binding = if matches!(info.encoding, Encoding::Skip) {
+            quote! { _ }
+        } else {
+            let binding = &info.binding;
+            quote! { #binding }
+        };
+        match &info.member {
+            Member::Named(ident) => quote! { #ident: #binding },
+            Member::Unnamed(_) => binding,
+        }
+    });
+    match fields {
+        Fields::Named(_) => quote! { #path { #(#bindings),* } },
+        Fields::Unnamed(_) => quote! { #path ( #(#bindings),* ) },
+        Fields::Unit => path,
+    }
+}
+
+/// Expression that builds the value from the local variables
+fn pattern_construct(path: TokenStream, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+    let values = infos.iter().map(|info| {
+        let binding = &info.binding;
+        match &info.member {
+            Member::Named(ident) => quote! { #ident: #binding },
+            Member::Unnamed(_) => quote! { #binding },
+        }
+    });
+    match fields {
+        Fields::Named(_) => quote! { #path { #(#values),* } },
+        Fields::Unnamed(_) => quote! { #path ( #(#values),* ) },
+        Fields::Unit => path,
+    }
+}
+
+fn segments(infos: &[FieldInfo]) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    for info in infos {
+        match info.encoding {
+            Encoding::Skip => {}
+            Encoding::Quantize { .. } => match segments.last_mut() {
+                Some(Segment::Bits(group)) => group.push(info),
+                _ => segments.push(Segment::Bits(vec![info])),
+            },
+            _ => segments.push(Segment::Single(info)),
+        }
+    }
+    segments
+}
+
+fn quantize_params(info: &FieldInfo) -> (&Expr, &Expr, u32) {
+    match &info.encoding {
+        Encoding::Quantize { min, max, bits } => (min, max, *bits),
+        _ => unreachable!(),
+    }
+}
+
+fn bytes_len_expr(infos: &[FieldInfo], krate: &TokenStream) -> TokenStream {
+    let terms = segments(infos).into_iter().map(|segment| match segment {
+        Segment::Single(info) => {
+            let binding = &info.binding;
+            match info.encoding {
+                Encoding::Varint => quote! { #krate::varint::varint_len(*#binding as u64) },
+                _ => quote! { #krate::ToBytes::bytes_len(#binding) },
+            }
+        }
+        Segment::Bits(group) => {
+            let total_bits: u32 = group.iter().map(|info| quantize_params(info).2).sum();
+            quote! { #krate::bits::bits_to_bytes(#total_bits) }
+        }
+    });
+    quote! { 0 #(+ #terms)* }
+}
+
+fn to_bytes_stmts(infos: &[FieldInfo], krate: &TokenStream) -> TokenStream {
+    let stmts = segments(infos).into_iter().map(|segment| match segment {
+        Segment::Single(info) => {
+            let binding = &info.binding;
+            match info.encoding {
+                Encoding::Varint => quote! {
+                    #krate::writer::WriteInteger::write_varint(buffer, *#binding as u64)?;
+                },
+                _ => quote! {
+                    #krate::ToBytes::to_bytes(#binding, buffer)?;
+                },
+            }
+        }
+        Segment::Bits(group) => {
+            let writes = group.iter().map(|info| {
+                let binding = &info.binding;
+                let (min, max, bits) = quantize_params(info);
+                quote! {
+                    bit_writer.write_bits(
+                        #krate::quantize::quantize_f32(*#binding, (#min) as f32, (#max) as f32, #bits),
+                        #bits,
+                    )?;
+                }
+            });
+            quote! {
+                let mut bit_writer = #krate::bits::BitWriter::new(&mut *buffer);
+                #(#writes)*
+                bit_writer.finish()?;
+            }
+        }
+    });
+    quote! { #(#stmts)* }
+}
+
+fn from_bytes_stmts(infos: &[FieldInfo], krate: &TokenStream) -> TokenStream {
+    let mut stmts = Vec::new();
+    for segment in segments(infos) {
+        match segment {
+            Segment::Single(info) => {
+                let binding = &info.binding;
+                let ty = &info.ty;
+                stmts.push(match info.encoding {
+                    Encoding::Varint => quote! {
+                        let #binding = <#ty as ::core::convert::TryFrom<u64>>::try_from(
+                            #krate::reader::ReadVarInt::read_varint(buffer)?,
+                        )
+                        .map_err(|_| #krate::SerializationError::InvalidValue)?;
+                    },
+                    _ => quote! {
+                        let #binding = <#ty as #krate::ToBytes>::from_bytes(buffer)?;
+                    },
+                });
+            }
+            Segment::Bits(group) => {
+                let bindings = group.iter().map(|info| &info.binding);
+                let reads = group.iter().map(|info| {
+                    let (min, max, bits) = quantize_params(info);
+                    quote! {
+                        #krate::quantize::dequantize_f32(
+                            bit_reader.read_bits(#bits)?,
+                            (#min) as f32,
+                            (#max) as f32,
+                            #bits,
+                        )
+                    }
+                });
+                stmts.push(quote! {
+                    let (#(#bindings,)*) = {
+                        let mut bit_reader = #krate::bits::BitReader::new(&mut *buffer);
+                        (#(#reads,)*)
+                    };
+                });
+            }
+        }
+    }
+    // skipped fields are not serialized
+    for info in infos {
+        if matches!(info.encoding, Encoding::Skip) {
+            let binding = &info.binding;
+            let ty = &info.ty;
+            stmts.push(quote! {
+                let #binding = <#ty as ::core::default::Default>::default();
+            });
+        }
+    }
+    quote! { #(#stmts)* }
+}
@@ -12,6 +12,7 @@ extern crate alloc;
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
+use crate::protocol::RemoteVersions;
 use crate::registry::MessageKind;
 use bevy::ecs::component::ComponentId;
 use bevy::prelude::{Component, Reflect};
@@ -23,6 +24,7 @@ use lightyear_transport::prelude::Transport;
 mod client;
 pub mod multi;
 pub mod plugin;
+pub mod protocol;
 pub mod receive;
 mod receive_trigger;
 pub mod registry;
@@ -34,10 +36,14 @@ mod trigger;
 
 pub mod prelude {
     pub use crate::plugin::MessageSet;
+    pub use crate::protocol::{
+        AppProtocolExt, ProtocolCheckTimeout, ProtocolHash, ProtocolMismatch, VersionMismatch,
+        VersionedType,
+    };
     pub use crate::receive::MessageReceiver;
     pub use crate::receive_trigger::RemoteTrigger;
     pub use crate::registry::AppMessageExt;
-    pub use crate::send::MessageSender;
+    pub use crate::send::{MessageSender, SendOptions};
     pub use crate::send_trigger::TriggerSender;
     pub use crate::trigger::AppTriggerExt;
     pub use crate::{Message, MessageManager};
@@ -78,4 +84,7 @@ pub struct MessageManager {
     /// List of component ids of the MessageReceiver<M> present on this entity
     pub(crate) receive_messages: Vec<(MessageKind, ComponentId)>,
     pub entity_mapper: RemoteEntityMap,
+    /// Versions of the types of the remote peer, used to write versioned messages and components
+    #[reflect(ignore)]
+    pub remote_versions: RemoteVersions,
 }
@@ -1,3 +1,4 @@
+use crate::protocol::RemoteVersions;
 use crate::registry::MessageRegistry;
 use crate::send::Priority;
 use crate::{Message, MessageManager};
@@ -27,14 +28,15 @@ impl MultiMessageSender<'_, '_> {
         senders: impl EntitySet,
         priority: Priority,
     ) -> Result {
-        // if the message is not map-entities, we can serialize it once and clone the bytes
-        if !self.registry.is_map_entities::<M>()? {
+        // if the message is not map-entities or versioned, we can serialize it once and clone the bytes
+        if !self.registry.is_map_entities::<M>()? && !self.registry.is_versioned::<M>()? {
             // TODO: serialize once for all senders. Figure out how to get a shared writer. Maybe on Server? Or as a global resource?
             //   or as Local?
             self.registry.serialize::<M>(
                 message,
                 &mut self.writer,
                 &mut SendEntityMap::default(),
+                &RemoteVersions::default(),
             )?;
             let bytes = self.writer.split();
             self.query
@@ -46,11 +48,14 @@ impl MultiMessageSender<'_, '_> {
             self.query
                 .iter_many_unique_mut(senders)
                 .try_for_each(|(mut manager, transport)| {
+                    // enable split borrows
+                    let manager = &mut *manager;
                     self.registry.serialize::<M>(
                         message,
                         &mut self.writer,
                         // TODO: ideally we could do entity mapping without Mut!!!
                         &mut manager.entity_mapper.local_to_remote,
+                        &manager.remote_versions,
                     )?;
                     let bytes = self.writer.split();
                     transport.send_with_priority::<C>(bytes, priority)?;
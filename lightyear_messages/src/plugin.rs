@@ -116,6 +116,7 @@
 // }
 
 use crate::MessageManager;
+use crate::protocol::ProtocolCheckPlugin;
 use crate::registry::MessageRegistry;
 use bevy::app::{App, Last, PostUpdate, PreUpdate};
 use bevy::ecs::system::{ParamBuilder, QueryParamBuilder};
@@ -164,6 +165,11 @@ impl Plugin for MessagePlugin {
         if !app.is_plugin_added::<TransportPlugin>() {
             app.add_plugins(TransportPlugin);
         }
+        // register the protocol check first, so that its channel and message have the same
+        // network ids on both peers
+        if !app.is_plugin_added::<ProtocolCheckPlugin>() {
+            app.add_plugins(ProtocolCheckPlugin);
+        }
 
         app.add_observer(Self::handle_disconnection);
 
@@ -359,6 +365,7 @@ mod tests {
                 remote_tick: Tick::default(),
                 channel_kind: ChannelKind::of::<C>(),
                 message_id: None,
+                lateness: None,
             });
         app.update();
 
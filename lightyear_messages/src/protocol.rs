@@ -0,0 +1,585 @@
+//! Check that both peers use the same protocol.
+//!
+//! The network ids of channels, messages and components are assigned in registration order,
+//! so if some plugins are only added on one peer (or if types are registered in a different order),
+//! the two peers would silently deserialize garbage.
+//!
+//! To prevent this, each peer computes a [`ProtocolHash`] from its registries, and sends it to the
+//! remote peer as soon as the connection is [`Connected`]. If the hashes differ, a [`ProtocolMismatch`]
+//! event is triggered and the connection is closed.
+//!
+//! The hash is computed from the network ids, directions and modes of the registered types, and from
+//! the names given to them with `with_protocol_name` when they were registered. The names returned by
+//! [`core::any::type_name`] are not used, since they can differ between compiler versions. Without
+//! protocol names, two types with the same settings that are registered in a different order are not detected.
+//!
+//! The [`ProtocolCheck`] also contains the versions of the messages and components that were
+//! registered with [`VersionFns`](lightyear_serde::version::VersionFns), identified by their network id.
+//! The versions are not part of the [`ProtocolHash`], so that a peer can stay compatible with the
+//! previous build of the remote peer.
+//! The remote versions are stored in the [`MessageManager`] and are used to write the values we send
+//! in a format that the remote peer understands. If the remote uses a version that we cannot exchange
+//! values with, a [`VersionMismatch`] event is triggered and the connection is closed.
+//!
+//! Triggers cannot be versioned: a trigger must keep the same wire format for the peers to stay
+//! compatible.
+//!
+//! A peer that doesn't send its [`ProtocolCheck`] within the [`ProtocolCheckTimeout`] is disconnected.
+#[cfg(not(feature = "std"))]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::Hasher;
+use core::time::Duration;
+
+use crate::MessageManager;
+use crate::plugin::MessageSet;
+use crate::prelude::{AppMessageExt, MessageReceiver, MessageSender};
+use crate::registry::MessageRegistry;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use lightyear_connection::client::{Connected, disconnect_with_reason};
+use lightyear_connection::direction::NetworkDirection;
+use lightyear_core::network::NetId;
+use lightyear_serde::registry::ErasedSerializeFns;
+use lightyear_serde::version::ErasedVersionFns;
+use lightyear_transport::prelude::{AppChannelExt, ChannelMode, ChannelRegistry, ChannelSettings};
+use seahash::SeaHasher;
+use serde::{Deserialize, Serialize};
+use tracing::{error, trace};
+
+/// Channel used to exchange the [`ProtocolHash`] with the remote peer
+pub struct ProtocolChannel;
+
+/// Message sent to the remote peer when the connection is established
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProtocolCheck {
+    pub hash: u64,
+    /// Version and oldest compatible version of each versioned type of the protocol
+    /// (see [`ErasedVersionFns::min_compatible_version`])
+    pub versions: Vec<(VersionedType, u16, u16)>,
+}
+
+/// Identifies a versioned type of the protocol by its network id.
+///
+/// The versions are only compared once the [`ProtocolHash`] of both peers match, so a network id
+/// refers to the same type on both peers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VersionedType {
+    Message(NetId),
+    Component(NetId),
+}
+
+/// Hash of the protocol (channels, messages, components, etc.) of the local peer.
+///
+/// It is computed when the [`App`] is finished, once all the types have been registered.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolHash(pub u64);
+
+/// Default value of the [`ProtocolCheckTimeout`]
+pub const DEFAULT_PROTOCOL_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Duration after which a connected peer that didn't send its [`ProtocolCheck`] is disconnected
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolCheckTimeout(pub Duration);
+
+impl Default for ProtocolCheckTimeout {
+    fn default() -> Self {
+        Self(DEFAULT_PROTOCOL_CHECK_TIMEOUT)
+    }
+}
+
+/// Inserted on a connection entity until the [`ProtocolCheck`] of the remote peer is received
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingProtocolCheck {
+    /// Elapsed real time after which the remote peer is disconnected
+    deadline: Duration,
+}
+
+/// Function that feeds a registry of the [`World`] to the hasher used to compute the [`ProtocolHash`]
+pub type ProtocolHashFn = fn(&World, &mut dyn Hasher);
+
+#[derive(Resource, Default)]
+struct ProtocolHashFns(Vec<ProtocolHashFn>);
+
+/// Versions of the versioned types of the local protocol, with the names of the types
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct ProtocolVersions(HashMap<VersionedType, (&'static str, ErasedVersionFns)>);
+
+impl ProtocolVersions {
+    /// Returns the first type that we cannot exchange with a peer that uses the `remote` versions
+    /// and oldest compatible versions.
+    fn find_mismatch(
+        &self,
+        remote: &HashMap<VersionedType, (u16, u16)>,
+    ) -> Option<VersionMismatch> {
+        self.0
+            .iter()
+            .find_map(|(versioned_type, (name, fns))| {
+                let remote_versions = remote.get(versioned_type).copied();
+                (!remote_versions.is_some_and(|(version, min_compatible_version)| {
+                    fns.is_compatible(version, min_compatible_version)
+                }))
+                .then(|| VersionMismatch {
+                    versioned_type: *versioned_type,
+                    name: Some(*name),
+                    local: Some(fns.version),
+                    remote: remote_versions.map(|(version, _)| version),
+                })
+            })
+            .or_else(|| {
+                // the remote peer versioned a type that is not versioned locally
+                remote
+                    .iter()
+                    .find(|(versioned_type, _)| !self.0.contains_key(*versioned_type))
+                    .map(|(versioned_type, (version, _))| VersionMismatch {
+                        versioned_type: *versioned_type,
+                        name: None,
+                        local: None,
+                        remote: Some(*version),
+                    })
+            })
+    }
+}
+
+/// Versions of the versioned types of the remote peer, received in its [`ProtocolCheck`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RemoteVersions(Option<HashMap<VersionedType, u16>>);
+
+impl RemoteVersions {
+    /// Version of the type used by the remote peer, or None if it is not known yet
+    pub fn get(
+        &self,
+        versioned_type: VersionedType,
+        serialize_fns: &ErasedSerializeFns,
+    ) -> Option<u16> {
+        serialize_fns.version.as_ref()?;
+        self.0.as_ref()?.get(&versioned_type).copied()
+    }
+}
+
+pub trait AppProtocolExt {
+    /// Include an additional registry in the [`ProtocolHash`].
+    ///
+    /// The [`ChannelRegistry`] and the [`MessageRegistry`] are always included.
+    fn add_protocol_hash_fn(&mut self, hash_fn: ProtocolHashFn);
+
+    /// Include a versioned type in the versions that are sent to the remote peer.
+    ///
+    /// This is called when a message or a component is registered with a version.
+    fn add_protocol_version(
+        &mut self,
+        versioned_type: VersionedType,
+        serialize_fns: &ErasedSerializeFns,
+    );
+}
+
+impl AppProtocolExt for App {
+    fn add_protocol_hash_fn(&mut self, hash_fn: ProtocolHashFn) {
+        self.world_mut()
+            .get_resource_or_init::<ProtocolHashFns>()
+            .0
+            .push(hash_fn);
+    }
+
+    fn add_protocol_version(
+        &mut self,
+        versioned_type: VersionedType,
+        serialize_fns: &ErasedSerializeFns,
+    ) {
+        let Some(version_fns) = serialize_fns.version.clone() else {
+            return;
+        };
+        self.world_mut()
+            .get_resource_or_init::<ProtocolVersions>()
+            .0
+            .insert(versioned_type, (serialize_fns.type_name, version_fns));
+    }
+}
+
+/// Triggered on the connection entity when the remote peer uses a different protocol.
+///
+/// The entity is then disconnected with [`disconnect_with_reason`], with a reason describing the mismatch.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ProtocolMismatch {
+    /// [`ProtocolHash`] of the local peer
+    pub local: u64,
+    /// [`ProtocolHash`] of the remote peer
+    pub remote: u64,
+}
+
+/// Triggered on the connection entity when the remote peer uses a version of a type that we
+/// cannot exchange values with.
+///
+/// The entity is then disconnected with [`disconnect_with_reason`], with a reason describing the mismatch.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct VersionMismatch {
+    pub versioned_type: VersionedType,
+    /// Name of the type, or None if the type is only versioned by the remote peer
+    pub name: Option<&'static str>,
+    /// Local version of the type, or None if the type is not versioned
+    pub local: Option<u16>,
+    /// Remote version of the type, or None if the type is not versioned
+    pub remote: Option<u16>,
+}
+
+/// Plugin that exchanges the [`ProtocolHash`] with the remote peer on connection, and disconnects
+/// if the protocols don't match.
+///
+/// It is added by the [`MessagePlugin`](crate::plugin::MessagePlugin), before any other channel
+/// or message is registered, so that the [`ProtocolCheck`] message can be read even if the rest of
+/// the protocol differs.
+pub struct ProtocolCheckPlugin;
+
+impl ProtocolCheckPlugin {
+    fn compute_hash(world: &mut World) {
+        let mut hasher = SeaHasher::new();
+        if let Some(registry) = world.get_resource::<ChannelRegistry>() {
+            registry.hash_protocol(&mut hasher);
+        }
+        if let Some(registry) = world.get_resource::<MessageRegistry>() {
+            registry.hash_protocol(&mut hasher);
+        }
+        if let Some(hash_fns) = world.get_resource::<ProtocolHashFns>() {
+            hash_fns
+                .0
+                .iter()
+                .for_each(|hash_fn| hash_fn(world, &mut hasher));
+        }
+        let hash = hasher.finish();
+        trace!(?hash, "Computed protocol hash");
+        world.insert_resource(ProtocolHash(hash));
+    }
+
+    /// Send our [`ProtocolHash`] and versions to the remote peer as soon as we are connected
+    fn send_hash(
+        trigger: Trigger<OnAdd, Connected>,
+        real_time: Res<Time<Real>>,
+        timeout: Res<ProtocolCheckTimeout>,
+        hash: Option<Res<ProtocolHash>>,
+        versions: Option<Res<ProtocolVersions>>,
+        mut query: Query<(&mut MessageSender<ProtocolCheck>, &mut MessageManager)>,
+        mut commands: Commands,
+    ) {
+        let Some(hash) = hash else {
+            return;
+        };
+        if let Ok((mut sender, mut manager)) = query.get_mut(trigger.target()) {
+            // the remote versions are unknown until we receive the remote ProtocolCheck
+            manager.remote_versions = RemoteVersions::default();
+            let versions = versions.map_or_else(Vec::new, |versions| {
+                versions
+                    .0
+                    .iter()
+                    .map(|(versioned_type, (_, fns))| {
+                        (*versioned_type, fns.version, fns.min_compatible_version())
+                    })
+                    .collect()
+            });
+            sender.send::<ProtocolChannel>(ProtocolCheck {
+                hash: hash.0,
+                versions,
+            });
+            commands
+                .entity(trigger.target())
+                .insert(PendingProtocolCheck {
+                    deadline: real_time.elapsed() + timeout.0,
+                });
+        }
+    }
+
+    /// Disconnect from the peers that use a different protocol or that didn't send their
+    /// [`ProtocolCheck`] in time, or store their versions
+    fn receive_hash(
+        real_time: Res<Time<Real>>,
+        hash: Option<Res<ProtocolHash>>,
+        versions: Option<Res<ProtocolVersions>>,
+        mut query: Query<
+            (
+                Entity,
+                &mut MessageReceiver<ProtocolCheck>,
+                &mut MessageManager,
+                Option<&PendingProtocolCheck>,
+            ),
+            With<Connected>,
+        >,
+        mut commands: Commands,
+    ) {
+        let Some(hash) = hash else {
+            return;
+        };
+        let no_versions = ProtocolVersions::default();
+        let versions = versions.as_deref().unwrap_or(&no_versions);
+        query
+            .iter_mut()
+            .for_each(|(entity, mut receiver, mut manager, pending)| {
+                let mut received = false;
+                receiver.receive().for_each(|check| {
+                    received = true;
+                    if check.hash != hash.0 {
+                        commands.trigger_targets(
+                            ProtocolMismatch {
+                                local: hash.0,
+                                remote: check.hash,
+                            },
+                            entity,
+                        );
+                        let reason = format!(
+                            "Protocol mismatch: local protocol hash is {:#x}, remote protocol hash is {:#x}",
+                            hash.0, check.hash
+                        );
+                        Self::disconnect(&mut commands, entity, reason);
+                        return;
+                    }
+                    let remote_versions: HashMap<VersionedType, (u16, u16)> = check
+                        .versions
+                        .into_iter()
+                        .map(|(versioned_type, version, min_compatible_version)| {
+                            (versioned_type, (version, min_compatible_version))
+                        })
+                        .collect();
+                    if let Some(mismatch) = versions.find_mismatch(&remote_versions) {
+                        let reason = format!(
+                            "Version mismatch for {}: local version is {:?}, remote version is {:?}",
+                            mismatch.name.map_or_else(
+                                || format!("{:?}", mismatch.versioned_type),
+                                ToString::to_string
+                            ),
+                            mismatch.local,
+                            mismatch.remote
+                        );
+                        commands.trigger_targets(mismatch, entity);
+                        Self::disconnect(&mut commands, entity, reason);
+                        return;
+                    }
+                    trace!(?entity, ?remote_versions, "Received remote protocol versions");
+                    manager.remote_versions = RemoteVersions(Some(
+                        remote_versions
+                            .into_iter()
+                            .map(|(versioned_type, (version, _))| (versioned_type, version))
+                            .collect(),
+                    ));
+                });
+                if received {
+                    commands.entity(entity).remove::<PendingProtocolCheck>();
+                } else if pending.is_some_and(|pending| real_time.elapsed() > pending.deadline) {
+                    let reason = "Protocol check timed out: the remote peer didn't send its protocol hash".to_string();
+                    Self::disconnect(&mut commands, entity, reason);
+                }
+            });
+    }
+
+    fn disconnect(commands: &mut Commands, entity: Entity, reason: String) {
+        error!(?entity, "{reason}");
+        disconnect_with_reason(commands, entity, reason);
+    }
+}
+
+impl Plugin for ProtocolCheckPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_channel::<ProtocolChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(default()),
+            ..default()
+        })
+        .add_direction(NetworkDirection::Bidirectional);
+        app.add_message::<ProtocolCheck>()
+            .add_direction(NetworkDirection::Bidirectional);
+
+        app.init_resource::<ProtocolCheckTimeout>();
+        app.add_systems(PreUpdate, Self::receive_hash.after(MessageSet::Receive));
+        app.add_observer(Self::send_hash);
+    }
+
+    // NOTE: the hash is computed in `finish`, once every plugin has registered its types. We don't use a
+    //  `Startup` system because running it would apply the commands queued before the first update
+    //  (for example by the `Connect` observers), which would change the timing of the connection
+    fn finish(&self, app: &mut App) {
+        Self::compute_hash(app.world_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use crate::registry::MessageKind;
+    use lightyear_serde::version::VersionFns;
+
+    struct C1;
+    struct C2;
+
+    #[derive(Serialize, Deserialize)]
+    struct M1;
+
+    #[derive(Serialize, Deserialize)]
+    struct M2;
+
+    fn protocol_hash(app: &mut App) -> u64 {
+        app.add_plugins(ProtocolCheckPlugin);
+        ProtocolCheckPlugin::compute_hash(app.world_mut());
+        app.world().resource::<ProtocolHash>().0
+    }
+
+    #[test]
+    fn test_protocol_hash() {
+        let mut app = App::new();
+        app.add_channel::<C1>(default());
+        app.add_message::<M1>();
+        let hash = protocol_hash(&mut app);
+
+        // same protocol
+        let mut same_app = App::new();
+        same_app.add_channel::<C1>(default());
+        same_app.add_message::<M1>();
+        assert_eq!(protocol_hash(&mut same_app), hash);
+
+        // channels registered in a different order
+        let mut other_app = App::new();
+        other_app.add_channel::<C2>(default());
+        other_app.add_channel::<C1>(default());
+        other_app.add_message::<M1>();
+        assert_ne!(protocol_hash(&mut other_app), hash);
+
+        // different direction
+        let mut other_app = App::new();
+        other_app.add_channel::<C1>(default());
+        other_app
+            .add_message::<M1>()
+            .add_direction(NetworkDirection::ClientToServer);
+        assert_ne!(protocol_hash(&mut other_app), hash);
+
+        // additional registry
+        let mut other_app = App::new();
+        other_app.add_channel::<C1>(default());
+        other_app.add_message::<M1>();
+        other_app.add_protocol_hash_fn(|_, hasher| hasher.write_u8(1));
+        assert_ne!(protocol_hash(&mut other_app), hash);
+    }
+
+    #[test]
+    fn test_protocol_name() {
+        let mut app = App::new();
+        app.add_channel::<C1>(default());
+        app.add_message::<M1>();
+        app.add_message::<M2>();
+        let hash = protocol_hash(&mut app);
+
+        // the type names are not part of the hash, since they can change with the compiler version
+        let mut other_app = App::new();
+        other_app.add_channel::<C1>(default());
+        other_app.add_message::<M2>();
+        other_app.add_message::<M1>();
+        assert_eq!(protocol_hash(&mut other_app), hash);
+
+        // messages registered in a different order are detected with their protocol names
+        let mut app = App::new();
+        app.add_channel::<C1>(default()).with_protocol_name("C1");
+        app.add_message::<M1>().with_protocol_name("M1");
+        app.add_message::<M2>().with_protocol_name("M2");
+        let hash = protocol_hash(&mut app);
+        let mut other_app = App::new();
+        other_app
+            .add_channel::<C1>(default())
+            .with_protocol_name("C1");
+        other_app.add_message::<M2>().with_protocol_name("M2");
+        other_app.add_message::<M1>().with_protocol_name("M1");
+        assert_ne!(protocol_hash(&mut other_app), hash);
+    }
+
+    fn message_net_id<M: Message>(app: &App) -> NetId {
+        let registry = app.world().resource::<MessageRegistry>();
+        *registry.kind_map.net_id(&MessageKind::of::<M>()).unwrap()
+    }
+
+    fn mismatch(
+        versioned_type: VersionedType,
+        local: Option<u16>,
+        remote: Option<u16>,
+    ) -> Option<VersionMismatch> {
+        Some(VersionMismatch {
+            versioned_type,
+            name: local.map(|_| core::any::type_name::<M1>()),
+            local,
+            remote,
+        })
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let mut app = App::new();
+        app.add_message::<M1>().add_version(
+            VersionFns::new(2)
+                .with_upgrade(1, |_, _| Ok(M1))
+                .with_downgrade(|_, _, _| Ok(())),
+        );
+        let versions = app.world().resource::<ProtocolVersions>();
+        let net_id = message_net_id::<M1>(&app);
+        let m1 = VersionedType::Message(net_id);
+
+        // the remote peer uses a version that we can convert from and to
+        let remote = HashMap::from_iter([(m1, (1, 1))]);
+        assert_eq!(versions.find_mismatch(&remote), None);
+        // the more recent remote peer can convert from and to our version
+        let remote = HashMap::from_iter([(m1, (3, 2))]);
+        assert_eq!(versions.find_mismatch(&remote), None);
+
+        // the more recent remote peer cannot convert from and to our version
+        let remote = HashMap::from_iter([(m1, (3, 3))]);
+        assert_eq!(
+            versions.find_mismatch(&remote),
+            mismatch(m1, Some(2), Some(3))
+        );
+
+        // the remote peer uses a version that is too old
+        let remote = HashMap::from_iter([(m1, (0, 0))]);
+        assert_eq!(
+            versions.find_mismatch(&remote),
+            mismatch(m1, Some(2), Some(0))
+        );
+
+        // the type is not versioned on the remote peer
+        assert_eq!(
+            versions.find_mismatch(&HashMap::default()),
+            mismatch(m1, Some(2), None)
+        );
+
+        // the type is only versioned on the remote peer
+        let remote = HashMap::from_iter([(m1, (1, 1))]);
+        assert_eq!(
+            ProtocolVersions::default().find_mismatch(&remote),
+            mismatch(m1, None, Some(1))
+        );
+
+        // a component with the same network id is a different type
+        let component = VersionedType::Component(net_id);
+        let remote = HashMap::from_iter([(m1, (2, 2)), (component, (1, 1))]);
+        assert_eq!(
+            versions.find_mismatch(&remote),
+            mismatch(component, None, Some(1))
+        );
+    }
+
+    /// Without a downgrade function, we cannot write values that an older peer can read
+    #[test]
+    fn test_version_mismatch_upgrade_only() {
+        let mut app = App::new();
+        app.add_message::<M1>()
+            .add_version(VersionFns::new(2).with_upgrade(1, |_, _| Ok(M1)));
+        let versions = app.world().resource::<ProtocolVersions>();
+        let m1 = VersionedType::Message(message_net_id::<M1>(&app));
+
+        let remote = HashMap::from_iter([(m1, (1, 1))]);
+        assert_eq!(
+            versions.find_mismatch(&remote),
+            mismatch(m1, Some(2), Some(1))
+        );
+        // a more recent remote peer without a downgrade function cannot write our version
+        let remote = HashMap::from_iter([(m1, (3, 3))]);
+        assert_eq!(
+            versions.find_mismatch(&remote),
+            mismatch(m1, Some(2), Some(3))
+        );
+    }
+}
@@ -5,12 +5,13 @@ use crate::{Message, MessageNetId};
 use bevy::ecs::change_detection::MutUntyped;
 use bevy::ecs::world::{DeferredWorld, FilteredEntityMut};
 use bevy::prelude::{Component, Entity, ParallelCommands, Query, Res, With, World};
+use lightyear_core::prelude::{LocalTimeline, NetworkTimeline};
 use lightyear_core::tick::Tick;
 use lightyear_serde::ToBytes;
 use lightyear_serde::entity_map::ReceiveEntityMap;
 use lightyear_serde::reader::Reader;
 use lightyear_transport::channel::ChannelKind;
-use lightyear_transport::channel::receivers::ChannelReceive;
+use lightyear_transport::limits::{LimitError, ReceiveLimits};
 use lightyear_transport::prelude::Transport;
 use tracing::{error, trace};
 
@@ -50,6 +51,12 @@ pub struct ReceivedMessage<M: Message> {
     pub channel_kind: ChannelKind,
     /// MessageId of the message
     pub message_id: Option<MessageId>,
+    /// For messages received on a [`ChannelMode::TickBuffered`](lightyear_transport::prelude::ChannelMode::TickBuffered)
+    /// channel, number of ticks between `remote_tick` and the local tick at which the message was delivered.
+    ///
+    /// This is 0 if the message was delivered on time. The sender's tick is compared directly with
+    /// the local tick, so this is only meaningful for messages sent by a client to the server.
+    pub lateness: Option<u16>,
 }
 
 impl<M: Message> Default for MessageReceiver<M> {
@@ -98,6 +105,7 @@ pub(crate) type ReceiveMessageFn = unsafe fn(
     channel_kind: ChannelKind,
     remote_tick: Tick,
     message_id: Option<MessageId>,
+    lateness: Option<u16>,
     serialize_metadata: &ErasedSerializeFns,
     entity_map: &mut ReceiveEntityMap,
 ) -> Result<(), MessageError>;
@@ -115,6 +123,7 @@ impl<M: Message> MessageReceiver<M> {
         channel_kind: ChannelKind,
         remote_tick: Tick,
         message_id: Option<MessageId>,
+        lateness: Option<u16>,
         serialize_metadata: &ErasedSerializeFns,
         entity_map: &mut ReceiveEntityMap,
     ) -> Result<(), MessageError> {
@@ -127,6 +136,7 @@ impl<M: Message> MessageReceiver<M> {
             remote_tick,
             channel_kind,
             message_id,
+            lateness,
         };
         trace!(
             "Received message {:?} on channel {channel_kind:?}",
@@ -150,7 +160,14 @@ impl MessagePlugin {
     /// - Otherwise, buffer the message in the `MessageReceiver<M>` component.
     pub fn recv(
         // NOTE: we only need the mut bound on MessageManager because EntityMapper requires mut
-        mut transport_query: Query<(Entity, &mut MessageManager, &mut Transport, &Connected)>,
+        mut transport_query: Query<(
+            Entity,
+            &mut MessageManager,
+            &mut Transport,
+            &Connected,
+            &LocalTimeline,
+            Option<&ReceiveLimits>,
+        )>,
         // List of ChannelReceivers<M> present on that entity
         receiver_query: Query<FilteredEntityMut>,
         registry: Res<MessageRegistry>,
@@ -159,7 +176,8 @@ impl MessagePlugin {
         // We use Arc to make the query Clone, since we know that we will only access MessageReceiver<M> components
         // on potentially different entities in parallel (though the current loop isn't parallel)
         let receiver_query = Arc::new(receiver_query);
-        transport_query.par_iter_mut().for_each(|(entity, mut message_manager, mut transport, connected)| {
+        transport_query.par_iter_mut().for_each(|(entity, mut message_manager, mut transport, connected, timeline, limits)| {
+            let limits = limits.copied().unwrap_or_default();
             // SAFETY: we know that this won't lead to violating the aliasing rule
             let mut receiver_query = unsafe { receiver_query.reborrow_unsafe() };
             // enable split borrows
@@ -168,13 +186,21 @@ impl MessagePlugin {
             transport.receivers.values_mut().try_for_each(|receiver_metadata| {
                 let channel_kind = receiver_metadata.channel_kind;
                 let remote_peer_id = connected.remote_peer_id;
-                while let Some((tick, bytes, message_id)) = receiver_metadata.receiver.read_message() {
+                while let Some((tick, bytes, message_id)) = receiver_metadata.read_message() {
                     trace!("Received message {message_id:?} from peer {:?} on channel {channel_kind:?}", remote_peer_id);
-                    let mut reader = Reader::from(bytes);
+                    let lateness = receiver_metadata
+                        .is_tick_buffered()
+                        .then(|| (timeline.tick() - tick).max(0) as u16);
+                    let mut reader = limits.apply_to(Reader::from(bytes));
                     // we receive the message NetId, and then deserialize the message
                     let message_net_id = MessageNetId::from_bytes(&mut reader)?;
                     let message_kind = registry.kind_map.kind(message_net_id).ok_or(MessageError::UnrecognizedMessageId(message_net_id))?;
                     let serialize_fns = registry.serialize_fns_map.get(message_kind).ok_or(MessageError::UnrecognizedMessage(*message_kind))?;
+                    // messages written with an older version are converted to the current version
+                    let mut upgraded = serialize_fns
+                        .read_version(&mut reader)?
+                        .map(|upgraded| limits.apply_to(upgraded));
+                    let reader = upgraded.as_mut().unwrap_or(&mut reader);
 
                     if let Some(recv_metadata) = registry.receive_metadata.get(message_kind) {
                         let component_id = recv_metadata.component_id;
@@ -186,10 +212,11 @@ impl MessagePlugin {
                         unsafe {
                             (recv_metadata.receive_message_fn)(
                                 receiver,
-                                &mut reader,
+                                reader,
                                 channel_kind,
                                 tick,
                                 message_id,
+                                lateness,
                                 serialize_fns,
                                 &mut message_manager.entity_mapper.remote_to_local
                             )?;
@@ -200,7 +227,7 @@ impl MessagePlugin {
                         unsafe {
                             trigger_fn(
                                 &commands,
-                                &mut reader,
+                                reader,
                                 channel_kind,
                                 tick,
                                 message_id,
@@ -214,7 +241,18 @@ impl MessagePlugin {
                     };
                 }
                 Ok::<_, MessageError>(())
-            }).inspect_err(|e| error!("Error receiving messages: {e:?}")).ok();
+            }).inspect_err(|e| {
+                if let Some(limit_error) = match e {
+                    MessageError::Serialization(e) => LimitError::from_serialization(e),
+                    _ => None,
+                } {
+                    commands.command_scope(|mut commands| {
+                        limits.on_exceeded(&mut commands, entity, limit_error);
+                    });
+                    return;
+                }
+                error!("Error receiving messages: {e:?}")
+            }).ok();
         })
     }
 
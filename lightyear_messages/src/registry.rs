@@ -1,3 +1,4 @@
+use crate::protocol::{AppProtocolExt, RemoteVersions, VersionedType};
 use crate::receive::{ClearMessageFn, MessageReceiver, ReceiveMessageFn};
 use crate::send::{MessageSender, SendMessageFn};
 use crate::{Message, MessageNetId};
@@ -7,6 +8,7 @@ use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
 use core::any::TypeId;
 use core::cell::UnsafeCell;
+use core::hash::{Hash, Hasher};
 use lightyear_connection::direction::NetworkDirection;
 use lightyear_core::network::NetId;
 use lightyear_serde::entity_map::{ReceiveEntityMap, RemoteEntityMap, SendEntityMap};
@@ -15,6 +17,7 @@ use lightyear_serde::registry::{
     ContextDeserializeFn, ContextDeserializeFns, ContextSerializeFn, ContextSerializeFns,
     DeserializeFn, ErasedSerializeFns, SerializeFn, SerializeFns,
 };
+use lightyear_serde::version::VersionFns;
 use lightyear_serde::writer::Writer;
 use lightyear_serde::{SerializationError, ToBytes};
 use lightyear_transport::channel::ChannelKind;
@@ -149,6 +152,9 @@ pub struct MessageRegistry {
     pub(crate) receive_metadata: HashMap<MessageKind, ReceiveMessageMetadata>,
     pub(crate) receive_trigger: HashMap<MessageKind, ReceiveTriggerFn>,
     pub serialize_fns_map: HashMap<MessageKind, ErasedSerializeFns>,
+    pub(crate) direction_map: HashMap<MessageKind, NetworkDirection>,
+    /// Names of the messages and triggers that are used in the [`ProtocolHash`](crate::protocol::ProtocolHash)
+    pub(crate) protocol_names: HashMap<MessageKind, &'static str>,
     pub kind_map: TypeMapper<MessageKind>,
 }
 
@@ -179,6 +185,33 @@ fn mapped_context_deserialize<M: MapEntities>(
 }
 
 impl MessageRegistry {
+    /// Return the name of the message type associated with the [`MessageNetId`]
+    pub fn name(&self, net_id: MessageNetId) -> Option<&'static str> {
+        let kind = self.kind_map.kind(net_id)?;
+        self.serialize_fns_map.get(kind).map(|fns| fns.type_name)
+    }
+
+    /// Feed the parts of the registry that need to be identical on both peers (network ids, protocol
+    /// names and directions of the messages and triggers) to the `hasher`.
+    ///
+    /// The result does not depend on the platform, so it can be compared with a remote peer.
+    pub fn hash_protocol<H: Hasher>(&self, hasher: &mut H) {
+        self.kind_map.iter().for_each(|(net_id, kind)| {
+            hasher.write_u16(net_id);
+            self.protocol_names
+                .get(kind)
+                .copied()
+                .unwrap_or("")
+                .hash(hasher);
+            hasher.write_u8(self.send_trigger_metadata.contains_key(kind) as u8);
+            hasher.write_u8(
+                self.direction_map
+                    .get(kind)
+                    .map_or(0, NetworkDirection::protocol_id),
+            );
+        });
+    }
+
     pub(crate) fn register_message<M: Message, I: 'static>(
         &mut self,
         serialize: ContextSerializeFns<SendEntityMap, M, I>,
@@ -224,6 +257,17 @@ impl MessageRegistry {
         Ok(erased_fns.map_entities.is_some())
     }
 
+    /// Returns true if the wire format of the message is versioned, in which case the
+    /// message might have to be serialized differently for each remote peer.
+    pub(crate) fn is_versioned<M: 'static>(&self) -> Result<bool> {
+        let kind = MessageKind::of::<M>();
+        let erased_fns = self
+            .serialize_fns_map
+            .get(&kind)
+            .ok_or(MessageError::MissingSerializationFns)?;
+        Ok(erased_fns.version.is_some())
+    }
+
     pub(crate) fn add_map_entities<
         M: Clone + MapEntities + 'static,
         I: Clone + MapEntities + 'static,
@@ -247,6 +291,7 @@ impl MessageRegistry {
         message: &M,
         writer: &mut Writer,
         entity_map: &mut SendEntityMap,
+        remote_versions: &RemoteVersions,
     ) -> Result<(), MessageError> {
         let kind = MessageKind::of::<M>();
         let erased_fns = self
@@ -255,9 +300,13 @@ impl MessageRegistry {
             .ok_or(MessageError::MissingSerializationFns)?;
         let net_id = self.kind_map.net_id(&kind).unwrap();
         net_id.to_bytes(writer)?;
-        unsafe {
-            erased_fns.serialize::<SendEntityMap, M, M>(message, writer, entity_map)?;
-        }
+        erased_fns.serialize_versioned(
+            writer,
+            remote_versions.get(VersionedType::Message(*net_id), erased_fns),
+            |writer| unsafe {
+                erased_fns.serialize::<SendEntityMap, M, M>(message, writer, entity_map)
+            },
+        )?;
         Ok(())
     }
 
@@ -309,7 +358,45 @@ impl<'a, M: Message> MessageRegistration<'a, M> {
         self
     }
 
+    /// Version the wire format of the message, so that it can still be exchanged with peers that
+    /// use an older version of the message.
+    ///
+    /// See [`VersionFns`] for more details.
+    pub fn add_version(&mut self, version_fns: VersionFns<M>) -> &mut Self {
+        let mut registry = self.app.world_mut().resource_mut::<MessageRegistry>();
+        let kind = MessageKind::of::<M>();
+        let net_id = *registry
+            .kind_map
+            .net_id(&kind)
+            .expect("the message is not part of the protocol");
+        let erased_fns = registry.serialize_fns_map.get_mut(&kind).unwrap();
+        // SAFETY: the ErasedSerializeFns was created for the type M
+        unsafe { erased_fns.set_version(version_fns) };
+        let erased_fns = erased_fns.clone();
+        self.app
+            .add_protocol_version(VersionedType::Message(net_id), &erased_fns);
+        self
+    }
+
+    /// Name of the message that is used in the [`ProtocolHash`](crate::protocol::ProtocolHash).
+    ///
+    /// The name must stay the same across builds; it lets the peers detect that the messages were
+    /// registered in a different order.
+    pub fn with_protocol_name(&mut self, name: &'static str) -> &mut Self {
+        self.app
+            .world_mut()
+            .resource_mut::<MessageRegistry>()
+            .protocol_names
+            .insert(MessageKind::of::<M>(), name);
+        self
+    }
+
     pub fn add_direction(&mut self, direction: NetworkDirection) -> &mut Self {
+        self.app
+            .world_mut()
+            .resource_mut::<MessageRegistry>()
+            .direction_map
+            .insert(MessageKind::of::<M>(), direction);
         #[cfg(feature = "client")]
         self.add_client_direction(direction);
         #[cfg(feature = "server")]
@@ -332,8 +419,10 @@ pub trait AppMessageExt {
         serialize_fns: SerializeFns<M>,
     ) -> MessageRegistration<'_, M>;
 
-    #[doc(hidden)]
     /// Register a regular message type `M` that uses `ToBytes` for serialization.
+    ///
+    /// This is usually more compact than the default bincode serialization, especially
+    /// when `ToBytes` is derived with field-level encoding attributes.
     fn add_message_to_bytes<M: Message + ToBytes>(&mut self) -> MessageRegistration<'_, M>;
 }
 
@@ -437,7 +526,12 @@ mod tests {
         let message = Message1(1.0);
         let mut writer = Writer::default();
         registry
-            .serialize(&message, &mut writer, &mut SendEntityMap::default())
+            .serialize(
+                &message,
+                &mut writer,
+                &mut SendEntityMap::default(),
+                &RemoteVersions::default(),
+            )
             .unwrap();
         let data = writer.to_bytes();
 
@@ -459,7 +553,12 @@ mod tests {
         let message = Message2(1.0);
         let mut writer = Writer::default();
         registry
-            .serialize(&message, &mut writer, &mut SendEntityMap::default())
+            .serialize(
+                &message,
+                &mut writer,
+                &mut SendEntityMap::default(),
+                &RemoteVersions::default(),
+            )
             .unwrap();
         let data = writer.to_bytes();
 
@@ -495,7 +594,12 @@ mod tests {
         let mut entity_map = SendEntityMap::default();
         entity_map.set_mapped(Entity::from_raw(1), Entity::from_raw(2));
         registry
-            .serialize(&message, &mut writer, &mut entity_map)
+            .serialize(
+                &message,
+                &mut writer,
+                &mut entity_map,
+                &RemoteVersions::default(),
+            )
             .unwrap();
         let data = writer.to_bytes();
 
@@ -1,4 +1,5 @@
 use crate::plugin::MessagePlugin;
+use crate::protocol::VersionedType;
 use crate::registry::{MessageError, MessageKind, MessageRegistry};
 use crate::{Message, MessageManager, MessageNetId};
 use alloc::sync::Arc;
@@ -8,24 +9,70 @@ use bevy::ecs::change_detection::MutUntyped;
 use bevy::ecs::component::HookContext;
 use bevy::ecs::world::{DeferredWorld, FilteredEntityMut};
 use bevy::prelude::{Component, Entity, Query, Reflect, Res, With, World};
+use core::time::Duration;
 use lightyear_connection::client::Connected;
 use lightyear_serde::ToBytes;
 use lightyear_serde::entity_map::SendEntityMap;
 use lightyear_serde::registry::ErasedSerializeFns;
 use lightyear_serde::writer::Writer;
 use lightyear_transport::channel::{Channel, ChannelKind};
-use lightyear_transport::prelude::Transport;
+use lightyear_transport::prelude::{MessageHandle, MessageHandleAllocator, Transport};
 use tracing::{error, trace};
 
 pub type Priority = f32;
 
+/// Options of a message sent with [`MessageSender::send_with_priority`].
+///
+/// A [`Priority`] can be used directly when the other options keep their default value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendOptions {
+    pub priority: Priority,
+    /// The message is dropped if it could not be sent (or, for reliable channels, acked) within `ttl`.
+    ///
+    /// This overrides the `ttl` of the [`ChannelSettings`](lightyear_transport::prelude::ChannelSettings).
+    pub ttl: Option<Duration>,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            priority: 1.0,
+            ttl: None,
+        }
+    }
+}
+
+impl SendOptions {
+    pub fn new(priority: Priority) -> Self {
+        Self {
+            priority,
+            ..Default::default()
+        }
+    }
+
+    /// Override the time-to-live of the channel for this message
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+impl From<Priority> for SendOptions {
+    fn from(priority: Priority) -> Self {
+        Self::new(priority)
+    }
+}
+
 #[derive(Component, Reflect)]
 #[component(on_add = MessageSender::<M>::on_add_hook)]
 #[require(MessageManager)]
 pub struct MessageSender<M: Message> {
-    send: Vec<(M, ChannelKind, Priority)>,
+    send: Vec<(M, ChannelKind, Priority, Option<Duration>, MessageHandle)>,
     #[reflect(ignore)]
     writer: Writer,
+    /// Shared with the [`Transport`] of the entity, so that handles are unique across all senders
+    #[reflect(ignore)]
+    handles: MessageHandleAllocator,
 }
 
 // enable sending with target?
@@ -40,6 +87,7 @@ impl<M: Message> Default for MessageSender<M> {
         Self {
             send: Vec::new(),
             writer: Writer::default(),
+            handles: MessageHandleAllocator::default(),
         }
     }
 }
@@ -51,22 +99,39 @@ pub(crate) type SendMessageFn = unsafe fn(
     transport: &Transport,
     serialize_metadata: &ErasedSerializeFns,
     entity_map: &mut SendEntityMap,
+    remote_version: Option<u16>,
 ) -> Result<(), MessageError>;
 
 impl<M: Message> MessageSender<M> {
-    /// Buffers a message to be sent over the channel
-    pub fn send_with_priority<C: Channel>(&mut self, message: M, priority: Priority) {
+    /// Buffers a message to be sent over the channel.
+    ///
+    /// The returned [`MessageHandle`] identifies the message in the
+    /// [`MessageAcked`](lightyear_transport::prelude::MessageAcked) and
+    /// [`MessageLost`](lightyear_transport::prelude::MessageLost) events triggered on this entity
+    /// when the channel tracks acks.
+    ///
+    /// `options` is either a [`Priority`] or [`SendOptions`], for example to override the
+    /// time-to-live of the channel.
+    pub fn send_with_priority<C: Channel>(
+        &mut self,
+        message: M,
+        options: impl Into<SendOptions>,
+    ) -> MessageHandle {
         // // TODO: how to include the sender in the metric?
         // metrics::counter!("message::send", 1,
         //     channel => core::any::type_name::<C>(),
         //     message => core::any::type_name::<M>()
         // );
-        self.send.push((message, ChannelKind::of::<C>(), priority));
+        let SendOptions { priority, ttl } = options.into();
+        let handle = self.handles.next();
+        self.send
+            .push((message, ChannelKind::of::<C>(), priority, ttl, handle));
+        handle
     }
 
     /// Buffers a message to be sent over the channel
-    pub fn send<C: Channel>(&mut self, message: M) {
-        self.send_with_priority::<C>(message, 1.0);
+    pub fn send<C: Channel>(&mut self, message: M) -> MessageHandle {
+        self.send_with_priority::<C>(message, 1.0)
     }
 
     /// Take all messages from the MessageSender<M>, serialize them, and buffer them
@@ -79,24 +144,34 @@ impl<M: Message> MessageSender<M> {
         transport: &Transport,
         serialize_metadata: &ErasedSerializeFns,
         entity_map: &mut SendEntityMap,
+        remote_version: Option<u16>,
     ) -> Result<(), MessageError> {
         // SAFETY:  the `message_sender` must be of type `MessageSender<M>`
         let mut sender = unsafe { message_sender.with_type::<Self>() };
         // enable split borrows
         let sender = &mut *sender;
-        sender.send.drain(..).try_for_each(|(message, channel_kind, priority)| {
+        sender.send.drain(..).try_for_each(|(message, channel_kind, priority, ttl, handle)| {
             // we write the message NetId, and then serialize the message
             net_id.to_bytes(&mut sender.writer)?;
-            // SAFETY: the message has been checked to be of type `M`
-            unsafe { serialize_metadata.serialize::<SendEntityMap, M, M>(&message, &mut sender.writer, entity_map)? };
+            serialize_metadata.serialize_versioned(&mut sender.writer, remote_version, |writer| {
+                // SAFETY: the message has been checked to be of type `M`
+                unsafe { serialize_metadata.serialize::<SendEntityMap, M, M>(&message, writer, entity_map) }
+            })?;
             let bytes = sender.writer.split();
             trace!("Sending message of type {:?} with net_id {net_id:?}/kind {:?} on channel {channel_kind:?}", core::any::type_name::<M>(), MessageKind::of::<M>());
-            transport.send_erased(channel_kind, bytes, priority)?;
+            transport.send_erased_tracked(channel_kind, bytes, priority, ttl, handle)?;
             Ok(())
         })
     }
 
     pub fn on_add_hook(mut world: DeferredWorld, context: HookContext) {
+        // the Transport is a required component, so it is always present
+        if let Some(handles) = world
+            .get::<Transport>(context.entity)
+            .map(Transport::message_handles)
+        {
+            world.get_mut::<Self>(context.entity).unwrap().handles = handles;
+        }
         world.commands().queue(move |world: &mut World| {
             let mut entity_mut = world.entity_mut(context.entity);
             let mut message_manager = entity_mut.get_mut::<MessageManager>().unwrap();
@@ -170,6 +245,9 @@ impl MessagePlugin {
                                 transport,
                                 serialize_fns,
                                 &mut message_manager.entity_mapper.local_to_remote,
+                                message_manager
+                                    .remote_versions
+                                    .get(VersionedType::Message(*message_id), serialize_fns),
                             )?;
                         }
                         Ok::<_, MessageError>(())
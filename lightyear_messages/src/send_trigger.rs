@@ -57,7 +57,7 @@ impl<M: Event> TriggerSender<M> {
             unsafe { serialize_metadata.serialize::<SendEntityMap, TriggerMessage<M>, M>(&message, &mut sender.writer, entity_map)? };
             let bytes = sender.writer.split();
             trace!("Sending message of type {:?} with net_id {net_id:?} on channel {channel_kind:?}", core::any::type_name::<TriggerMessage<M>>());
-            transport.send_erased(channel_kind, bytes, priority)?;
+            transport.send_erased(channel_kind, bytes, priority, None)?;
             Ok(())
         })
     }
@@ -1,6 +1,7 @@
 use crate::Message;
 use crate::multi::MultiMessageSender;
 use crate::prelude::{MessageReceiver, MessageSender};
+use crate::protocol::RemoteVersions;
 use crate::registry::MessageRegistration;
 use crate::send::Priority;
 use crate::send_trigger::TriggerSender;
@@ -39,14 +40,17 @@ impl ServerMultiMessageSender<'_, '_> {
         target: &NetworkTarget,
         priority: Priority,
     ) -> Result {
-        // if the message is not map-entities, we can serialize it once and clone the bytes
-        if !self.sender.registry.is_map_entities::<M>()? {
+        // if the message is not map-entities or versioned, we can serialize it once and clone the bytes
+        if !self.sender.registry.is_map_entities::<M>()?
+            && !self.sender.registry.is_versioned::<M>()?
+        {
             // TODO: serialize once for all senders. Figure out how to get a shared writer. Maybe on Server? Or as a global resource?
             //   or as Local?
             self.sender.registry.serialize::<M>(
                 message,
                 &mut self.sender.writer,
                 &mut SendEntityMap::default(),
+                &RemoteVersions::default(),
             )?;
             let bytes = self.sender.writer.split();
             target.apply_targets(
@@ -66,13 +70,14 @@ impl ServerMultiMessageSender<'_, '_> {
                 server.collection().iter().copied(),
                 &self.metadata.mapping,
                 &mut |sender| {
-                    if let Ok((_, transport)) = self.sender.query.get(sender) {
+                    if let Ok((manager, transport)) = self.sender.query.get(sender) {
                         self.sender
                             .registry
                             .serialize::<M>(
                                 message,
                                 &mut self.sender.writer,
                                 &mut SendEntityMap::default(),
+                                &manager.remote_versions,
                             )
                             .unwrap();
                         let bytes = self.sender.writer.split();
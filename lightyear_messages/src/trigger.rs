@@ -35,6 +35,10 @@ impl<M: Message + MapEntities> MapEntities for TriggerMessage<M> {
     }
 }
 
+/// Registration of a trigger message.
+///
+/// Triggers cannot be versioned like messages and components: changing the wire format of a
+/// trigger makes the peers incompatible with the previous builds.
 pub struct TriggerRegistration<'a, M> {
     pub app: &'a mut App,
     pub(crate) _marker: core::marker::PhantomData<M>,
@@ -64,12 +68,30 @@ impl<'a, M: Event> TriggerRegistration<'a, M> {
     }
 
     pub fn add_direction(&mut self, direction: NetworkDirection) -> &mut Self {
+        self.app
+            .world_mut()
+            .resource_mut::<MessageRegistry>()
+            .direction_map
+            .insert(MessageKind::of::<M>(), direction);
         #[cfg(feature = "client")]
         self.add_client_direction(direction);
         #[cfg(feature = "server")]
         self.add_server_direction(direction);
         self
     }
+
+    /// Name of the trigger that is used in the [`ProtocolHash`](crate::protocol::ProtocolHash).
+    ///
+    /// The name must stay the same across builds; it lets the peers detect that the triggers were
+    /// registered in a different order.
+    pub fn with_protocol_name(&mut self, name: &'static str) -> &mut Self {
+        self.app
+            .world_mut()
+            .resource_mut::<MessageRegistry>()
+            .protocol_names
+            .insert(MessageKind::of::<TriggerMessage<M>>(), name);
+        self
+    }
 }
 
 pub trait AppTriggerExt {
@@ -86,8 +108,7 @@ pub trait AppTriggerExt {
         serialize_fns: SerializeFns<M>,
     ) -> TriggerRegistration<'_, M>;
 
-    #[doc(hidden)]
-    /// Register a trigger type `M`.
+    /// Register a trigger type `M` that uses `ToBytes` for serialization.
     fn add_trigger_to_bytes<M: Event + ToBytes>(&mut self) -> TriggerRegistration<'_, M> {
         self.add_trigger_custom_serde(SerializeFns::<M>::with_to_bytes())
     }
@@ -8,7 +8,8 @@ use super::{
     bytes::Bytes,
     error::{Error, Result},
     packet::{
-        DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket, RequestPacket, ResponsePacket,
+        DisconnectPacket, KeepAlivePacket, Packet, PathChallengePacket, PayloadPacket,
+        RequestPacket, ResponsePacket,
     },
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken},
@@ -290,7 +291,8 @@ impl<Ctx> Client<Ctx> {
         | 1 << Packet::CHALLENGE
         | 1 << Packet::KEEP_ALIVE
         | 1 << Packet::PAYLOAD
-        | 1 << Packet::DISCONNECT;
+        | 1 << Packet::DISCONNECT
+        | 1 << Packet::PATH_CHALLENGE;
     fn set_state(&mut self, state: ClientState) {
         debug!("client state changing from {:?} to {:?}", self.state, state);
         if let Some(ref mut cb) = self.cfg.on_state_change {
@@ -425,6 +427,12 @@ impl<Ctx> Client<Ctx> {
                 // TODO: control the size of the packet queue?
                 Some(pkt.buf)
             }
+            (Packet::PathChallenge(pkt), ClientState::Connected) => {
+                debug!("client received path challenge packet from server");
+                // echo the challenge so that the server can validate our new address
+                self.send_netcode_packet(PathChallengePacket::create(pkt.nonce))?;
+                None
+            }
             (Packet::Disconnect(_), ClientState::Connected) => {
                 debug!("client received disconnect packet from server");
                 self.should_disconnect = true;
@@ -517,9 +525,9 @@ impl<Ctx> Client<Ctx> {
         // Processing them might mean that we're re-adding them to the receiver so that
         // the Transport can read them later
         for _ in 0..receiver.len() {
-            if let Some(recv_packet) = receiver.pop() {
+            if let Some((recv_packet, received_at)) = receiver.pop_with_instant() {
                 if let Some(payload) = self.recv_packet(recv_packet, now)? {
-                    receiver.push_raw(payload);
+                    receiver.push_raw_at(payload, received_at);
                 }
             }
         }
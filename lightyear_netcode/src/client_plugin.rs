@@ -219,7 +219,13 @@ impl Plugin for NetcodeClientPlugin {
         );
         app.configure_sets(
             PostUpdate,
-            (TransportSet::Send, ConnectionSet::Send, LinkSet::Send).chain(),
+            (
+                TransportSet::Send,
+                ConnectionSet::Send,
+                LinkSet::ApplyConditioner,
+                LinkSet::Send,
+            )
+                .chain(),
         );
 
         app.add_systems(PreUpdate, Self::receive.in_set(ConnectionSet::Receive));
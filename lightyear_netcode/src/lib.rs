@@ -139,7 +139,7 @@ pub use client_plugin::NetcodeClient;
 pub use crypto::{Key, generate_key, try_generate_key};
 pub use error::{Error, Result};
 #[cfg(feature = "server")]
-pub use server::{Callback, Server, ServerConfig};
+pub use server::{Callback, MigrateCallback, Server, ServerConfig};
 #[cfg(feature = "server")]
 pub use server_plugin::NetcodeServer;
 pub use token::{ConnectToken, ConnectTokenBuilder, InvalidTokenError};
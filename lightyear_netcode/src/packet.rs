@@ -360,6 +360,31 @@ impl Bytes for KeepAlivePacket {
     }
 }
 
+/// Sent by the server to the new address of a connected client before migrating the connection to it.
+/// The client echoes the packet back unchanged, which proves that it can receive packets at that address.
+pub struct PathChallengePacket {
+    pub nonce: u64,
+}
+
+impl PathChallengePacket {
+    pub fn create(nonce: u64) -> Packet {
+        Packet::PathChallenge(PathChallengePacket { nonce })
+    }
+}
+
+impl Bytes for PathChallengePacket {
+    type Error = io::Error;
+    fn write_to(&self, writer: &mut impl WriteInteger) -> Result<(), Self::Error> {
+        writer.write_u64(self.nonce)?;
+        Ok(())
+    }
+
+    fn read_from(reader: &mut impl ReadInteger) -> Result<Self, io::Error> {
+        let nonce = reader.read_u64()?;
+        Ok(Self { nonce })
+    }
+}
+
 pub struct PayloadPacket {
     pub buf: SendPayload,
 }
@@ -398,6 +423,7 @@ pub enum Packet {
     KeepAlive(KeepAlivePacket),
     Payload(PayloadPacket),
     Disconnect(DisconnectPacket),
+    PathChallenge(PathChallengePacket),
 }
 
 impl core::fmt::Display for Packet {
@@ -410,6 +436,7 @@ impl core::fmt::Display for Packet {
             Packet::Disconnect(_) => write!(f, "disconnect packet"),
             Packet::Denied(_) => write!(f, "denied packet"),
             Packet::Challenge(_) => write!(f, "challenge packet"),
+            Packet::PathChallenge(_) => write!(f, "path challenge packet"),
         }
     }
 }
@@ -424,6 +451,7 @@ impl Packet {
     pub const KEEP_ALIVE: PacketKind = 4;
     pub const PAYLOAD: PacketKind = 5;
     pub const DISCONNECT: PacketKind = 6;
+    pub const PATH_CHALLENGE: PacketKind = 7;
     fn kind(&self) -> PacketKind {
         match self {
             Packet::Request(_) => Packet::REQUEST,
@@ -433,6 +461,7 @@ impl Packet {
             Packet::KeepAlive(_) => Packet::KEEP_ALIVE,
             Packet::Payload(_) => Packet::PAYLOAD,
             Packet::Disconnect(_) => Packet::DISCONNECT,
+            Packet::PathChallenge(_) => Packet::PATH_CHALLENGE,
         }
     }
     fn set_prefix(&self, sequence: u64) -> u8 {
@@ -478,6 +507,7 @@ impl Packet {
             Packet::Response(pkt) => pkt.write_to(&mut cursor)?,
             Packet::KeepAlive(pkt) => pkt.write_to(&mut cursor)?,
             Packet::Disconnect(pkt) => pkt.write_to(&mut cursor)?,
+            Packet::PathChallenge(pkt) => pkt.write_to(&mut cursor)?,
             Packet::Payload(PayloadPacket { buf }) => cursor.write_all(buf)?,
             _ => unreachable!(), // Packet::Request variant is handled above
         }
@@ -513,8 +543,9 @@ impl Packet {
         let mut cursor = io::Cursor::new(buf);
         let prefix_byte = cursor.read_u8()?;
         let (sequence_len, pkt_kind) = Packet::get_prefix(prefix_byte);
-        if allowed_packets & (1 << pkt_kind) == 0 {
+        if u16::from(allowed_packets) & (1 << pkt_kind) == 0 {
             debug!("ignoring packet of type {}, not allowed", pkt_kind);
+            return Err(Error::InvalidType(pkt_kind).into());
         }
         if prefix_byte == Packet::REQUEST {
             // connection request packet: first byte should be 0x00
@@ -564,6 +595,9 @@ impl Packet {
             Packet::RESPONSE => Packet::Response(ResponsePacket::read_from(&mut cursor)?),
             Packet::KEEP_ALIVE => Packet::KeepAlive(KeepAlivePacket::read_from(&mut cursor)?),
             Packet::DISCONNECT => Packet::Disconnect(DisconnectPacket::read_from(&mut cursor)?),
+            Packet::PATH_CHALLENGE => {
+                Packet::PathChallenge(PathChallengePacket::read_from(&mut cursor)?)
+            }
             Packet::PAYLOAD => {
                 let mut buf = cursor.into_inner();
                 buf.truncate(buf.len() - MAC_BYTES);
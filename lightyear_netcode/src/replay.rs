@@ -23,6 +23,10 @@ impl ReplayProtection {
         self.received_packet[index] = sequence;
     }
 
+    pub fn most_recent_sequence(&self) -> u64 {
+        self.most_recent_sequence
+    }
+
     pub fn is_already_received(&self, sequence: u64) -> bool {
         if sequence + self.received_packet.len() as u64 <= self.most_recent_sequence {
             return true;
@@ -13,8 +13,8 @@ use super::{
     crypto::{self, Key},
     error::{Error, Result},
     packet::{
-        ChallengePacket, DeniedPacket, DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket,
-        RequestPacket, ResponsePacket,
+        ChallengePacket, DeniedPacket, DisconnectPacket, KeepAlivePacket, Packet,
+        PathChallengePacket, PayloadPacket, RequestPacket, ResponsePacket,
     },
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken, ConnectTokenBuilder, ConnectTokenPrivate},
@@ -94,6 +94,16 @@ struct Connection {
     send_key: Key,
     receive_key: Key,
     sequence: u64,
+    path_challenge: Option<PathChallenge>,
+}
+
+/// A validation of the new address of a connected client, which is in progress
+#[derive(Debug, Clone, Copy)]
+struct PathChallenge {
+    /// Entity of the link on which packets from the new address were received
+    link_entity: Entity,
+    nonce: u64,
+    send_time: f64,
 }
 
 impl Connection {
@@ -164,6 +174,7 @@ impl ConnectionCache {
             send_key,
             receive_key,
             sequence: 1 << 62,
+            path_challenge: None,
         };
         self.clients.insert(client_id, conn);
         self.replay_protection
@@ -221,12 +232,18 @@ impl ConnectionCache {
 
 pub type Callback<Ctx> = Box<dyn FnMut(ClientId, Entity, &mut Ctx) + Send + Sync + 'static>;
 
+/// Callback called with the client id, the entity of the connection and the entity of the link
+/// on which the packets from the new address were received.
+pub type MigrateCallback<Ctx> =
+    Box<dyn FnMut(ClientId, Entity, Entity, &mut Ctx) + Send + Sync + 'static>;
+
 /// Configuration for a server.
 ///
 /// * `num_disconnect_packets` - The number of redundant disconnect packets that will be sent to a client when the server is disconnecting it.
 /// * `keep_alive_send_rate` - The rate at which keep-alive packets will be sent to clients.
 /// * `on_connect` - A callback that will be called when a client is connected to the server.
 /// * `on_disconnect` - A callback that will be called when a client is disconnected from the server.
+/// * `on_migrate` - A callback that will be called when a connected client starts sending packets from a new address.
 ///
 /// # Example
 /// ```
@@ -251,9 +268,11 @@ pub struct ServerConfig<Ctx> {
     client_timeout_secs: i32,
     connection_request_handler: Arc<dyn ConnectionRequestHandler>,
     server_addr: SocketAddr,
+    connection_migration: bool,
     pub(crate) context: Ctx,
     on_connect: Option<Callback<Ctx>>,
     on_disconnect: Option<Callback<Ctx>>,
+    on_migrate: Option<MigrateCallback<Ctx>>,
 }
 
 impl Default for ServerConfig<()> {
@@ -265,9 +284,11 @@ impl Default for ServerConfig<()> {
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            connection_migration: false,
             context: (),
             on_connect: None,
             on_disconnect: None,
+            on_migrate: None,
         }
     }
 }
@@ -286,9 +307,11 @@ impl<Ctx> ServerConfig<Ctx> {
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            connection_migration: false,
             context: ctx,
             on_connect: None,
             on_disconnect: None,
+            on_migrate: None,
         }
     }
     /// Set the number of redundant disconnect packets that will be sent to a client when the server is disconnecting it. <br>
@@ -321,6 +344,21 @@ impl<Ctx> ServerConfig<Ctx> {
         self.server_addr = server_addr;
         self
     }
+    /// Allow connected clients to keep their connection when their address changes
+    /// (for example after a NAT rebinding, or when switching from Wi-Fi to cellular). <br>
+    /// A packet received from an unknown address is matched with a connected client if it is
+    /// authenticated with that client's key, and if it is the most recent packet received from that client.
+    /// The server then sends a challenge to the new address, and only migrates the connection once the client
+    /// has echoed it back from that address, so that a packet captured and re-sent from another address
+    /// cannot redirect the connection. <br>
+    /// Every packet received from an unknown address is decrypted with the key of each connected client,
+    /// so anyone able to send packets to the server can make it spend CPU time proportional to the number
+    /// of connected clients. Only enable this if migration is needed. <br>
+    /// The default is `false`.
+    pub fn connection_migration(mut self, enabled: bool) -> Self {
+        self.connection_migration = enabled;
+        self
+    }
     /// Provide a callback that will be called when a client is connected to the server. <br>
     /// The callback will be called with the client index and the context that was provided (provide a `None` context if you don't need one).
     ///
@@ -343,6 +381,18 @@ impl<Ctx> ServerConfig<Ctx> {
         self.on_disconnect = Some(Box::new(cb));
         self
     }
+    /// Provide a callback that will be called when a connected client starts sending packets from a new address. <br>
+    /// The callback will be called with the client id, the entity of the client's connection, the entity of the link
+    /// on which the packet was received, and the context that was provided.
+    ///
+    /// See [`ServerConfig::connection_migration`].
+    pub fn on_migrate<F>(mut self, cb: F) -> Self
+    where
+        F: FnMut(ClientId, Entity, Entity, &mut Ctx) + Send + Sync + 'static,
+    {
+        self.on_migrate = Some(Box::new(cb));
+        self
+    }
 }
 
 /// The `netcode` server.
@@ -439,7 +489,11 @@ impl<Ctx> Server<Ctx> {
         | 1 << Packet::RESPONSE
         | 1 << Packet::KEEP_ALIVE
         | 1 << Packet::PAYLOAD
-        | 1 << Packet::DISCONNECT;
+        | 1 << Packet::DISCONNECT
+        | 1 << Packet::PATH_CHALLENGE;
+    /// Packets that can be received from a connected client on a new link
+    const MIGRATION_PACKETS: u8 =
+        1 << Packet::KEEP_ALIVE | 1 << Packet::PAYLOAD | 1 << Packet::PATH_CHALLENGE;
     fn on_connect(&mut self, client_id: ClientId, entity: Entity) {
         if let Some(cb) = self.cfg.on_connect.as_mut() {
             cb(client_id, entity, &mut self.cfg.context)
@@ -450,6 +504,11 @@ impl<Ctx> Server<Ctx> {
             cb(client_id, entity, &mut self.cfg.context)
         }
     }
+    fn on_migrate(&mut self, client_id: ClientId, entity: Entity, link_entity: Entity) {
+        if let Some(cb) = self.cfg.on_migrate.as_mut() {
+            cb(client_id, entity, link_entity, &mut self.cfg.context)
+        }
+    }
     fn handle_client_error(&mut self, error: Error) {
         self.client_errors.push(error);
     }
@@ -495,6 +554,13 @@ impl<Ctx> Server<Ctx> {
                     Ok(None)
                 }
             }
+            Packet::PathChallenge(_) => {
+                // the client answered the path challenge from its current link, so it did not migrate
+                if let Some(conn) = self.conn_cache.mut_by_entity(&entity) {
+                    conn.path_challenge = None;
+                }
+                Ok(None)
+            }
             Packet::Disconnect(_) => {
                 if let Some(idx) = self.conn_cache.find_by_entity(&entity).map(|c| c.client_id) {
                     debug!("server disconnected client {idx}");
@@ -814,6 +880,10 @@ impl<Ctx> Server<Ctx> {
                     self.conn_cache.replay_protection.get_mut(&client_id),
                 )
             }
+            // The packet might come from a connected client whose address changed
+            None if self.cfg.connection_migration => {
+                return self.recv_migrated_packet(reader.into_inner(), first_byte, now, entity);
+            }
             None => {
                 // Not a connection request packet, and not a known client, so ignore
                 return Err(Error::Ignored(entity));
@@ -832,6 +902,126 @@ impl<Ctx> Server<Ctx> {
         self.process_packet(packet, entity_mut)
     }
 
+    /// Handle a packet received on a link that is not associated with any client.
+    ///
+    /// If the packet is authenticated with the key of a connected client, and is the most recent packet
+    /// received from that client (so that packets delayed on the previous path are ignored), the client
+    /// might have migrated to the link. The connection is not migrated yet, because an attacker could have
+    /// captured the packet and forwarded it from its own address: the server sends a [`PathChallengePacket`]
+    /// on the new link, and migrates the connection only when the client echoes it back on that link.
+    /// The other packets received on the new link before that are dropped.
+    fn recv_migrated_packet(
+        &mut self,
+        buf: RecvPayload,
+        first_byte: u8,
+        now: u64,
+        link_entity: Entity,
+    ) -> Result<Option<RecvPayload>> {
+        let (_, pkt_kind) = Packet::get_prefix(first_byte);
+        if u16::from(Self::MIGRATION_PACKETS) & (1 << pkt_kind) == 0 {
+            return Err(Error::Ignored(link_entity));
+        }
+        // if the link is already being validated, only the key of that client needs to be tried
+        let pending = self
+            .conn_cache
+            .clients
+            .values()
+            .find(|conn| {
+                conn.path_challenge
+                    .is_some_and(|challenge| challenge.link_entity == link_entity)
+            })
+            .map(|conn| conn.client_id);
+        let authenticated = self
+            .conn_cache
+            .clients
+            .values()
+            .filter(|conn| conn.is_connected() && pending.is_none_or(|id| id == conn.client_id))
+            .find_map(|conn| {
+                let replay_protection =
+                    self.conn_cache.replay_protection.get_mut(&conn.client_id)?;
+                let most_recent_sequence = replay_protection.most_recent_sequence();
+                let packet = Packet::read(
+                    buf.clone(),
+                    self.protocol_id,
+                    now,
+                    conn.receive_key,
+                    Some(&mut *replay_protection),
+                    Self::MIGRATION_PACKETS,
+                )
+                .ok()?;
+                // ignore packets that were delayed on the previous path
+                let is_most_recent =
+                    replay_protection.most_recent_sequence() > most_recent_sequence;
+                Some((conn.client_id, conn.entity, packet, is_most_recent))
+            });
+        let Some((client_id, entity, packet, is_most_recent)) = authenticated else {
+            return Err(Error::Ignored(link_entity));
+        };
+        // the echoed nonce proves that the packet is not a delayed one
+        let Packet::PathChallenge(packet) = packet else {
+            if !is_most_recent {
+                return Err(Error::Ignored(link_entity));
+            }
+            self.send_path_challenge(client_id, link_entity)?;
+            return Ok(None);
+        };
+        let conn = self
+            .conn_cache
+            .clients
+            .get_mut(&client_id)
+            .ok_or(Error::ClientNotFound(id::PeerId::Netcode(client_id)))?;
+        if !conn.path_challenge.is_some_and(|challenge| {
+            challenge.link_entity == link_entity && challenge.nonce == packet.nonce
+        }) {
+            return Err(Error::Ignored(link_entity));
+        }
+        conn.path_challenge = None;
+        debug!(
+            ?entity,
+            ?link_entity,
+            "server client {client_id} migrated to a new link"
+        );
+        self.touch_client(client_id);
+        self.on_migrate(client_id, entity, link_entity);
+        Ok(None)
+    }
+
+    /// Send a [`PathChallengePacket`] to a connected client on a new link.
+    ///
+    /// The challenge is sent again (with the same nonce) at most every `keep_alive_send_rate` seconds
+    /// while the client keeps sending packets on that link.
+    fn send_path_challenge(&mut self, client_id: ClientId, link_entity: Entity) -> Result<()> {
+        let time = self.time;
+        let resend_rate = self.cfg.keep_alive_send_rate;
+        let conn = self
+            .conn_cache
+            .clients
+            .get_mut(&client_id)
+            .ok_or(Error::ClientNotFound(id::PeerId::Netcode(client_id)))?;
+        let nonce = match conn.path_challenge {
+            Some(challenge) if challenge.link_entity == link_entity => {
+                if challenge.send_time + resend_rate > time {
+                    return Ok(());
+                }
+                challenge.nonce
+            }
+            _ => {
+                let key = crypto::generate_key();
+                u64::from_le_bytes(key[..8].try_into().unwrap())
+            }
+        };
+        conn.path_challenge = Some(PathChallenge {
+            link_entity,
+            nonce,
+            send_time: time,
+        });
+        trace!(
+            ?link_entity,
+            "server sent path challenge packet to client {client_id}"
+        );
+        self.send_netcode_to_client(PathChallengePacket::create(nonce), client_id, link_entity)
+    }
+
     fn recv_packets(
         &mut self,
         receiver: &mut LinkReceiver,
@@ -843,9 +1033,9 @@ impl<Ctx> Server<Ctx> {
         // Processing them might mean that we're re-adding them to the receiver so that
         // the Transport can read them later
         for _ in 0..receiver.len() {
-            if let Some(recv_packet) = receiver.pop() {
+            if let Some((recv_packet, received_at)) = receiver.pop_with_instant() {
                 match self.recv_packet(recv_packet, now, entity_mut) {
-                    Ok(Some(payload)) => receiver.push_raw(payload),
+                    Ok(Some(payload)) => receiver.push_raw_at(payload, received_at),
                     Err(e) => self.handle_client_error(e),
                     _ => {}
                 }
@@ -7,7 +7,7 @@ use lightyear_connection::prelude::{server::*, *};
 use lightyear_connection::server::Stopping;
 use lightyear_core::id::PeerId;
 use lightyear_link::prelude::{LinkOf, Server};
-use lightyear_link::{Link, LinkSet};
+use lightyear_link::{Link, LinkMigrated, LinkSet};
 use lightyear_transport::plugin::TransportSet;
 use tracing::{error, info, trace};
 
@@ -17,6 +17,8 @@ pub struct NetcodeServerPlugin;
 pub(crate) struct NetcodeServerContext {
     pub(crate) connections: Vec<(ClientId, Entity)>,
     pub(crate) disconnections: Vec<(ClientId, Entity)>,
+    /// (client_id, client entity, entity of the link that received packets from the new address)
+    pub(crate) migrations: Vec<(ClientId, Entity, Entity)>,
 }
 
 #[derive(Component)]
@@ -36,6 +38,12 @@ pub struct NetcodeConfig {
     pub client_timeout_secs: i32,
     pub protocol_id: u64,
     pub private_key: Key,
+    /// If true, a connected client whose address changes (NAT rebinding, switch from Wi-Fi to cellular, etc.)
+    /// keeps its connection: the [`Link`] of the client is updated to use the new address.
+    /// The default is `false`.
+    ///
+    /// See [`ServerConfig::connection_migration`].
+    pub connection_migration: bool,
 }
 
 impl Default for NetcodeConfig {
@@ -46,6 +54,7 @@ impl Default for NetcodeConfig {
             client_timeout_secs: 3,
             protocol_id: 0,
             private_key: [0; PRIVATE_KEY_BYTES],
+            connection_migration: false,
         }
     }
 }
@@ -64,6 +73,11 @@ impl NetcodeConfig {
         self.client_timeout_secs = client_timeout_secs;
         self
     }
+
+    pub fn with_connection_migration(mut self, connection_migration: bool) -> Self {
+        self.connection_migration = connection_migration;
+        self
+    }
 }
 
 impl NetcodeServer {
@@ -75,10 +89,14 @@ impl NetcodeServer {
             })
             .on_disconnect(|id, entity, ctx| {
                 ctx.disconnections.push((id, entity));
+            })
+            .on_migrate(|id, entity, link_entity, ctx| {
+                ctx.migrations.push((id, entity, link_entity));
             });
         cfg = cfg.keep_alive_send_rate(config.keep_alive_send_rate);
         cfg = cfg.num_disconnect_packets(config.num_disconnect_packets);
         cfg = cfg.client_timeout_secs(config.client_timeout_secs);
+        cfg = cfg.connection_migration(config.connection_migration);
         let server =
             crate::server::Server::with_config(config.protocol_id, config.private_key, cfg)
                 .expect("Could not create server netcode");
@@ -227,6 +245,44 @@ impl NetcodeServerPlugin {
                             }
                         });
 
+                    // Migrations: the client keeps its entity, but the packets now come from the address of the new link
+                    netcode_server
+                        .inner
+                        .cfg
+                        .context
+                        .migrations
+                        .drain(..)
+                        .for_each(|(id, entity, link_entity)| {
+                            // the same link can receive multiple packets from the migrated client in a single frame
+                            let Ok([(_, mut link), (_, mut new_link)]) =
+                                link_query.get_many_mut([entity, link_entity])
+                            else {
+                                return;
+                            };
+                            // the payloads that were received on the new link belong to the client
+                            while let Some((payload, received_at)) =
+                                new_link.recv.pop_with_instant()
+                            {
+                                link.recv.push_raw_at(payload, received_at);
+                            }
+                            let Some(new_addr) = new_link.remote_addr else {
+                                return;
+                            };
+                            if link.remote_addr == Some(new_addr) {
+                                return;
+                            }
+                            info!(
+                                "Netcode client {:?} ({:?}) migrated from {:?} to {:?}",
+                                id, entity, link.remote_addr, new_addr
+                            );
+                            let migrated = LinkMigrated {
+                                old_addr: link.remote_addr.replace(new_addr),
+                                new_addr,
+                            };
+                            c.entity(entity).trigger(migrated);
+                            c.entity(link_entity).despawn();
+                        });
+
                     // Connections: we know the connection comes from the current entity!
                     netcode_server
                         .inner
@@ -318,6 +374,34 @@ impl NetcodeServerPlugin {
         }
         Ok(())
     }
+
+    /// Disconnect a single client, for example because it sent invalid data
+    fn disconnect(
+        trigger: Trigger<Disconnect>,
+        mut commands: Commands,
+        mut query: Query<&mut NetcodeServer>,
+        mut link_query: Query<(&LinkOf, &mut Link, &Connected), With<ClientOf>>,
+    ) -> Result {
+        let entity = trigger.target();
+        let Ok((link_of, mut link, connected)) = link_query.get_mut(entity) else {
+            return Ok(());
+        };
+        let Ok(mut netcode_server) = query.get_mut(link_of.server) else {
+            return Ok(());
+        };
+        let PeerId::Netcode(client_id) = connected.remote_peer_id else {
+            error!(
+                "Client {:?} is not a Netcode client",
+                connected.remote_peer_id
+            );
+            return Err(crate::error::Error::UnknownClient(connected.remote_peer_id).into());
+        };
+        // this will make sure that `netcode.on_disconnect` is called, so the entity will get disconnected
+        // in the next frame from the `receive` system.
+        netcode_server.inner.disconnect(client_id, &mut link.send)?;
+        commands.entity(entity).insert(Disconnecting);
+        Ok(())
+    }
 }
 
 impl Plugin for NetcodeServerPlugin {
@@ -340,7 +424,13 @@ impl Plugin for NetcodeServerPlugin {
         );
         app.configure_sets(
             PostUpdate,
-            (TransportSet::Send, ConnectionSet::Send, LinkSet::Send).chain(),
+            (
+                TransportSet::Send,
+                ConnectionSet::Send,
+                LinkSet::ApplyConditioner,
+                LinkSet::Send,
+            )
+                .chain(),
         );
 
         app.add_systems(PreUpdate, Self::receive.in_set(ConnectionSet::Receive));
@@ -348,5 +438,6 @@ impl Plugin for NetcodeServerPlugin {
 
         app.add_observer(Self::start);
         app.add_observer(Self::stop);
+        app.add_observer(Self::disconnect);
     }
 }
@@ -80,6 +80,7 @@ impl Plugin for AuthorityPlugin {
             mode: ChannelMode::SequencedReliable(ReliableSettings::default()),
             send_frequency: Default::default(),
             priority: 10.0,
+            ..Default::default()
         })
         .add_direction(NetworkDirection::Bidirectional);
         app.add_trigger::<AuthorityTransferRequest>()
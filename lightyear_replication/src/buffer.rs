@@ -36,6 +36,7 @@ use lightyear_link::prelude::LinkOf;
 #[cfg(feature = "server")]
 use lightyear_link::prelude::Server;
 use lightyear_messages::MessageManager;
+use lightyear_messages::protocol::RemoteVersions;
 use lightyear_serde::entity_map::RemoteEntityMap;
 
 #[derive(Clone, Default, Debug, PartialEq, Reflect)]
@@ -394,6 +395,7 @@ pub(crate) fn replicate(
 
             // enable split borrows
             let sender = &mut *sender;
+            let message_manager = &mut *message_manager;
             if !sender.send_timer.finished() {
                 return;
             }
@@ -423,6 +425,7 @@ pub(crate) fn replicate(
                     &root_entity_ref,
                     None,
                     &mut message_manager.entity_mapper,
+                    &message_manager.remote_versions,
                     sender,
                     sender_entity,
                     component_registry.as_ref(),
@@ -438,6 +441,7 @@ pub(crate) fn replicate(
                             &root_entity_ref,
                             Some(&(child_entity_ref, entity)),
                             &mut message_manager.entity_mapper,
+                            &message_manager.remote_versions,
                             sender,
                             sender_entity,
                             component_registry.as_ref(),
@@ -461,6 +465,7 @@ pub(crate) fn replicate_entity(
     root_entity_ref: &FilteredEntityRef,
     child_entity_ref: Option<&(FilteredEntityRef, Entity)>,
     entity_mapper: &mut RemoteEntityMap,
+    remote_versions: &RemoteVersions,
     sender: &mut ReplicationSender,
     sender_entity: Entity,
     component_registry: &ComponentRegistry,
@@ -655,6 +660,7 @@ pub(crate) fn replicate_entity(
             delta_compression,
             replicate_once,
             entity_mapper,
+            remote_versions,
             sender,
             delta_manager,
         )
@@ -890,6 +896,7 @@ fn replicate_component_update(
     delta_compression: bool,
     replicate_once: bool,
     entity_map: &mut RemoteEntityMap,
+    remote_versions: &RemoteVersions,
     sender: &mut ReplicationSender,
     delta: &mut DeltaManager,
 ) -> Result<(), ReplicationError> {
@@ -940,6 +947,7 @@ fn replicate_component_update(
                     writer,
                     component_kind,
                     &mut entity_map.local_to_remote,
+                    remote_versions,
                 )?;
             };
             let raw_data = writer.split();
@@ -983,6 +991,7 @@ fn replicate_component_update(
                         writer,
                         component_kind,
                         &mut entity_map.local_to_remote,
+                        remote_versions,
                     )?;
                     let raw_data = writer.split();
                     sender.prepare_component_update(entity, group_id, raw_data);
@@ -0,0 +1,345 @@
+//! Decode the packets recorded by a [`LinkCapture`](lightyear_link::capture::LinkCapture).
+//!
+//! The [`CaptureDecoder`] uses the [`ChannelRegistry`], [`MessageRegistry`] and [`ComponentRegistry`]
+//! of the protocol to turn the raw packets into a human-readable form: packet header, channel,
+//! message type, and the content of the replication actions/updates.
+//!
+//! The decoder must be built from an app that registered the same protocol (channels, messages and
+//! components, in the same order) as the app that recorded the capture.
+//!
+//! ```rust,ignore
+//! use lightyear::prelude::*;
+//!
+//! let mut decoder = CaptureDecoder::from_world(app.world());
+//! for record in CaptureReader::open("client.lycap")? {
+//!     println!("{}", decoder.decode(&record?)?);
+//! }
+//! ```
+use crate::components::ReplicationGroupId;
+use crate::error::ReplicationError;
+use crate::message::{ActionsMessage, SpawnAction, UpdatesMessage};
+use crate::registry::ComponentNetId;
+use crate::registry::registry::ComponentRegistry;
+use bevy::prelude::{Entity, World};
+use bytes::Bytes;
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use lightyear_core::tick::Tick;
+use lightyear_link::capture::{CaptureDirection, CaptureRecord};
+use lightyear_messages::MessageNetId;
+use lightyear_messages::registry::{MessageKind, MessageRegistry};
+use lightyear_serde::ToBytes;
+use lightyear_serde::reader::Reader;
+use lightyear_transport::channel::registry::ChannelRegistry;
+use lightyear_transport::error::TransportError;
+use lightyear_transport::packet::decode::{DecodedPacket, PacketDecoder};
+use lightyear_transport::packet::message::MessageId;
+
+/// A [`CaptureRecord`] that was decoded by the [`CaptureDecoder`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedRecord {
+    pub elapsed: Duration,
+    pub direction: CaptureDirection,
+    pub packet: DecodedPacket,
+    /// The content of each message of the packet, in the same order as `packet.messages`
+    pub contents: Vec<MessageContent>,
+}
+
+/// The decoded content of a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageContent {
+    /// The message is a fragment of a message that has not been fully re-assembled yet
+    Fragment,
+    /// A message registered in the [`MessageRegistry`]
+    Message {
+        net_id: MessageNetId,
+        name: Option<&'static str>,
+        /// Number of bytes of the serialized message
+        len: usize,
+    },
+    /// Replication actions (spawn, despawn, insert, remove) for a replication group
+    Actions {
+        sequence_id: MessageId,
+        group_id: ReplicationGroupId,
+        entities: Vec<(Entity, DecodedActions)>,
+    },
+    /// Replication updates for a replication group
+    Updates {
+        group_id: ReplicationGroupId,
+        last_action_tick: Option<Tick>,
+        entities: Vec<(Entity, Vec<DecodedComponent>)>,
+    },
+    /// The message could not be decoded
+    Invalid(String),
+}
+
+/// The replication actions for a single entity
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedActions {
+    pub spawn: bool,
+    pub despawn: bool,
+    pub insert: Vec<DecodedComponent>,
+    /// The removed components (their `len` is always 0)
+    pub remove: Vec<DecodedComponent>,
+    pub updates: Vec<DecodedComponent>,
+}
+
+/// A component contained in the replication actions/updates
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedComponent {
+    pub net_id: ComponentNetId,
+    /// Name of the component, if it is registered in the [`ComponentRegistry`]
+    pub name: Option<&'static str>,
+    /// Number of bytes of the serialized component
+    pub len: usize,
+}
+
+/// Decodes the [`CaptureRecord`]s of a single capture into [`DecodedRecord`]s
+pub struct CaptureDecoder {
+    channel_registry: ChannelRegistry,
+    message_registry: MessageRegistry,
+    component_registry: ComponentRegistry,
+    // messages are fragmented independently in each direction
+    send_decoder: PacketDecoder,
+    recv_decoder: PacketDecoder,
+}
+
+impl CaptureDecoder {
+    pub fn new(
+        channel_registry: ChannelRegistry,
+        message_registry: MessageRegistry,
+        component_registry: ComponentRegistry,
+    ) -> Self {
+        Self {
+            channel_registry,
+            message_registry,
+            component_registry,
+            send_decoder: PacketDecoder::default(),
+            recv_decoder: PacketDecoder::default(),
+        }
+    }
+
+    /// Create a decoder from the registries present in the [`World`].
+    ///
+    /// The [`ComponentRegistry`] is optional, in case replication is not used.
+    pub fn from_world(world: &World) -> Self {
+        Self::new(
+            world.resource::<ChannelRegistry>().clone(),
+            world.resource::<MessageRegistry>().clone(),
+            world
+                .get_resource::<ComponentRegistry>()
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn decode(&mut self, record: &CaptureRecord) -> Result<DecodedRecord, ReplicationError> {
+        let packet_decoder = match record.direction {
+            CaptureDirection::Send => &mut self.send_decoder,
+            CaptureDirection::Recv => &mut self.recv_decoder,
+        };
+        let packet = packet_decoder
+            .decode(record.payload.clone(), &self.channel_registry)
+            .map_err(TransportError::from)?;
+        let contents = packet
+            .messages
+            .iter()
+            .map(|message| match &message.bytes {
+                None => MessageContent::Fragment,
+                Some(bytes) => self
+                    .decode_message(bytes.clone())
+                    .unwrap_or_else(|e| MessageContent::Invalid(e.to_string())),
+            })
+            .collect();
+        Ok(DecodedRecord {
+            elapsed: record.elapsed,
+            direction: record.direction,
+            packet,
+            contents,
+        })
+    }
+
+    fn decode_message(&self, bytes: Bytes) -> Result<MessageContent, ReplicationError> {
+        let mut reader = Reader::from(bytes);
+        let net_id = MessageNetId::from_bytes(&mut reader)?;
+        let kind = self.message_registry.kind_map.kind(net_id).copied();
+        if kind == Some(MessageKind::of::<ActionsMessage>()) {
+            let message = ActionsMessage::from_bytes(&mut reader)?;
+            let entities = message
+                .actions
+                .into_iter()
+                .map(|(entity, actions)| {
+                    Ok((
+                        entity,
+                        DecodedActions {
+                            spawn: actions.spawn == SpawnAction::Spawn,
+                            despawn: actions.spawn == SpawnAction::Despawn,
+                            insert: self.decode_components(actions.insert)?,
+                            remove: actions
+                                .remove
+                                .into_iter()
+                                .map(|net_id| DecodedComponent {
+                                    net_id,
+                                    name: self.component_registry.name_from_net_id(net_id),
+                                    len: 0,
+                                })
+                                .collect(),
+                            updates: self.decode_components(actions.updates)?,
+                        },
+                    ))
+                })
+                .collect::<Result<_, ReplicationError>>()?;
+            return Ok(MessageContent::Actions {
+                sequence_id: message.sequence_id,
+                group_id: message.group_id,
+                entities,
+            });
+        }
+        if kind == Some(MessageKind::of::<UpdatesMessage>()) {
+            let message = UpdatesMessage::from_bytes(&mut reader)?;
+            let entities = message
+                .updates
+                .into_iter()
+                .map(|(entity, updates)| Ok((entity, self.decode_components(updates)?)))
+                .collect::<Result<_, ReplicationError>>()?;
+            return Ok(MessageContent::Updates {
+                group_id: message.group_id,
+                last_action_tick: message.last_action_tick,
+                entities,
+            });
+        }
+        Ok(MessageContent::Message {
+            net_id,
+            name: self.message_registry.name(net_id),
+            len: reader.remaining(),
+        })
+    }
+
+    fn decode_components(
+        &self,
+        components: Vec<Bytes>,
+    ) -> Result<Vec<DecodedComponent>, ReplicationError> {
+        components
+            .into_iter()
+            .map(|bytes| {
+                let mut reader = Reader::from(bytes);
+                let net_id = ComponentNetId::from_bytes(&mut reader)?;
+                Ok(DecodedComponent {
+                    net_id,
+                    name: self.component_registry.name_from_net_id(net_id),
+                    len: reader.remaining(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Display for DecodedComponent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{name}")?,
+            None => write!(f, "<unknown component {}>", self.net_id)?,
+        }
+        if self.len > 0 {
+            write!(f, " ({}B)", self.len)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_components(
+    f: &mut Formatter<'_>,
+    label: &str,
+    components: &[DecodedComponent],
+) -> core::fmt::Result {
+    if components.is_empty() {
+        return Ok(());
+    }
+    write!(f, " {label}=[")?;
+    for (i, component) in components.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{component}")?;
+    }
+    write!(f, "]")
+}
+
+impl Display for MessageContent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MessageContent::Fragment => write!(f, "<incomplete fragmented message>"),
+            MessageContent::Message { net_id, name, len } => match name {
+                Some(name) => write!(f, "{name} ({len}B)"),
+                None => write!(f, "<unknown message {net_id}> ({len}B)"),
+            },
+            MessageContent::Actions {
+                sequence_id,
+                group_id,
+                entities,
+            } => {
+                write!(f, "Actions group={group_id:?} sequence_id={sequence_id:?}")?;
+                for (entity, actions) in entities {
+                    write!(f, "\n      {entity}:")?;
+                    if actions.spawn {
+                        write!(f, " spawn")?;
+                    }
+                    if actions.despawn {
+                        write!(f, " despawn")?;
+                    }
+                    write_components(f, "insert", &actions.insert)?;
+                    write_components(f, "remove", &actions.remove)?;
+                    write_components(f, "update", &actions.updates)?;
+                }
+                Ok(())
+            }
+            MessageContent::Updates {
+                group_id,
+                last_action_tick,
+                entities,
+            } => {
+                write!(
+                    f,
+                    "Updates group={group_id:?} last_action_tick={last_action_tick:?}"
+                )?;
+                for (entity, updates) in entities {
+                    write!(f, "\n      {entity}:")?;
+                    write_components(f, "update", updates)?;
+                }
+                Ok(())
+            }
+            MessageContent::Invalid(error) => write!(f, "<invalid message: {error}>"),
+        }
+    }
+}
+
+impl Display for DecodedRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let direction = match self.direction {
+            CaptureDirection::Send => "SEND",
+            CaptureDirection::Recv => "RECV",
+        };
+        write!(
+            f,
+            "[{:.6}s] {direction} packet_id={:?} tick={:?} ack={:?} ack_bitfield={:#034b}",
+            self.elapsed.as_secs_f64(),
+            self.packet.packet_id,
+            self.packet.tick,
+            self.packet.last_ack_packet_id,
+            self.packet.ack_bitfield,
+        )?;
+        for (message, content) in self.packet.messages.iter().zip(self.contents.iter()) {
+            write!(
+                f,
+                "\n  channel={} message_id={:?}",
+                message.channel_name.unwrap_or("<unknown channel>"),
+                message.message_id,
+            )?;
+            if let Some((index, num_fragments)) = message.fragment {
+                write!(f, " fragment={}/{num_fragments}", index + 1)?;
+            }
+            write!(f, "\n    {content}")?;
+        }
+        Ok(())
+    }
+}
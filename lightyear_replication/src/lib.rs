@@ -21,6 +21,9 @@ pub(crate) mod archetypes;
 pub mod components;
 
 pub(crate) mod authority;
+/// Decodes the packets recorded by a `LinkCapture` into replication actions and updates.
+#[cfg(feature = "std")]
+pub mod capture;
 /// Defines error types that can occur during replication.
 pub mod error;
 pub(crate) mod hierarchy;
@@ -47,6 +50,8 @@ pub mod prelude {
         GiveAuthority, RequestAuthority,
     };
     pub use crate::buffer::Replicate;
+    #[cfg(feature = "std")]
+    pub use crate::capture::CaptureDecoder;
     pub use crate::components::*;
     pub use crate::control::{Controlled, ControlledBy, ControlledByRemote, Lifetime};
     pub use crate::hierarchy::{
@@ -8,10 +8,11 @@ use crate::control::Controlled;
 use crate::hierarchy::{DisableReplicateHierarchy, ReplicateLike};
 use crate::message::{ActionsChannel, MetadataChannel, SenderMetadata, UpdatesChannel};
 use crate::prelude::{ActionsMessage, AppComponentExt, UpdatesMessage};
+use crate::registry::registry::ComponentRegistry;
 use bevy::prelude::*;
 use core::time::Duration;
 use lightyear_connection::prelude::NetworkDirection;
-use lightyear_messages::prelude::{AppMessageExt, AppTriggerExt};
+use lightyear_messages::prelude::{AppMessageExt, AppProtocolExt, AppTriggerExt};
 use lightyear_transport::channel::builder::ReliableSettings;
 use lightyear_transport::prelude::{AppChannelExt, ChannelMode, ChannelSettings};
 
@@ -47,6 +48,11 @@ impl Plugin for SharedPlugin {
             .register_type::<ReplicationGroupId>();
 
         app.register_component::<Controlled>();
+        app.add_protocol_hash_fn(|world, mut hasher| {
+            if let Some(registry) = world.get_resource::<ComponentRegistry>() {
+                registry.hash_protocol(&mut hasher);
+            }
+        });
 
         #[cfg(feature = "interpolation")]
         {
@@ -63,6 +69,7 @@ impl Plugin for SharedPlugin {
             mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
             send_frequency: Duration::default(),
             priority: 10.0,
+            ..Default::default()
         })
         .add_direction(NetworkDirection::Bidirectional);
         app.add_channel::<UpdatesChannel>(ChannelSettings {
@@ -73,6 +80,7 @@ impl Plugin for SharedPlugin {
             // directly on the replication_sender
             send_frequency: Duration::default(),
             priority: 1.0,
+            ..Default::default()
         })
         .add_direction(NetworkDirection::Bidirectional);
         app.add_channel::<ActionsChannel>(ChannelSettings {
@@ -84,6 +92,7 @@ impl Plugin for SharedPlugin {
             send_frequency: Duration::default(),
             // we want to send the entity actions as soon as possible
             priority: 10.0,
+            ..Default::default()
         })
         .add_direction(NetworkDirection::Bidirectional);
         app.add_message_to_bytes::<ActionsMessage>()
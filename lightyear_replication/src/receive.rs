@@ -26,6 +26,7 @@ use lightyear_core::timeline::NetworkTimeline;
 use lightyear_messages::plugin::MessageSet;
 use lightyear_messages::prelude::MessageReceiver;
 use lightyear_messages::MessageManager;
+use lightyear_transport::limits::{LimitError, ReceiveLimits};
 use lightyear_transport::prelude::Transport;
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
@@ -136,11 +137,15 @@ impl ReplicationReceivePlugin {
                 let local_timeline = unsafe { unsafe_world.world_mut() }
                     .get::<LocalTimeline>(entity)
                     .unwrap();
+                let limits = unsafe { unsafe_world.world_mut() }
+                    .get::<ReceiveLimits>(entity)
+                    .copied()
+                    .unwrap_or_default();
                 // SAFETY: the world will only be used to apply replication updates, which doesn't conflict with other accesses
                 let world = unsafe { unsafe_world.world_mut() };
 
                 let tick = local_timeline.tick();
-                receiver.apply_world(
+                let limit_error = receiver.apply_world(
                     world,
                     entity,
                     remote_peer,
@@ -148,8 +153,12 @@ impl ReplicationReceivePlugin {
                     authority_map,
                     component_registry,
                     tick,
+                    &limits,
                 );
                 receiver.tick_cleanup(tick);
+                if let Some(limit_error) = limit_error {
+                    limits.on_exceeded(&mut world.commands(), entity, limit_error);
+                }
             });
     }
 }
@@ -363,7 +372,10 @@ impl ReplicationReceiver {
         authority_map: Option<&EntityIndexMap<bool>>,
         component_registry: &ComponentRegistry,
         current_tick: Tick,
-    ) {
+        limits: &ReceiveLimits,
+    ) -> Option<LimitError> {
+        // the first ReceiveLimits exceeded by the received components
+        let mut limit_error = None;
         // apply all actions first
         // TODO: it's extremely strange, but it seems like the value of TempWriteBuffer can linger from previous
         //  frames. Let's clear it manually for now
@@ -399,7 +411,7 @@ impl ReplicationReceiver {
                     // Update the latest server tick that we have processed
                     channel.latest_tick = Some(remote_tick);
 
-                    channel.apply_actions_message(
+                    let error = channel.apply_actions_message(
                         world,
                         receiver_entity,
                         remote,
@@ -410,7 +422,9 @@ impl ReplicationReceiver {
                         authority_map,
                         &mut self.local_entity_to_group,
                         &mut self.buffer,
+                        limits,
                     );
+                    limit_error = limit_error.or(error);
                 }
             });
 
@@ -460,7 +474,7 @@ impl ReplicationReceiver {
                         // the Confirmed.tick when the Confirmed entity is just spawned
                         channel.latest_tick = Some(remote_tick);
                     }
-                    channel.apply_updates_message(
+                    let error = channel.apply_updates_message(
                         world,
                         remote,
                         component_registry,
@@ -469,9 +483,12 @@ impl ReplicationReceiver {
                         message,
                         remote_entity_map,
                         authority_map,
+                        limits,
                     );
+                    limit_error = limit_error.or(error);
                 }
-            })
+            });
+        limit_error
     }
 }
 
@@ -707,8 +724,10 @@ impl GroupChannel {
         authority_map: Option<&EntityIndexMap<bool>>,
         local_entity_to_group: &mut EntityHashMap<Entity, ReplicationGroupId>,
         temp_write_buffer: &mut BufferedChanges,
-    ) {
+        limits: &ReceiveLimits,
+    ) -> Option<LimitError> {
         let group_id = message.group_id;
+        let mut limit_error = None;
         debug!(
             ?remote_tick,
             ?message,
@@ -823,8 +842,14 @@ impl GroupChannel {
                     &mut buffered_entity,
                     remote_tick,
                     &mut remote_entity_map.remote_to_local,
+                    limits,
                 )
-            }).inspect_err(|e| error!("could not insert the components to the entity: {:?}", e));
+            }).inspect_err(|e| match e.limit_error() {
+                Some(error) => {
+                    limit_error.get_or_insert(error);
+                }
+                None => error!("could not insert the components to the entity: {:?}", e),
+            });
            
 
             // TODO: find a way to handle this elegantly. Maybe the server should send a Spawn::Reuse
@@ -864,9 +889,13 @@ impl GroupChannel {
                         &mut buffered_entity,
                         remote_tick,
                         &mut remote_entity_map.remote_to_local,
+                        limits,
                     )
-                    .inspect_err(|e| {
-                        error!("could not write the component to the entity: {:?}", e)
+                    .inspect_err(|e| match e.limit_error() {
+                        Some(error) => {
+                            limit_error.get_or_insert(error);
+                        }
+                        None => error!("could not write the component to the entity: {:?}", e),
                     });
             }
         }
@@ -878,6 +907,7 @@ impl GroupChannel {
 
         // TODO: apply authority check for the update confirmed tick?
         self.update_confirmed_tick(world, group_id, remote_tick);
+        limit_error
     }
 
     pub(crate) fn apply_updates_message(
@@ -890,12 +920,14 @@ impl GroupChannel {
         message: UpdatesMessage,
         remote_entity_map: &mut RemoteEntityMap,
         authority_map: Option<&EntityIndexMap<bool>>,
-    ) {
+        limits: &ReceiveLimits,
+    ) -> Option<LimitError> {
         let group_id = message.group_id;
         // TODO: store this in ConfirmedHistory?
         if is_history {
-            return;
+            return None;
         }
+        let mut limit_error = None;
         debug!(
             ?remote_tick,
             ?message,
@@ -933,9 +965,13 @@ impl GroupChannel {
                         &mut local_entity_mut,
                         remote_tick,
                         &mut remote_entity_map.remote_to_local,
+                        limits,
                     )
-                    .inspect_err(|e| {
-                        error!("could not write the component to the entity: {:?}", e)
+                    .inspect_err(|e| match e.limit_error() {
+                        Some(error) => {
+                            limit_error.get_or_insert(error);
+                        }
+                        None => error!("could not write the component to the entity: {:?}", e),
                     });
             }
             
@@ -950,6 +986,7 @@ impl GroupChannel {
         // TODO: should the update_confirmed_tick only be for entities in the group for which
         //  we have authority?
         self.update_confirmed_tick(world, group_id, remote_tick);
+        limit_error
     }
 
     /// Update the Confirmed tick for all entities in the replication group
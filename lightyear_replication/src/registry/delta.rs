@@ -12,6 +12,7 @@ use bevy::ptr::{Ptr, PtrMut};
 use core::any::TypeId;
 use core::ptr::NonNull;
 use lightyear_core::tick::Tick;
+use lightyear_messages::protocol::RemoteVersions;
 use lightyear_serde::entity_map::{ReceiveEntityMap, SendEntityMap};
 use lightyear_serde::reader::Reader;
 use lightyear_serde::registry::ContextDeserializeFns;
@@ -100,6 +101,8 @@ impl ComponentRegistry {
             writer,
             delta_fns.delta_kind,
             entity_map,
+            // the diffs are not versioned
+            &RemoteVersions::default(),
         )?;
         // drop the delta message
         unsafe { (delta_fns.drop_delta_message)(delta) };
@@ -127,6 +130,8 @@ impl ComponentRegistry {
             writer,
             delta_fns.delta_kind,
             entity_map,
+            // the diffs are not versioned
+            &RemoteVersions::default(),
         )?;
         // drop the delta message
         unsafe { (delta_fns.drop_delta_message)(delta) };
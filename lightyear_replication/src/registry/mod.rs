@@ -4,6 +4,7 @@ use bevy::prelude::Reflect;
 use core::any::TypeId;
 use lightyear_core::network::NetId;
 use lightyear_serde::SerializationError;
+use lightyear_transport::limits::LimitError;
 use lightyear_utils::registry::TypeKind;
 
 mod delta;
@@ -29,6 +30,17 @@ pub enum ComponentError {
     SerializationError(#[from] SerializationError),
 }
 
+impl ComponentError {
+    /// Returns the limit that was exceeded, if the error was caused by the
+    /// [`ReceiveLimits`](lightyear_transport::limits::ReceiveLimits) of the connection
+    pub fn limit_error(&self) -> Option<LimitError> {
+        match self {
+            Self::SerializationError(e) => LimitError::from_serialization(e),
+            _ => None,
+        }
+    }
+}
+
 /// [`ComponentKind`] is an internal wrapper around the type of the component
 #[derive(Debug, Eq, Hash, Copy, Clone, PartialEq, Reflect)]
 pub struct ComponentKind(pub TypeId);
@@ -11,13 +11,16 @@ use bevy::ecs::entity::MapEntities;
 use bevy::platform::collections::HashMap;
 use bevy::prelude::{Resource, Transform, TypePath, World};
 use bevy::ptr::Ptr;
+use core::hash::{Hash, Hasher};
 use lightyear_core::network::NetId;
+use lightyear_messages::protocol::{AppProtocolExt, RemoteVersions, VersionedType};
 use lightyear_serde::entity_map::{EntityMap, ReceiveEntityMap, SendEntityMap};
 use lightyear_serde::reader::Reader;
 use lightyear_serde::registry::{
     ContextDeserializeFn, ContextDeserializeFns, ContextSerializeFn, ContextSerializeFns,
     DeserializeFn, ErasedSerializeFns, SerializeFn, SerializeFns,
 };
+use lightyear_serde::version::VersionFns;
 use lightyear_serde::writer::Writer;
 use lightyear_serde::{SerializationError, ToBytes};
 use lightyear_utils::registry::TypeMapper;
@@ -124,6 +127,8 @@ pub struct ComponentRegistry {
     pub replication_map: HashMap<ComponentKind, ReplicationMetadata>,
     pub serialize_fns_map: HashMap<ComponentKind, ErasedSerializeFns>,
     pub(crate) delta_fns_map: HashMap<ComponentKind, ErasedDeltaFns>,
+    /// Names of the components that are used in the [`ProtocolHash`](lightyear_messages::protocol::ProtocolHash)
+    pub(crate) protocol_names: HashMap<ComponentKind, &'static str>,
     pub kind_map: TypeMapper<ComponentKind>,
 }
 
@@ -148,6 +153,29 @@ impl ComponentRegistry {
         self.serialize_fns_map.get(&kind).unwrap().type_name
     }
 
+    /// Return the name of the component from its [`ComponentNetId`]
+    pub fn name_from_net_id(&self, net_id: ComponentNetId) -> Option<&'static str> {
+        let kind = self.kind_map.kind(net_id)?;
+        self.serialize_fns_map.get(kind).map(|fns| fns.type_name)
+    }
+
+    /// Feed the parts of the registry that need to be identical on both peers (network ids and
+    /// protocol names of the components, and whether they are replicated with deltas) to the `hasher`.
+    ///
+    /// The result does not depend on the platform, so it can be compared with a remote peer.
+    pub fn hash_protocol<H: Hasher>(&self, hasher: &mut H) {
+        self.kind_map.iter().for_each(|(net_id, kind)| {
+            hasher.write_u16(net_id);
+            self.protocol_names
+                .get(kind)
+                .copied()
+                .unwrap_or("")
+                .hash(hasher);
+            hasher.write_u8(self.replication_map.contains_key(kind) as u8);
+            hasher.write_u8(self.delta_fns_map.contains_key(kind) as u8);
+        });
+    }
+
     pub fn is_registered<C: 'static>(&self) -> bool {
         self.kind_map.net_id(&ComponentKind::of::<C>()).is_some()
     }
@@ -258,12 +286,14 @@ impl ComponentRegistry {
         component: &C,
         writer: &mut Writer,
         entity_map: &mut SendEntityMap,
+        remote_versions: &RemoteVersions,
     ) -> Result<(), ComponentError> {
         self.erased_serialize(
             Ptr::from(component),
             writer,
             ComponentKind::of::<C>(),
             entity_map,
+            remote_versions,
         )
     }
 
@@ -274,6 +304,7 @@ impl ComponentRegistry {
         writer: &mut Writer,
         kind: ComponentKind,
         entity_map: &mut SendEntityMap,
+        remote_versions: &RemoteVersions,
     ) -> Result<(), ComponentError> {
         let erased_fns = self
             .serialize_fns_map
@@ -281,10 +312,11 @@ impl ComponentRegistry {
             .ok_or(ComponentError::MissingSerializationFns)?;
         let net_id = self.kind_map.net_id(&kind).unwrap();
         net_id.to_bytes(writer)?;
-        // SAFETY: the ErasedSerializeFns corresponds to type C
-        unsafe {
-            (erased_fns.erased_serialize)(erased_fns, component, writer, entity_map)?;
-        }
+        let remote_version = remote_versions.get(VersionedType::Component(*net_id), erased_fns);
+        erased_fns.serialize_versioned(writer, remote_version, |writer| {
+            // SAFETY: the ErasedSerializeFns corresponds to type C
+            unsafe { (erased_fns.erased_serialize)(erased_fns, component, writer, entity_map) }
+        })?;
         Ok(())
     }
 
@@ -344,6 +376,17 @@ pub trait AppComponentExt {
         &mut self,
         serialize_fns: SerializeFns<C>,
     ) -> ComponentRegistration<'_, C>;
+
+    /// Registers the component in the Registry: this component can now be sent over the network.
+    ///
+    /// The component is serialized with its `ToBytes` implementation instead of bincode.
+    fn register_component_to_bytes<
+        C: Component<Mutability: GetWriteFns<C>> + ToBytes + PartialEq,
+    >(
+        &mut self,
+    ) -> ComponentRegistration<'_, C> {
+        self.register_component_custom_serde(SerializeFns::<C>::with_to_bytes())
+    }
 }
 
 impl AppComponentExt for App {
@@ -398,6 +441,48 @@ impl<C> ComponentRegistration<'_, C> {
         self
     }
 
+    /// Version the wire format of the component, so that it can still be replicated with peers that
+    /// use an older version of the component.
+    ///
+    /// This has no effect if the component uses delta-compression, since the diffs are not versioned.
+    /// See [`VersionFns`] for more details.
+    pub fn add_version(self, version_fns: VersionFns<C>) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        let kind = ComponentKind::of::<C>();
+        let net_id = *registry.kind_map.net_id(&kind).unwrap_or_else(|| {
+            panic!(
+                "Component {} is not part of the protocol",
+                core::any::type_name::<C>()
+            )
+        });
+        let erased_fns = registry.serialize_fns_map.get_mut(&kind).unwrap();
+        // SAFETY: the ErasedSerializeFns was created for the type C
+        unsafe { erased_fns.set_version(version_fns) };
+        let erased_fns = erased_fns.clone();
+        self.app
+            .add_protocol_version(VersionedType::Component(net_id), &erased_fns);
+        self
+    }
+
+    /// Name of the component that is used in the [`ProtocolHash`](lightyear_messages::protocol::ProtocolHash).
+    ///
+    /// The name must stay the same across builds; it lets the peers detect that the components were
+    /// registered in a different order.
+    pub fn with_protocol_name(self, name: &'static str) -> Self
+    where
+        C: 'static,
+    {
+        self.app
+            .world_mut()
+            .resource_mut::<ComponentRegistry>()
+            .protocol_names
+            .insert(ComponentKind::of::<C>(), name);
+        self
+    }
+
     pub fn with_replication_config(self, config: ComponentReplicationConfig) -> Self
     where
         C: Component<Mutability: GetWriteFns<C>> + PartialEq,
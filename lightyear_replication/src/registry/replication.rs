@@ -10,6 +10,7 @@ use lightyear_serde::entity_map::ReceiveEntityMap;
 use lightyear_serde::reader::Reader;
 use lightyear_serde::registry::{ContextDeserializeFns, ErasedSerializeFns};
 use lightyear_serde::ToBytes;
+use lightyear_transport::limits::ReceiveLimits;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReplicationMetadata {
@@ -86,14 +87,17 @@ impl ComponentRegistry {
     ///
     /// This method will insert all the components simultaneously.
     /// If any component already existed on the entity, it will be updated instead of inserted.
+    ///
+    /// The component is read with the `limits` of the connection it was received on.
     pub(crate) fn buffer(
         &self,
         bytes: Bytes,
         entity_mut: &mut BufferedEntity,
         tick: Tick,
         entity_map: &mut ReceiveEntityMap,
+        limits: &ReceiveLimits,
     ) -> Result<(), ComponentError> {
-        let mut reader = Reader::from(bytes);
+        let mut reader = limits.apply_to(Reader::from(bytes));
         let net_id = ComponentNetId::from_bytes(&mut reader)?;
         let kind = self
             .kind_map
@@ -107,10 +111,14 @@ impl ComponentRegistry {
             .serialize_fns_map
             .get(kind)
             .ok_or(ComponentError::MissingSerializationFns)?;
+        // components written with an older version are converted to the current version
+        let mut upgraded = erased_serialize_fns
+            .read_version(&mut reader)?
+            .map(|upgraded| limits.apply_to(upgraded));
         (replication_metadata.buffer)(
             replication_metadata,
             erased_serialize_fns,
-            &mut reader,
+            upgraded.as_mut().unwrap_or(&mut reader),
             tick,
             entity_mut,
             entity_map,
@@ -40,6 +40,7 @@ use lightyear_core::timeline::NetworkTimeline;
 use lightyear_messages::MessageNetId;
 use lightyear_messages::plugin::MessageSet;
 use lightyear_messages::prelude::TriggerSender;
+use lightyear_messages::protocol::RemoteVersions;
 use lightyear_messages::registry::{MessageKind, MessageRegistry};
 use lightyear_serde::ToBytes;
 use lightyear_serde::entity_map::{RemoteEntityMap, SendEntityMap};
@@ -762,7 +763,7 @@ impl ReplicationSender {
 
     /// Helper function to prepare component insert for components for which we know the type
     ///
-    /// Only use this for components where we don't need EntityMapping
+    /// Only use this for components where we don't need EntityMapping or versioning
     pub(crate) fn prepare_typed_component_insert<C: Component>(
         &mut self,
         entity: Entity,
@@ -770,7 +771,12 @@ impl ReplicationSender {
         component_registry: &ComponentRegistry,
         data: &C,
     ) -> Result<(), ComponentError> {
-        component_registry.serialize(data, &mut self.writer, &mut SendEntityMap::default())?;
+        component_registry.serialize(
+            data,
+            &mut self.writer,
+            &mut SendEntityMap::default(),
+            &RemoteVersions::default(),
+        )?;
         let raw_data = self.writer.split();
         self.prepare_component_insert(entity, group_id, raw_data);
         Ok(())
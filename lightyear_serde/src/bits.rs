@@ -0,0 +1,173 @@
+//! Bit-level writer and reader
+//!
+//! The [`Writer`](crate::writer::Writer) and [`Reader`](crate::reader::Reader) operate on whole bytes.
+//! When a type is made of several small values (booleans, quantized floats, enum discriminants, etc.)
+//! it is more compact to pack them at the bit level.
+//!
+//! A [`BitWriter`] wraps any byte writer and accumulates bits until a full byte can be written.
+//! When you are done writing, call [`BitWriter::finish`] to flush the last partial byte (padded with zeros).
+//! The [`BitReader`] reads the bits back in the same order; the padding bits are discarded when it is dropped.
+use crate::SerializationError;
+use crate::reader::ReadInteger;
+use crate::writer::WriteInteger;
+
+/// Number of bits needed to represent all the values in `0..=max`
+pub const fn bits_required(max: u32) -> u32 {
+    u32::BITS - max.leading_zeros()
+}
+
+/// Number of bytes used by a [`BitWriter`] after writing `bits` bits
+pub const fn bits_to_bytes(bits: u32) -> usize {
+    bits.div_ceil(8) as usize
+}
+
+/// Mask that keeps the `bits` lowest bits of a value
+#[inline(always)]
+const fn mask(bits: u32) -> u64 {
+    (1u64 << bits) - 1
+}
+
+/// Writes values bit by bit into an underlying byte writer.
+///
+/// Bits are written in most-significant-bit first order.
+pub struct BitWriter<'a, W: WriteInteger> {
+    writer: &'a mut W,
+    /// Bits that have not been written to the writer yet. Only the `scratch_bits` lowest bits are used.
+    scratch: u64,
+    scratch_bits: u32,
+}
+
+impl<'a, W: WriteInteger> BitWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            scratch: 0,
+            scratch_bits: 0,
+        }
+    }
+
+    /// Write the `bits` lowest bits of `value`.
+    ///
+    /// `bits` must be at most 32.
+    pub fn write_bits(&mut self, value: u32, bits: u32) -> Result<(), SerializationError> {
+        debug_assert!(bits <= 32, "cannot write more than 32 bits at once");
+        if bits == 0 {
+            return Ok(());
+        }
+        self.scratch = (self.scratch << bits) | (value as u64 & mask(bits));
+        self.scratch_bits += bits;
+        while self.scratch_bits >= 8 {
+            self.scratch_bits -= 8;
+            self.writer
+                .write_u8((self.scratch >> self.scratch_bits) as u8)?;
+        }
+        self.scratch &= mask(self.scratch_bits);
+        Ok(())
+    }
+
+    pub fn write_bool(&mut self, value: bool) -> Result<(), SerializationError> {
+        self.write_bits(value as u32, 1)
+    }
+
+    /// Flush the remaining bits to the underlying writer, padding the last byte with zeros.
+    pub fn finish(self) -> Result<(), SerializationError> {
+        if self.scratch_bits > 0 {
+            self.writer
+                .write_u8((self.scratch << (8 - self.scratch_bits)) as u8)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads values bit by bit from an underlying byte reader.
+///
+/// The bits must have been written by a [`BitWriter`].
+pub struct BitReader<'a, R: ReadInteger> {
+    reader: &'a mut R,
+    /// Bits that have been read from the reader but not consumed yet. Only the `scratch_bits` lowest bits are used.
+    scratch: u64,
+    scratch_bits: u32,
+}
+
+impl<'a, R: ReadInteger> BitReader<'a, R> {
+    pub fn new(reader: &'a mut R) -> Self {
+        Self {
+            reader,
+            scratch: 0,
+            scratch_bits: 0,
+        }
+    }
+
+    /// Read `bits` bits and return them as the lowest bits of a `u32`.
+    ///
+    /// `bits` must be at most 32.
+    pub fn read_bits(&mut self, bits: u32) -> Result<u32, SerializationError> {
+        debug_assert!(bits <= 32, "cannot read more than 32 bits at once");
+        while self.scratch_bits < bits {
+            self.scratch = (self.scratch << 8) | self.reader.read_u8()? as u64;
+            self.scratch_bits += 8;
+        }
+        self.scratch_bits -= bits;
+        let value = (self.scratch >> self.scratch_bits) & mask(bits);
+        self.scratch &= mask(self.scratch_bits);
+        Ok(value as u32)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SerializationError> {
+        Ok(self.read_bits(1)? != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+    use crate::writer::Writer;
+
+    #[test]
+    fn test_bits_required() {
+        assert_eq!(bits_required(0), 0);
+        assert_eq!(bits_required(1), 1);
+        assert_eq!(bits_required(7), 3);
+        assert_eq!(bits_required(8), 4);
+        assert_eq!(bits_required(u32::MAX), 32);
+    }
+
+    #[test]
+    fn test_write_read_bits() {
+        let mut writer = Writer::default();
+        let mut bit_writer = BitWriter::new(&mut writer);
+        bit_writer.write_bool(true).unwrap();
+        bit_writer.write_bits(5, 3).unwrap();
+        bit_writer.write_bits(u32::MAX, 32).unwrap();
+        bit_writer.write_bits(0x1234, 13).unwrap();
+        bit_writer.write_bool(false).unwrap();
+        bit_writer.finish().unwrap();
+        // 1 + 3 + 32 + 13 + 1 = 50 bits
+        assert_eq!(writer.len(), bits_to_bytes(50));
+
+        let mut reader = Reader::from(writer.to_bytes());
+        let mut bit_reader = BitReader::new(&mut reader);
+        assert!(bit_reader.read_bool().unwrap());
+        assert_eq!(bit_reader.read_bits(3).unwrap(), 5);
+        assert_eq!(bit_reader.read_bits(32).unwrap(), u32::MAX);
+        assert_eq!(bit_reader.read_bits(13).unwrap(), 0x1234);
+        assert!(!bit_reader.read_bool().unwrap());
+        assert!(!reader.has_remaining());
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let mut writer = Writer::default();
+        let mut bit_writer = BitWriter::new(&mut writer);
+        bit_writer.write_bits(3, 4).unwrap();
+        bit_writer.finish().unwrap();
+
+        let mut reader = Reader::from(writer.to_bytes());
+        let mut bit_reader = BitReader::new(&mut reader);
+        assert_eq!(bit_reader.read_bits(4).unwrap(), 3);
+        // the padding bits are still readable, but not past the last byte
+        assert_eq!(bit_reader.read_bits(4).unwrap(), 0);
+        assert!(bit_reader.read_bits(1).is_err());
+    }
+}
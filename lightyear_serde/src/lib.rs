@@ -5,37 +5,48 @@
 //! along with `Reader` and `Writer` utilities for handling byte streams.
 //!
 //! It includes implementations for common types and collections, and utilities for
-//! efficient serialization, such as varint encoding.
+//! efficient serialization, such as varint encoding, bit-packing and quantized math types.
 //!
 //! This crate is fundamental for preparing data to be sent over the network and for
 //! reconstructing data received from remote peers.
 #![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate alloc;
+// allows the `ToBytesInternal` derive to refer to this crate as `lightyear_serde`
+extern crate self as lightyear_serde;
 
 use crate::reader::{ReadInteger, ReadVarInt, Reader};
 use crate::varint::varint_len;
 #[cfg(not(feature = "std"))]
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 use bevy::platform::collections::HashMap;
 use bytes::Bytes;
 use core::hash::{BuildHasher, Hash};
 use no_std_io2::io;
 
+/// Provides the `BitWriter` and `BitReader` to pack values at the bit level.
+pub mod bits;
 /// Utilities for mapping entities during serialization and deserialization.
 pub mod entity_map;
+/// Quantized floats, vectors and rotations that are serialized with a fixed number of bits.
+pub mod quantize;
 /// Provides the `Reader` struct and traits for deserializing data from a byte stream.
 pub mod reader;
 /// Defines traits and structures for registering serializable types.
 pub mod registry;
 /// Utilities for variable-length integer encoding and decoding.
 pub mod varint;
+/// Versioning of the wire format of registered types.
+pub mod version;
 /// Provides the `Writer` struct and traits for serializing data into a byte stream.
 pub mod writer;
 
 /// Commonly used items from the `lightyear_serde` crate.
 pub mod prelude {
     pub use crate::SerializationError;
+    pub use crate::ToBytes;
+    pub use crate::quantize::{BoundedF32, QuantizedQuat, QuantizedVec2, QuantizedVec3};
+    pub use crate::version::VersionFns;
 }
 
 use crate::writer::WriteInteger;
@@ -54,6 +65,12 @@ pub enum SerializationError {
     InvalidValue,
     #[error("Substraction overflow")]
     SubstractionOverflow,
+    #[error("collection length {len} exceeds the maximum of {max}")]
+    CollectionTooLong { len: u64, max: usize },
+    #[error("decoded value exceeds the maximum of {max} bytes")]
+    DecodeTooLarge { max: usize },
+    #[error("version {0} of the type is not supported")]
+    UnsupportedVersion(u16),
     #[error(transparent)]
     BincodeEncode(#[from] bincode::error::EncodeError),
     #[error(transparent)]
@@ -68,6 +85,16 @@ pub enum SerializationError {
 /// - Deserialize the type from a buffer (`from_bytes`).
 ///
 /// It is implemented for various primitive types, collections, and `bytes::Bytes`.
+/// It can be derived with `#[derive(ToBytes)]`, see `lightyear_macros`.
+///
+/// `#[lightyear(varint)]` can only be used on unsigned integers:
+///
+/// ```rust,compile_fail
+/// use lightyear_macros::ToBytesInternal;
+///
+/// #[derive(ToBytesInternal)]
+/// struct Score(#[lightyear(varint)] i32);
+/// ```
 #[allow(clippy::len_without_is_empty)]
 pub trait ToBytes {
     fn bytes_len(&self) -> usize;
@@ -129,8 +156,7 @@ impl ToBytes for Bytes {
         Self: Sized,
     {
         let len = buffer.read_varint()? as usize;
-        let bytes = buffer.split_len(len);
-        Ok(bytes)
+        buffer.try_split_len(len)
     }
 }
 
@@ -170,6 +196,117 @@ impl ToBytes for u16 {
     }
 }
 
+impl ToBytes for bool {
+    fn bytes_len(&self) -> usize {
+        1
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        buffer.write_u8(*self as u8)?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        match buffer.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SerializationError::InvalidValue),
+        }
+    }
+}
+
+/// Integers and floats that are written with a fixed size, in network byte order.
+///
+/// Use a varint (for example with `#[lightyear(varint)]` when deriving `ToBytes`) for integers that are usually small.
+macro_rules! impl_fixed_size {
+    ($ty:ty, $len:expr, $write:ident, $read:ident) => {
+        impl ToBytes for $ty {
+            fn bytes_len(&self) -> usize {
+                $len
+            }
+
+            fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+                buffer.$write(*self)?;
+                Ok(())
+            }
+
+            fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+            where
+                Self: Sized,
+            {
+                Ok(buffer.$read()?)
+            }
+        }
+    };
+}
+
+impl_fixed_size!(u32, 4, write_u32, read_u32);
+impl_fixed_size!(u64, 8, write_u64, read_u64);
+impl_fixed_size!(i8, 1, write_i8, read_i8);
+impl_fixed_size!(i16, 2, write_i16, read_i16);
+impl_fixed_size!(i32, 4, write_i32, read_i32);
+impl_fixed_size!(i64, 8, write_i64, read_i64);
+
+impl ToBytes for f32 {
+    fn bytes_len(&self) -> usize {
+        4
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        buffer.write_u32(self.to_bits())?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        Ok(f32::from_bits(buffer.read_u32()?))
+    }
+}
+
+impl ToBytes for f64 {
+    fn bytes_len(&self) -> usize {
+        8
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        buffer.write_u64(self.to_bits())?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        Ok(f64::from_bits(buffer.read_u64()?))
+    }
+}
+
+impl ToBytes for String {
+    fn bytes_len(&self) -> usize {
+        varint_len(self.len() as u64) + self.len()
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        buffer.write_varint(self.len() as u64)?;
+        buffer.write_all(self.as_bytes())?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let len = buffer.read_varint()? as usize;
+        let bytes = buffer.try_split_len(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SerializationError::InvalidValue)
+    }
+}
+
 macro_rules! impl_tuple_query_data {
     ($($name: ident),*) => {
 
@@ -214,9 +351,8 @@ impl<M: ToBytes> ToBytes for Vec<M> {
     where
         Self: Sized,
     {
-        let len = buffer.read_varint()? as usize;
-        // TODO: if we know the MIN_LEN we can preallocate
-        let mut vec = Vec::with_capacity(len);
+        let len = buffer.read_collection_len()?;
+        let mut vec = Vec::with_capacity(Reader::preallocated_len::<M>(len));
         for _ in 0..len {
             vec.push(M::from_bytes(buffer)?);
         }
@@ -247,9 +383,11 @@ impl<K: ToBytes + Eq + Hash, V: ToBytes, S: Default + BuildHasher> ToBytes for H
     where
         Self: Sized,
     {
-        let len = buffer.read_varint()? as usize;
-        // TODO: if we know the MIN_LEN we can preallocate
-        let mut res = HashMap::with_capacity_and_hasher(len, S::default());
+        let len = buffer.read_collection_len()?;
+        let mut res = HashMap::with_capacity_and_hasher(
+            Reader::preallocated_len::<(K, V)>(len),
+            S::default(),
+        );
         for _ in 0..len {
             let key = K::from_bytes(buffer)?;
             let value = V::from_bytes(buffer)?;
@@ -264,8 +402,96 @@ mod tests {
     use super::*;
     use crate::writer::Writer;
     #[cfg(not(feature = "std"))]
-    use alloc::vec;
+    use alloc::{string::ToString, vec};
     use bevy::prelude::Entity;
+    use lightyear_macros::ToBytesInternal;
+
+    /// Write the value with [`ToBytes`] and read it back, checking that the `bytes_len` is correct
+    pub(crate) fn roundtrip<T: ToBytes>(value: &T) -> T {
+        let mut writer = Writer::default();
+        value.to_bytes(&mut writer).unwrap();
+        assert_eq!(writer.len(), value.bytes_len());
+        let mut reader = Reader::from(writer.to_bytes());
+        let read = T::from_bytes(&mut reader).unwrap();
+        assert!(!reader.has_remaining());
+        read
+    }
+
+    #[test]
+    fn test_serialize_primitives() {
+        assert!(roundtrip(&true));
+        assert_eq!(roundtrip(&u32::MAX), u32::MAX);
+        assert_eq!(roundtrip(&-7i64), -7);
+        assert_eq!(roundtrip(&1.5f32), 1.5);
+        assert_eq!(roundtrip(&-0.25f64), -0.25);
+        assert_eq!(roundtrip(&"hello".to_string()), "hello");
+    }
+
+    #[derive(ToBytesInternal, Debug, Default, PartialEq)]
+    struct DeriveStruct {
+        #[lightyear(varint)]
+        id: u64,
+        #[lightyear(quantize(min = -10.0, max = 10.0, bits = 12))]
+        x: f32,
+        #[lightyear(quantize(min = -10.0, max = 10.0, bits = 12))]
+        y: f32,
+        name: String,
+        #[lightyear(skip)]
+        cached: u32,
+    }
+
+    #[derive(ToBytesInternal, Debug, PartialEq)]
+    struct DeriveTuple<T>(T, #[lightyear(varint)] usize);
+
+    #[derive(ToBytesInternal, Debug, PartialEq)]
+    enum DeriveEnum {
+        Unit,
+        Tuple(u8, Option<u16>),
+        Named {
+            #[lightyear(quantize(min = 0, max = 1, bits = 8))]
+            ratio: f32,
+            inner: DeriveTuple<i32>,
+        },
+    }
+
+    #[test]
+    fn test_derive_struct() {
+        let value = DeriveStruct {
+            id: 3,
+            x: 2.5,
+            y: -10.0,
+            name: "a".to_string(),
+            cached: 10,
+        };
+        // 1 byte for the varint, 3 bytes for the 24 quantized bits, 2 bytes for the string
+        assert_eq!(value.bytes_len(), 6);
+        let read = roundtrip(&value);
+        assert_eq!(read.id, 3);
+        assert!((read.x - 2.5).abs() < 0.01);
+        assert_eq!(read.y, -10.0);
+        assert_eq!(read.name, "a");
+        // skipped fields are set to their default value
+        assert_eq!(read.cached, 0);
+
+        let value = DeriveTuple(u64::MAX, 1000);
+        assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_derive_enum() {
+        assert_eq!(roundtrip(&DeriveEnum::Unit), DeriveEnum::Unit);
+        let value = DeriveEnum::Tuple(1, Some(2));
+        assert_eq!(roundtrip(&value), value);
+        let value = DeriveEnum::Named {
+            ratio: 1.0,
+            inner: DeriveTuple(-1, 2),
+        };
+        assert_eq!(roundtrip(&value), value);
+
+        // unknown variant
+        let mut reader = Reader::from(vec![3]);
+        assert!(DeriveEnum::from_bytes(&mut reader).is_err());
+    }
 
     #[test]
     fn test_serialize_bytes() {
@@ -306,6 +532,60 @@ mod tests {
         assert_eq!(a, read);
     }
 
+    #[test]
+    fn test_collection_limits() {
+        let a = vec![1u8, 2, 3];
+        let mut writer = Writer::default();
+        a.to_bytes(&mut writer).unwrap();
+        let bytes = writer.to_bytes();
+
+        let mut reader = Reader::from(bytes.clone()).with_max_collection_len(2);
+        assert!(matches!(
+            Vec::<u8>::from_bytes(&mut reader),
+            Err(SerializationError::CollectionTooLong { len: 3, max: 2 })
+        ));
+        let mut reader = Reader::from(bytes).with_max_collection_len(3);
+        assert_eq!(Vec::<u8>::from_bytes(&mut reader).unwrap(), a);
+
+        // the remote claims a huge length, but doesn't send the elements
+        let mut writer = Writer::default();
+        writer.write_varint(1 << 40).unwrap();
+        let bytes = writer.to_bytes();
+        let mut reader = Reader::from(bytes.clone()).with_max_collection_len(usize::MAX);
+        assert!(Vec::<u64>::from_bytes(&mut reader).is_err());
+        let mut reader = Reader::from(bytes);
+        assert!(Bytes::from_bytes(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let fns = crate::registry::SerializeFns::<String>::default();
+        let a = "a".repeat(2000);
+        let mut writer = Writer::default();
+        (fns.serialize)(&a, &mut writer).unwrap();
+        let bytes = writer.to_bytes();
+
+        let mut reader = Reader::from(bytes.clone()).with_max_decode_size(1000);
+        assert!(matches!(
+            (fns.deserialize)(&mut reader),
+            Err(SerializationError::DecodeTooLarge { max: 1024 })
+        ));
+        let mut reader = Reader::from(bytes).with_max_decode_size(2000);
+        assert_eq!((fns.deserialize)(&mut reader).unwrap(), a);
+
+        // the remote claims a huge length, but doesn't send the bytes
+        let mut writer = Writer::default();
+        (fns.serialize)(&"a".repeat(100), &mut writer).unwrap();
+        let mut bytes = writer.to_bytes().to_vec();
+        // bincode encodes lengths above 2^32 as 0xFD followed by a u64
+        bytes.splice(0..1, [0xFD].into_iter().chain((1u64 << 40).to_le_bytes()));
+        let mut reader = Reader::from(bytes);
+        assert!(matches!(
+            (fns.deserialize)(&mut reader),
+            Err(SerializationError::DecodeTooLarge { .. })
+        ));
+    }
+
     #[test]
     fn test_serialize_entity() {
         let a = Entity::from_raw(0);
@@ -0,0 +1,410 @@
+//! Quantized math types
+//!
+//! Floats are usually sent with full precision, which is wasteful for values whose range and
+//! required precision are known in advance (positions inside a bounded map, rotations, etc.).
+//!
+//! The types in this module store a regular value but are serialized as fixed-size integers
+//! packed with a [`BitWriter`]. They implement [`ToBytes`] as well as [`Serialize`]/[`Deserialize`],
+//! so they can be used inside replicated components and messages. The value received by the remote
+//! peer is the quantized value, which can differ from the original by up to half of the quantization step.
+//!
+//! The bounds are const generics, so they have to be integers. `BITS` must be between 1 and 32,
+//! which is checked at compile time:
+//!
+//! ```rust,compile_fail
+//! use lightyear_serde::quantize::BoundedF32;
+//! use lightyear_serde::{ToBytes, writer::Writer};
+//!
+//! let mut writer = Writer::default();
+//! BoundedF32::<0, 1, 33>(0.5).to_bytes(&mut writer).unwrap();
+//! ```
+//!
+//! ```rust
+//! use lightyear_serde::quantize::QuantizedVec3;
+//! use serde::{Deserialize, Serialize};
+//!
+//! /// Position inside a map of 2000x2000x2000 units, with a precision of ~2mm
+//! #[derive(Serialize, Deserialize)]
+//! struct Position(QuantizedVec3<-1000, 1000, 20>);
+//! ```
+use crate::bits::{BitReader, BitWriter, bits_to_bytes};
+use crate::reader::Reader;
+use crate::writer::WriteInteger;
+use crate::{SerializationError, ToBytes};
+use bevy::math::{Quat, Vec2, Vec3, ops};
+use core::f32::consts::FRAC_1_SQRT_2;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Check that a quantized type uses between 1 and 32 bits.
+///
+/// It is called in a `const` block by every quantized type, so that an invalid `BITS` is a compile error.
+const fn check_bits(bits: u32) {
+    assert!(
+        bits > 0 && bits <= 32,
+        "quantized values must use between 1 and 32 bits"
+    );
+}
+
+/// Maximum integer value that can be stored with `bits` bits
+#[inline(always)]
+fn max_quantized(bits: u32) -> f32 {
+    ((1u64 << bits) - 1) as f32
+}
+
+/// Map `value` from `[min, max]` to an integer in `[0, 2^bits - 1]`.
+///
+/// Values outside of the range are clamped.
+pub fn quantize_f32(value: f32, min: f32, max: f32, bits: u32) -> u32 {
+    let normalized = (value.clamp(min, max) - min) / (max - min);
+    // the value is positive, so adding 0.5 before truncating rounds to the nearest integer
+    (normalized * max_quantized(bits) + 0.5) as u32
+}
+
+/// Map an integer produced by [`quantize_f32`] back to `[min, max]`
+pub fn dequantize_f32(quantized: u32, min: f32, max: f32, bits: u32) -> f32 {
+    min + (quantized as f32 / max_quantized(bits)) * (max - min)
+}
+
+/// Float in the range `[MIN, MAX]`, serialized with `BITS` bits
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BoundedF32<const MIN: i32, const MAX: i32, const BITS: u32>(pub f32);
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> BoundedF32<MIN, MAX, BITS> {
+    fn quantize(&self) -> u32 {
+        const { check_bits(BITS) };
+        quantize_f32(self.0, MIN as f32, MAX as f32, BITS)
+    }
+
+    fn dequantize(quantized: u32) -> Self {
+        const { check_bits(BITS) };
+        Self(dequantize_f32(quantized, MIN as f32, MAX as f32, BITS))
+    }
+}
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> ToBytes for BoundedF32<MIN, MAX, BITS> {
+    fn bytes_len(&self) -> usize {
+        bits_to_bytes(BITS)
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        let mut writer = BitWriter::new(buffer);
+        writer.write_bits(self.quantize(), BITS)?;
+        writer.finish()
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let mut reader = BitReader::new(buffer);
+        Ok(Self::dequantize(reader.read_bits(BITS)?))
+    }
+}
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> Serialize for BoundedF32<MIN, MAX, BITS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.quantize().serialize(serializer)
+    }
+}
+
+impl<'de, const MIN: i32, const MAX: i32, const BITS: u32> Deserialize<'de>
+    for BoundedF32<MIN, MAX, BITS>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Self::dequantize)
+    }
+}
+
+/// [`Vec2`] whose coordinates are in the range `[MIN, MAX]`, serialized with `BITS` bits per coordinate
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QuantizedVec2<const MIN: i32, const MAX: i32, const BITS: u32>(pub Vec2);
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> QuantizedVec2<MIN, MAX, BITS> {
+    fn quantize(&self) -> [u32; 2] {
+        const { check_bits(BITS) };
+        self.0
+            .to_array()
+            .map(|v| quantize_f32(v, MIN as f32, MAX as f32, BITS))
+    }
+
+    fn dequantize(quantized: [u32; 2]) -> Self {
+        const { check_bits(BITS) };
+        Self(Vec2::from_array(
+            quantized.map(|q| dequantize_f32(q, MIN as f32, MAX as f32, BITS)),
+        ))
+    }
+}
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> ToBytes for QuantizedVec2<MIN, MAX, BITS> {
+    fn bytes_len(&self) -> usize {
+        bits_to_bytes(2 * BITS)
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        let mut writer = BitWriter::new(buffer);
+        self.quantize()
+            .into_iter()
+            .try_for_each(|q| writer.write_bits(q, BITS))?;
+        writer.finish()
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let mut reader = BitReader::new(buffer);
+        Ok(Self::dequantize([
+            reader.read_bits(BITS)?,
+            reader.read_bits(BITS)?,
+        ]))
+    }
+}
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> Serialize for QuantizedVec2<MIN, MAX, BITS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.quantize().serialize(serializer)
+    }
+}
+
+impl<'de, const MIN: i32, const MAX: i32, const BITS: u32> Deserialize<'de>
+    for QuantizedVec2<MIN, MAX, BITS>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <[u32; 2]>::deserialize(deserializer).map(Self::dequantize)
+    }
+}
+
+/// [`Vec3`] whose coordinates are in the range `[MIN, MAX]`, serialized with `BITS` bits per coordinate
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QuantizedVec3<const MIN: i32, const MAX: i32, const BITS: u32>(pub Vec3);
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> QuantizedVec3<MIN, MAX, BITS> {
+    fn quantize(&self) -> [u32; 3] {
+        const { check_bits(BITS) };
+        self.0
+            .to_array()
+            .map(|v| quantize_f32(v, MIN as f32, MAX as f32, BITS))
+    }
+
+    fn dequantize(quantized: [u32; 3]) -> Self {
+        const { check_bits(BITS) };
+        Self(Vec3::from_array(
+            quantized.map(|q| dequantize_f32(q, MIN as f32, MAX as f32, BITS)),
+        ))
+    }
+}
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> ToBytes for QuantizedVec3<MIN, MAX, BITS> {
+    fn bytes_len(&self) -> usize {
+        bits_to_bytes(3 * BITS)
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        let mut writer = BitWriter::new(buffer);
+        self.quantize()
+            .into_iter()
+            .try_for_each(|q| writer.write_bits(q, BITS))?;
+        writer.finish()
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let mut reader = BitReader::new(buffer);
+        Ok(Self::dequantize([
+            reader.read_bits(BITS)?,
+            reader.read_bits(BITS)?,
+            reader.read_bits(BITS)?,
+        ]))
+    }
+}
+
+impl<const MIN: i32, const MAX: i32, const BITS: u32> Serialize for QuantizedVec3<MIN, MAX, BITS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.quantize().serialize(serializer)
+    }
+}
+
+impl<'de, const MIN: i32, const MAX: i32, const BITS: u32> Deserialize<'de>
+    for QuantizedVec3<MIN, MAX, BITS>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <[u32; 3]>::deserialize(deserializer).map(Self::dequantize)
+    }
+}
+
+/// Rotation serialized with the 'smallest three' encoding.
+///
+/// Since the quaternion is normalized, we only need to send the index of the component with the
+/// largest absolute value (2 bits) and the 3 other components, which are all in the range
+/// `[-1/sqrt(2), 1/sqrt(2)]`, with `BITS` bits each. The largest component is recomputed on the
+/// receiving side.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QuantizedQuat<const BITS: u32>(pub Quat);
+
+impl<const BITS: u32> QuantizedQuat<BITS> {
+    fn quantize(&self) -> (u8, [u32; 3]) {
+        const { check_bits(BITS) };
+        let components = self.0.normalize().to_array();
+        let (largest, _) = components
+            .iter()
+            .enumerate()
+            .fold((0, 0.0), |(index, max), (i, v)| {
+                if v.abs() > max {
+                    (i, v.abs())
+                } else {
+                    (index, max)
+                }
+            });
+        // q and -q represent the same rotation, so we can always make the largest component positive
+        let sign = if components[largest] < 0.0 { -1.0 } else { 1.0 };
+        let mut quantized = [0; 3];
+        components
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != largest)
+            .zip(quantized.iter_mut())
+            .for_each(|((_, v), q)| {
+                *q = quantize_f32(v * sign, -FRAC_1_SQRT_2, FRAC_1_SQRT_2, BITS);
+            });
+        (largest as u8, quantized)
+    }
+
+    fn dequantize((largest, quantized): (u8, [u32; 3])) -> Self {
+        const { check_bits(BITS) };
+        let smallest = quantized.map(|q| dequantize_f32(q, -FRAC_1_SQRT_2, FRAC_1_SQRT_2, BITS));
+        let sum_squares: f32 = smallest.iter().map(|v| v * v).sum();
+        let largest_value = ops::sqrt((1.0 - sum_squares).max(0.0));
+        let mut components = [0.0; 4];
+        let mut smallest = smallest.into_iter();
+        components.iter_mut().enumerate().for_each(|(i, v)| {
+            *v = if i == largest as usize {
+                largest_value
+            } else {
+                smallest.next().unwrap_or_default()
+            };
+        });
+        Self(Quat::from_array(components))
+    }
+}
+
+impl<const BITS: u32> ToBytes for QuantizedQuat<BITS> {
+    fn bytes_len(&self) -> usize {
+        bits_to_bytes(2 + 3 * BITS)
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        let (largest, quantized) = self.quantize();
+        let mut writer = BitWriter::new(buffer);
+        writer.write_bits(largest as u32, 2)?;
+        quantized
+            .into_iter()
+            .try_for_each(|q| writer.write_bits(q, BITS))?;
+        writer.finish()
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let mut reader = BitReader::new(buffer);
+        let largest = reader.read_bits(2)? as u8;
+        Ok(Self::dequantize((
+            largest,
+            [
+                reader.read_bits(BITS)?,
+                reader.read_bits(BITS)?,
+                reader.read_bits(BITS)?,
+            ],
+        )))
+    }
+}
+
+impl<const BITS: u32> Serialize for QuantizedQuat<BITS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.quantize().serialize(serializer)
+    }
+}
+
+impl<'de, const BITS: u32> Deserialize<'de> for QuantizedQuat<BITS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (largest, quantized) = <(u8, [u32; 3])>::deserialize(deserializer)?;
+        if largest > 3 {
+            return Err(serde::de::Error::custom(
+                "invalid quaternion component index",
+            ));
+        }
+        Ok(Self::dequantize((largest, quantized)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::roundtrip;
+
+    #[test]
+    fn test_quantize_f32() {
+        assert_eq!(quantize_f32(-10.0, -10.0, 10.0, 8), 0);
+        assert_eq!(quantize_f32(10.0, -10.0, 10.0, 8), 255);
+        // values are clamped
+        assert_eq!(quantize_f32(20.0, -10.0, 10.0, 8), 255);
+        assert_eq!(quantize_f32(-20.0, -10.0, 10.0, 8), 0);
+        assert_eq!(quantize_f32(5.0, 0.0, 10.0, 32), u32::MAX / 2 + 1);
+        assert_eq!(dequantize_f32(255, -10.0, 10.0, 8), 10.0);
+    }
+
+    #[test]
+    fn test_bounded_f32() {
+        let value = BoundedF32::<-100, 100, 16>(42.42);
+        let read = roundtrip(&value);
+        // the quantization step is 200 / (2^16 - 1)
+        assert!((read.0 - value.0).abs() < 0.002);
+        assert_eq!(value.bytes_len(), 2);
+    }
+
+    #[test]
+    fn test_quantized_vec() {
+        let value = QuantizedVec2::<-10, 10, 10>(Vec2::new(-3.3, 7.5));
+        let read = roundtrip(&value);
+        assert!(read.0.abs_diff_eq(value.0, 0.01));
+        assert_eq!(value.bytes_len(), 3);
+
+        let value = QuantizedVec3::<-1000, 1000, 20>(Vec3::new(-999.9, 0.001, 512.123));
+        let read = roundtrip(&value);
+        assert!(read.0.abs_diff_eq(value.0, 0.001));
+        assert_eq!(value.bytes_len(), 8);
+    }
+
+    #[test]
+    fn test_quantized_quat() {
+        for rotation in [
+            Quat::IDENTITY,
+            Quat::from_rotation_y(core::f32::consts::PI),
+            Quat::from_euler(bevy::math::EulerRot::XYZ, 0.3, -2.1, 1.4),
+            -Quat::from_rotation_z(0.7),
+        ] {
+            let value = QuantizedQuat::<10>(rotation);
+            let read = roundtrip(&value);
+            // the quaternion can be negated, which represents the same rotation
+            assert!(read.0.angle_between(rotation) < 0.01);
+            assert_eq!(value.bytes_len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let value = QuantizedQuat::<12>(Quat::from_rotation_x(1.0));
+        let bytes = bincode::serde::encode_to_vec(value, bincode::config::standard()).unwrap();
+        let (read, _): (QuantizedQuat<12>, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert!(read.0.angle_between(value.0) < 0.01);
+
+        let value = QuantizedVec3::<-10, 10, 16>(Vec3::new(1.0, 2.0, 3.0));
+        let bytes = bincode::serde::encode_to_vec(value, bincode::config::standard()).unwrap();
+        let (read, _): (QuantizedVec3<-10, 10, 16>, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert!(read.0.abs_diff_eq(value.0, 0.001));
+    }
+}
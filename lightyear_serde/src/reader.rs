@@ -1,60 +1,131 @@
 use crate::SerializationError;
 use crate::varint::varint_parse_len;
 use bytes::Bytes;
-use no_std_io2::io::{Cursor, Error, Read, Result, Seek, SeekFrom};
+use no_std_io2::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
 
 #[cfg(not(feature = "std"))]
 pub use no_std::Reader;
 #[cfg(feature = "std")]
 pub use std::Reader;
 
+/// Default maximum number of elements of a collection (`Vec`, `HashMap`, etc.) that can be read from a [`Reader`]
+pub const DEFAULT_MAX_COLLECTION_LEN: usize = 1 << 20;
+
+/// Default maximum number of bytes that bincode can claim when deserializing a value from a [`Reader`]
+pub const DEFAULT_MAX_DECODE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Maximum number of bytes that are preallocated when reading a collection.
+///
+/// The length of a collection is sent by the remote peer, so we don't trust it to preallocate memory.
+const MAX_PREALLOCATED_BYTES: usize = 1 << 20;
+
+impl Reader {
+    /// Read the length of a collection, and check that it doesn't exceed the maximum collection length
+    pub fn read_collection_len(&mut self) -> core::result::Result<usize, SerializationError> {
+        let len = self.read_varint()?;
+        if len > self.max_collection_len() as u64 {
+            return Err(SerializationError::CollectionTooLong {
+                len,
+                max: self.max_collection_len(),
+            });
+        }
+        Ok(len as usize)
+    }
+
+    /// Number of elements of type `T` that can be safely preallocated for a collection of length `len`
+    pub fn preallocated_len<T>(len: usize) -> usize {
+        len.min(MAX_PREALLOCATED_BYTES / core::mem::size_of::<T>().max(1))
+    }
+
+    /// Split of the next `len` bytes from the reader into a separate Bytes,
+    /// returning an error if the reader doesn't have enough bytes remaining.
+    pub fn try_split_len(&mut self, len: usize) -> core::result::Result<Bytes, SerializationError> {
+        if len > self.remaining() {
+            return Err(Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        Ok(self.split_len(len))
+    }
+}
+
 #[cfg(feature = "std")]
 pub(crate) mod std {
     use super::*;
     use bytes::Buf;
 
     #[derive(Clone)]
-    pub struct Reader(Cursor<Bytes>);
+    pub struct Reader {
+        cursor: Cursor<Bytes>,
+        max_collection_len: usize,
+        max_decode_size: usize,
+    }
 
     impl From<Bytes> for Reader {
         fn from(value: Bytes) -> Self {
             // TODO: check that this has no cost
-            Self(Cursor::new(value))
+            Self::new(Cursor::new(value))
         }
     }
 
     impl From<Vec<u8>> for Reader {
         fn from(value: Vec<u8>) -> Self {
-            Self(Cursor::new(value.into()))
+            Self::new(Cursor::new(value.into()))
         }
     }
 
     impl Seek for Reader {
         fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-            self.0.seek(pos)
+            self.cursor.seek(pos)
         }
     }
 
     impl Read for Reader {
         fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-            self.0.read(buf)
+            self.cursor.read(buf)
         }
     }
 
     impl AsRef<[u8]> for Reader {
         fn as_ref(&self) -> &[u8] {
-            self.0.get_ref().as_ref()
+            self.cursor.get_ref().as_ref()
         }
     }
 
     impl Reader {
+        fn new(cursor: Cursor<Bytes>) -> Self {
+            Self {
+                cursor,
+                max_collection_len: DEFAULT_MAX_COLLECTION_LEN,
+                max_decode_size: DEFAULT_MAX_DECODE_SIZE,
+            }
+        }
+
+        /// Set the maximum number of elements of a collection that can be read from this reader.
+        pub fn with_max_collection_len(mut self, max_collection_len: usize) -> Self {
+            self.max_collection_len = max_collection_len;
+            self
+        }
+
+        pub fn max_collection_len(&self) -> usize {
+            self.max_collection_len
+        }
+
+        /// Set the maximum number of bytes that bincode can claim when deserializing a value from this reader.
+        pub fn with_max_decode_size(mut self, max_decode_size: usize) -> Self {
+            self.max_decode_size = max_decode_size;
+            self
+        }
+
+        pub fn max_decode_size(&self) -> usize {
+            self.max_decode_size
+        }
+
         /// Returns the underlying RawData
         pub fn consume(self) -> Bytes {
-            self.0.into_inner()
+            self.cursor.into_inner()
         }
 
         pub fn len(&self) -> usize {
-            self.0.get_ref().len()
+            self.cursor.get_ref().len()
         }
 
         pub fn is_empty(&self) -> bool {
@@ -65,12 +136,12 @@ pub(crate) mod std {
         ///
         /// This doesn't allocate and just increases some reference counts. O(1) cost.
         pub fn split_len(&mut self, len: usize) -> Bytes {
-            let current_pos = self.0.position() as usize;
+            let current_pos = self.cursor.position() as usize;
             let new_pos = current_pos + len;
             // slice off the subset into a separate Bytes
-            let bytes = self.0.get_ref().slice(current_pos..new_pos);
+            let bytes = self.cursor.get_ref().slice(current_pos..new_pos);
             // increment the position
-            self.0.set_position(new_pos as u64);
+            self.cursor.set_position(new_pos as u64);
             bytes
         }
 
@@ -79,15 +150,15 @@ pub(crate) mod std {
         }
 
         pub fn position(&self) -> u64 {
-            self.0.position()
+            self.cursor.position()
         }
 
         pub fn set_position(&mut self, pos: u64) {
-            self.0.set_position(pos)
+            self.cursor.set_position(pos)
         }
 
         pub fn remaining(&self) -> usize {
-            self.0.remaining()
+            self.cursor.remaining()
         }
     }
 }
@@ -99,7 +170,11 @@ pub(crate) mod no_std {
     use bincode::error::DecodeError;
 
     #[derive(Clone)]
-    pub struct Reader(Cursor<Bytes>);
+    pub struct Reader {
+        cursor: Cursor<Bytes>,
+        max_collection_len: usize,
+        max_decode_size: usize,
+    }
 
     #[inline(always)]
     fn saturating_sub_usize_u64(a: usize, b: u64) -> usize {
@@ -113,42 +188,70 @@ pub(crate) mod no_std {
     impl From<Bytes> for Reader {
         fn from(value: Bytes) -> Self {
             // TODO: check that this has no cost
-            Self(Cursor::new(value))
+            Self::new(Cursor::new(value))
         }
     }
 
     impl From<Vec<u8>> for Reader {
         fn from(value: Vec<u8>) -> Self {
-            Self(Cursor::new(value.into()))
+            Self::new(Cursor::new(value.into()))
         }
     }
 
     impl Seek for Reader {
         fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-            self.0.seek(pos)
+            self.cursor.seek(pos)
         }
     }
 
     impl Read for Reader {
         fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-            self.0.read(buf)
+            self.cursor.read(buf)
         }
     }
 
     impl AsRef<[u8]> for Reader {
         fn as_ref(&self) -> &[u8] {
-            self.0.get_ref().as_ref()
+            self.cursor.get_ref().as_ref()
         }
     }
 
     impl Reader {
+        fn new(cursor: Cursor<Bytes>) -> Self {
+            Self {
+                cursor,
+                max_collection_len: DEFAULT_MAX_COLLECTION_LEN,
+                max_decode_size: DEFAULT_MAX_DECODE_SIZE,
+            }
+        }
+
+        /// Set the maximum number of elements of a collection that can be read from this reader.
+        pub fn with_max_collection_len(mut self, max_collection_len: usize) -> Self {
+            self.max_collection_len = max_collection_len;
+            self
+        }
+
+        pub fn max_collection_len(&self) -> usize {
+            self.max_collection_len
+        }
+
+        /// Set the maximum number of bytes that bincode can claim when deserializing a value from this reader.
+        pub fn with_max_decode_size(mut self, max_decode_size: usize) -> Self {
+            self.max_decode_size = max_decode_size;
+            self
+        }
+
+        pub fn max_decode_size(&self) -> usize {
+            self.max_decode_size
+        }
+
         /// Returns the underlying RawData
         pub fn consume(self) -> Bytes {
-            self.0.into_inner()
+            self.cursor.into_inner()
         }
 
         pub fn len(&self) -> usize {
-            self.0.get_ref().len()
+            self.cursor.get_ref().len()
         }
 
         pub fn is_empty(&self) -> bool {
@@ -159,12 +262,12 @@ pub(crate) mod no_std {
         ///
         /// This doesn't allocate and just increases some reference counts. O(1) cost.
         pub fn split_len(&mut self, len: usize) -> Bytes {
-            let current_pos = self.0.position() as usize;
+            let current_pos = self.cursor.position() as usize;
             let new_pos = current_pos + len;
             // slice off the subset into a separate Bytes
-            let bytes = self.0.get_ref().slice(current_pos..new_pos);
+            let bytes = self.cursor.get_ref().slice(current_pos..new_pos);
             // increment the position
-            self.0.set_position(new_pos as u64);
+            self.cursor.set_position(new_pos as u64);
             bytes
         }
 
@@ -173,11 +276,11 @@ pub(crate) mod no_std {
         }
 
         pub fn position(&self) -> u64 {
-            self.0.position()
+            self.cursor.position()
         }
 
         pub fn set_position(&mut self, pos: u64) {
-            self.0.set_position(pos)
+            self.cursor.set_position(pos)
         }
 
         pub fn remaining(&self) -> usize {
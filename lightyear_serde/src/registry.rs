@@ -1,12 +1,13 @@
 use crate::entity_map::{EntityMap, ReceiveEntityMap, SendEntityMap};
 use crate::reader::Reader;
+use crate::version::ErasedVersionFns;
 use crate::writer::Writer;
 use crate::{SerializationError, ToBytes};
 use bevy::ecs::entity::MapEntities;
 use bevy::ptr::{Ptr, PtrMut};
 use core::any::TypeId;
-use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
 
 // TODO: this should be in lightyear_serde? it's not strictly related to messages?
 /// Stores function pointers related to serialization and deserialization
@@ -22,6 +23,8 @@ pub struct ErasedSerializeFns {
     pub context_deserialize: unsafe fn(),
     pub erased_clone: Option<unsafe fn()>,
     pub map_entities: Option<ErasedMapEntitiesFn>,
+    /// Set if the wire format of the type is versioned
+    pub version: Option<ErasedVersionFns>,
 }
 
 pub struct ContextSerializeFns<C, M, I = M> {
@@ -186,18 +189,50 @@ fn default_serialize<M: Serialize>(
     Ok(())
 }
 
-#[cfg(feature = "std")]
 /// Default deserialize function using bincode
+///
+/// The number of bytes that bincode can claim (for example to preallocate a `Vec` or a `String`) is bounded
+/// by [`Reader::max_decode_size`], rounded up to the next power of 4. Sizes above 1 GiB are not bounded.
 fn default_deserialize<M: DeserializeOwned>(buffer: &mut Reader) -> Result<M, SerializationError> {
-    let data = bincode::serde::decode_from_std_read(buffer, bincode::config::standard())?;
-    Ok(data)
+    // the bincode limit is a const generic, so we dispatch the runtime limit to a fixed set of limits
+    macro_rules! decode_with_limit {
+        ($($exp:literal),*) => {
+            $(
+                if buffer.max_decode_size() <= 1 << $exp {
+                    return decode_with_limit::<M, { 1 << $exp }>(buffer);
+                }
+            )*
+        };
+    }
+    decode_with_limit!(10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30);
+    Ok(decode(buffer, bincode::config::standard())?)
+}
+
+fn decode_with_limit<M: DeserializeOwned, const LIMIT: usize>(
+    buffer: &mut Reader,
+) -> Result<M, SerializationError> {
+    decode(buffer, bincode::config::standard().with_limit::<LIMIT>()).map_err(|e| match e {
+        bincode::error::DecodeError::LimitExceeded => {
+            SerializationError::DecodeTooLarge { max: LIMIT }
+        }
+        e => e.into(),
+    })
+}
+
+#[cfg(feature = "std")]
+fn decode<M: DeserializeOwned, C: bincode::config::Config>(
+    buffer: &mut Reader,
+    config: C,
+) -> Result<M, bincode::error::DecodeError> {
+    bincode::serde::decode_from_std_read(buffer, config)
 }
 
 #[cfg(not(feature = "std"))]
-/// Default deserialize function using bincode
-fn default_deserialize<M: DeserializeOwned>(buffer: &mut Reader) -> Result<M, SerializationError> {
-    let data = bincode::serde::decode_from_reader(buffer, bincode::config::standard())?;
-    Ok(data)
+fn decode<M: DeserializeOwned, C: bincode::config::Config>(
+    buffer: &mut Reader,
+    config: C,
+) -> Result<M, bincode::error::DecodeError> {
+    bincode::serde::decode_from_reader(buffer, config)
 }
 
 fn erased_clone<M: Clone>(message: &M) -> M {
@@ -262,6 +297,7 @@ impl ErasedSerializeFns {
             context_deserialize: unsafe { core::mem::transmute(deserialize.context_deserialize) },
             erased_clone: None,
             map_entities: None,
+            version: None,
         }
     }
 
@@ -318,7 +354,7 @@ impl ErasedSerializeFns {
             unsafe { core::mem::transmute(self.context_deserialize) };
         context_deserialize(context, reader, deserialize)
     }
-    
+
     /// Get the deserialize functions for the type M.
     ///
     /// # Safety
@@ -329,7 +365,7 @@ impl ErasedSerializeFns {
             unsafe { core::mem::transmute(self.context_deserialize) };
         ContextDeserializeFns {
             deserialize,
-            context_deserialize
+            context_deserialize,
         }
     }
 }
@@ -0,0 +1,411 @@
+//! Versioning of the wire format of a type
+//!
+//! By default, changing the fields of a registered type breaks the compatibility with peers that
+//! were built with the previous definition. To support rolling updates, a type can be registered with
+//! [`VersionFns`]:
+//! - the value is prefixed with the version that was used to serialize it;
+//! - when receiving a value written with an older version, it is converted with the [`UpgradeFn`];
+//! - when sending to a peer that uses an older version, it is written with the [`DowngradeFn`].
+//!
+//! Upgrades and downgrades go through the current wire format, so they are compatible with custom
+//! serialization functions and entity mapping.
+//!
+//! A peer can only exchange values with a peer that uses an older version of the type if it can
+//! both upgrade and downgrade the values.
+//!
+//! A type must already be versioned in the builds that need to stay compatible: a type that was
+//! registered without a version is not prefixed with a version on the wire.
+//!
+//! Only messages and components can be versioned. Triggers are always written with their current
+//! wire format, so changing a trigger breaks the compatibility with the previous builds.
+use core::cmp::Ordering;
+
+use crate::SerializationError;
+use crate::ToBytes;
+use crate::reader::Reader;
+use crate::registry::{DeserializeFn, ErasedSerializeFns, SerializeFn};
+use crate::writer::Writer;
+use bytes::Bytes;
+
+/// Function that deserializes a value that was serialized with an older `version` of the type
+pub type UpgradeFn<M> = fn(version: u16, reader: &mut Reader) -> Result<M, SerializationError>;
+
+/// Function that serializes a value with an older `version` of the type
+pub type DowngradeFn<M> =
+    fn(message: &M, version: u16, writer: &mut Writer) -> Result<(), SerializationError>;
+
+/// Describes the versions of the wire format of a type `M`
+pub struct VersionFns<M> {
+    /// Version of the current wire format of the type
+    pub version: u16,
+    /// Oldest version that can still be exchanged with a remote peer
+    pub min_version: u16,
+    /// Called to read the values serialized with a version in `min_version..version`
+    pub upgrade: Option<UpgradeFn<M>>,
+    /// Called to write values for peers that use a version in `min_version..version`
+    pub downgrade: Option<DowngradeFn<M>>,
+}
+
+impl<M> VersionFns<M> {
+    /// The type is at `version`, and only that version is supported
+    pub fn new(version: u16) -> Self {
+        Self {
+            version,
+            min_version: version,
+            upgrade: None,
+            downgrade: None,
+        }
+    }
+
+    /// Support reading the versions from `min_version` onwards with the `upgrade` function
+    pub fn with_upgrade(mut self, min_version: u16, upgrade: UpgradeFn<M>) -> Self {
+        self.min_version = min_version.min(self.version);
+        self.upgrade = Some(upgrade);
+        self
+    }
+
+    /// Support writing the older versions with the `downgrade` function, so that peers that are
+    /// still using an older version can read the values we send.
+    pub fn with_downgrade(mut self, downgrade: DowngradeFn<M>) -> Self {
+        self.downgrade = Some(downgrade);
+        self
+    }
+}
+
+/// Converts a value serialized with an older version to the current wire format
+type TranscodeUpgradeFn = unsafe fn(
+    fns: &ErasedSerializeFns,
+    version: u16,
+    reader: &mut Reader,
+    writer: &mut Writer,
+) -> Result<(), SerializationError>;
+
+/// Converts a value serialized with the current wire format to an older version
+type TranscodeDowngradeFn = unsafe fn(
+    fns: &ErasedSerializeFns,
+    version: u16,
+    bytes: Bytes,
+    writer: &mut Writer,
+) -> Result<(), SerializationError>;
+
+/// Type-erased [`VersionFns`], stored in the [`ErasedSerializeFns`]
+#[derive(Clone, Debug)]
+pub struct ErasedVersionFns {
+    pub version: u16,
+    pub min_version: u16,
+    upgrade: Option<unsafe fn()>,
+    downgrade: Option<unsafe fn()>,
+    transcode_upgrade: TranscodeUpgradeFn,
+    transcode_downgrade: TranscodeDowngradeFn,
+}
+
+impl ErasedVersionFns {
+    fn new<M: 'static>(fns: VersionFns<M>) -> Self {
+        Self {
+            version: fns.version,
+            min_version: fns.min_version,
+            upgrade: fns
+                .upgrade
+                .map(|f| unsafe { core::mem::transmute::<UpgradeFn<M>, unsafe fn()>(f) }),
+            downgrade: fns
+                .downgrade
+                .map(|f| unsafe { core::mem::transmute::<DowngradeFn<M>, unsafe fn()>(f) }),
+            transcode_upgrade: transcode_upgrade::<M>,
+            transcode_downgrade: transcode_downgrade::<M>,
+        }
+    }
+
+    /// Oldest version used by a remote peer that we can exchange values with.
+    ///
+    /// Reading the values of an older peer requires the [`UpgradeFn`], and writing values that it
+    /// can read requires the [`DowngradeFn`].
+    pub fn min_compatible_version(&self) -> u16 {
+        if self.upgrade.is_some() && self.downgrade.is_some() {
+            self.min_version
+        } else {
+            self.version
+        }
+    }
+
+    /// Returns true if we can exchange values with a peer that uses `remote_version`, and that can
+    /// exchange values with peers that use a version from `remote_min_compatible_version` onwards
+    pub fn is_compatible(&self, remote_version: u16, remote_min_compatible_version: u16) -> bool {
+        match remote_version.cmp(&self.version) {
+            Ordering::Equal => true,
+            // we convert the values from and to the version of the older remote peer
+            Ordering::Less => remote_version >= self.min_compatible_version(),
+            // the more recent remote peer converts the values from and to our version
+            Ordering::Greater => self.version >= remote_min_compatible_version,
+        }
+    }
+
+    /// Version that should be used to write values for a peer that uses `remote_version`.
+    ///
+    /// If the remote version is not known yet, we use the oldest version we can write.
+    fn write_version(&self, remote_version: Option<u16>) -> u16 {
+        if self.downgrade.is_none() {
+            return self.version;
+        }
+        remote_version
+            .unwrap_or(self.min_version)
+            .clamp(self.min_version, self.version)
+    }
+}
+
+// NOTE: function pointers cannot be compared reliably, so we only compare the versions
+impl PartialEq for ErasedVersionFns {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.min_version == other.min_version
+            && self.upgrade.is_some() == other.upgrade.is_some()
+            && self.downgrade.is_some() == other.downgrade.is_some()
+    }
+}
+
+/// SAFETY: the ErasedSerializeFns must have been created for the type M
+unsafe fn transcode_upgrade<M: 'static>(
+    fns: &ErasedSerializeFns,
+    version: u16,
+    reader: &mut Reader,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    let upgrade = fns
+        .version
+        .as_ref()
+        .and_then(|v| v.upgrade)
+        .ok_or(SerializationError::UnsupportedVersion(version))?;
+    let upgrade: UpgradeFn<M> = unsafe { core::mem::transmute(upgrade) };
+    let serialize: SerializeFn<M> = unsafe { core::mem::transmute(fns.serialize) };
+    let message = upgrade(version, reader)?;
+    serialize(&message, writer)
+}
+
+/// SAFETY: the ErasedSerializeFns must have been created for the type M
+unsafe fn transcode_downgrade<M: 'static>(
+    fns: &ErasedSerializeFns,
+    version: u16,
+    bytes: Bytes,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    let downgrade = fns
+        .version
+        .as_ref()
+        .and_then(|v| v.downgrade)
+        .ok_or(SerializationError::UnsupportedVersion(version))?;
+    let downgrade: DowngradeFn<M> = unsafe { core::mem::transmute(downgrade) };
+    let deserialize: DeserializeFn<M> = unsafe { core::mem::transmute(fns.deserialize) };
+    let message = deserialize(&mut Reader::from(bytes))?;
+    downgrade(&message, version, writer)
+}
+
+impl ErasedSerializeFns {
+    /// Version the wire format of the type. `M` is the type that is passed to the [`SerializeFn`].
+    ///
+    /// # Safety
+    /// the ErasedSerializeFns must be created for the type M
+    pub unsafe fn set_version<M: 'static>(&mut self, version_fns: VersionFns<M>) {
+        self.version = Some(ErasedVersionFns::new(version_fns));
+    }
+
+    /// Write the value with the version used by the remote peer.
+    ///
+    /// `serialize` writes the value with the current wire format.
+    /// If the type is not versioned, this just calls `serialize`.
+    pub fn serialize_versioned(
+        &self,
+        writer: &mut Writer,
+        remote_version: Option<u16>,
+        serialize: impl FnOnce(&mut Writer) -> Result<(), SerializationError>,
+    ) -> Result<(), SerializationError> {
+        let Some(version_fns) = &self.version else {
+            return serialize(writer);
+        };
+        let version = version_fns.write_version(remote_version);
+        version.to_bytes(writer)?;
+        if version == version_fns.version {
+            return serialize(writer);
+        }
+        let mut current = Writer::default();
+        serialize(&mut current)?;
+        // SAFETY: the transcode function was created for the same type as the ErasedSerializeFns
+        unsafe { (version_fns.transcode_downgrade)(self, version, current.split(), writer) }
+    }
+
+    /// Read the version of a value that was written with [`serialize_versioned`](Self::serialize_versioned).
+    ///
+    /// If the value was written with an older version, it is upgraded and the returned [`Reader`]
+    /// contains the value in the current wire format; it should be used instead of `reader` to
+    /// deserialize the value.
+    pub fn read_version(&self, reader: &mut Reader) -> Result<Option<Reader>, SerializationError> {
+        let Some(version_fns) = &self.version else {
+            return Ok(None);
+        };
+        let version = u16::from_bytes(reader)?;
+        if version == version_fns.version {
+            return Ok(None);
+        }
+        if version > version_fns.version || version < version_fns.min_version {
+            return Err(SerializationError::UnsupportedVersion(version));
+        }
+        let mut current = Writer::default();
+        // SAFETY: the transcode function was created for the same type as the ErasedSerializeFns
+        unsafe { (version_fns.transcode_upgrade)(self, version, reader, &mut current)? };
+        Ok(Some(Reader::from(current.split())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{ContextDeserializeFns, ContextSerializeFns, SerializeFns};
+    use serde::{Deserialize, Serialize};
+
+    /// Version 1 of the type
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct PositionV1 {
+        x: f32,
+    }
+
+    /// Version 2 of the type, with an additional field
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    fn upgrade(version: u16, reader: &mut Reader) -> Result<Position, SerializationError> {
+        match version {
+            1 => {
+                let v1: PositionV1 = (SerializeFns::<PositionV1>::default().deserialize)(reader)?;
+                Ok(Position { x: v1.x, y: 0.0 })
+            }
+            _ => Err(SerializationError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn downgrade(
+        position: &Position,
+        version: u16,
+        writer: &mut Writer,
+    ) -> Result<(), SerializationError> {
+        match version {
+            1 => (SerializeFns::<PositionV1>::default().serialize)(
+                &PositionV1 { x: position.x },
+                writer,
+            ),
+            _ => Err(SerializationError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn erased_fns<M: Serialize + serde::de::DeserializeOwned + 'static>() -> ErasedSerializeFns {
+        let fns = SerializeFns::<M>::default();
+        ErasedSerializeFns::new::<(), (), M, M>(
+            ContextSerializeFns::new(fns.serialize),
+            ContextDeserializeFns::new(fns.deserialize),
+        )
+    }
+
+    fn write<M: 'static>(
+        fns: &ErasedSerializeFns,
+        message: &M,
+        remote_version: Option<u16>,
+    ) -> Bytes {
+        let mut writer = Writer::default();
+        fns.serialize_versioned(&mut writer, remote_version, |writer| unsafe {
+            fns.serialize::<(), M, M>(message, writer, &mut ())
+        })
+        .unwrap();
+        writer.split()
+    }
+
+    fn read<M: 'static>(fns: &ErasedSerializeFns, bytes: Bytes) -> Result<M, SerializationError> {
+        let mut reader = Reader::from(bytes);
+        let mut upgraded = fns.read_version(&mut reader)?;
+        let reader = upgraded.as_mut().unwrap_or(&mut reader);
+        unsafe { fns.deserialize::<(), M, M>(reader, &mut ()) }
+    }
+
+    #[test]
+    fn test_unversioned() {
+        let fns = erased_fns::<Position>();
+        let position = Position { x: 1.0, y: 2.0 };
+        let bytes = write(&fns, &position, Some(3));
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(read::<Position>(&fns, bytes).unwrap(), position);
+    }
+
+    #[test]
+    fn test_upgrade_downgrade() {
+        // peer that is still using the version 1 of the type
+        let mut old_fns = erased_fns::<PositionV1>();
+        unsafe { old_fns.set_version(VersionFns::<PositionV1>::new(1)) };
+        // peer that uses the version 2 of the type
+        let mut new_fns = erased_fns::<Position>();
+        unsafe {
+            new_fns.set_version(
+                VersionFns::<Position>::new(2)
+                    .with_upgrade(1, upgrade)
+                    .with_downgrade(downgrade),
+            )
+        };
+        let position = Position { x: 1.0, y: 2.0 };
+
+        // old -> new: the value is upgraded
+        let bytes = write(&old_fns, &PositionV1 { x: 1.0 }, Some(2));
+        assert_eq!(
+            read::<Position>(&new_fns, bytes).unwrap(),
+            Position { x: 1.0, y: 0.0 }
+        );
+
+        // new -> old: the value is downgraded
+        let bytes = write(&new_fns, &position, Some(1));
+        assert_eq!(
+            read::<PositionV1>(&old_fns, bytes).unwrap(),
+            PositionV1 { x: 1.0 }
+        );
+
+        // new -> new: the current version is used
+        let bytes = write(&new_fns, &position, Some(2));
+        assert_eq!(read::<Position>(&new_fns, bytes).unwrap(), position);
+
+        // the remote version is unknown: we use the oldest version
+        let bytes = write(&new_fns, &position, None);
+        assert!(read::<PositionV1>(&old_fns, bytes.clone()).is_ok());
+        assert!(read::<Position>(&new_fns, bytes).is_ok());
+
+        // the old peer cannot read the version 2
+        let mut writer = Writer::default();
+        new_fns
+            .serialize_versioned(&mut writer, Some(2), |writer| unsafe {
+                new_fns.serialize::<(), Position, Position>(&position, writer, &mut ())
+            })
+            .unwrap();
+        assert!(matches!(
+            read::<PositionV1>(&old_fns, writer.split()),
+            Err(SerializationError::UnsupportedVersion(2))
+        ));
+        let new_version = new_fns.version.as_ref().unwrap();
+        let old_version = old_fns.version.as_ref().unwrap();
+        assert_eq!(new_version.min_compatible_version(), 1);
+        assert!(new_version.is_compatible(1, 1));
+        assert!(old_version.is_compatible(2, new_version.min_compatible_version()));
+        assert!(!old_version.is_compatible(0, 0));
+    }
+
+    /// A peer that can only upgrade the values cannot exchange values with an older peer
+    #[test]
+    fn test_upgrade_only() {
+        let mut old_fns = erased_fns::<PositionV1>();
+        unsafe { old_fns.set_version(VersionFns::<PositionV1>::new(1)) };
+        let mut new_fns = erased_fns::<Position>();
+        unsafe { new_fns.set_version(VersionFns::<Position>::new(2).with_upgrade(1, upgrade)) };
+        let new_version = new_fns.version.as_ref().unwrap();
+        let old_version = old_fns.version.as_ref().unwrap();
+
+        // the older peer would not be able to read the values we send
+        assert_eq!(new_version.min_compatible_version(), 2);
+        assert!(!new_version.is_compatible(1, 1));
+        // the older peer doesn't accept the newer peer either
+        assert!(!old_version.is_compatible(2, new_version.min_compatible_version()));
+    }
+}
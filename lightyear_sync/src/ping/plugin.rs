@@ -32,6 +32,7 @@ impl PingPlugin {
         mut query: Query<
             (
                 &mut Link,
+                &Transport,
                 &mut PingManager,
                 &mut MessageReceiver<Ping>,
                 &mut MessageReceiver<Pong>,
@@ -39,25 +40,32 @@ impl PingPlugin {
             With<Connected>,
         >,
     ) {
-        query
-            .par_iter_mut()
-            .for_each(|(mut link, mut m, mut ping_receiver, mut pong_receiver)| {
+        query.par_iter_mut().for_each(
+            |(mut link, transport, mut m, mut ping_receiver, mut pong_receiver)| {
                 // update
                 m.update(&real_time);
 
+                // use the time at which the IO layer received the packet containing the message
+                // (which can be earlier than the start of the frame if the IO runs on a separate
+                // thread), so that the frame time does not inflate the RTT.
+                let now = Instant::now();
+                let receive_time =
+                    |remote_tick| transport.packet_received_at(remote_tick).unwrap_or(now);
+
                 // receive pings
-                ping_receiver.receive().for_each(|ping| {
-                    m.buffer_pending_pong(&ping, Instant::now());
+                ping_receiver.receive_with_tick().for_each(|ping| {
+                    m.buffer_pending_pong(&ping.data, receive_time(ping.remote_tick));
                 });
                 // receive pongs
-                pong_receiver.receive().for_each(|pong| {
+                pong_receiver.receive_with_tick().for_each(|pong| {
                     // process the pong
-                    m.process_pong(&pong, Instant::now(), tick_duration.0);
+                    m.process_pong(&pong.data, receive_time(pong.remote_tick), tick_duration.0);
                 });
 
                 link.stats.rtt = m.rtt();
                 link.stats.jitter = m.jitter();
-            })
+            },
+        )
     }
 
     /// Send pings/pongs to the remote
@@ -124,6 +132,7 @@ impl Plugin for PingPlugin {
             send_frequency: Duration::default(),
             // we always want to include the ping in the packet
             priority: f32::INFINITY,
+            ..Default::default()
         })
         .add_direction(NetworkDirection::Bidirectional);
         app.add_message_to_bytes::<Ping>()
@@ -0,0 +1,68 @@
+//! Check that the packets recorded by a LinkCapture can be decoded offline
+
+use crate::protocol::CompA;
+use crate::stepper::ClientServerStepper;
+use lightyear_link::capture::{CaptureDirection, CaptureReader, LinkCapture, SharedBuffer};
+use lightyear_replication::capture::{CaptureDecoder, MessageContent};
+use lightyear_replication::prelude::Replicate;
+use std::io::Cursor;
+use test_log::test;
+
+#[test]
+fn test_decode_replication_capture() {
+    let mut stepper = ClientServerStepper::single();
+    let buffer = SharedBuffer::default();
+    stepper
+        .client_mut(0)
+        .insert(LinkCapture::new(buffer.clone()).unwrap());
+
+    let client_entity = stepper
+        .client_app()
+        .world_mut()
+        .spawn((Replicate::to_server(), CompA(1.0)))
+        .id();
+    stepper.frame_step(1);
+    stepper
+        .client_app()
+        .world_mut()
+        .entity_mut(client_entity)
+        .get_mut::<CompA>()
+        .unwrap()
+        .0 = 2.0;
+    stepper.frame_step(1);
+
+    let bytes = buffer.contents();
+    let mut decoder = CaptureDecoder::from_world(stepper.client_app().world());
+    let records = CaptureReader::new(Cursor::new(bytes))
+        .unwrap()
+        .map(|record| decoder.decode(&record.unwrap()).unwrap())
+        .collect::<Vec<_>>();
+    assert!(!records.is_empty());
+
+    let component_name = core::any::type_name::<CompA>();
+    let sent_contents = records
+        .iter()
+        .filter(|record| record.direction == CaptureDirection::Send)
+        .flat_map(|record| record.contents.iter())
+        .collect::<Vec<_>>();
+    assert!(sent_contents.iter().any(|content| matches!(
+        content,
+        MessageContent::Actions { entities, .. } if entities.iter().any(|(entity, actions)| {
+            *entity == client_entity
+                && actions.spawn
+                && actions.insert.iter().any(|c| c.name == Some(component_name))
+        })
+    )));
+    assert!(sent_contents.iter().any(|content| matches!(
+        content,
+        MessageContent::Updates { entities, .. } if entities.iter().any(|(entity, updates)| {
+            *entity == client_entity && updates.iter().any(|c| c.name == Some(component_name))
+        })
+    )));
+    // the pretty-printed output contains the component name
+    assert!(
+        records
+            .iter()
+            .any(|r| r.to_string().contains(component_name))
+    );
+}
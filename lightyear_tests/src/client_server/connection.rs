@@ -1,8 +1,24 @@
 //! Check various replication scenarios between 2 peers only
 
-use crate::stepper::ClientServerStepper;
-use bevy::prelude::{Entity, With};
+use crate::protocol::{Channel1, CompBytesList, StringMessage};
+use crate::stepper::{ClientServerStepper, KEY, PROTOCOL_ID};
+use bevy::prelude::*;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+use lightyear::prelude::{NetworkTarget, Replicate};
+use lightyear_connection::client::{Connected, Disconnected};
 use lightyear_connection::client_of::ClientOf;
+use lightyear_connection::prelude::ConnectionSet;
+use lightyear_link::prelude::LinkOf;
+use lightyear_link::{Link, LinkSet, Linked};
+use lightyear_messages::prelude::{
+    AppMessageExt, MessageManager, MessageSender, ProtocolCheckTimeout, ProtocolHash,
+    ProtocolMismatch, VersionMismatch,
+};
+use lightyear_netcode::NetcodeServer;
+use lightyear_netcode::server_plugin::NetcodeConfig;
+use lightyear_serde::prelude::VersionFns;
+use lightyear_transport::prelude::{LimitError, ReceiveLimitExceeded, ReceiveLimits};
 use test_log::test;
 
 #[test]
@@ -21,3 +37,425 @@ fn test_disconnection() {
             .is_err()
     );
 }
+
+/// Moves the packets received on the `from` link to the `to` link, as if the remote peer
+/// had started sending from the address of `to`
+#[derive(Resource)]
+struct Migrate {
+    from: Entity,
+    to: Entity,
+}
+
+fn migrate(migrate: Option<Res<Migrate>>, mut query: Query<&mut Link>, mut commands: Commands) {
+    let Some(migrate) = migrate else {
+        return;
+    };
+    // the `to` link is despawned once the migration is complete
+    let Ok([mut from, mut to]) = query.get_many_mut([migrate.from, migrate.to]) else {
+        commands.remove_resource::<Migrate>();
+        return;
+    };
+    from.recv
+        .drain()
+        .for_each(|payload| to.recv.push_raw(payload));
+}
+
+/// Sends the packets buffered on the `to` link through the `from` link, so that they reach the remote peer
+fn forward(migrate: Option<Res<Migrate>>, mut query: Query<&mut Link>) {
+    let Some(migrate) = migrate else {
+        return;
+    };
+    let Ok([mut from, mut to]) = query.get_many_mut([migrate.from, migrate.to]) else {
+        return;
+    };
+    to.send.drain().for_each(|payload| from.send.push(payload));
+}
+
+/// The client starts sending packets from a new address: the server should keep the same
+/// connection and update the address of the link instead of treating it as a new client.
+#[test]
+fn test_connection_migration() {
+    let mut stepper = ClientServerStepper::default_no_init();
+    let server_entity = stepper.server_entity;
+    stepper
+        .server_app
+        .world_mut()
+        .entity_mut(server_entity)
+        .insert(NetcodeServer::new(NetcodeConfig {
+            protocol_id: PROTOCOL_ID,
+            private_key: KEY,
+            connection_migration: true,
+            ..Default::default()
+        }));
+    stepper.new_client();
+    stepper.init();
+    stepper.server_app.add_systems(
+        PreUpdate,
+        migrate
+            .after(LinkSet::Receive)
+            .before(ConnectionSet::Receive),
+    );
+    stepper.server_app.add_systems(
+        PostUpdate,
+        forward.after(ConnectionSet::Send).before(LinkSet::Send),
+    );
+    let client_of = stepper.client_of(0).id();
+    let new_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1000);
+    let new_link = stepper
+        .server_app
+        .world_mut()
+        .spawn((
+            LinkOf {
+                server: server_entity,
+            },
+            Link::new(new_addr, None),
+            Linked,
+        ))
+        .id();
+    stepper.server_app.insert_resource(Migrate {
+        from: client_of,
+        to: new_link,
+    });
+    stepper.frame_step(1);
+
+    // the server does not migrate the client before it has answered the challenge sent to the new address
+    assert!(stepper.server_app.world().get_entity(new_link).is_ok());
+    assert_ne!(
+        stepper
+            .server_app
+            .world()
+            .get::<Link>(client_of)
+            .unwrap()
+            .remote_addr,
+        Some(new_addr)
+    );
+
+    stepper.frame_step(2);
+
+    // the link that received the packets from the new address has been merged into the existing client
+    assert!(stepper.server_app.world().get_entity(new_link).is_err());
+    let client_of_ref = stepper.server_app.world().entity(client_of);
+    assert!(client_of_ref.contains::<Connected>());
+    assert_eq!(
+        client_of_ref.get::<Link>().unwrap().remote_addr,
+        Some(new_addr)
+    );
+
+    // the connection keeps working
+    stepper.frame_step(5);
+    assert!(stepper.client_of(0).contains::<Connected>());
+    assert!(stepper.client(0).contains::<Connected>());
+}
+
+/// Packets from a new address are ignored if connection migration is not enabled
+#[test]
+fn test_connection_migration_disabled() {
+    let mut stepper = ClientServerStepper::single();
+    stepper.server_app.add_systems(
+        PreUpdate,
+        migrate
+            .after(LinkSet::Receive)
+            .before(ConnectionSet::Receive),
+    );
+    let client_of = stepper.client_of(0).id();
+    let new_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1000);
+    let server_entity = stepper.server_entity;
+    let new_link = stepper
+        .server_app
+        .world_mut()
+        .spawn((
+            LinkOf {
+                server: server_entity,
+            },
+            Link::new(new_addr, None),
+            Linked,
+        ))
+        .id();
+    stepper.server_app.insert_resource(Migrate {
+        from: client_of,
+        to: new_link,
+    });
+    stepper.frame_step(3);
+
+    assert!(stepper.server_app.world().get_entity(new_link).is_ok());
+    assert_ne!(
+        stepper
+            .server_app
+            .world()
+            .get::<Link>(client_of)
+            .unwrap()
+            .remote_addr,
+        Some(new_addr)
+    );
+}
+
+#[test]
+fn test_protocol_mismatch() {
+    #[derive(Resource, Default)]
+    struct Mismatches(Vec<Entity>);
+
+    let mut stepper = ClientServerStepper::default_no_init();
+    stepper.new_client();
+    // the server uses a different protocol
+    stepper.server_app.insert_resource(ProtocolHash(0));
+    stepper.client_app().init_resource::<Mismatches>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<ProtocolMismatch>, mut mismatches: ResMut<Mismatches>| {
+            mismatches.0.push(trigger.target());
+        },
+    );
+    stepper.init();
+
+    assert_eq!(
+        stepper.client_apps[0].world().resource::<Mismatches>().0,
+        vec![stepper.client_entities[0]]
+    );
+    let disconnected = stepper.client(0).get::<Disconnected>().unwrap();
+    assert!(
+        disconnected
+            .reason
+            .as_ref()
+            .unwrap()
+            .starts_with("Protocol mismatch")
+    );
+    // the client notified the server that it disconnected
+    stepper.frame_step(2);
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .get::<Connected>(stepper.client_of_entities[0])
+            .is_none()
+    );
+}
+
+#[test]
+fn test_protocol_check_timeout() {
+    let mut stepper = ClientServerStepper::default_no_init();
+    stepper.new_client();
+    // the client never sends its ProtocolCheck
+    stepper
+        .client_app()
+        .world_mut()
+        .remove_resource::<ProtocolHash>();
+    stepper
+        .server_app
+        .insert_resource(ProtocolCheckTimeout(Duration::from_millis(100)));
+    stepper.init();
+    assert!(stepper.client_of(0).contains::<Connected>());
+
+    stepper.frame_step(10);
+    // the server disconnected the client through netcode, which despawns the ClientOf entity
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .get_entity(stepper.client_of_entities[0])
+            .is_err()
+    );
+    assert!(stepper.client(0).contains::<Disconnected>());
+}
+
+#[test]
+fn test_version_mismatch() {
+    #[derive(Resource, Default)]
+    struct Mismatches(Vec<VersionMismatch>);
+
+    let mut stepper = ClientServerStepper::default_no_init();
+    stepper.new_client();
+    // the server cannot write the version of the message used by the client anymore, and the
+    // client cannot read the version used by the server
+    stepper
+        .client_app()
+        .add_message::<u32>()
+        .add_version(VersionFns::new(1));
+    stepper
+        .server_app
+        .add_message::<u32>()
+        .add_version(VersionFns::new(2));
+    stepper.client_app().init_resource::<Mismatches>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<VersionMismatch>, mut mismatches: ResMut<Mismatches>| {
+            mismatches.0.push(trigger.event().clone());
+        },
+    );
+    stepper.init();
+
+    // the older client also rejects the server
+    let mismatches = &stepper.client_apps[0].world().resource::<Mismatches>().0;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].local, Some(1));
+    assert_eq!(mismatches[0].remote, Some(2));
+    // the server disconnected the client through netcode, which despawns the ClientOf entity
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .get_entity(stepper.client_of_entities[0])
+            .is_err()
+    );
+    assert!(stepper.client(0).contains::<Disconnected>());
+}
+
+#[test]
+fn test_receive_limit_exceeded() {
+    #[derive(Resource, Default)]
+    struct Exceeded(Vec<(Entity, LimitError)>);
+
+    let mut stepper = ClientServerStepper::single();
+    stepper.client_mut(0).insert(ReceiveLimits {
+        max_message_size: 1000,
+        disconnect_on_violation: true,
+        ..default()
+    });
+    stepper.client_app().init_resource::<Exceeded>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<ReceiveLimitExceeded>, mut exceeded: ResMut<Exceeded>| {
+            exceeded.0.push((trigger.target(), trigger.error));
+        },
+    );
+
+    // a message that is small enough is received normally
+    stepper
+        .client_of_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<Channel1>(StringMessage("a".repeat(500)));
+    stepper.frame_step(2);
+    assert!(
+        stepper.client_apps[0]
+            .world()
+            .resource::<Exceeded>()
+            .0
+            .is_empty()
+    );
+
+    // a fragmented message that is too large is rejected and the server gets disconnected
+    stepper
+        .client_of_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<Channel1>(StringMessage("a".repeat(5000)));
+    stepper.frame_step(2);
+    let exceeded = &stepper.client_apps[0].world().resource::<Exceeded>().0;
+    assert!(!exceeded.is_empty());
+    assert_eq!(exceeded[0].0, stepper.client_entities[0]);
+    assert!(matches!(
+        exceeded[0].1,
+        LimitError::MessageTooLarge { max: 1000, .. }
+    ));
+    let disconnected = stepper.client(0).get::<Disconnected>().unwrap();
+    assert!(
+        disconnected
+            .reason
+            .as_ref()
+            .unwrap()
+            .starts_with("Receive limit exceeded")
+    );
+    // the client notified the server that it disconnected
+    stepper.frame_step(2);
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .get::<Connected>(stepper.client_of_entities[0])
+            .is_none()
+    );
+}
+
+#[test]
+fn test_receive_limit_exceeded_on_server() {
+    #[derive(Resource, Default)]
+    struct Exceeded(Vec<(Entity, LimitError)>);
+
+    let mut stepper = ClientServerStepper::single();
+    stepper.client_of_mut(0).insert(ReceiveLimits {
+        max_message_size: 1000,
+        disconnect_on_violation: true,
+        ..default()
+    });
+    stepper.server_app.init_resource::<Exceeded>();
+    stepper.server_app.add_observer(
+        |trigger: Trigger<ReceiveLimitExceeded>, mut exceeded: ResMut<Exceeded>| {
+            exceeded.0.push((trigger.target(), trigger.error));
+        },
+    );
+
+    // a fragmented message that is too large is rejected and the client gets disconnected
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<Channel1>(StringMessage("a".repeat(5000)));
+    stepper.frame_step(2);
+    let exceeded = &stepper.server_app.world().resource::<Exceeded>().0;
+    assert!(!exceeded.is_empty());
+    assert_eq!(exceeded[0].0, stepper.client_of_entities[0]);
+    assert!(matches!(
+        exceeded[0].1,
+        LimitError::MessageTooLarge { max: 1000, .. }
+    ));
+
+    // the server notified the client with netcode disconnect packets, and despawned the ClientOf entity
+    stepper.frame_step(2);
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .get_entity(stepper.client_of_entities[0])
+            .is_err()
+    );
+    assert!(stepper.client(0).contains::<Disconnected>());
+}
+
+/// The limits also apply to the components received through replication
+#[test]
+fn test_receive_limit_exceeded_by_replication() {
+    #[derive(Resource, Default)]
+    struct Exceeded(Vec<(Entity, LimitError)>);
+
+    let mut stepper = ClientServerStepper::single();
+    stepper.client_mut(0).insert(ReceiveLimits {
+        max_collection_len: 10,
+        ..default()
+    });
+    stepper.client_app().init_resource::<Exceeded>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<ReceiveLimitExceeded>, mut exceeded: ResMut<Exceeded>| {
+            exceeded.0.push((trigger.target(), trigger.error));
+        },
+    );
+
+    let server_entity = stepper
+        .server_app
+        .world_mut()
+        .spawn((
+            Replicate::to_clients(NetworkTarget::All),
+            CompBytesList(vec![0; 20]),
+        ))
+        .id();
+    stepper.frame_step(2);
+
+    let exceeded = &stepper.client_apps[0].world().resource::<Exceeded>().0;
+    assert_eq!(
+        exceeded,
+        &vec![(
+            stepper.client_entities[0],
+            LimitError::CollectionTooLong { len: 20, max: 10 }
+        )]
+    );
+    // the entity is spawned without the component that exceeded the limit
+    let client_entity = stepper
+        .client(0)
+        .get::<MessageManager>()
+        .unwrap()
+        .entity_mapper
+        .get_local(server_entity)
+        .unwrap();
+    assert!(
+        stepper.client_apps[0]
+            .world()
+            .get::<CompBytesList>(client_entity)
+            .is_none()
+    );
+}
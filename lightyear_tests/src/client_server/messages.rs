@@ -3,9 +3,14 @@ use crate::stepper::ClientServerStepper;
 use bevy::ecs::entity::UniqueEntityArray;
 use bevy::prelude::*;
 use core::fmt::Debug;
+use core::time::Duration;
 use lightyear::prelude::*;
 use lightyear_connection::client::PeerMetadata;
+use lightyear_core::time::TickDelta;
 use lightyear_messages::multi::MultiMessageSender;
+use lightyear_messages::registry::{MessageKind, MessageRegistry};
+use lightyear_serde::entity_map::{ReceiveEntityMap, SendEntityMap};
+use lightyear_serde::registry::{ContextDeserializeFns, ContextSerializeFns, ErasedSerializeFns};
 use test_log::test;
 use tracing::trace;
 
@@ -78,6 +83,411 @@ fn test_send_messages() {
     );
 }
 
+/// Big messages sent on a channel that uses compression are compressed before being fragmented,
+/// and decompressed by the receiver
+#[test]
+fn test_send_compressed_messages() {
+    let mut stepper = ClientServerStepper::single();
+    stepper.server_app.init_resource::<Buffer<StringMessage>>();
+    stepper
+        .server_app
+        .add_systems(Update, count_messages_observer::<StringMessage>);
+
+    // the message is bigger than a packet
+    let send_message = StringMessage("hello world ".repeat(1000));
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<CompressedChannel>(send_message.clone());
+    stepper.frame_step(2);
+
+    let received_messages = stepper
+        .server_app
+        .world()
+        .resource::<Buffer<StringMessage>>();
+    assert_eq!(
+        &received_messages.0,
+        &vec![(stepper.client_of_entities[0], send_message)]
+    );
+}
+
+/// Messages registered with `add_message_to_bytes` use their `ToBytes` implementation
+#[test]
+fn test_send_to_bytes_messages() {
+    let mut stepper = ClientServerStepper::single();
+    stepper.server_app.init_resource::<Buffer<BytesMessage>>();
+    stepper
+        .server_app
+        .add_systems(Update, count_messages_observer::<BytesMessage>);
+
+    let send_message = BytesMessage {
+        id: 1000,
+        text: "Hello".to_string(),
+    };
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<BytesMessage>>()
+        .unwrap()
+        .send::<Channel1>(send_message.clone());
+    stepper.frame_step(1);
+
+    let received_messages = stepper
+        .server_app
+        .world()
+        .resource::<Buffer<BytesMessage>>();
+    assert_eq!(
+        &received_messages.0,
+        &vec![(stepper.client_of_entities[0], send_message)]
+    );
+}
+
+/// The client still uses version 1 of a message while the server uses version 2:
+/// the server upgrades the messages it receives and downgrades the messages it sends
+#[test]
+fn test_send_versioned_messages() {
+    let mut stepper = ClientServerStepper::default_no_init();
+    stepper.new_client();
+    // replace the client's registration of the message with the one from an older build
+    let client_app = stepper.client_app();
+    let mut registry = client_app.world_mut().resource_mut::<MessageRegistry>();
+    let kind = MessageKind::of::<VersionedMessage>();
+    let net_id = *registry.kind_map.net_id(&kind).unwrap();
+    let fns = registry.serialize_fns_map.get_mut(&kind).unwrap();
+    *fns = ErasedSerializeFns::new::<SendEntityMap, ReceiveEntityMap, _, VersionedMessage>(
+        ContextSerializeFns::new(VersionedMessage::write_v1),
+        ContextDeserializeFns::new(VersionedMessage::read_v1),
+    );
+    // SAFETY: the ErasedSerializeFns was created for VersionedMessage
+    unsafe { fns.set_version(VersionFns::<VersionedMessage>::new(1)) };
+    let fns = fns.clone();
+    client_app.add_protocol_version(VersionedType::Message(net_id), &fns);
+    stepper.init();
+
+    stepper
+        .server_app
+        .init_resource::<Buffer<VersionedMessage>>();
+    stepper
+        .server_app
+        .add_systems(Update, count_messages_observer::<VersionedMessage>);
+    stepper
+        .client_app()
+        .init_resource::<Buffer<VersionedMessage>>();
+    stepper
+        .client_app()
+        .add_systems(Update, count_messages_observer::<VersionedMessage>);
+
+    let send_message = VersionedMessage { x: 1.0, y: 2.0 };
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<VersionedMessage>>()
+        .unwrap()
+        .send::<Channel1>(send_message.clone());
+    stepper
+        .client_of_mut(0)
+        .get_mut::<MessageSender<VersionedMessage>>()
+        .unwrap()
+        .send::<Channel1>(send_message);
+    stepper.frame_step(2);
+
+    // the field that doesn't exist in version 1 is lost
+    let expected = VersionedMessage { x: 1.0, y: 0.0 };
+    assert_eq!(
+        &stepper
+            .server_app
+            .world()
+            .resource::<Buffer<VersionedMessage>>()
+            .0,
+        &vec![(stepper.client_of_entities[0], expected.clone())]
+    );
+    assert_eq!(
+        &stepper.client_apps[0]
+            .world()
+            .resource::<Buffer<VersionedMessage>>()
+            .0,
+        &vec![(stepper.client_entities[0], expected)]
+    );
+}
+
+#[test]
+fn test_message_acked() {
+    let mut stepper = ClientServerStepper::single();
+    #[derive(Resource, Default)]
+    struct Acked(Vec<(Entity, MessageHandle)>);
+    stepper.client_app().init_resource::<Acked>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<MessageAcked>, mut acked: ResMut<Acked>| {
+            acked.0.push((trigger.target(), trigger.handle));
+        },
+    );
+
+    let handle = stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<Channel2>(StringMessage("Hello".to_string()));
+    stepper.frame_step(10);
+
+    assert_eq!(
+        &stepper.client_apps[0].world().resource::<Acked>().0,
+        &vec![(stepper.client_entities[0], handle)]
+    );
+}
+
+/// A reliable message that is never acked is abandoned after the maximum number of retries
+#[test]
+fn test_message_abandoned() {
+    let mut stepper = ClientServerStepper::single();
+    #[derive(Resource, Default)]
+    struct Abandoned(Vec<(Entity, Option<MessageHandle>)>);
+    stepper.client_app().init_resource::<Abandoned>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<MessageAbandoned>, mut abandoned: ResMut<Abandoned>| {
+            abandoned.0.push((trigger.target(), trigger.handle));
+        },
+    );
+    // every packet sent by the client is lost
+    stepper.set_client_outgoing_loss(0, 1.0);
+
+    let handle = stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<RetryChannel>(StringMessage("Hello".to_string()));
+    stepper.frame_step(10);
+
+    assert_eq!(
+        &stepper.client_apps[0].world().resource::<Abandoned>().0,
+        &vec![(stepper.client_entities[0], Some(handle))]
+    );
+}
+
+/// A reliable message whose ttl expires before it is sent is abandoned without being delivered
+#[test]
+fn test_message_ttl() {
+    let mut stepper = ClientServerStepper::single();
+    #[derive(Resource, Default)]
+    struct Abandoned(Vec<(Entity, Option<MessageHandle>)>);
+    stepper.client_app().init_resource::<Abandoned>();
+    stepper.client_app().add_observer(
+        |trigger: Trigger<MessageAbandoned>, mut abandoned: ResMut<Abandoned>| {
+            abandoned.0.push((trigger.target(), trigger.handle));
+        },
+    );
+    stepper.server_app.init_resource::<Buffer<StringMessage>>();
+    stepper
+        .server_app
+        .add_systems(Update, count_messages_observer::<StringMessage>);
+
+    let handle = stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send_with_priority::<RetryChannel>(
+            StringMessage("Hello".to_string()),
+            SendOptions::new(1.0).with_ttl(Duration::ZERO),
+        );
+    stepper.frame_step(5);
+
+    assert_eq!(
+        &stepper.client_apps[0].world().resource::<Abandoned>().0,
+        &vec![(stepper.client_entities[0], Some(handle))]
+    );
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .resource::<Buffer<StringMessage>>()
+            .0
+            .is_empty()
+    );
+}
+
+/// An unreliable message sent on a channel with acks is reported as lost if its packet is lost
+#[test]
+fn test_message_lost() {
+    let mut stepper = ClientServerStepper::single();
+    #[derive(Resource, Default)]
+    struct Lost(Vec<(Entity, MessageHandle)>);
+    stepper.client_app().init_resource::<Lost>();
+    stepper
+        .client_app()
+        .add_observer(|trigger: Trigger<MessageLost>, mut lost: ResMut<Lost>| {
+            lost.0.push((trigger.target(), trigger.handle));
+        });
+    // every packet sent by the client is lost
+    stepper.set_client_outgoing_loss(0, 1.0);
+
+    let handle = stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<Channel2>(StringMessage("Hello".to_string()));
+    stepper.frame_step(20);
+
+    assert_eq!(
+        &stepper.client_apps[0].world().resource::<Lost>().0,
+        &vec![(stepper.client_entities[0], handle)]
+    );
+}
+
+/// Messages sent on a TickBuffered channel are delivered once the server reaches the tick at which
+/// they were sent by the client
+#[test]
+fn test_tick_buffered_messages() {
+    let mut stepper = ClientServerStepper::single();
+    #[derive(Resource, Default)]
+    struct Received(Vec<(Tick, Tick, Option<u16>)>);
+    stepper.server_app.init_resource::<Received>();
+    stepper.server_app.add_systems(
+        Update,
+        |mut receiver: Query<&mut MessageReceiver<StringMessage>>,
+         timeline: Single<&LocalTimeline, With<Server>>,
+         mut received: ResMut<Received>| {
+            receiver.iter_mut().for_each(|mut receiver| {
+                receiver.receive_with_tick().for_each(|m| {
+                    received
+                        .0
+                        .push((timeline.tick(), m.remote_tick, m.lateness));
+                });
+            });
+        },
+    );
+
+    // the client's timeline runs ahead of the server's
+    stepper
+        .client_mut(0)
+        .get_mut::<LocalTimeline>()
+        .unwrap()
+        .apply_delta(TickDelta::from_i16(2));
+    // messages are sent in PostUpdate, after the tick was incremented in FixedUpdate
+    let send_tick = stepper.client_tick(0) + 1;
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<TickBufferedChannel>(StringMessage("Hello".to_string()));
+
+    // the message is buffered until the server reaches the tick at which it was sent
+    stepper.frame_step(3);
+    assert!(
+        stepper
+            .server_app
+            .world()
+            .resource::<Received>()
+            .0
+            .is_empty()
+    );
+    // the server receives messages in PreUpdate, before its tick is incremented in FixedUpdate
+    stepper.frame_step(1);
+    assert_eq!(
+        &stepper.server_app.world().resource::<Received>().0,
+        &vec![(send_tick + 1, send_tick, Some(0))]
+    );
+
+    // a message sent in the past is delivered right away, with its lateness
+    stepper
+        .client_mut(0)
+        .get_mut::<LocalTimeline>()
+        .unwrap()
+        .apply_delta(TickDelta::from_i16(-5));
+    let send_tick = stepper.client_tick(0) + 1;
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<TickBufferedChannel>(StringMessage("Late".to_string()));
+    stepper.frame_step(1);
+    let server_tick = stepper.server_tick();
+    assert_eq!(
+        stepper.server_app.world().resource::<Received>().0[1],
+        (
+            server_tick,
+            send_tick,
+            Some((server_tick - 1 - send_tick) as u16)
+        )
+    );
+}
+
+/// A message that is lost on a channel with forward error correction is reconstructed from the
+/// parity message, which is sent in a later packet
+#[test]
+fn test_forward_error_correction() {
+    let mut stepper = ClientServerStepper::single();
+    stepper.server_app.init_resource::<Buffer<StringMessage>>();
+    stepper
+        .server_app
+        .add_systems(Update, count_messages_observer::<StringMessage>);
+    let send = |stepper: &mut ClientServerStepper, message: &str| {
+        stepper
+            .client_mut(0)
+            .get_mut::<MessageSender<StringMessage>>()
+            .unwrap()
+            .send::<FecChannel>(StringMessage(message.to_string()));
+    };
+
+    send(&mut stepper, "1");
+    stepper.frame_step(1);
+    // the packet containing the last message of the group is lost
+    stepper.set_client_outgoing_loss(0, 1.0);
+    send(&mut stepper, "2");
+    stepper.frame_step(1);
+    assert_eq!(
+        stepper
+            .server_app
+            .world()
+            .resource::<Buffer<StringMessage>>()
+            .0,
+        vec![(
+            stepper.client_of_entities[0],
+            StringMessage("1".to_string())
+        )]
+    );
+
+    // the parity message is sent in the next packet
+    stepper.set_client_outgoing_loss(0, 0.0);
+    stepper.frame_step(1);
+    assert_eq!(
+        stepper
+            .server_app
+            .world()
+            .resource::<Buffer<StringMessage>>()
+            .0,
+        vec![
+            (
+                stepper.client_of_entities[0],
+                StringMessage("1".to_string())
+            ),
+            (
+                stepper.client_of_entities[0],
+                StringMessage("2".to_string())
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_transport_stats() {
+    let mut stepper = ClientServerStepper::single();
+    stepper
+        .client_mut(0)
+        .get_mut::<MessageSender<StringMessage>>()
+        .unwrap()
+        .send::<Channel1>(StringMessage("Hello".to_string()));
+    stepper.frame_step(1);
+
+    let client_stats = stepper.client(0).get::<TransportStats>().unwrap();
+    let sent = client_stats.channel::<Channel1>().unwrap().send;
+    assert_eq!(sent.messages_sent, 1);
+    assert!(sent.bytes_sent > 0);
+    assert!(client_stats.total().send.messages_sent >= 1);
+
+    let server_stats = stepper.client_of(0).get::<TransportStats>().unwrap();
+    let received = server_stats.channel::<Channel1>().unwrap().receive;
+    assert_eq!(received.messages_received, 1);
+    assert_eq!(received.bytes_received, sent.bytes_sent);
+}
+
 #[derive(Resource)]
 struct TriggerBuffer<M>(Vec<(Entity, M, Entity)>);
 
@@ -1,10 +1,12 @@
 mod base;
 
 mod authority;
+mod capture;
 mod connection;
 mod hierarchy;
 mod input;
 mod messages;
 mod prediction;
 mod replication;
+mod stream;
 mod visibility;
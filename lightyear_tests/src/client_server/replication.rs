@@ -1,6 +1,6 @@
 //! Check various replication scenarios between 2 peers only
 
-use crate::protocol::{CompA, CompDisabled, CompReplicateOnce};
+use crate::protocol::{CompA, CompBytes, CompDisabled, CompReplicateOnce};
 use crate::stepper::ClientServerStepper;
 use bevy::prelude::{Name, ResMut, Resource, Single, default};
 use lightyear_connection::network_target::NetworkTarget;
@@ -211,6 +211,35 @@ fn test_component_insert() {
     );
 }
 
+/// Components registered with `register_component_to_bytes` use their `ToBytes` implementation
+#[test]
+fn test_component_to_bytes() {
+    let mut stepper = ClientServerStepper::single();
+
+    let client_entity = stepper
+        .client_app()
+        .world_mut()
+        .spawn((Replicate::to_server(), CompBytes { x: 1.0, y: -2.0 }))
+        .id();
+    stepper.frame_step(1);
+    let server_entity = stepper
+        .client_of(0)
+        .get::<MessageManager>()
+        .unwrap()
+        .entity_mapper
+        .get_local(client_entity)
+        .unwrap();
+    let component = stepper
+        .server_app
+        .world()
+        .entity(server_entity)
+        .get::<CompBytes>()
+        .expect("component missing");
+    // the values are quantized
+    assert!((component.x - 1.0).abs() < 0.01);
+    assert!((component.y + 2.0).abs() < 0.01);
+}
+
 #[test]
 fn test_component_update() {
     let mut stepper = ClientServerStepper::single();
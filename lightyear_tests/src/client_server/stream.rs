@@ -0,0 +1,85 @@
+use crate::protocol::*;
+use crate::stepper::ClientServerStepper;
+use bevy::prelude::*;
+use bytes::Bytes;
+use core::task::Poll;
+use lightyear::prelude::*;
+use lightyear_transport::error::TransportError;
+use test_log::test;
+
+#[derive(Resource, Default)]
+struct Events {
+    progress: Vec<StreamProgress>,
+    completed: Vec<StreamCompleted>,
+}
+
+fn record_events(app: &mut App) {
+    app.init_resource::<Events>();
+    app.add_observer(
+        |trigger: Trigger<StreamProgress>, mut events: ResMut<Events>| {
+            events.progress.push(*trigger);
+        },
+    );
+    app.add_observer(
+        |trigger: Trigger<StreamCompleted>, mut events: ResMut<Events>| {
+            events.completed.push(*trigger);
+        },
+    );
+}
+
+#[test]
+fn test_send_stream() {
+    let mut stepper = ClientServerStepper::single();
+    record_events(stepper.client_app());
+    record_events(&mut stepper.server_app);
+
+    let data: Bytes = (0..10_000u32).map(|i| i as u8).collect::<Vec<_>>().into();
+    let stream_id = stepper
+        .client_mut(0)
+        .get_mut::<Transport>()
+        .unwrap()
+        .send_stream::<StreamChannel>(data.clone())
+        .unwrap();
+    stepper.frame_step(40);
+
+    // the server received the whole stream progressively
+    let events = stepper.server_app.world().resource::<Events>();
+    assert_eq!(
+        events.completed,
+        vec![StreamCompleted {
+            stream_id,
+            direction: StreamDirection::Receive,
+        }]
+    );
+    assert_eq!(events.progress.len(), 10);
+    let mut transport = stepper.client_of_mut(0);
+    let mut transport = transport.get_mut::<Transport>().unwrap();
+    let reader = transport.stream_reader(stream_id).unwrap();
+    let mut received = vec![0; 20_000];
+    let Poll::Ready(Ok(len)) = reader.poll_read(&mut received) else {
+        panic!("the stream should be readable");
+    };
+    assert_eq!(&received[..len], &data[..]);
+    assert_eq!(reader.poll_read(&mut received), Poll::Ready(Ok(0)));
+
+    // the client knows that the stream was fully received
+    let events = stepper.client_apps[0].world().resource::<Events>();
+    assert_eq!(
+        events.completed,
+        vec![StreamCompleted {
+            stream_id,
+            direction: StreamDirection::Send,
+        }]
+    );
+}
+
+/// Regular messages cannot be sent on a stream channel
+#[test]
+fn test_stream_channel_rejects_messages() {
+    let stepper = ClientServerStepper::single();
+    let transport = stepper.client(0).get::<Transport>().unwrap();
+    assert!(matches!(
+        transport.send::<StreamChannel>(Bytes::from("hello")),
+        Err(TransportError::StreamChannel(_))
+    ));
+}
@@ -10,6 +10,9 @@ use leafwing_input_manager::Actionlike;
 use lightyear::prelude::input::*;
 use lightyear::prelude::*;
 use lightyear_connection::direction::NetworkDirection;
+use lightyear_serde::reader::Reader;
+use lightyear_serde::registry::SerializeFns;
+use lightyear_serde::writer::Writer;
 use serde::{Deserialize, Serialize};
 
 // Messages
@@ -19,6 +22,33 @@ pub struct StringMessage(pub String);
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, MapEntities, Reflect)]
 pub struct EntityMessage(#[entities] pub Entity);
 
+/// Message serialized with a derived `ToBytes` implementation
+#[derive(ToBytes, Debug, PartialEq, Clone, Reflect)]
+pub struct BytesMessage {
+    #[lightyear(varint)]
+    pub id: u32,
+    pub text: String,
+}
+
+/// Message at version 2, where the `y` field was added
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Reflect)]
+pub struct VersionedMessage {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl VersionedMessage {
+    /// Wire format of version 1, that only contained the `x` field
+    pub fn write_v1(&self, writer: &mut Writer) -> Result<(), SerializationError> {
+        (SerializeFns::<f32>::default().serialize)(&self.x, writer)
+    }
+
+    pub fn read_v1(reader: &mut Reader) -> Result<Self, SerializationError> {
+        let x = (SerializeFns::<f32>::default().deserialize)(reader)?;
+        Ok(Self { x, y: 0.0 })
+    }
+}
+
 // Triggers
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Reflect, Event)]
 pub struct StringTrigger(pub String);
@@ -44,6 +74,24 @@ pub struct Channel1;
 #[derive(Reflect)]
 pub struct Channel2;
 
+#[derive(Reflect)]
+pub struct StreamChannel;
+
+#[derive(Reflect)]
+pub struct CompressedChannel;
+
+/// Channel that delivers the messages sent by the client at the tick at which they were sent
+#[derive(Reflect)]
+pub struct TickBufferedChannel;
+
+/// Unreliable channel that sends a parity message after every 2 messages
+#[derive(Reflect)]
+pub struct FecChannel;
+
+/// Reliable channel that gives up on a message after one retry
+#[derive(Reflect)]
+pub struct RetryChannel;
+
 // Components
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
 pub struct CompA(pub f32);
@@ -80,6 +128,19 @@ impl Ease for CompCorr {
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
 pub struct CompNotNetworked(pub f32);
 
+/// Component serialized with a derived `ToBytes` implementation
+#[derive(Component, ToBytes, Clone, Debug, PartialEq, Reflect)]
+pub struct CompBytes {
+    #[lightyear(quantize(min = -100.0, max = 100.0, bits = 16))]
+    pub x: f32,
+    #[lightyear(quantize(min = -100.0, max = 100.0, bits = 16))]
+    pub y: f32,
+}
+
+/// Component containing a collection, serialized with a derived `ToBytes` implementation
+#[derive(Component, ToBytes, Clone, Debug, PartialEq, Reflect)]
+pub struct CompBytesList(pub Vec<u16>);
+
 // Inputs
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Reflect)]
 pub struct NativeInput(pub i16);
@@ -98,6 +159,15 @@ impl Plugin for ProtocolPlugin {
         app.add_message::<EntityMessage>()
             .add_map_entities()
             .add_direction(NetworkDirection::Bidirectional);
+        app.add_message_to_bytes::<BytesMessage>()
+            .add_direction(NetworkDirection::Bidirectional);
+        app.add_message::<VersionedMessage>()
+            .add_version(
+                VersionFns::new(2)
+                    .with_upgrade(1, |_, reader| VersionedMessage::read_v1(reader))
+                    .with_downgrade(|message, _, writer| message.write_v1(writer)),
+            )
+            .add_direction(NetworkDirection::Bidirectional);
         // triggers
         app.add_trigger::<StringTrigger>()
             .add_direction(NetworkDirection::Bidirectional);
@@ -115,6 +185,40 @@ impl Plugin for ProtocolPlugin {
             ..default()
         })
         .add_direction(NetworkDirection::Bidirectional);
+        app.add_channel::<CompressedChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            compression: Compression::Lz4 { dictionary: None },
+            ..default()
+        })
+        .add_direction(NetworkDirection::Bidirectional);
+        app.add_channel::<RetryChannel>(ChannelSettings {
+            mode: ChannelMode::UnorderedReliable(ReliableSettings {
+                max_retries: Some(1),
+                ..default()
+            }),
+            ..default()
+        })
+        .add_direction(NetworkDirection::Bidirectional);
+        app.add_channel::<FecChannel>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            fec: ForwardErrorCorrection::XorParity { group_size: 2 },
+            ..default()
+        })
+        .add_direction(NetworkDirection::Bidirectional);
+        app.add_channel::<TickBufferedChannel>(ChannelSettings {
+            mode: ChannelMode::TickBuffered(ReliableSettings::default()),
+            ..default()
+        })
+        .add_direction(NetworkDirection::ClientToServer);
+        app.add_channel::<StreamChannel>(ChannelSettings {
+            mode: ChannelMode::Stream(StreamSettings {
+                chunk_size: 1000,
+                window: 2,
+                ..default()
+            }),
+            ..default()
+        })
+        .add_direction(NetworkDirection::Bidirectional);
         // components
         app.register_component::<CompA>();
         app.register_component::<CompFull>()
@@ -144,6 +248,8 @@ impl Plugin for ProtocolPlugin {
                 replicate_once: true,
                 ..default()
             });
+        app.register_component_to_bytes::<CompBytes>();
+        app.register_component_to_bytes::<CompBytesList>();
         // inputs
         app.add_plugins(native::InputPlugin::<NativeInput> {
             config: InputConfig::<NativeInput> {
@@ -11,8 +11,8 @@ use core::time::Duration;
 use lightyear::prelude::{client::*, server::*, *};
 use lightyear_netcode::client_plugin::NetcodeConfig;
 
-const PROTOCOL_ID: u64 = 0;
-const KEY: [u8; 32] = [0; 32];
+pub(crate) const PROTOCOL_ID: u64 = 0;
+pub(crate) const KEY: [u8; 32] = [0; 32];
 const SERVER_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
 
 /// Stepper with:
@@ -228,6 +228,18 @@ impl ClientServerStepper {
             .entity_mut(self.client_of_entities[id])
     }
 
+    /// Drop the given fraction of the packets sent by the client `id` (1.0 drops every packet)
+    pub fn set_client_outgoing_loss(&mut self, id: usize, loss: f32) {
+        self.client_mut(id)
+            .get_mut::<Link>()
+            .unwrap()
+            .send
+            .set_conditioner(Some(SendLinkConditioner::new(LinkConditionerConfig {
+                outgoing_loss: loss,
+                ..default()
+            })));
+    }
+
     pub(crate) fn init(&mut self) {
         // Initialize Real time (needed only for the first TimeSystem run)
         let now = bevy::platform::time::Instant::now();
@@ -1,14 +1,17 @@
 //! This module contains the [`Channel`] trait
-use crate::channel::receivers::ChannelReceiverEnum;
+use crate::channel::compression::{ChannelCompressor, Compression};
+use crate::channel::fec::{FecDecoder, FecEncoder, ForwardErrorCorrection};
+use crate::channel::receivers::{ChannelReceive, ChannelReceiverEnum};
 use crate::channel::registry::{ChannelId, ChannelKind};
 use crate::channel::senders::ChannelSend;
 use crate::channel::senders::ChannelSenderEnum;
-#[cfg(feature = "trace")]
-use crate::channel::stats::send::ChannelSendStats;
-use crate::packet::message::{MessageAck, MessageId};
-use crate::packet::packet::PacketId;
+use crate::channel::stats::{ChannelReceiveStats, ChannelSendStats, TransportStats};
+use crate::channel::stream::{StreamId, StreamManager, StreamReader, StreamSettings};
+use crate::packet::message::{MessageAck, MessageHandle, MessageHandleAllocator, MessageId};
+use crate::packet::packet::{PacketId, fragment_size};
 use crate::packet::packet_builder::{PacketBuilder, RecvPayload};
 use crate::packet::priority_manager::PriorityManager;
+use bevy::platform::time::Instant;
 use bevy::prelude::Component;
 use bytes::Bytes;
 use core::time::Duration;
@@ -17,10 +20,15 @@ use lightyear_utils::collections::HashMap;
 
 use crate::channel::Channel;
 use crate::error::TransportError;
+use crate::plugin::DEFAULT_TRACKING_TIMEOUT;
 use crate::prelude::{ChannelRegistry, PriorityConfig};
 use crossbeam_channel::{Receiver, Sender};
 use lightyear_core::prelude::LocalTimeline;
+use lightyear_core::tick::Tick;
 use lightyear_link::SendPayload;
+use lightyear_serde::ToBytes;
+use lightyear_serde::writer::Writer;
+use tracing::error;
 // TODO: hook when you insert ChannelSettings, it creates a ChannelSender and ChannelReceiver component
 
 #[cfg(not(feature = "std"))]
@@ -37,6 +45,20 @@ pub struct ChannelSettings {
     pub send_frequency: Duration,
     /// Sets the priority of the channel. The final priority of a message will be `MessagePriority * ChannelPriority`
     pub priority: f32,
+    /// Compression applied to the messages of the channel, before they are split into fragments
+    pub compression: Compression,
+    /// Forward error correction applied to the messages of the channel, so that the receiver can
+    /// reconstruct lost messages without waiting for the next one.
+    ///
+    /// Only supported on [`ChannelMode::UnorderedUnreliable`] and [`ChannelMode::UnorderedUnreliableWithAcks`] channels.
+    pub fec: ForwardErrorCorrection,
+    /// Messages that could not be sent within this duration (or, for reliable channels, that were
+    /// not acked within this duration) are dropped instead of being sent late.
+    ///
+    /// Can be overridden for each message. Not used on [`ChannelMode::OrderedReliable`] channels,
+    /// since the receiver would wait forever for the dropped message, and must be `None` on
+    /// [`ChannelMode::Stream`] channels.
+    pub ttl: Option<Duration>,
 }
 
 impl Default for ChannelSettings {
@@ -45,14 +67,52 @@ impl Default for ChannelSettings {
             mode: ChannelMode::UnorderedUnreliable,
             send_frequency: Duration::default(),
             priority: 1.0,
+            compression: Compression::None,
+            fec: ForwardErrorCorrection::None,
+            ttl: None,
         }
     }
 }
 
+impl ChannelSettings {
+    /// Check that the settings of the channel `name` are consistent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReliableSettings::backoff_factor`] is smaller than 1.0, or if a
+    /// [`ChannelMode::Stream`] channel can drop messages (with a ttl, `max_retries` or `max_duration`),
+    /// or if forward error correction is used on a channel that does not support it.
+    pub(crate) fn validate(&self, name: &str) {
+        if let Some(reliable_settings) = self.mode.reliable_settings() {
+            assert!(
+                reliable_settings.backoff_factor >= 1.0,
+                "Channel {name}: the backoff_factor must be at least 1.0, got {}",
+                reliable_settings.backoff_factor
+            );
+        }
+        if let ChannelMode::Stream(stream_settings) = &self.mode {
+            assert!(
+                stream_settings.reliable.max_retries.is_none()
+                    && stream_settings.reliable.max_duration.is_none(),
+                "Channel {name}: a stream channel cannot abandon its chunks, max_retries and max_duration must be None"
+            );
+            assert!(
+                self.ttl.is_none(),
+                "Channel {name}: a stream channel cannot have a ttl"
+            );
+        }
+        assert!(
+            self.fec == ForwardErrorCorrection::None || self.mode.supports_fec(),
+            "Channel {name}: forward error correction is only supported on unordered unreliable channels"
+        );
+    }
+}
+
 /// Holds information about all the channels present on the entity.
 #[derive(Component)]
 #[require(LocalTimeline)]
 #[require(Link)]
+#[require(TransportStats)]
 pub struct Transport {
     pub receivers: HashMap<ChannelId, ReceiverMetadata>,
     pub senders: HashMap<ChannelKind, SenderMetadata>,
@@ -65,13 +125,37 @@ pub struct Transport {
     pub(crate) packet_to_message_ack_map: HashMap<PacketId, Vec<(ChannelKind, MessageAck)>>,
 
     /// mpsc channel sender/receiver to allow users to write bytes to the same channel in parallel
-    pub send_channel: Sender<(ChannelKind, Bytes, f32)>,
-    pub recv_channel: Receiver<(ChannelKind, Bytes, f32)>,
+    pub send_channel: Sender<(
+        ChannelKind,
+        Bytes,
+        f32,
+        Option<Duration>,
+        Option<MessageHandle>,
+    )>,
+    pub recv_channel: Receiver<(
+        ChannelKind,
+        Bytes,
+        f32,
+        Option<Duration>,
+        Option<MessageHandle>,
+    )>,
     /// Buffer to store payloads that have been processed by the transport, and will be processed
     /// by the Link or the Connection
     pub send: Vec<SendPayload>,
     /// Buffer to store payloads that will be processed by the transport and stored in the ChannelReceiverEnum
     pub recv: Vec<RecvPayload>,
+    /// Streams of data sent or received on the [`ChannelMode::Stream`] channels
+    pub(crate) streams: StreamManager,
+    /// Instant at which each packet processed during the last receive was received by the IO
+    /// layer, along with the remote tick at which the packet was sent
+    pub(crate) packet_receive_times: Vec<(Tick, Instant)>,
+    /// Creates the [`MessageHandle`]s of the messages sent on this transport
+    pub(crate) message_handles: MessageHandleAllocator,
+    /// Duration after which we stop waiting for the ack of a tracked unreliable message, and
+    /// consider it lost.
+    ///
+    /// The default is [`DEFAULT_TRACKING_TIMEOUT`].
+    pub tracking_timeout: Duration,
 }
 
 impl Transport {
@@ -87,6 +171,10 @@ impl Transport {
             recv_channel,
             send: vec![],
             recv: vec![],
+            streams: StreamManager::default(),
+            packet_receive_times: vec![],
+            message_handles: MessageHandleAllocator::default(),
+            tracking_timeout: DEFAULT_TRACKING_TIMEOUT,
         }
     }
 }
@@ -98,6 +186,18 @@ impl Default for Transport {
 }
 
 impl Transport {
+    /// Instant at which the IO layer received the packet sent at `remote_tick`.
+    ///
+    /// Only the packets processed during the most recent receive are tracked. This can be used
+    /// to measure network timings (for example the RTT) for a given message, using the
+    /// `remote_tick` of the message.
+    pub fn packet_received_at(&self, remote_tick: Tick) -> Option<Instant> {
+        self.packet_receive_times
+            .iter()
+            .find(|(tick, _)| *tick == remote_tick)
+            .map(|(_, instant)| *instant)
+    }
+
     pub fn has_sender<C: Channel>(&self) -> bool {
         self.senders.contains_key(&ChannelKind::of::<C>())
     }
@@ -112,12 +212,22 @@ impl Transport {
         &mut self,
         sender: ChannelSenderEnum,
         mode: ChannelMode,
+        compression: Compression,
+        fec: ForwardErrorCorrection,
+        ttl: Option<Duration>,
         channel_id: ChannelId,
     ) {
         self.senders.insert(
             ChannelKind::of::<C>(),
             SenderMetadata {
                 sender,
+                compressor: compression.into(),
+                fec: FecEncoder::new(fec, &mode),
+                pending_parity: None,
+                ttl,
+                tracked_messages: HashMap::default(),
+                current_tick: Tick::default(),
+                stats: ChannelSendStats::default(),
                 message_acks: vec![],
                 message_nacks: vec![],
                 messages_sent: vec![],
@@ -138,18 +248,32 @@ impl Transport {
         };
         let channel_id = *registry.get_net_from_kind(&ChannelKind::of::<C>()).unwrap();
         let sender = settings.into();
-        self.add_sender::<C>(sender, settings.mode, channel_id);
+        self.add_sender::<C>(
+            sender,
+            settings.mode,
+            settings.compression,
+            settings.fec,
+            settings.ttl,
+            channel_id,
+        );
     }
 
     pub fn add_receiver<C: Channel>(
         &mut self,
         receiver: ChannelReceiverEnum,
+        mode: ChannelMode,
+        compression: Compression,
+        fec: ForwardErrorCorrection,
         channel_id: ChannelId,
     ) {
         self.receivers.insert(
             channel_id,
             ReceiverMetadata {
                 receiver,
+                mode,
+                compressor: compression.into(),
+                fec: FecDecoder::new(fec, &mode),
+                stats: ChannelReceiveStats::default(),
                 channel_kind: ChannelKind::of::<C>(),
             },
         );
@@ -164,7 +288,13 @@ impl Transport {
         };
         let channel_id = *registry.get_net_from_kind(&ChannelKind::of::<C>()).unwrap();
         let receiver = settings.into();
-        self.add_receiver::<C>(receiver, channel_id);
+        self.add_receiver::<C>(
+            receiver,
+            settings.mode,
+            settings.compression,
+            settings.fec,
+            channel_id,
+        );
     }
 
     pub fn send_with_priority<C: Channel>(
@@ -172,23 +302,78 @@ impl Transport {
         bytes: SendPayload,
         priority: f32,
     ) -> Result<(), TransportError> {
-        self.send_erased(ChannelKind::of::<C>(), bytes, priority)
+        self.send_erased(ChannelKind::of::<C>(), bytes, priority, None)
     }
 
     pub fn send<C: Channel>(&self, bytes: SendPayload) -> Result<(), TransportError> {
         self.send_with_priority::<C>(bytes, 1.0)
     }
 
+    /// Send a message on the channel `kind`.
+    ///
+    /// If `ttl` is provided, the message is dropped if it could not be delivered within `ttl`,
+    /// overriding the [`ChannelSettings::ttl`] of the channel.
     pub fn send_erased(
         &self,
         kind: ChannelKind,
         bytes: SendPayload,
         priority: f32,
+        ttl: Option<Duration>,
     ) -> Result<(), TransportError> {
-        self.send_channel.try_send((kind, bytes, priority))?;
+        self.check_message_channel(kind)?;
+        self.send_channel
+            .try_send((kind, bytes, priority, ttl, None))?;
         Ok(())
     }
 
+    /// Send a message and return a [`MessageHandle`] to track its delivery.
+    ///
+    /// On channels that track acks (reliable channels and [`ChannelMode::UnorderedUnreliableWithAcks`]),
+    /// a [`MessageAcked`](crate::plugin::MessageAcked) or [`MessageLost`](crate::plugin::MessageLost)
+    /// trigger is emitted on the entity once the delivery of the message is known.
+    pub fn send_tracked<C: Channel>(
+        &self,
+        bytes: SendPayload,
+        priority: f32,
+    ) -> Result<MessageHandle, TransportError> {
+        let handle = self.message_handles.next();
+        self.send_erased_tracked(ChannelKind::of::<C>(), bytes, priority, None, handle)?;
+        Ok(handle)
+    }
+
+    /// Allocator of the handles of the messages sent on this transport.
+    ///
+    /// Use it to create the handles passed to [`Transport::send_erased_tracked`].
+    pub fn message_handles(&self) -> MessageHandleAllocator {
+        self.message_handles.clone()
+    }
+
+    /// Send a message whose delivery will be tracked with the provided `handle`
+    pub fn send_erased_tracked(
+        &self,
+        kind: ChannelKind,
+        bytes: SendPayload,
+        priority: f32,
+        ttl: Option<Duration>,
+        handle: MessageHandle,
+    ) -> Result<(), TransportError> {
+        self.check_message_channel(kind)?;
+        self.send_channel
+            .try_send((kind, bytes, priority, ttl, Some(handle)))?;
+        Ok(())
+    }
+
+    /// Regular messages cannot be sent on a [`ChannelMode::Stream`] channel: the receiver would
+    /// interpret them as stream frames
+    fn check_message_channel(&self, kind: ChannelKind) -> Result<(), TransportError> {
+        match self.senders.get(&kind) {
+            Some(sender_metadata) if matches!(sender_metadata.mode, ChannelMode::Stream(_)) => {
+                Err(TransportError::StreamChannel(kind))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn send_mut<C: Channel>(
         &mut self,
         bytes: SendPayload,
@@ -210,20 +395,80 @@ impl Transport {
         bytes: SendPayload,
         priority: f32,
     ) -> Result<Option<MessageId>, TransportError> {
+        self.check_message_channel(kind)?;
         let sender_metadata = self
             .senders
             .get_mut(&kind)
             .ok_or(TransportError::ChannelNotFound(kind))?;
-        let message_id = sender_metadata.sender.buffer_send(bytes, priority);
+        let message_id = sender_metadata.buffer_send(bytes, priority, None);
         Ok(message_id)
     }
 
+    /// Start streaming the `data` blob over the [`ChannelMode::Stream`] channel `C`.
+    ///
+    /// The blob is split into chunks that are sent progressively; the [`StreamProgress`](crate::channel::stream::StreamProgress)
+    /// and [`StreamCompleted`](crate::channel::stream::StreamCompleted) triggers report how much of it was received.
+    pub fn send_stream<C: Channel>(&mut self, data: Bytes) -> Result<StreamId, TransportError> {
+        let kind = ChannelKind::of::<C>();
+        let sender_metadata = self
+            .senders
+            .get(&kind)
+            .ok_or(TransportError::ChannelNotFound(kind))?;
+        if !matches!(sender_metadata.mode, ChannelMode::Stream(_)) {
+            return Err(TransportError::NotAStreamChannel(kind));
+        }
+        Ok(self.streams.start(kind, data))
+    }
+
+    /// Stop sending a stream. Returns false if the stream was not being sent
+    pub fn cancel_stream(&mut self, stream_id: StreamId) -> bool {
+        self.streams.cancel(stream_id)
+    }
+
+    /// Ask the remote peer to stop sending a stream. Returns false if the stream is not being received
+    pub fn cancel_incoming_stream(&mut self, stream_id: StreamId) -> bool {
+        self.streams.reject(stream_id)
+    }
+
+    /// Get the reader of a stream sent by the remote peer
+    pub fn stream_reader(&mut self, stream_id: StreamId) -> Option<&mut StreamReader> {
+        self.streams.reader_mut(stream_id)
+    }
+
+    /// Remove the reader of a stream sent by the remote peer, once it is not needed anymore
+    pub fn remove_stream_reader(&mut self, stream_id: StreamId) -> Option<StreamReader> {
+        self.streams.remove_reader(stream_id)
+    }
+
+    /// Set the maximum size of the packets built by this transport.
+    ///
+    /// The messages that are buffered afterwards are fragmented according to the new size.
+    pub fn set_mtu(&mut self, mtu: usize) {
+        debug_assert!(mtu >= crate::packet::mtu::MIN_MTU);
+        self.packet_manager.set_mtu(mtu);
+        let fragment_size = fragment_size(mtu);
+        self.senders
+            .values_mut()
+            .for_each(|sender_metadata| sender_metadata.sender.set_fragment_size(fragment_size));
+    }
+
+    /// Current estimate of the bandwidth of the link, in bytes per second.
+    ///
+    /// Returns `None` if congestion control is not enabled in the [`PriorityConfig`].
+    pub fn bandwidth_estimate(&self) -> Option<f32> {
+        self.priority_manager.bandwidth_estimate()
+    }
+
     /// Reset the Transport to a default state upon disconnection
     pub(crate) fn reset(&mut self, registry: &ChannelRegistry) {
         self.receivers.iter_mut().for_each(|(channel_id, r)| {
             let settings = registry.settings_from_net_id(*channel_id).unwrap();
             *r = ReceiverMetadata {
                 receiver: settings.into(),
+                mode: settings.mode,
+                compressor: settings.compression.into(),
+                fec: FecDecoder::new(settings.fec, &settings.mode),
+                stats: ChannelReceiveStats::default(),
                 channel_kind: r.channel_kind,
             };
         });
@@ -231,6 +476,13 @@ impl Transport {
             let settings = registry.settings(*channel_kind).unwrap();
             *s = SenderMetadata {
                 sender: settings.into(),
+                compressor: settings.compression.into(),
+                fec: FecEncoder::new(settings.fec, &settings.mode),
+                pending_parity: None,
+                ttl: settings.ttl,
+                tracked_messages: HashMap::default(),
+                current_tick: Tick::default(),
+                stats: ChannelSendStats::default(),
                 message_acks: vec![],
                 message_nacks: vec![],
                 messages_sent: vec![],
@@ -239,7 +491,7 @@ impl Transport {
                 name: s.name,
             };
         });
-        self.priority_manager = Default::default();
+        self.priority_manager = PriorityManager::new(self.priority_manager.config.clone());
         self.packet_manager = Default::default();
         self.packet_to_message_ack_map = Default::default();
         let (send_channel, recv_channel) = crossbeam_channel::unbounded();
@@ -247,18 +499,82 @@ impl Transport {
         self.recv_channel = recv_channel;
         self.recv.clear();
         self.send.clear();
+        self.streams = StreamManager::default();
     }
 }
 
 pub struct ReceiverMetadata {
     pub receiver: ChannelReceiverEnum,
+    pub(crate) mode: ChannelMode,
+    pub(crate) compressor: ChannelCompressor,
+    pub(crate) fec: FecDecoder,
+    pub(crate) stats: ChannelReceiveStats,
     pub channel_kind: ChannelKind,
 }
 
+impl ReceiverMetadata {
+    /// Returns true if the messages of the channel are only delivered once the local tick reaches
+    /// the tick at which they were sent
+    pub fn is_tick_buffered(&self) -> bool {
+        matches!(self.mode, ChannelMode::TickBuffered(_))
+    }
+
+    /// Read the next message from the receiver, decompressing it if the channel uses compression.
+    ///
+    /// On channels that use forward error correction, lost messages that could be reconstructed are
+    /// also returned (without a [`MessageId`]).
+    ///
+    /// Messages that cannot be decompressed are dropped.
+    pub fn read_message(&mut self) -> Option<(Tick, Bytes, Option<MessageId>)> {
+        loop {
+            let (tick, bytes, message_id) = match self.fec.take_recovered() {
+                Some((tick, bytes)) => (tick, bytes, None),
+                None => {
+                    let (tick, bytes, message_id) = self.receiver.read_message()?;
+                    match self.fec.decode(tick, bytes) {
+                        Ok(Some(bytes)) => (tick, bytes, message_id),
+                        // parity or duplicate message
+                        Ok(None) => continue,
+                        Err(e) => {
+                            error!(
+                                ?message_id,
+                                channel = ?self.channel_kind,
+                                "Dropping message with invalid forward error correction data: {e}"
+                            );
+                            continue;
+                        }
+                    }
+                }
+            };
+            match self.compressor.decompress(bytes) {
+                Ok(bytes) => return Some((tick, bytes, message_id)),
+                Err(e) => error!(
+                    ?message_id,
+                    channel = ?self.channel_kind,
+                    "Dropping message that could not be decompressed: {e}"
+                ),
+            }
+        }
+    }
+}
+
 #[doc(hidden)]
 pub struct SenderMetadata {
     /// The component id of the ChannelSender<C> component
     pub sender: ChannelSenderEnum,
+    pub(crate) compressor: ChannelCompressor,
+    pub(crate) fec: FecEncoder,
+    /// Parity message of the last forward error correction group, with its priority and ttl.
+    ///
+    /// It is only buffered once the last message of the group was sent, so that both are not in the same packet.
+    pub(crate) pending_parity: Option<(Bytes, f32, Option<Duration>)>,
+    /// Default time-to-live of the messages of the channel
+    pub(crate) ttl: Option<Duration>,
+    /// Handles of the messages whose delivery is tracked, with the time when they were buffered
+    pub(crate) tracked_messages: HashMap<MessageId, (MessageHandle, Duration)>,
+    /// Tick of the [`LocalTimeline`] used to stamp the messages of [`ChannelMode::TickBuffered`] channels
+    pub(crate) current_tick: Tick,
+    pub(crate) stats: ChannelSendStats,
     // TODO: these are currently only used by EntityUpdatesChannel. Maybe limit their computation only to that channel?
     /// List of messages that have been acked; is cleared every frame.
     pub message_acks: Vec<MessageId>,
@@ -272,6 +588,95 @@ pub struct SenderMetadata {
     pub(crate) name: &'static str,
 }
 
+impl SenderMetadata {
+    /// Buffer a message in the sender, compressing it first if the channel uses compression.
+    ///
+    /// On [`ChannelMode::TickBuffered`] channels, the message is also stamped with the current tick.
+    /// On channels that use forward error correction, a parity message is also created at the end of each group;
+    /// it is buffered by [`SenderMetadata::flush_parity`] once the messages of the group were sent.
+    ///
+    /// `ttl` overrides the default time-to-live of the channel.
+    pub fn buffer_send(
+        &mut self,
+        bytes: Bytes,
+        priority: f32,
+        ttl: Option<Duration>,
+    ) -> Option<MessageId> {
+        let bytes = self.compressor.compress(bytes);
+        let bytes = match self.mode {
+            ChannelMode::TickBuffered(_) => stamp_tick(self.current_tick, bytes),
+            _ => bytes,
+        };
+        // the receiver of an ordered reliable channel would wait forever for an expired message
+        let ttl = match self.mode {
+            ChannelMode::OrderedReliable(_) => None,
+            _ => ttl.or(self.ttl),
+        };
+        let (bytes, parity) = self.fec.encode(bytes);
+        let message_id = self.sender.buffer_send(bytes, priority, ttl);
+        if let Some(parity) = parity {
+            // the previous group was not sent yet, its parity cannot be delayed further
+            self.flush_parity();
+            self.pending_parity = Some((parity, priority, ttl));
+        }
+        message_id
+    }
+
+    /// Buffer the parity message of the last forward error correction group.
+    ///
+    /// Called after the sender sent its messages, so that the parity is sent in a later packet
+    /// than the last message of the group.
+    pub(crate) fn flush_parity(&mut self) {
+        if let Some((parity, priority, ttl)) = self.pending_parity.take() {
+            self.sender.buffer_send(parity, priority, ttl);
+        }
+    }
+
+    /// Track the delivery of a message that was buffered in the sender
+    pub(crate) fn track(
+        &mut self,
+        message_id: Option<MessageId>,
+        handle: MessageHandle,
+        now: Duration,
+    ) {
+        // we only know if the message was delivered on channels that track acks
+        if !self.mode.is_watching_acks() {
+            return;
+        }
+        if let Some(message_id) = message_id {
+            self.tracked_messages.insert(message_id, (handle, now));
+        }
+    }
+
+    /// Stop tracking the unreliable messages that were buffered before `cutoff`, and return their handles.
+    ///
+    /// These messages were never sent (for example because of the bandwidth quota), or were sent in
+    /// packets that were neither acked nor lost.
+    pub(crate) fn untrack_stale(&mut self, cutoff: Duration) -> Vec<MessageHandle> {
+        if self.mode.is_reliable() {
+            return vec![];
+        }
+        let mut stale = vec![];
+        self.tracked_messages.retain(|_, (handle, buffered_at)| {
+            if *buffered_at < cutoff {
+                stale.push(*handle);
+                return false;
+            }
+            true
+        });
+        stale
+    }
+}
+
+/// Prefix the message with the tick at which it was sent
+fn stamp_tick(tick: Tick, bytes: Bytes) -> Bytes {
+    let mut writer = Writer::with_capacity(tick.bytes_len() + bytes.len());
+    // writing to a Writer cannot fail
+    tick.to_bytes(&mut writer).unwrap();
+    writer.extend_from_slice(&bytes);
+    writer.split()
+}
+
 // fn on_add<C: Channel>(mut world: DeferredWorld, context: HookContext) {
 //     let entity = context.entity;
 //     let mut registry = world.resource_mut::<ChannelRegistry>();
@@ -338,9 +743,45 @@ pub enum ChannelMode {
     SequencedReliable(ReliableSettings),
     /// Messages will arrive in the correct order at the destination
     OrderedReliable(ReliableSettings),
+    /// Messages are reliable and stamped with the tick of the sender's [`LocalTimeline`] when they were sent.
+    ///
+    /// The receiver only delivers a message once its own [`LocalTimeline`] reaches that tick, or
+    /// as soon as it arrives if the tick is already in the past.
+    /// This is useful for gameplay events that must be applied at a specific tick (e.g. "ability cast at tick N").
+    ///
+    /// The ticks of the two [`LocalTimeline`]s are compared directly, so this mode is only meant for
+    /// messages sent from a client to the server: the client's [`LocalTimeline`] runs ahead of the server's
+    /// (in the same way as the inputs in the `InputBuffer`), whereas messages sent from the server would
+    /// always be late on the client.
+    ///
+    /// Messages whose tick is too far ahead of the receiver's tick are dropped.
+    TickBuffered(ReliableSettings),
+    /// The channel is used to stream large blobs of data with [`Transport::send_stream`].
+    ///
+    /// The channel cannot be used to send regular messages: sending one returns a [`TransportError::StreamChannel`] error.
+    Stream(StreamSettings),
 }
 
 impl ChannelMode {
+    fn reliable_settings(&self) -> Option<&ReliableSettings> {
+        match self {
+            ChannelMode::UnorderedReliable(settings)
+            | ChannelMode::SequencedReliable(settings)
+            | ChannelMode::OrderedReliable(settings)
+            | ChannelMode::TickBuffered(settings) => Some(settings),
+            ChannelMode::Stream(settings) => Some(&settings.reliable),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the channel can use [`ForwardErrorCorrection`]
+    pub(crate) fn supports_fec(&self) -> bool {
+        matches!(
+            self,
+            ChannelMode::UnorderedUnreliable | ChannelMode::UnorderedUnreliableWithAcks
+        )
+    }
+
     pub fn is_reliable(&self) -> bool {
         match self {
             ChannelMode::UnorderedUnreliableWithAcks => false,
@@ -349,6 +790,8 @@ impl ChannelMode {
             ChannelMode::UnorderedReliable(_) => true,
             ChannelMode::SequencedReliable(_) => true,
             ChannelMode::OrderedReliable(_) => true,
+            ChannelMode::TickBuffered(_) => true,
+            ChannelMode::Stream(_) => true,
         }
     }
 
@@ -361,6 +804,8 @@ impl ChannelMode {
             ChannelMode::UnorderedReliable(_) => true,
             ChannelMode::SequencedReliable(_) => true,
             ChannelMode::OrderedReliable(_) => true,
+            ChannelMode::TickBuffered(_) => true,
+            ChannelMode::Stream(_) => true,
         }
     }
 }
@@ -371,6 +816,20 @@ pub struct ReliableSettings {
     pub rtt_resend_factor: f32,
     /// Minimum duration to wait before resending a packet if it has not been acked
     pub rtt_resend_min_delay: Duration,
+    /// The resend delay is multiplied by this factor every time a message is resent.
+    ///
+    /// Set to 1.0 to always wait the same delay before resending. Must be at least 1.0.
+    pub backoff_factor: f32,
+    /// Maximum delay between two sends of a message, once the backoff is applied
+    pub max_resend_delay: Option<Duration>,
+    /// Maximum number of times a message is resent before we give up on it.
+    ///
+    /// Not used on [`ChannelMode::OrderedReliable`] channels, since the receiver would wait forever for the abandoned message.
+    pub max_retries: Option<u32>,
+    /// Maximum duration after the first send of a message before we give up on it.
+    ///
+    /// Not used on [`ChannelMode::OrderedReliable`] channels, since the receiver would wait forever for the abandoned message.
+    pub max_duration: Option<Duration>,
 }
 
 impl Default for ReliableSettings {
@@ -378,15 +837,44 @@ impl Default for ReliableSettings {
         Self {
             rtt_resend_factor: 1.5,
             rtt_resend_min_delay: Duration::default(),
+            backoff_factor: 1.0,
+            max_resend_delay: None,
+            max_retries: None,
+            max_duration: None,
         }
     }
 }
 
 impl ReliableSettings {
+    /// Settings that never give up on a message
+    pub(crate) fn without_abandonment(self) -> Self {
+        Self {
+            max_retries: None,
+            max_duration: None,
+            ..self
+        }
+    }
+
     pub(crate) fn resend_delay(&self, rtt: Duration) -> Duration {
         let delay = rtt.mul_f32(self.rtt_resend_factor);
         core::cmp::max(delay, self.rtt_resend_min_delay)
     }
+
+    /// Delay to wait before resending a message that was already sent `num_sends` times
+    pub(crate) fn backoff_delay(&self, resend_delay: Duration, num_sends: u32) -> Duration {
+        let max_resend_delay = self.max_resend_delay.unwrap_or(Duration::MAX);
+        let mut delay = resend_delay;
+        if self.backoff_factor > 1.0 {
+            for _ in 1..num_sends {
+                if delay >= max_resend_delay {
+                    break;
+                }
+                delay = Duration::try_from_secs_f32(delay.as_secs_f32() * self.backoff_factor)
+                    .unwrap_or(max_resend_delay);
+            }
+        }
+        delay.min(max_resend_delay)
+    }
 }
 
 /// Default channel to send inputs from client to server. This is a Sequenced Unreliable channel.
@@ -0,0 +1,310 @@
+//! Compression of the messages sent on a channel.
+//!
+//! The [`Compression`] of a channel is set in its [`ChannelSettings`](crate::prelude::ChannelSettings).
+//! Messages are compressed when they are buffered in the channel sender, before they get split into
+//! fragments, so that big messages (for example the initial replication of many entities, or a chat
+//! history) are sent in fewer packets.
+//!
+//! On channels that use compression, every message starts with a byte that indicates if the rest of
+//! the message is compressed: messages that are too small, or that don't get smaller when compressed,
+//! are sent as-is.
+use bytes::{BufMut, Bytes, BytesMut};
+use core::fmt::{Debug, Formatter};
+use tracing::error;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Messages smaller than this number of bytes are never compressed
+const MIN_COMPRESSED_SIZE: usize = 64;
+
+/// Default maximum size of a decompressed message.
+///
+/// Protects the receiver against small packets that would decompress to a huge message.
+/// See [`ChannelCompressor::set_max_decompressed_size`].
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+/// The rest of the message is not compressed
+const UNCOMPRESSED: u8 = 0;
+/// The rest of the message contains the size of the decompressed message as a u32, followed by
+/// the compressed bytes
+const COMPRESSED: u8 = 1;
+
+/// Compression algorithm used for the messages of a channel.
+///
+/// Both peers must use the same [`Compression`] (and the same dictionary) for a given channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Compression {
+    /// Messages are not compressed
+    #[default]
+    None,
+    /// LZ4 block compression: very fast, with a moderate compression ratio.
+    ///
+    /// A dictionary trained on typical messages of the channel can greatly improve the compression
+    /// ratio of small messages.
+    #[cfg(feature = "lz4")]
+    Lz4 { dictionary: Option<&'static [u8]> },
+    /// Zstandard compression: better compression ratio than LZ4, at a higher CPU cost.
+    ///
+    /// A dictionary trained on typical messages of the channel (for example with `zstd --train`)
+    /// can greatly improve the compression ratio of small messages.
+    #[cfg(feature = "zstd")]
+    Zstd {
+        level: i32,
+        dictionary: Option<&'static [u8]>,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CompressionError {
+    #[error("the message does not contain the compression header")]
+    MissingHeader,
+    #[error("invalid compression header {0}")]
+    InvalidHeader(u8),
+    #[error(
+        "the decompressed message would be {size} bytes, which is more than the maximum of {max} bytes"
+    )]
+    TooLarge { size: usize, max: usize },
+    #[error("the decompressed message has {actual} bytes instead of {expected}")]
+    SizeMismatch { expected: usize, actual: usize },
+    #[cfg(feature = "lz4")]
+    #[error(transparent)]
+    Lz4(#[from] lz4_flex::block::DecompressError),
+    #[cfg(feature = "zstd")]
+    #[error(transparent)]
+    Zstd(#[from] std::io::Error),
+}
+
+/// Compresses and decompresses the messages of a channel, according to its [`Compression`]
+pub struct ChannelCompressor {
+    compression: Compression,
+    max_decompressed_size: usize,
+    /// Zstd contexts are re-used between messages to avoid re-loading the dictionary every time
+    #[cfg(feature = "zstd")]
+    zstd: Option<ZstdContext>,
+}
+
+#[cfg(feature = "zstd")]
+struct ZstdContext {
+    compressor: bevy::utils::synccell::SyncCell<zstd::bulk::Compressor<'static>>,
+    decompressor: bevy::utils::synccell::SyncCell<zstd::bulk::Decompressor<'static>>,
+}
+
+impl Debug for ChannelCompressor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ChannelCompressor")
+            .field("compression", &self.compression)
+            .field("max_decompressed_size", &self.max_decompressed_size)
+            .finish()
+    }
+}
+
+impl From<Compression> for ChannelCompressor {
+    fn from(compression: Compression) -> Self {
+        Self::new(compression)
+    }
+}
+
+impl ChannelCompressor {
+    /// Create the compressor for a channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the zstd dictionary is invalid.
+    pub fn new(compression: Compression) -> Self {
+        #[cfg(feature = "zstd")]
+        let zstd = match compression {
+            Compression::Zstd { level, dictionary } => {
+                let (compressor, decompressor) = match dictionary {
+                    Some(dictionary) => (
+                        zstd::bulk::Compressor::with_dictionary(level, dictionary),
+                        zstd::bulk::Decompressor::with_dictionary(dictionary),
+                    ),
+                    None => (
+                        zstd::bulk::Compressor::new(level),
+                        zstd::bulk::Decompressor::new(),
+                    ),
+                };
+                Some(ZstdContext {
+                    compressor: bevy::utils::synccell::SyncCell::new(
+                        compressor.expect("invalid zstd compression settings"),
+                    ),
+                    decompressor: bevy::utils::synccell::SyncCell::new(
+                        decompressor.expect("invalid zstd dictionary"),
+                    ),
+                })
+            }
+            _ => None,
+        };
+        Self {
+            compression,
+            max_decompressed_size: MAX_DECOMPRESSED_SIZE,
+            #[cfg(feature = "zstd")]
+            zstd,
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Set the maximum size of a decompressed message; bigger messages are rejected before being decompressed.
+    ///
+    /// The default is [`MAX_DECOMPRESSED_SIZE`].
+    pub fn set_max_decompressed_size(&mut self, max_decompressed_size: usize) {
+        self.max_decompressed_size = max_decompressed_size;
+    }
+
+    /// Compress a message before it is buffered in the channel sender
+    pub fn compress(&mut self, bytes: Bytes) -> Bytes {
+        if self.compression == Compression::None {
+            return bytes;
+        }
+        if bytes.len() >= MIN_COMPRESSED_SIZE {
+            match self.compress_block(&bytes) {
+                // only use the compressed message if it is smaller than the original
+                Ok(compressed) if compressed.len() + 4 < bytes.len() => {
+                    let mut writer = BytesMut::with_capacity(5 + compressed.len());
+                    writer.put_u8(COMPRESSED);
+                    writer.put_u32_le(bytes.len() as u32);
+                    writer.put_slice(&compressed);
+                    return writer.freeze();
+                }
+                Ok(_) => {}
+                Err(e) => error!("Error compressing message: {e:?}"),
+            }
+        }
+        let mut writer = BytesMut::with_capacity(1 + bytes.len());
+        writer.put_u8(UNCOMPRESSED);
+        writer.put_slice(&bytes);
+        writer.freeze()
+    }
+
+    /// Decompress a message that was read from the channel receiver
+    pub fn decompress(&mut self, bytes: Bytes) -> Result<Bytes, CompressionError> {
+        if self.compression == Compression::None {
+            return Ok(bytes);
+        }
+        match bytes.first().copied() {
+            None => Err(CompressionError::MissingHeader),
+            Some(UNCOMPRESSED) => Ok(bytes.slice(1..)),
+            Some(COMPRESSED) => {
+                let Some(size) = bytes.get(1..5) else {
+                    return Err(CompressionError::MissingHeader);
+                };
+                let size = u32::from_le_bytes(size.try_into().unwrap()) as usize;
+                if size > self.max_decompressed_size {
+                    return Err(CompressionError::TooLarge {
+                        size,
+                        max: self.max_decompressed_size,
+                    });
+                }
+                let decompressed = self.decompress_block(&bytes[5..], size)?;
+                if decompressed.len() != size {
+                    return Err(CompressionError::SizeMismatch {
+                        expected: size,
+                        actual: decompressed.len(),
+                    });
+                }
+                Ok(Bytes::from(decompressed))
+            }
+            Some(header) => Err(CompressionError::InvalidHeader(header)),
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn compress_block(&mut self, bytes: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        match self.compression {
+            Compression::None => unreachable!(),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 { dictionary } => Ok(match dictionary {
+                Some(dictionary) => lz4_flex::block::compress_with_dict(bytes, dictionary),
+                None => lz4_flex::block::compress(bytes),
+            }),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => {
+                let zstd = self.zstd.as_mut().unwrap();
+                Ok(zstd.compressor.get().compress(bytes)?)
+            }
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn decompress_block(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, CompressionError> {
+        match self.compression {
+            Compression::None => unreachable!(),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 { dictionary } => Ok(match dictionary {
+                Some(dictionary) => lz4_flex::block::decompress_with_dict(bytes, size, dictionary)?,
+                None => lz4_flex::block::decompress(bytes, size)?,
+            }),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => {
+                let zstd = self.zstd.as_mut().unwrap();
+                Ok(zstd.decompressor.get().decompress(bytes, size)?)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "lz4", feature = "zstd")))]
+mod tests {
+    use super::*;
+
+    fn compressions() -> Vec<Compression> {
+        vec![
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 { dictionary: None },
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                level: 3,
+                dictionary: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        for compression in compressions() {
+            let mut compressor = ChannelCompressor::new(compression);
+            // repetitive messages are compressed
+            let message = Bytes::from("hello world ".repeat(100));
+            let compressed = compressor.compress(message.clone());
+            assert_eq!(compressed[0], COMPRESSED);
+            assert!(compressed.len() < message.len());
+            assert_eq!(compressor.decompress(compressed).unwrap(), message);
+
+            // small messages are sent as-is
+            let message = Bytes::from_static(b"hello");
+            let compressed = compressor.compress(message.clone());
+            assert_eq!(compressed[0], UNCOMPRESSED);
+            assert_eq!(compressor.decompress(compressed).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        for compression in compressions() {
+            let mut compressor = ChannelCompressor::new(compression);
+            let mut message = BytesMut::new();
+            message.put_u8(COMPRESSED);
+            message.put_u32_le(MAX_DECOMPRESSED_SIZE as u32 + 1);
+            message.put_slice(&[0; 16]);
+            assert!(matches!(
+                compressor.decompress(message.freeze()),
+                Err(CompressionError::TooLarge { .. })
+            ));
+
+            // the limit can be lowered
+            compressor.set_max_decompressed_size(100);
+            let compressed = compressor.compress(Bytes::from("hello world ".repeat(100)));
+            assert!(matches!(
+                compressor.decompress(compressed),
+                Err(CompressionError::TooLarge {
+                    size: 1200,
+                    max: 100
+                })
+            ));
+        }
+    }
+}
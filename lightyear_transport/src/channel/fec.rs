@@ -0,0 +1,338 @@
+//! Forward error correction for the unreliable channels.
+//!
+//! The [`ForwardErrorCorrection`] of a channel is set in its [`ChannelSettings`](crate::prelude::ChannelSettings).
+//! On unreliable channels, a lost message is never resent; for channels that send state updates
+//! every frame, the receiver has to wait for the next update, which can cause a visible hitch.
+//!
+//! With [`ForwardErrorCorrection::XorParity`], the messages are split in groups of `group_size`
+//! messages, and the sender sends an extra parity message after each group, containing the XOR of
+//! all the messages of the group. If a single message of the group is lost, the receiver can
+//! reconstruct it from the other messages and the parity message.
+//!
+//! The parity message is sent in a later packet than the last message of the group, so that
+//! losing a single packet cannot lose both of them.
+//!
+//! The reconstructed messages arrive after the newer messages that were sent in the meantime,
+//! so forward error correction is only supported on [`ChannelMode::UnorderedUnreliable`] and
+//! [`ChannelMode::UnorderedUnreliableWithAcks`] channels.
+//!
+//! On channels that use forward error correction, every message starts with a 3-bytes header
+//! containing the group and the index of the message in the group.
+use alloc::collections::VecDeque;
+use bytes::{BufMut, Bytes, BytesMut};
+use lightyear_core::tick::Tick;
+use lightyear_utils::collections::HashMap;
+
+use crate::channel::builder::ChannelMode;
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+/// Number of groups that the receiver keeps in memory to reconstruct lost messages
+const MAX_GROUPS: u16 = 32;
+
+const HEADER_SIZE: usize = 3;
+
+/// Forward error correction scheme used for the messages of a channel.
+///
+/// Both peers must use the same [`ForwardErrorCorrection`] for a given channel.
+/// Forward error correction is only used on unordered unreliable channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ForwardErrorCorrection {
+    #[default]
+    None,
+    /// Send a parity message after every `group_size` messages, which lets the receiver
+    /// reconstruct one lost message in each group.
+    ///
+    /// Smaller groups can recover from more losses, at the cost of more bandwidth.
+    XorParity { group_size: u8 },
+}
+
+impl ForwardErrorCorrection {
+    fn group_size(&self) -> Option<u8> {
+        match self {
+            ForwardErrorCorrection::None => None,
+            ForwardErrorCorrection::XorParity { group_size } => Some((*group_size).max(1)),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum FecError {
+    #[error("the message does not contain the forward error correction header")]
+    MissingHeader,
+    #[error("invalid message index {0} in the forward error correction group")]
+    InvalidIndex(u8),
+    #[error("invalid parity message")]
+    InvalidParity,
+}
+
+/// Returns true if group `a` is more recent than group `b`, taking into account the wrapping of the group ids
+fn is_newer_group(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < u16::MAX / 2
+}
+
+fn write_header(writer: &mut BytesMut, group: u16, index: u8) {
+    writer.put_u16(group);
+    writer.put_u8(index);
+}
+
+/// Adds the forward error correction data to the messages of a channel
+#[derive(Debug)]
+pub struct FecEncoder {
+    group_size: Option<u8>,
+    group: u16,
+    index: u8,
+    /// XOR of the lengths of the messages of the current group
+    parity_len: u32,
+    /// XOR of the messages of the current group
+    parity: Vec<u8>,
+}
+
+impl FecEncoder {
+    /// Create the encoder for a channel. Forward error correction is disabled on the channels that don't support it
+    pub fn new(fec: ForwardErrorCorrection, mode: &ChannelMode) -> Self {
+        Self {
+            group_size: fec.group_size().filter(|_| mode.supports_fec()),
+            group: 0,
+            index: 0,
+            parity_len: 0,
+            parity: Vec::new(),
+        }
+    }
+
+    /// Add the header to a message before it is buffered in the channel sender.
+    ///
+    /// Returns the parity message of the group if the message was the last of its group.
+    pub fn encode(&mut self, bytes: Bytes) -> (Bytes, Option<Bytes>) {
+        let Some(group_size) = self.group_size else {
+            return (bytes, None);
+        };
+        let mut writer = BytesMut::with_capacity(HEADER_SIZE + bytes.len());
+        write_header(&mut writer, self.group, self.index);
+        writer.put_slice(&bytes);
+
+        self.parity_len ^= bytes.len() as u32;
+        if self.parity.len() < bytes.len() {
+            self.parity.resize(bytes.len(), 0);
+        }
+        self.parity
+            .iter_mut()
+            .zip(bytes.iter())
+            .for_each(|(p, b)| *p ^= b);
+        self.index += 1;
+        if self.index < group_size {
+            return (writer.freeze(), None);
+        }
+
+        // the group is complete, send the parity message
+        let mut parity = BytesMut::with_capacity(HEADER_SIZE + 4 + self.parity.len());
+        write_header(&mut parity, self.group, group_size);
+        parity.put_u32(self.parity_len);
+        parity.put_slice(&self.parity);
+        self.group = self.group.wrapping_add(1);
+        self.index = 0;
+        self.parity_len = 0;
+        self.parity.clear();
+        (writer.freeze(), Some(parity.freeze()))
+    }
+}
+
+#[derive(Debug)]
+struct FecGroup {
+    messages: Vec<Option<Bytes>>,
+    parity: Option<(Tick, Bytes)>,
+    /// True if all the messages of the group were received or reconstructed
+    complete: bool,
+}
+
+/// Reconstructs the lost messages of a channel from the forward error correction data
+#[derive(Debug)]
+pub struct FecDecoder {
+    group_size: Option<u8>,
+    groups: HashMap<u16, FecGroup>,
+    newest_group: Option<u16>,
+    recovered: VecDeque<(Tick, Bytes)>,
+}
+
+impl FecDecoder {
+    /// Create the decoder for a channel. Forward error correction is disabled on the channels that don't support it
+    pub fn new(fec: ForwardErrorCorrection, mode: &ChannelMode) -> Self {
+        Self {
+            group_size: fec.group_size().filter(|_| mode.supports_fec()),
+            groups: HashMap::default(),
+            newest_group: None,
+            recovered: VecDeque::new(),
+        }
+    }
+
+    /// Returns a message that was reconstructed from the parity data.
+    ///
+    /// The tick of a reconstructed message is the tick at which the parity message was sent.
+    pub fn take_recovered(&mut self) -> Option<(Tick, Bytes)> {
+        self.recovered.pop_front()
+    }
+
+    /// Process a message that was read from the channel receiver.
+    ///
+    /// Returns the content of the message, or None if it was a parity message or a duplicate.
+    pub fn decode(&mut self, tick: Tick, bytes: Bytes) -> Result<Option<Bytes>, FecError> {
+        let Some(group_size) = self.group_size else {
+            return Ok(Some(bytes));
+        };
+        if bytes.len() < HEADER_SIZE {
+            return Err(FecError::MissingHeader);
+        }
+        let group_id = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let index = bytes[2];
+        if index > group_size {
+            return Err(FecError::InvalidIndex(index));
+        }
+        if self.newest_group.is_some_and(|newest| {
+            !is_newer_group(group_id, newest) && newest.wrapping_sub(group_id) >= MAX_GROUPS
+        }) {
+            // the group is too old
+            return Ok(None);
+        }
+        self.update_newest_group(group_id);
+        let group = self.groups.entry(group_id).or_insert_with(|| FecGroup {
+            messages: vec![None; group_size as usize],
+            parity: None,
+            complete: false,
+        });
+
+        let mut message = None;
+        if index == group_size {
+            group.parity = Some((tick, bytes.slice(HEADER_SIZE..)));
+        } else if group.messages[index as usize].is_none() {
+            let content = bytes.slice(HEADER_SIZE..);
+            group.messages[index as usize] = Some(content.clone());
+            message = Some(content);
+        }
+        self.try_recover(group_id)?;
+        Ok(message)
+    }
+
+    fn update_newest_group(&mut self, group_id: u16) {
+        let newest = match self.newest_group {
+            Some(newest) if !is_newer_group(group_id, newest) => newest,
+            _ => group_id,
+        };
+        self.newest_group = Some(newest);
+        self.groups
+            .retain(|id, _| newest.wrapping_sub(*id) < MAX_GROUPS);
+    }
+
+    /// Reconstruct the missing message of the group if all the other messages and the parity were received
+    fn try_recover(&mut self, group_id: u16) -> Result<(), FecError> {
+        let Some(group) = self.groups.get_mut(&group_id) else {
+            return Ok(());
+        };
+        if group.complete {
+            return Ok(());
+        }
+        let mut missing = group
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.is_none())
+            .map(|(i, _)| i);
+        let Some(missing_index) = missing.next() else {
+            group.complete = true;
+            return Ok(());
+        };
+        if missing.next().is_some() {
+            return Ok(());
+        }
+        let Some((tick, parity)) = &group.parity else {
+            return Ok(());
+        };
+        if parity.len() < 4 {
+            return Err(FecError::InvalidParity);
+        }
+        let mut len = u32::from_be_bytes(parity[..4].try_into().unwrap());
+        let mut content = parity[4..].to_vec();
+        for message in group.messages.iter().flatten() {
+            len ^= message.len() as u32;
+            if message.len() > content.len() {
+                return Err(FecError::InvalidParity);
+            }
+            content
+                .iter_mut()
+                .zip(message.iter())
+                .for_each(|(c, m)| *c ^= m);
+        }
+        let len = len as usize;
+        if len > content.len() {
+            return Err(FecError::InvalidParity);
+        }
+        content.truncate(len);
+        let content = Bytes::from(content);
+        let tick = *tick;
+        group.messages[missing_index] = Some(content.clone());
+        group.complete = true;
+        self.recovered.push_back((tick, content));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEC: ForwardErrorCorrection = ForwardErrorCorrection::XorParity { group_size: 3 };
+
+    fn encode_group(encoder: &mut FecEncoder, messages: [&'static str; 3]) -> Vec<Bytes> {
+        let mut encoded = vec![];
+        for message in messages {
+            let (bytes, parity) = encoder.encode(Bytes::from(message));
+            encoded.push(bytes);
+            encoded.extend(parity);
+        }
+        encoded
+    }
+
+    #[test]
+    fn test_recover_lost_message() {
+        let mut encoder = FecEncoder::new(FEC, &ChannelMode::UnorderedUnreliable);
+        let mut decoder = FecDecoder::new(FEC, &ChannelMode::UnorderedUnreliable);
+        let encoded = encode_group(&mut encoder, ["hello", "a", "world!"]);
+        assert_eq!(encoded.len(), 4);
+
+        // the second message is lost
+        assert_eq!(
+            decoder.decode(Tick(0), encoded[0].clone()).unwrap(),
+            Some(Bytes::from("hello"))
+        );
+        assert_eq!(
+            decoder.decode(Tick(2), encoded[2].clone()).unwrap(),
+            Some(Bytes::from("world!"))
+        );
+        assert_eq!(decoder.take_recovered(), None);
+        assert_eq!(decoder.decode(Tick(3), encoded[3].clone()).unwrap(), None);
+        assert_eq!(decoder.take_recovered(), Some((Tick(3), Bytes::from("a"))));
+
+        // the lost message arrives late: it is not returned twice
+        assert_eq!(decoder.decode(Tick(1), encoded[1].clone()).unwrap(), None);
+
+        // two messages are lost in the next group: they cannot be recovered
+        let encoded = encode_group(&mut encoder, ["1", "2", "3"]);
+        decoder.decode(Tick(4), encoded[0].clone()).unwrap();
+        decoder.decode(Tick(7), encoded[3].clone()).unwrap();
+        assert_eq!(decoder.take_recovered(), None);
+    }
+
+    #[test]
+    fn test_disabled_on_unsupported_channels() {
+        let mode = ChannelMode::SequencedUnreliable;
+        let mut encoder = FecEncoder::new(FEC, &mode);
+        let mut decoder = FecDecoder::new(FEC, &mode);
+        let (bytes, parity) = encoder.encode(Bytes::from("1"));
+        assert_eq!(bytes, Bytes::from("1"));
+        assert_eq!(parity, None);
+        assert_eq!(
+            decoder.decode(Tick(0), bytes).unwrap(),
+            Some(Bytes::from("1"))
+        );
+    }
+}
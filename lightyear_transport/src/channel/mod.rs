@@ -4,11 +4,13 @@
 pub use crate::channel::registry::ChannelKind;
 
 pub mod builder;
+pub mod compression;
+pub mod fec;
 pub mod receivers;
 pub mod senders;
+pub mod stream;
 
 pub mod registry;
-#[cfg(feature = "trace")]
 pub mod stats;
 
 pub trait Channel: Send + Sync + 'static {}
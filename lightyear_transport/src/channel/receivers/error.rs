@@ -1,8 +1,14 @@
 //! Errors for receiving packets
 
+use crate::limits::LimitError;
+
 pub type Result<T> = core::result::Result<T, ChannelReceiveError>;
 #[derive(thiserror::Error, Debug)]
 pub enum ChannelReceiveError {
     #[error("A message was received without a message ID")]
     MissingMessageId,
+    #[error("A fragment is inconsistent with the other fragments of its message")]
+    InvalidFragment,
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitError),
 }
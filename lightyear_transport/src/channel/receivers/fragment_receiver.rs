@@ -2,8 +2,9 @@
 use alloc::{vec, vec::Vec};
 use bevy::platform::collections::HashMap;
 
+use super::error::{ChannelReceiveError, Result};
+use crate::limits::{LimitError, ReceiveLimits};
 use crate::packet::message::{FragmentData, MessageId};
-use crate::packet::packet::FRAGMENT_SIZE;
 use bytes::Bytes;
 use core::time::Duration;
 use lightyear_core::tick::Tick;
@@ -13,15 +14,25 @@ use tracing::trace;
 #[derive(Debug)]
 pub struct FragmentReceiver {
     fragment_messages: HashMap<MessageId, FragmentConstructor>,
+    max_message_size: usize,
+    max_in_flight_fragments: usize,
 }
 
 impl FragmentReceiver {
     pub fn new() -> Self {
+        let limits = ReceiveLimits::default();
         Self {
             fragment_messages: HashMap::default(),
+            max_message_size: limits.max_message_size,
+            max_in_flight_fragments: limits.max_in_flight_fragments,
         }
     }
 
+    pub fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.max_message_size = limits.max_message_size;
+        self.max_in_flight_fragments = limits.max_in_flight_fragments;
+    }
+
     /// Discard all messages for which the latest fragment was received before the cleanup time
     /// (i.e. we probably lost some fragments and we will never complete the message)
     ///
@@ -34,34 +45,78 @@ impl FragmentReceiver {
         })
     }
 
+    /// Number of fragments of the messages that are being reassembled
+    pub fn num_in_flight_fragments(&self) -> usize {
+        self.fragment_messages
+            .values()
+            .map(|c| c.num_fragments)
+            .sum()
+    }
+
+    /// Number of bytes of the fragments that have been received but not reassembled yet
+    pub fn num_buffered_bytes(&self) -> usize {
+        self.fragment_messages.values().map(|c| c.num_bytes).sum()
+    }
+
     /// Receive a fragment of a FragmentData message.
     ///
     /// When we complete the final message by aggregating all fragments, we will return the
     /// `remote_sent_tick` associated with the first fragment received.
+    ///
+    /// Returns an error if the fragment is invalid or if it exceeds the limits of the receiver;
+    /// in that case the partially reassembled message is discarded.
     pub fn receive_fragment(
         &mut self,
         fragment: FragmentData,
         remote_sent_tick: Tick,
         current_time: Option<Duration>,
-    ) -> Option<(Tick, Bytes)> {
+    ) -> Result<Option<(Tick, Bytes)>> {
+        let num_fragments = fragment.num_fragments.0 as usize;
+        let fragment_index = fragment.fragment_id.0 as usize;
+        if fragment_index >= num_fragments {
+            return Err(ChannelReceiveError::InvalidFragment);
+        }
+        if !self.fragment_messages.contains_key(&fragment.message_id) {
+            // check the limit before allocating the constructor
+            let fragments = self.num_in_flight_fragments() + num_fragments;
+            if fragments > self.max_in_flight_fragments {
+                return Err(LimitError::TooManyFragments {
+                    fragments,
+                    max: self.max_in_flight_fragments,
+                }
+                .into());
+            }
+        }
         let fragment_message = self
             .fragment_messages
             .entry(fragment.message_id)
-            .or_insert_with(|| {
-                FragmentConstructor::new(remote_sent_tick, fragment.num_fragments.0 as usize)
-            });
-
-        // completed the fragmented message!
-        if let Some(payload) = fragment_message.receive_fragment(
-            fragment.fragment_id.0 as usize,
-            fragment.bytes.as_ref(),
-            current_time,
-        ) {
+            .or_insert_with(|| FragmentConstructor::new(remote_sent_tick, num_fragments));
+        if fragment_message.num_fragments != num_fragments {
             self.fragment_messages.remove(&fragment.message_id);
-            return Some(payload);
+            return Err(ChannelReceiveError::InvalidFragment);
+        }
+        let size = fragment_message.num_bytes + fragment.bytes.len();
+        if size > self.max_message_size {
+            self.fragment_messages.remove(&fragment.message_id);
+            return Err(LimitError::MessageTooLarge {
+                size,
+                max: self.max_message_size,
+            }
+            .into());
         }
 
-        None
+        match fragment_message.receive_fragment(fragment_index, fragment.bytes, current_time) {
+            // completed the fragmented message!
+            Ok(Some(payload)) => {
+                self.fragment_messages.remove(&fragment.message_id);
+                Ok(Some(payload))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.fragment_messages.remove(&fragment.message_id);
+                Err(e)
+            }
+        }
     }
 }
 
@@ -70,9 +125,17 @@ impl FragmentReceiver {
 pub struct FragmentConstructor {
     num_fragments: usize,
     num_received_fragments: usize,
-    received: Vec<bool>,
-    // bytes: Bytes,
-    bytes: Vec<u8>,
+    /// Total size of the fragments received so far
+    num_bytes: usize,
+    /// The fragments received so far.
+    ///
+    /// We don't assume a fragment size, because the remote can change its MTU
+    /// (and therefore the size of its fragments) at any time.
+    fragments: Vec<Option<Bytes>>,
+    /// Size of the fragments of this message, except for the last one which can be smaller.
+    ///
+    /// All the fragments of a message are built with the same fragment size.
+    fragment_size: Option<usize>,
 
     tick: Tick,
     last_received: Option<Duration>,
@@ -83,52 +146,79 @@ impl FragmentConstructor {
         Self {
             num_fragments,
             num_received_fragments: 0,
-            received: vec![false; num_fragments],
-            bytes: vec![0; num_fragments * FRAGMENT_SIZE],
+            num_bytes: 0,
+            fragments: vec![None; num_fragments],
+            fragment_size: None,
             tick,
             last_received: None,
         }
     }
 
+    /// Buffer a fragment. Returns the reassembled message once all the fragments are received.
+    ///
+    /// Returns an error if the size of the fragment is inconsistent with the other fragments.
     pub fn receive_fragment(
         &mut self,
         fragment_index: usize,
-        bytes: &[u8],
+        bytes: Bytes,
         received_time: Option<Duration>,
-    ) -> Option<(Tick, Bytes)> {
+    ) -> Result<Option<(Tick, Bytes)>> {
         self.last_received = received_time;
 
-        let is_last_fragment = fragment_index == self.num_fragments - 1;
-
-        // TODO: check sizes?
-
-        if !self.received[fragment_index] {
-            self.received[fragment_index] = true;
+        if self.fragments[fragment_index].is_none() {
+            self.check_fragment_size(fragment_index, bytes.len())?;
+            self.num_bytes += bytes.len();
+            self.fragments[fragment_index] = Some(bytes);
             self.num_received_fragments += 1;
-
-            if is_last_fragment {
-                let len = (self.num_fragments - 1) * FRAGMENT_SIZE + bytes.len();
-                self.bytes.resize(len, 0);
-            }
-
-            let start = fragment_index * FRAGMENT_SIZE;
-            let end = start + bytes.len();
-            self.bytes[start..end].copy_from_slice(bytes);
         }
 
         if self.num_received_fragments == self.num_fragments {
             trace!("Received all fragments!");
-            let payload = core::mem::take(&mut self.bytes);
-            return Some((self.tick, payload.into()));
+            let fragments = core::mem::take(&mut self.fragments);
+            let mut payload = Vec::with_capacity(self.num_bytes);
+            fragments
+                .iter()
+                .flatten()
+                .for_each(|fragment| payload.extend_from_slice(fragment));
+            return Ok(Some((self.tick, payload.into())));
         }
 
-        None
+        Ok(None)
+    }
+
+    /// Every fragment must be non-empty, all the fragments except the last one must have the
+    /// same size, and the last fragment cannot be bigger than the others.
+    fn check_fragment_size(&mut self, fragment_index: usize, len: usize) -> Result<()> {
+        if len == 0 {
+            return Err(ChannelReceiveError::InvalidFragment);
+        }
+        let last_index = self.num_fragments - 1;
+        let last_len = self.fragments[last_index].as_ref().map(Bytes::len);
+        if fragment_index == last_index {
+            if self.fragment_size.is_some_and(|size| len > size) {
+                return Err(ChannelReceiveError::InvalidFragment);
+            }
+            return Ok(());
+        }
+        match self.fragment_size {
+            Some(size) if size != len => Err(ChannelReceiveError::InvalidFragment),
+            Some(_) => Ok(()),
+            None if last_len.is_some_and(|last_len| last_len > len) => {
+                Err(ChannelReceiveError::InvalidFragment)
+            }
+            None => {
+                self.fragment_size = Some(len);
+                Ok(())
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::channel::senders::fragment_sender::FragmentSender;
+    use crate::packet::message::FragmentIndex;
+    use crate::packet::packet::FRAGMENT_SIZE;
 
     use super::*;
 
@@ -140,12 +230,147 @@ mod tests {
         let fragments = FragmentSender::new().build_fragments(MessageId(0), message_bytes.clone());
 
         assert_eq!(
-            receiver.receive_fragment(fragments[0].clone(), Tick(0), None),
+            receiver
+                .receive_fragment(fragments[0].clone(), Tick(0), None)
+                .unwrap(),
             None
         );
         assert_eq!(
-            receiver.receive_fragment(fragments[1].clone(), Tick(1), None),
+            receiver
+                .receive_fragment(fragments[1].clone(), Tick(1), None)
+                .unwrap(),
             Some((Tick(0), message_bytes.clone()))
         );
     }
+
+    /// The fragments can be re-assembled even if the remote uses a different fragment size,
+    /// and if they arrive out of order
+    #[test]
+    fn test_receiver_custom_fragment_size() {
+        let mut receiver = FragmentReceiver::new();
+        let message_bytes = Bytes::from((0..250).collect::<Vec<u8>>());
+        let mut sender = FragmentSender::new();
+        sender.fragment_size = 100;
+        let fragments = sender.build_fragments(MessageId(0), message_bytes.clone());
+        assert_eq!(fragments.len(), 3);
+
+        assert_eq!(
+            receiver
+                .receive_fragment(fragments[2].clone(), Tick(0), None)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            receiver
+                .receive_fragment(fragments[0].clone(), Tick(1), None)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            receiver
+                .receive_fragment(fragments[1].clone(), Tick(2), None)
+                .unwrap(),
+            Some((Tick(0), message_bytes))
+        );
+    }
+
+    /// Fragments whose size is inconsistent with the other fragments of the message are rejected
+    #[test]
+    fn test_receiver_inconsistent_fragment_size() {
+        let mut receiver = FragmentReceiver::new();
+        let message_bytes = Bytes::from((0..250).collect::<Vec<u8>>());
+        let mut sender = FragmentSender::new();
+        sender.fragment_size = 100;
+        let mut fragments = sender.build_fragments(MessageId(0), message_bytes.clone());
+        assert_eq!(
+            receiver
+                .receive_fragment(fragments[0].clone(), Tick(0), None)
+                .unwrap(),
+            None
+        );
+        fragments[1].bytes = fragments[1].bytes.slice(..50);
+        assert!(matches!(
+            receiver.receive_fragment(fragments[1].clone(), Tick(0), None),
+            Err(ChannelReceiveError::InvalidFragment)
+        ));
+        // the partially reassembled message was discarded
+        assert_eq!(receiver.num_in_flight_fragments(), 0);
+
+        // the last fragment cannot be bigger than the other fragments
+        let fragments = sender.build_fragments(MessageId(1), message_bytes);
+        let mut last = fragments[2].clone();
+        last.bytes = Bytes::from(vec![0; 150]);
+        assert_eq!(
+            receiver.receive_fragment(last, Tick(0), None).unwrap(),
+            None
+        );
+        assert!(matches!(
+            receiver.receive_fragment(fragments[0].clone(), Tick(0), None),
+            Err(ChannelReceiveError::InvalidFragment)
+        ));
+    }
+
+    #[test]
+    fn test_receiver_limits() {
+        let mut receiver = FragmentReceiver::new();
+        receiver.update_limits(&ReceiveLimits {
+            max_message_size: 150,
+            max_in_flight_fragments: 4,
+            ..Default::default()
+        });
+        let mut sender = FragmentSender::new();
+        sender.fragment_size = 100;
+
+        // the fragment index must be lower than the number of fragments
+        let mut fragment =
+            sender.build_fragments(MessageId(0), Bytes::from(vec![0; 150]))[0].clone();
+        fragment.fragment_id = FragmentIndex(2);
+        assert!(matches!(
+            receiver.receive_fragment(fragment, Tick(0), None),
+            Err(ChannelReceiveError::InvalidFragment)
+        ));
+
+        // the message is too large
+        let fragments = sender.build_fragments(MessageId(1), Bytes::from(vec![0; 250]));
+        assert_eq!(
+            receiver
+                .receive_fragment(fragments[0].clone(), Tick(0), None)
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            receiver.receive_fragment(fragments[1].clone(), Tick(0), None),
+            Err(ChannelReceiveError::LimitExceeded(
+                LimitError::MessageTooLarge {
+                    size: 200,
+                    max: 150
+                }
+            ))
+        ));
+        // the partial message was discarded
+        assert_eq!(receiver.num_in_flight_fragments(), 0);
+
+        // too many fragments in flight
+        let fragments = sender.build_fragments(MessageId(2), Bytes::from(vec![0; 120]));
+        assert_eq!(
+            receiver
+                .receive_fragment(fragments[0].clone(), Tick(0), None)
+                .unwrap(),
+            None
+        );
+        let mut fragment = fragments[0].clone();
+        fragment.message_id = MessageId(3);
+        fragment.num_fragments = FragmentIndex(3);
+        assert!(matches!(
+            receiver.receive_fragment(fragment, Tick(0), None),
+            Err(ChannelReceiveError::LimitExceeded(
+                LimitError::TooManyFragments {
+                    fragments: 5,
+                    max: 4
+                }
+            ))
+        ));
+        assert_eq!(receiver.num_in_flight_fragments(), 2);
+        assert_eq!(receiver.num_buffered_bytes(), 100);
+    }
 }
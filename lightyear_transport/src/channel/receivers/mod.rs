@@ -5,8 +5,10 @@ use enum_dispatch::enum_dispatch;
 use crate::channel::receivers::ordered_reliable::OrderedReliableReceiver;
 use crate::channel::receivers::sequenced_reliable::SequencedReliableReceiver;
 use crate::channel::receivers::sequenced_unreliable::SequencedUnreliableReceiver;
+use crate::channel::receivers::tick_buffered::TickBufferedReceiver;
 use crate::channel::receivers::unordered_reliable::UnorderedReliableReceiver;
 use crate::channel::receivers::unordered_unreliable::UnorderedUnreliableReceiver;
+use crate::limits::ReceiveLimits;
 use crate::packet::message::{MessageId, ReceiveMessage};
 use crate::prelude::{ChannelMode, ChannelSettings};
 use core::time::Duration;
@@ -28,6 +30,9 @@ pub(crate) mod sequenced_unreliable;
 /// Receive messages in an Unordered Reliable manner
 pub(crate) mod unordered_reliable;
 
+/// Receive messages once the local tick reaches the tick at which they were sent
+pub(crate) mod tick_buffered;
+
 pub(crate) mod error;
 /// Receive messages in an Unordered Unreliable manner
 pub(crate) mod unordered_unreliable;
@@ -38,11 +43,23 @@ pub trait ChannelReceive {
     /// Bookkeeping on the channel
     fn update(&mut self, now: Duration);
 
+    /// Update the current tick of the local timeline
+    fn update_tick(&mut self, _tick: Tick) {}
+
     /// Queues a received message in an internal buffer
     fn buffer_recv(&mut self, message: ReceiveMessage) -> Result<()>;
 
     /// Reads a message from the internal buffer to get its content
     fn read_message(&mut self) -> Option<(Tick, Bytes, Option<MessageId>)>;
+
+    /// Number of messages held in the internal buffer that have not been read yet
+    fn num_buffered_messages(&self) -> usize;
+
+    /// Update the [`ReceiveLimits`] applied to the messages received on this channel
+    fn update_limits(&mut self, _limits: &ReceiveLimits) {}
+
+    /// Number of bytes held in the internal buffers (including partially received fragmented messages)
+    fn num_buffered_bytes(&self) -> usize;
 }
 
 /// This enum contains the various types of receivers available
@@ -54,6 +71,7 @@ pub enum ChannelReceiverEnum {
     OrderedReliable(OrderedReliableReceiver),
     SequencedReliable(SequencedReliableReceiver),
     UnorderedReliable(UnorderedReliableReceiver),
+    TickBuffered(TickBufferedReceiver),
 }
 
 impl From<&ChannelSettings> for ChannelReceiverEnum {
@@ -65,6 +83,8 @@ impl From<&ChannelSettings> for ChannelReceiverEnum {
             ChannelMode::UnorderedReliable(_) => UnorderedReliableReceiver::new().into(),
             ChannelMode::SequencedReliable(_) => SequencedReliableReceiver::new().into(),
             ChannelMode::OrderedReliable(_) => OrderedReliableReceiver::new().into(),
+            ChannelMode::TickBuffered(_) => TickBufferedReceiver::new().into(),
+            ChannelMode::Stream(_) => OrderedReliableReceiver::new().into(),
         }
     }
 }
@@ -3,6 +3,7 @@ use alloc::collections::{BTreeMap, btree_map};
 use super::error::{ChannelReceiveError, Result};
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+use crate::limits::ReceiveLimits;
 use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 use bytes::Bytes;
 use core::time::Duration;
@@ -63,7 +64,7 @@ impl ChannelReceive for OrderedReliableReceiver {
                         fragment,
                         message.remote_sent_tick,
                         None,
-                    ) {
+                    )? {
                         entry.insert(res);
                     }
                 }
@@ -87,6 +88,21 @@ impl ChannelReceive for OrderedReliableReceiver {
         self.pending_recv_message_id += 1;
         Some((tick, bytes, Some(self.pending_recv_message_id - 1)))
     }
+
+    fn num_buffered_messages(&self) -> usize {
+        self.recv_message_buffer.len()
+    }
+    fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.fragment_receiver.update_limits(limits);
+    }
+
+    fn num_buffered_bytes(&self) -> usize {
+        self.recv_message_buffer
+            .values()
+            .map(|(_, bytes)| bytes.len())
+            .sum::<usize>()
+            + self.fragment_receiver.num_buffered_bytes()
+    }
 }
 
 #[cfg(test)]
@@ -7,6 +7,7 @@ use lightyear_core::tick::Tick;
 
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+use crate::limits::ReceiveLimits;
 use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 
 /// Sequenced Reliable receiver: make sure that all messages are received,
@@ -69,7 +70,7 @@ impl ChannelReceive for SequencedReliableReceiver {
                         fragment,
                         message.remote_sent_tick,
                         None,
-                    ) {
+                    )? {
                         entry.insert(res);
                     }
                 }
@@ -86,6 +87,21 @@ impl ChannelReceive for SequencedReliableReceiver {
             }
         }
     }
+
+    fn num_buffered_messages(&self) -> usize {
+        self.recv_message_buffer.len()
+    }
+    fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.fragment_receiver.update_limits(limits);
+    }
+
+    fn num_buffered_bytes(&self) -> usize {
+        self.recv_message_buffer
+            .values()
+            .map(|(_, bytes)| bytes.len())
+            .sum::<usize>()
+            + self.fragment_receiver.num_buffered_bytes()
+    }
 }
 
 #[cfg(test)]
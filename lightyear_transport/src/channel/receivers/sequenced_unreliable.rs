@@ -7,6 +7,7 @@ use lightyear_core::tick::Tick;
 
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+use crate::limits::ReceiveLimits;
 use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 
 const DISCARD_AFTER: Duration = Duration::from_millis(3000);
@@ -77,7 +78,7 @@ impl ChannelReceive for SequencedUnreliableReceiver {
                     fragment,
                     message.remote_sent_tick,
                     Some(self.current_time),
-                ) {
+                )? {
                     self.recv_message_buffer
                         .push_back((tick, bytes, message_id));
                 }
@@ -90,6 +91,21 @@ impl ChannelReceive for SequencedUnreliableReceiver {
             .pop_front()
             .map(|(tick, bytes, message_id)| (tick, bytes, Some(message_id)))
     }
+
+    fn num_buffered_messages(&self) -> usize {
+        self.recv_message_buffer.len()
+    }
+    fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.fragment_receiver.update_limits(limits);
+    }
+
+    fn num_buffered_bytes(&self) -> usize {
+        self.recv_message_buffer
+            .iter()
+            .map(|(_, bytes, _)| bytes.len())
+            .sum::<usize>()
+            + self.fragment_receiver.num_buffered_bytes()
+    }
 }
 
 #[cfg(test)]
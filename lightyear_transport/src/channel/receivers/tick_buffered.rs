@@ -0,0 +1,184 @@
+use alloc::collections::VecDeque;
+use bytes::Bytes;
+use core::time::Duration;
+use lightyear_core::tick::Tick;
+use lightyear_serde::ToBytes;
+use lightyear_serde::reader::Reader;
+use tracing::error;
+
+use super::error::Result;
+use crate::channel::receivers::ChannelReceive;
+use crate::channel::receivers::unordered_reliable::UnorderedReliableReceiver;
+use crate::limits::ReceiveLimits;
+use crate::packet::message::{MessageId, ReceiveMessage};
+
+/// Maximum number of ticks that the tick of a received message can be ahead of the local tick
+pub const MAX_TICKS_AHEAD: i16 = 1024;
+
+/// Tick Buffered receiver: make sure that all messages are received (similarly to the Unordered Reliable receiver),
+/// but only return a message once the local tick has reached the tick at which the message was sent.
+///
+/// Messages whose tick is already in the past are returned immediately.
+/// Messages whose tick is more than [`MAX_TICKS_AHEAD`] ticks ahead of the local tick are dropped,
+/// so that a peer cannot make us buffer messages indefinitely.
+#[derive(Debug, Default)]
+pub struct TickBufferedReceiver {
+    reliable: UnorderedReliableReceiver,
+    /// Messages that we received, sorted by the tick at which they were sent
+    recv_message_buffer: VecDeque<(Tick, Bytes, MessageId)>,
+    local_tick: Tick,
+}
+
+impl TickBufferedReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the messages from the reliable receiver to the tick buffer
+    fn buffer_reliable_messages(&mut self) {
+        while let Some((_, bytes, message_id)) = self.reliable.read_message() {
+            // the reliable receiver always returns a MessageId
+            let Some(message_id) = message_id else {
+                continue;
+            };
+            // the tick at which the message was sent is written before the message content
+            let mut reader = Reader::from(bytes);
+            let tick = match Tick::from_bytes(&mut reader) {
+                Ok(tick) => tick,
+                Err(e) => {
+                    error!(?message_id, "Dropping message without a valid tick: {e:?}");
+                    continue;
+                }
+            };
+            if tick - self.local_tick > MAX_TICKS_AHEAD {
+                error!(
+                    ?message_id,
+                    ?tick,
+                    local_tick = ?self.local_tick,
+                    "Dropping message that is too far ahead of the local tick"
+                );
+                continue;
+            }
+            let bytes = reader.split_len(reader.remaining());
+            let index = self
+                .recv_message_buffer
+                .partition_point(|(buffered_tick, _, _)| *buffered_tick <= tick);
+            self.recv_message_buffer
+                .insert(index, (tick, bytes, message_id));
+        }
+    }
+}
+
+impl ChannelReceive for TickBufferedReceiver {
+    fn update(&mut self, now: Duration) {
+        self.reliable.update(now);
+    }
+
+    fn update_tick(&mut self, tick: Tick) {
+        self.local_tick = tick;
+    }
+
+    fn buffer_recv(&mut self, message: ReceiveMessage) -> Result<()> {
+        self.reliable.buffer_recv(message)?;
+        self.buffer_reliable_messages();
+        Ok(())
+    }
+
+    fn read_message(&mut self) -> Option<(Tick, Bytes, Option<MessageId>)> {
+        let (tick, _, _) = self.recv_message_buffer.front()?;
+        if *tick > self.local_tick {
+            return None;
+        }
+        self.recv_message_buffer
+            .pop_front()
+            .map(|(tick, bytes, message_id)| (tick, bytes, Some(message_id)))
+    }
+
+    fn num_buffered_messages(&self) -> usize {
+        self.recv_message_buffer.len() + self.reliable.num_buffered_messages()
+    }
+
+    fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.reliable.update_limits(limits);
+    }
+
+    fn num_buffered_bytes(&self) -> usize {
+        self.recv_message_buffer
+            .iter()
+            .map(|(_, bytes, _)| bytes.len())
+            .sum::<usize>()
+            + self.reliable.num_buffered_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::message::SingleData;
+    use lightyear_serde::writer::Writer;
+
+    fn stamped(tick: Tick, content: &'static str) -> Bytes {
+        let mut writer = Writer::default();
+        tick.to_bytes(&mut writer).unwrap();
+        writer.extend_from_slice(content.as_bytes());
+        writer.split()
+    }
+
+    #[test]
+    fn test_tick_buffered_receiver() -> Result<()> {
+        let mut receiver = TickBufferedReceiver::new();
+        receiver.update_tick(Tick(10));
+
+        let mut single1 = SingleData::new(None, stamped(Tick(12), "later"));
+        single1.id = Some(MessageId(0));
+        let mut single2 = SingleData::new(None, stamped(Tick(11), "sooner"));
+        single2.id = Some(MessageId(1));
+        let mut single3 = SingleData::new(None, stamped(Tick(8), "late"));
+        single3.id = Some(MessageId(2));
+        for (single, sent_tick) in [(single1, 12), (single2, 11), (single3, 8)] {
+            receiver.buffer_recv(ReceiveMessage {
+                data: single.into(),
+                remote_sent_tick: Tick(sent_tick),
+            })?;
+        }
+
+        // the message sent in the past is delivered right away
+        assert_eq!(
+            receiver.read_message(),
+            Some((Tick(8), Bytes::from("late"), Some(MessageId(2))))
+        );
+        assert_eq!(receiver.read_message(), None);
+
+        // the other messages are delivered in tick order once their tick is reached
+        receiver.update_tick(Tick(11));
+        assert_eq!(
+            receiver.read_message(),
+            Some((Tick(11), Bytes::from("sooner"), Some(MessageId(1))))
+        );
+        assert_eq!(receiver.read_message(), None);
+        receiver.update_tick(Tick(12));
+        assert_eq!(
+            receiver.read_message(),
+            Some((Tick(12), Bytes::from("later"), Some(MessageId(0))))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tick_buffered_receiver_too_far_ahead() -> Result<()> {
+        let mut receiver = TickBufferedReceiver::new();
+        receiver.update_tick(Tick(10));
+
+        let mut single =
+            SingleData::new(None, stamped(Tick(10 + MAX_TICKS_AHEAD as u16 + 1), "far"));
+        single.id = Some(MessageId(0));
+        receiver.buffer_recv(ReceiveMessage {
+            data: single.into(),
+            remote_sent_tick: Tick(10),
+        })?;
+
+        // the message is dropped instead of being buffered
+        assert!(receiver.recv_message_buffer.is_empty());
+        Ok(())
+    }
+}
@@ -9,6 +9,7 @@ use tracing::trace;
 
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+use crate::limits::ReceiveLimits;
 use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 
 /// Unordered Reliable receiver: make sure that all messages are received,
@@ -80,7 +81,7 @@ impl ChannelReceive for UnorderedReliableReceiver {
                         fragment,
                         message.remote_sent_tick,
                         None,
-                    ) {
+                    )? {
                         // receive the message if we haven't received it already
                         if !self.received_message_ids.contains(&message_id) {
                             self.received_message_ids.insert(message_id);
@@ -114,6 +115,21 @@ impl ChannelReceive for UnorderedReliableReceiver {
         let (tick, bytes, message_id) = data;
         Some((tick, bytes, Some(message_id)))
     }
+
+    fn num_buffered_messages(&self) -> usize {
+        self.recv_message_buffer.len()
+    }
+    fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.fragment_receiver.update_limits(limits);
+    }
+
+    fn num_buffered_bytes(&self) -> usize {
+        self.recv_message_buffer
+            .values()
+            .map(|(_, bytes, _)| bytes.len())
+            .sum::<usize>()
+            + self.fragment_receiver.num_buffered_bytes()
+    }
 }
 
 #[cfg(test)]
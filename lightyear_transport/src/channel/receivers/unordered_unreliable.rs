@@ -1,6 +1,7 @@
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::receivers::error::ChannelReceiveError;
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+use crate::limits::ReceiveLimits;
 use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 use alloc::collections::VecDeque;
 use bytes::Bytes;
@@ -50,7 +51,7 @@ impl ChannelReceive for UnorderedUnreliableReceiver {
                     fragment,
                     message.remote_sent_tick,
                     Some(self.current_time),
-                ) {
+                )? {
                     self.recv_message_buffer.push_back(data);
                 }
             }
@@ -63,6 +64,21 @@ impl ChannelReceive for UnorderedUnreliableReceiver {
             .pop_front()
             .map(|(tick, bytes)| (tick, bytes, None))
     }
+
+    fn num_buffered_messages(&self) -> usize {
+        self.recv_message_buffer.len()
+    }
+    fn update_limits(&mut self, limits: &ReceiveLimits) {
+        self.fragment_receiver.update_limits(limits);
+    }
+
+    fn num_buffered_bytes(&self) -> usize {
+        self.recv_message_buffer
+            .iter()
+            .map(|(_, bytes)| bytes.len())
+            .sum::<usize>()
+            + self.fragment_receiver.num_buffered_bytes()
+    }
 }
 
 #[cfg(test)]
@@ -1,9 +1,12 @@
 use crate::channel::Channel;
-use crate::channel::builder::ChannelSettings;
+use crate::channel::builder::{ChannelMode, ChannelSettings};
+use crate::channel::compression::Compression;
+use crate::channel::fec::ForwardErrorCorrection;
 use bevy::app::App;
 use bevy::platform::collections::HashMap;
 use bevy::prelude::{Resource, TypePath};
 use core::any::TypeId;
+use core::hash::{Hash, Hasher};
 use lightyear_connection::direction::NetworkDirection;
 use lightyear_core::network::NetId;
 use lightyear_utils::registry::{TypeKind, TypeMapper};
@@ -55,6 +58,10 @@ impl From<TypeId> for ChannelKind {
 #[derive(Resource, Default, Clone, Debug, PartialEq, TypePath)]
 pub struct ChannelRegistry {
     settings_map: HashMap<ChannelKind, ChannelSettings>,
+    name_map: HashMap<ChannelKind, &'static str>,
+    direction_map: HashMap<ChannelKind, NetworkDirection>,
+    /// Names of the channels that are used in the protocol hash
+    protocol_names: HashMap<ChannelKind, &'static str>,
     kind_map: TypeMapper<ChannelKind>,
     built: bool,
 }
@@ -82,7 +89,9 @@ impl ChannelRegistry {
         if let Some(net_id) = self.kind_map.net_id(&kind) {
             return (kind, *net_id);
         }
+        settings.validate(core::any::type_name::<C>());
         self.settings_map.insert(kind, settings);
+        self.name_map.insert(kind, core::any::type_name::<C>());
         let kind = self.kind_map.add::<C>();
         let net_id = self.get_net_from_kind(&kind).unwrap();
         (kind, *net_id)
@@ -92,9 +101,51 @@ impl ChannelRegistry {
         self.kind_map.kind(channel_id)
     }
 
+    /// Return the name of the channel from its [`ChannelId`]
+    pub fn name(&self, channel_id: ChannelId) -> Option<&'static str> {
+        let kind = self.kind_map.kind(channel_id)?;
+        self.name_map.get(kind).copied()
+    }
+
     pub fn get_net_from_kind(&self, kind: &ChannelKind) -> Option<&ChannelId> {
         self.kind_map.net_id(kind)
     }
+
+    /// Feed the parts of the registry that need to be identical on both peers (network ids, protocol
+    /// names, directions and modes of the channels) to the `hasher`.
+    ///
+    /// The result does not depend on the platform, so it can be compared with a remote peer.
+    pub fn hash_protocol<H: Hasher>(&self, hasher: &mut H) {
+        self.kind_map.iter().for_each(|(net_id, kind)| {
+            hasher.write_u16(net_id);
+            self.protocol_names
+                .get(kind)
+                .copied()
+                .unwrap_or("")
+                .hash(hasher);
+            hasher.write_u8(
+                self.direction_map
+                    .get(kind)
+                    .map_or(0, NetworkDirection::protocol_id),
+            );
+            if let Some(settings) = self.settings_map.get(kind) {
+                hasher.write_u8(mode_id(&settings.mode));
+                hasher.write_u8(compression_id(&settings.compression));
+                match settings.fec {
+                    ForwardErrorCorrection::None => hasher.write_u8(0),
+                    ForwardErrorCorrection::XorParity { group_size } => {
+                        hasher.write_u8(1);
+                        hasher.write_u8(group_size);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Iterate through all the registered channels, with their names
+    pub fn channels(&self) -> impl Iterator<Item = (ChannelKind, &'static str)> + '_ {
+        self.name_map.iter().map(|(kind, name)| (*kind, *name))
+    }
 }
 
 pub struct ChannelRegistration<'a, C> {
@@ -112,13 +163,69 @@ impl<'a, C: Channel> ChannelRegistration<'a, C> {
     }
 
     /// Add a new [`NetworkDirection`] to the registry
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is a [`ChannelMode::Stream`] channel and the direction is not
+    /// [`NetworkDirection::Bidirectional`]: the receiver of a stream sends frames back to the sender
+    /// to reject it.
     pub fn add_direction(&mut self, direction: NetworkDirection) -> &mut Self {
+        let mut registry = self.app.world_mut().resource_mut::<ChannelRegistry>();
+        let kind = ChannelKind::of::<C>();
+        if registry
+            .settings(kind)
+            .is_some_and(|settings| matches!(settings.mode, ChannelMode::Stream(_)))
+        {
+            assert_eq!(
+                direction,
+                NetworkDirection::Bidirectional,
+                "Channel {}: a stream channel must be Bidirectional",
+                core::any::type_name::<C>()
+            );
+        }
+        registry.direction_map.insert(kind, direction);
         #[cfg(feature = "client")]
         self.add_client_direction(direction);
         #[cfg(feature = "server")]
         self.add_server_direction(direction);
         self
     }
+
+    /// Name of the channel that is used in the protocol hash.
+    ///
+    /// The name must stay the same across builds; it lets the peers detect that the channels were
+    /// registered in a different order.
+    pub fn with_protocol_name(&mut self, name: &'static str) -> &mut Self {
+        self.app
+            .world_mut()
+            .resource_mut::<ChannelRegistry>()
+            .protocol_names
+            .insert(ChannelKind::of::<C>(), name);
+        self
+    }
+}
+
+fn mode_id(mode: &ChannelMode) -> u8 {
+    match mode {
+        ChannelMode::UnorderedUnreliableWithAcks => 0,
+        ChannelMode::UnorderedUnreliable => 1,
+        ChannelMode::SequencedUnreliable => 2,
+        ChannelMode::UnorderedReliable(_) => 3,
+        ChannelMode::SequencedReliable(_) => 4,
+        ChannelMode::OrderedReliable(_) => 5,
+        ChannelMode::TickBuffered(_) => 6,
+        ChannelMode::Stream(_) => 7,
+    }
+}
+
+fn compression_id(compression: &Compression) -> u8 {
+    match compression {
+        Compression::None => 0,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 { .. } => 1,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { .. } => 2,
+    }
 }
 
 /// Add a message to the list of messages that can be sent
@@ -139,3 +246,75 @@ impl AppChannelExt for App {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::stream::StreamSettings;
+    use crate::prelude::ReliableSettings;
+    use core::time::Duration;
+
+    struct C;
+
+    #[test]
+    #[should_panic(expected = "backoff_factor")]
+    fn test_invalid_backoff_factor() {
+        let mut registry = ChannelRegistry::default();
+        registry.add_channel::<C>(ChannelSettings {
+            mode: ChannelMode::UnorderedReliable(ReliableSettings {
+                backoff_factor: 0.5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "stream channel cannot abandon")]
+    fn test_stream_channel_with_max_retries() {
+        let mut registry = ChannelRegistry::default();
+        registry.add_channel::<C>(ChannelSettings {
+            mode: ChannelMode::Stream(StreamSettings {
+                reliable: ReliableSettings {
+                    max_retries: Some(3),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "stream channel cannot have a ttl")]
+    fn test_stream_channel_with_ttl() {
+        let mut registry = ChannelRegistry::default();
+        registry.add_channel::<C>(ChannelSettings {
+            mode: ChannelMode::Stream(StreamSettings::default()),
+            ttl: Some(Duration::from_secs(1)),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "stream channel must be Bidirectional")]
+    fn test_unidirectional_stream_channel() {
+        let mut app = App::new();
+        app.add_channel::<C>(ChannelSettings {
+            mode: ChannelMode::Stream(StreamSettings::default()),
+            ..Default::default()
+        })
+        .add_direction(NetworkDirection::ServerToClient);
+    }
+
+    #[test]
+    #[should_panic(expected = "forward error correction")]
+    fn test_fec_on_sequenced_channel() {
+        let mut registry = ChannelRegistry::default();
+        registry.add_channel::<C>(ChannelSettings {
+            mode: ChannelMode::SequencedUnreliable,
+            fec: ForwardErrorCorrection::XorParity { group_size: 2 },
+            ..Default::default()
+        });
+    }
+}
@@ -13,7 +13,6 @@ pub(crate) struct FragmentSender {
 impl FragmentSender {
     pub fn new() -> Self {
         Self {
-            // TODO: make this overridable?
             fragment_size: FRAGMENT_SIZE,
         }
     }
@@ -22,10 +21,10 @@ impl FragmentSender {
         fragment_message_id: MessageId,
         fragment_bytes: Bytes,
     ) -> Vec<FragmentData> {
-        if fragment_bytes.len() <= FRAGMENT_SIZE {
+        if fragment_bytes.len() <= self.fragment_size {
             unreachable!(
                 "Message size must be at least {} to need to be fragmented",
-                FRAGMENT_SIZE
+                self.fragment_size
             );
         }
         let chunks = fragment_bytes.chunks(self.fragment_size);
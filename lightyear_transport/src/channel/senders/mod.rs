@@ -5,8 +5,11 @@ use crate::channel::senders::unordered_unreliable_with_acks::UnorderedUnreliable
 use crate::packet::message::{MessageAck, MessageId, SendMessage};
 use crate::prelude::{ChannelMode, ChannelSettings};
 use alloc::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 use bevy::prelude::{Real, Time};
 use bytes::Bytes;
+use core::time::Duration;
 use enum_dispatch::enum_dispatch;
 use lightyear_link::LinkStats;
 
@@ -28,17 +31,43 @@ pub trait ChannelSend {
     fn update(&mut self, real_time: &Time<Real>, link_stats: &LinkStats);
 
     /// Queues a message to be transmitted.
-    /// The priority of the message needs to be specified
+    /// The priority of the message needs to be specified, and optionally how long the message
+    /// stays relevant: it is not sent anymore after that.
     ///
     /// Returns the MessageId of the message that was queued, if there is one
-    fn buffer_send(&mut self, message: Bytes, priority: f32) -> Option<MessageId>;
+    fn buffer_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+        ttl: Option<Duration>,
+    ) -> Option<MessageId>;
 
     /// Reads from the buffer of messages to send to prepare a list of Packets
     /// that can be sent over the network for this channel
     fn send_packet(&mut self) -> (VecDeque<SendMessage>, VecDeque<SendMessage>);
 
     /// Called when we receive acknowledgement that a Message has been received
-    fn receive_ack(&mut self, message_ack: &MessageAck);
+    ///
+    /// Returns true if the whole message was acked (for fragmented messages, all the fragments
+    /// have to be acked) and the channel is tracking acks.
+    fn receive_ack(&mut self, message_ack: &MessageAck) -> bool;
+
+    /// Set the maximum number of bytes of a message before it gets fragmented.
+    ///
+    /// Only applies to the messages that are buffered after this call.
+    fn set_fragment_size(&mut self, fragment_size: usize);
+
+    /// Returns the messages that the channel gave up on since the last call, because they were
+    /// not acked after the maximum number of retries or before they expired (only for reliable channels)
+    fn take_abandoned(&mut self) -> Vec<MessageId> {
+        Vec::new()
+    }
+
+    /// Total number of times a message or fragment was scheduled to be sent again because it
+    /// was not acked in time (only for reliable channels)
+    fn num_resends(&self) -> u64 {
+        0
+    }
 }
 
 /// Enum dispatch lets us derive ChannelSend on each enum variant
@@ -69,9 +98,18 @@ impl From<&ChannelSettings> for ChannelSenderEnum {
             ChannelMode::SequencedReliable(reliable_settings) => {
                 ReliableSender::new(reliable_settings, settings.send_frequency).into()
             }
-            ChannelMode::OrderedReliable(reliable_settings) => {
+            // the receiver would wait forever for a message that we gave up on
+            ChannelMode::OrderedReliable(reliable_settings) => ReliableSender::new(
+                reliable_settings.without_abandonment(),
+                settings.send_frequency,
+            )
+            .into(),
+            ChannelMode::TickBuffered(reliable_settings) => {
                 ReliableSender::new(reliable_settings, settings.send_frequency).into()
             }
+            ChannelMode::Stream(stream_settings) => {
+                ReliableSender::new(stream_settings.reliable, settings.send_frequency).into()
+            }
         }
     }
 }
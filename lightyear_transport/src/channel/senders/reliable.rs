@@ -11,13 +11,15 @@ use crate::packet::message::{FragmentData, MessageAck, MessageId, SendMessage, S
 use bytes::Bytes;
 use core::time::Duration;
 use lightyear_link::LinkStats;
-use tracing::trace;
+use tracing::{debug, trace};
 
 #[derive(Debug)]
 pub struct FragmentAck {
     data: FragmentData,
     acked: bool,
     last_sent: Option<Duration>,
+    /// Number of times this fragment was sent
+    num_sends: u32,
 }
 
 /// A message that has not been acked yet
@@ -28,6 +30,8 @@ pub enum UnackedMessage {
         /// If None: this packet has never been sent before
         /// else: the last instant when this packet was sent
         last_sent: Option<Duration>,
+        /// Number of times this message was sent
+        num_sends: u32,
     },
     Fragmented(Vec<FragmentAck>),
 }
@@ -37,6 +41,49 @@ pub struct UnackedMessageWithPriority {
     pub unacked_message: UnackedMessage,
     pub base_priority: f32,
     pub accumulated_priority: f32,
+    /// Real time after which we stop trying to send the message
+    pub expires_at: Option<Duration>,
+    /// Real time when the message was sent for the first time
+    pub first_sent: Option<Duration>,
+}
+
+impl UnackedMessageWithPriority {
+    /// Returns true if we should give up on this message because it was resent too many times
+    /// or for too long without being acked
+    fn should_abandon(
+        &self,
+        settings: &ReliableSettings,
+        resend_delay: Duration,
+        now: Duration,
+    ) -> bool {
+        if settings
+            .max_duration
+            .zip(self.first_sent)
+            .is_some_and(|(max_duration, first_sent)| now.saturating_sub(first_sent) > max_duration)
+        {
+            return true;
+        }
+        let Some(max_retries) = settings.max_retries else {
+            return false;
+        };
+        // we only give up once the last retry had the time to get acked
+        let retries_exhausted = |last_sent: Option<Duration>, num_sends: u32| {
+            num_sends > max_retries
+                && last_sent.is_some_and(|last_sent| {
+                    now.saturating_sub(last_sent) > settings.backoff_delay(resend_delay, num_sends)
+                })
+        };
+        match &self.unacked_message {
+            UnackedMessage::Single {
+                last_sent,
+                num_sends,
+                ..
+            } => retries_exhausted(*last_sent, *num_sends),
+            UnackedMessage::Fragmented(fragment_acks) => fragment_acks
+                .iter()
+                .any(|f| !f.acked && retries_exhausted(f.last_sent, f.num_sends)),
+        }
+    }
 }
 
 /// A sender that makes sure to resend messages until it receives an ack
@@ -68,6 +115,10 @@ pub struct ReliableSender {
     /// Factor that makes sure that the priority accumulates at the same right even the channel
     /// sends messages infrequently
     priority_multiplier: f32,
+    /// Messages that we gave up on since the last call to `take_abandoned`
+    abandoned_messages: Vec<MessageId>,
+    /// Number of times a message or fragment was scheduled to be sent again
+    num_resends: u64,
 }
 
 impl ReliableSender {
@@ -89,10 +140,36 @@ impl ReliableSender {
             current_time: Duration::default(),
             timer,
             priority_multiplier: 1.0,
+            abandoned_messages: Vec::new(),
+            num_resends: 0,
         }
     }
 }
 
+impl ReliableSender {
+    /// Give up on the messages that were resent too many times, or for too long
+    fn abandon_messages(&mut self) {
+        let settings = self.reliable_settings;
+        if settings.max_retries.is_none() && settings.max_duration.is_none() {
+            return;
+        }
+        let resend_delay = settings.resend_delay(self.current_rtt);
+        let now = self.current_time;
+        let abandoned_messages = &mut self.abandoned_messages;
+        self.unacked_messages.retain(|message_id, unacked_message| {
+            if unacked_message.should_abandon(&settings, resend_delay, now) {
+                debug!(
+                    ?message_id,
+                    "Reliable message abandoned because it was not acked"
+                );
+                abandoned_messages.push(*message_id);
+                return false;
+            }
+            true
+        });
+    }
+}
+
 impl ChannelSend for ReliableSender {
     fn update(&mut self, real_time: &Time<Real>, link_stats: &LinkStats) {
         self.current_time = real_time.elapsed();
@@ -106,11 +183,17 @@ impl ChannelSend for ReliableSender {
                 "Priority multiplier for reliable sender channel: {:?}", self.priority_multiplier
             );
         }
+        self.abandon_messages();
     }
 
     /// Add a new message to the buffer of messages to be sent.
     /// This is a client-facing function, to be called when you want to send a message
-    fn buffer_send(&mut self, message: Bytes, priority: f32) -> Option<MessageId> {
+    fn buffer_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+        ttl: Option<Duration>,
+    ) -> Option<MessageId> {
         let message_id = self.next_send_message_id;
         let unacked_message = if message.len() > self.fragment_sender.fragment_size {
             let fragments = self.fragment_sender.build_fragments(message_id, message);
@@ -121,6 +204,7 @@ impl ChannelSend for ReliableSender {
                         data: fragment,
                         acked: false,
                         last_sent: None,
+                        num_sends: 0,
                     })
                     .collect(),
             )
@@ -128,6 +212,7 @@ impl ChannelSend for ReliableSender {
             UnackedMessage::Single {
                 bytes: message,
                 last_sent: None,
+                num_sends: 0,
             }
         };
         let unacked_message_with_priority = UnackedMessageWithPriority {
@@ -136,6 +221,8 @@ impl ChannelSend for ReliableSender {
             // store with 0.0 accumulated priority because priority gets accumulated when we collect the messages
             // for sending (even the first time the message is sent)
             accumulated_priority: 0.0,
+            expires_at: ttl.map(|ttl| self.current_time + ttl),
+            first_sent: None,
         };
         self.unacked_messages
             .insert(message_id, unacked_message_with_priority);
@@ -156,15 +243,34 @@ impl ChannelSend for ReliableSender {
 
         // resend delay is based on the rtt
         let resend_delay = self.reliable_settings.resend_delay(self.current_rtt);
-        let should_send = |last_sent: &Option<Duration>| -> bool {
+        let should_send = |last_sent: &Option<Duration>, num_sends: u32| -> bool {
             match last_sent {
                 // send if the message has never been sent
                 None => true,
                 // or if we sent it a while back but didn't get an ack
-                Some(last_sent) => self.current_time - *last_sent > resend_delay,
+                Some(last_sent) => {
+                    self.current_time - *last_sent
+                        > self
+                            .reliable_settings
+                            .backoff_delay(resend_delay, num_sends)
+                }
             }
         };
 
+        // Stop resending the messages that are not relevant anymore
+        let now = self.current_time;
+        let abandoned_messages = &mut self.abandoned_messages;
+        self.unacked_messages.retain(|message_id, unacked_message| {
+            let expired = unacked_message
+                .expires_at
+                .is_some_and(|expires_at| now >= expires_at);
+            if expired {
+                debug!(?message_id, "Reliable message expired before being acked");
+                abandoned_messages.push(*message_id);
+            }
+            !expired
+        });
+
         // Iterate through all unacked messages, oldest message ids first
         for (message_id, unacked_message_with_priority) in self.unacked_messages.iter_mut() {
             // accumulate the priority for all messages (including the ones that were just added, since we set the accumulated priority to 0.0)
@@ -179,8 +285,12 @@ impl ChannelSend for ReliableSender {
             );
 
             match &mut unacked_message_with_priority.unacked_message {
-                UnackedMessage::Single { bytes, last_sent } => {
-                    if should_send(last_sent) {
+                UnackedMessage::Single {
+                    bytes,
+                    last_sent,
+                    num_sends,
+                } => {
+                    if should_send(last_sent, *num_sends) {
                         trace!("Should send message {:?}", message_id);
                         let message_info = MessageAck {
                             message_id: *message_id,
@@ -191,9 +301,17 @@ impl ChannelSend for ReliableSender {
                             self.single_messages_to_send.push_back(SendMessage {
                                 data: message.into(),
                                 priority: unacked_message_with_priority.accumulated_priority,
+                                expires_at: unacked_message_with_priority.expires_at,
                             });
                             self.message_ids_to_send.insert(message_info);
+                            if last_sent.is_some() {
+                                self.num_resends += 1;
+                            }
                             *last_sent = Some(self.current_time);
+                            *num_sends += 1;
+                            unacked_message_with_priority
+                                .first_sent
+                                .get_or_insert(self.current_time);
                         }
                     }
                 }
@@ -201,7 +319,7 @@ impl ChannelSend for ReliableSender {
                     // only send the fragments that haven't been acked and should be resent
                     fragment_acks
                         .iter_mut()
-                        .filter(|f| !f.acked && should_send(&f.last_sent))
+                        .filter(|f| !f.acked && should_send(&f.last_sent, f.num_sends))
                         .for_each(|f| {
                             let message_info = MessageAck {
                                 message_id: *message_id,
@@ -212,9 +330,17 @@ impl ChannelSend for ReliableSender {
                                 self.fragmented_messages_to_send.push_back(SendMessage {
                                     data: message.into(),
                                     priority: unacked_message_with_priority.accumulated_priority,
+                                    expires_at: unacked_message_with_priority.expires_at,
                                 });
                                 self.message_ids_to_send.insert(message_info);
+                                if f.last_sent.is_some() {
+                                    self.num_resends += 1;
+                                }
                                 f.last_sent = Some(self.current_time);
+                                f.num_sends += 1;
+                                unacked_message_with_priority
+                                    .first_sent
+                                    .get_or_insert(self.current_time);
                             }
                         })
                 }
@@ -252,7 +378,7 @@ impl ChannelSend for ReliableSender {
         // }
     }
 
-    fn receive_ack(&mut self, message_ack: &MessageAck) {
+    fn receive_ack(&mut self, message_ack: &MessageAck) -> bool {
         if let Some(unacked_message) = self.unacked_messages.get_mut(&message_ack.message_id) {
             trace!(
                 "Received message ack for message id: {:?}",
@@ -266,6 +392,7 @@ impl ChannelSend for ReliableSender {
                         )
                     }
                     self.unacked_messages.remove(&message_ack.message_id);
+                    return true;
                 }
                 UnackedMessage::Fragmented(fragment_acks) => {
                     let Some(fragment_id) = message_ack.fragment_id else {
@@ -279,11 +406,25 @@ impl ChannelSend for ReliableSender {
                         // all fragments were acked
                         if fragment_acks.iter().all(|f| f.acked) {
                             self.unacked_messages.remove(&message_ack.message_id);
+                            return true;
                         }
                     }
                 }
             }
         }
+        false
+    }
+
+    fn set_fragment_size(&mut self, fragment_size: usize) {
+        self.fragment_sender.fragment_size = fragment_size;
+    }
+
+    fn take_abandoned(&mut self) -> Vec<MessageId> {
+        core::mem::take(&mut self.abandoned_messages)
+    }
+
+    fn num_resends(&self) -> u64 {
+        self.num_resends
     }
 }
 
@@ -303,6 +444,7 @@ mod tests {
             ReliableSettings {
                 rtt_resend_factor: 1.5,
                 rtt_resend_min_delay: Duration::from_millis(100),
+                ..Default::default()
             },
             Duration::default(),
         );
@@ -311,7 +453,7 @@ mod tests {
 
         // Buffer a new message
         let message1 = Bytes::from("hello");
-        sender.buffer_send(message1.clone(), 1.0).unwrap();
+        sender.buffer_send(message1.clone(), 1.0, None).unwrap();
         assert_eq!(sender.unacked_messages.len(), 1);
         assert_eq!(sender.next_send_message_id, MessageId(1));
         // Collect the messages to be sent
@@ -332,15 +474,16 @@ mod tests {
             &SendMessage {
                 data: SingleData::new(Some(MessageId(0)), message1.clone()).into(),
                 // priority is accumulated every time the message is not sent
-                priority: 3.0
+                priority: 3.0,
+                expires_at: None,
             }
         );
 
         // Ack the first message
-        sender.receive_ack(&MessageAck {
+        assert!(sender.receive_ack(&MessageAck {
             message_id: MessageId(0),
             fragment_id: None,
-        });
+        }));
         assert_eq!(sender.unacked_messages.len(), 0);
 
         // Advance by a time that is above the resend threshold
@@ -348,4 +491,74 @@ mod tests {
         let (single, _) = sender.send_packet();
         assert_eq!(single.len(), 0);
     }
+
+    #[test]
+    fn test_reliable_sender_ttl() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings {
+                rtt_resend_factor: 1.5,
+                rtt_resend_min_delay: Duration::from_millis(100),
+                ..Default::default()
+            },
+            Duration::default(),
+        );
+        sender.current_rtt = Duration::from_millis(100);
+        sender.current_time = Duration::default();
+
+        sender
+            .buffer_send(Bytes::from("hello"), 1.0, Some(Duration::from_millis(250)))
+            .unwrap();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+        assert_eq!(
+            single.front().unwrap().expires_at,
+            Some(Duration::from_millis(250))
+        );
+
+        // the message is not acked in time: we stop resending it
+        sender.current_time += Duration::from_millis(300);
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 0);
+        assert_eq!(sender.unacked_messages.len(), 0);
+    }
+
+    #[test]
+    fn test_reliable_sender_max_retries() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings {
+                rtt_resend_factor: 1.5,
+                rtt_resend_min_delay: Duration::from_millis(100),
+                backoff_factor: 2.0,
+                max_retries: Some(1),
+                ..Default::default()
+            },
+            Duration::default(),
+        );
+        sender.current_time = Duration::default();
+
+        sender.buffer_send(Bytes::from("hello"), 1.0, None).unwrap();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+
+        // first retry after the resend delay
+        sender.current_time += Duration::from_millis(150);
+        sender.abandon_messages();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+
+        // the resend delay is doubled after each send
+        sender.current_time += Duration::from_millis(150);
+        sender.abandon_messages();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 0);
+        assert!(sender.take_abandoned().is_empty());
+
+        // the message was retried the maximum number of times: we give up on it
+        sender.current_time += Duration::from_millis(100);
+        sender.abandon_messages();
+        assert_eq!(sender.unacked_messages.len(), 0);
+        assert_eq!(sender.take_abandoned(), vec![MessageId(0)]);
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 0);
+    }
 }
@@ -23,6 +23,7 @@ pub struct SequencedUnreliableSender {
     fragment_sender: FragmentSender,
     /// Internal timer to determine if the channel is ready to send messages
     timer: Option<Timer>,
+    current_time: Duration,
 }
 
 impl SequencedUnreliableSender {
@@ -38,12 +39,14 @@ impl SequencedUnreliableSender {
             next_send_message_id: MessageId(0),
             fragment_sender: FragmentSender::new(),
             timer,
+            current_time: Duration::default(),
         }
     }
 }
 
 impl ChannelSend for SequencedUnreliableSender {
     fn update(&mut self, real_time: &Time<Real>, _: &LinkStats) {
+        self.current_time = real_time.elapsed();
         if let Some(timer) = &mut self.timer {
             timer.tick(real_time.delta());
         }
@@ -51,13 +54,20 @@ impl ChannelSend for SequencedUnreliableSender {
 
     /// Add a new message to the buffer of messages to be sent.
     /// This is a client-facing function, to be called when you want to send a message
-    fn buffer_send(&mut self, message: Bytes, priority: f32) -> Option<MessageId> {
+    fn buffer_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+        ttl: Option<Duration>,
+    ) -> Option<MessageId> {
+        let expires_at = ttl.map(|ttl| self.current_time + ttl);
         let message_id = self.next_send_message_id;
         if message.len() > self.fragment_sender.fragment_size {
             for fragment in self.fragment_sender.build_fragments(message_id, message) {
                 self.fragmented_messages_to_send.push_back(SendMessage {
                     data: MessageData::Fragment(fragment),
                     priority,
+                    expires_at,
                 });
             }
         } else {
@@ -65,6 +75,7 @@ impl ChannelSend for SequencedUnreliableSender {
             self.single_messages_to_send.push_back(SendMessage {
                 data: MessageData::Single(single_data),
                 priority,
+                expires_at,
             });
         }
         self.next_send_message_id += 1;
@@ -77,6 +88,12 @@ impl ChannelSend for SequencedUnreliableSender {
         if self.timer.as_ref().is_some_and(|t| !t.finished()) {
             return (VecDeque::new(), VecDeque::new());
         }
+        // drop the messages that were not sent in time
+        let now = self.current_time;
+        self.single_messages_to_send
+            .retain(|message| !message.is_expired(now));
+        self.fragmented_messages_to_send
+            .retain(|message| !message.is_expired(now));
         (
             core::mem::take(&mut self.single_messages_to_send),
             core::mem::take(&mut self.fragmented_messages_to_send),
@@ -87,7 +104,13 @@ impl ChannelSend for SequencedUnreliableSender {
         // self.messages_to_send = remaining_messages_to_send;
     }
 
-    fn receive_ack(&mut self, _message_ack: &MessageAck) {}
+    fn receive_ack(&mut self, _message_ack: &MessageAck) -> bool {
+        false
+    }
+
+    fn set_fragment_size(&mut self, fragment_size: usize) {
+        self.fragment_sender.fragment_size = fragment_size;
+    }
 }
 
 #[cfg(test)]
@@ -98,7 +121,7 @@ mod tests {
         let mut sender = SequencedUnreliableSender::new(Duration::from_secs(1));
         assert!(sender.timer.as_ref().is_some_and(|t| !t.finished()));
 
-        sender.buffer_send(Bytes::from("hello"), 1.0).unwrap();
+        sender.buffer_send(Bytes::from("hello"), 1.0, None).unwrap();
 
         // we do not send because we didn't reach the timer
         let (single, _) = sender.send_packet();
@@ -114,4 +137,24 @@ mod tests {
         let (single, _) = sender.send_packet();
         assert_eq!(single.len(), 1);
     }
+
+    #[test]
+    fn test_sequenced_unreliable_sender_ttl() {
+        let mut sender = SequencedUnreliableSender::new(Duration::from_secs(1));
+        let fragmented = Bytes::from(vec![0; sender.fragment_sender.fragment_size + 1]);
+        sender.buffer_send(fragmented, 1.0, Some(Duration::from_millis(500)));
+        sender.buffer_send(Bytes::from("stale"), 1.0, Some(Duration::from_millis(500)));
+        sender.buffer_send(Bytes::from("hello"), 1.0, None);
+        sender.buffer_send(Bytes::from("world"), 1.0, Some(Duration::from_secs(2)));
+
+        // the messages are only sent after 1 second, when the first two are stale
+        let mut real = Time::<Real>::default();
+        real.advance_by(Duration::from_secs(1));
+        sender.update(&real, &LinkStats::default());
+        let (single, fragment) = sender.send_packet();
+        assert!(fragment.is_empty());
+        assert_eq!(single.len(), 2);
+        assert_eq!(single[0].expires_at, None);
+        assert_eq!(single[1].expires_at, Some(Duration::from_secs(2)));
+    }
 }
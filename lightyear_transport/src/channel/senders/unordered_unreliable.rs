@@ -24,6 +24,7 @@ pub struct UnorderedUnreliableSender {
     fragment_sender: FragmentSender,
     /// Internal timer to determine if the channel is ready to send messages
     timer: Option<Timer>,
+    current_time: Duration,
 }
 
 impl UnorderedUnreliableSender {
@@ -39,12 +40,14 @@ impl UnorderedUnreliableSender {
             next_send_fragmented_message_id: MessageId::default(),
             fragment_sender: FragmentSender::new(),
             timer,
+            current_time: Duration::default(),
         }
     }
 }
 
 impl ChannelSend for UnorderedUnreliableSender {
     fn update(&mut self, real_time: &Time<Real>, _: &LinkStats) {
+        self.current_time = real_time.elapsed();
         if let Some(timer) = &mut self.timer {
             timer.tick(real_time.delta());
         }
@@ -52,7 +55,13 @@ impl ChannelSend for UnorderedUnreliableSender {
 
     /// Add a new message to the buffer of messages to be sent.
     /// This is a client-facing function, to be called when you want to send a message
-    fn buffer_send(&mut self, message: Bytes, priority: f32) -> Option<MessageId> {
+    fn buffer_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+        ttl: Option<Duration>,
+    ) -> Option<MessageId> {
+        let expires_at = ttl.map(|ttl| self.current_time + ttl);
         if message.len() > self.fragment_sender.fragment_size {
             for fragment in self
                 .fragment_sender
@@ -61,6 +70,7 @@ impl ChannelSend for UnorderedUnreliableSender {
                 self.fragmented_messages_to_send.push_back(SendMessage {
                     data: MessageData::Fragment(fragment),
                     priority,
+                    expires_at,
                 });
             }
             self.next_send_fragmented_message_id += 1;
@@ -70,6 +80,7 @@ impl ChannelSend for UnorderedUnreliableSender {
             self.single_messages_to_send.push_back(SendMessage {
                 data: MessageData::Single(single_data),
                 priority,
+                expires_at,
             });
             None
         }
@@ -80,6 +91,12 @@ impl ChannelSend for UnorderedUnreliableSender {
         if self.timer.as_ref().is_some_and(|t| !t.finished()) {
             return (VecDeque::new(), VecDeque::new());
         }
+        // drop the messages that were not sent in time
+        let now = self.current_time;
+        self.single_messages_to_send
+            .retain(|message| !message.is_expired(now));
+        self.fragmented_messages_to_send
+            .retain(|message| !message.is_expired(now));
         (
             core::mem::take(&mut self.single_messages_to_send),
             core::mem::take(&mut self.fragmented_messages_to_send),
@@ -90,13 +107,41 @@ impl ChannelSend for UnorderedUnreliableSender {
         // self.messages_to_send = remaining_messages_to_send;
     }
 
-    fn receive_ack(&mut self, _: &MessageAck) {}
+    fn receive_ack(&mut self, _: &MessageAck) -> bool {
+        false
+    }
+
+    fn set_fragment_size(&mut self, fragment_size: usize) {
+        self.fragment_sender.fragment_size = fragment_size;
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     // #[test]
     // fn test_unordered_unreliable_sender_internals() {
     //     todo!()
     // }
+
+    #[test]
+    fn test_unordered_unreliable_sender_ttl() {
+        let mut sender = UnorderedUnreliableSender::new(Duration::from_secs(1));
+        let fragmented = Bytes::from(vec![0; sender.fragment_sender.fragment_size + 1]);
+        sender.buffer_send(fragmented, 1.0, Some(Duration::from_millis(500)));
+        sender.buffer_send(Bytes::from("stale"), 1.0, Some(Duration::from_millis(500)));
+        sender.buffer_send(Bytes::from("hello"), 1.0, None);
+        sender.buffer_send(Bytes::from("world"), 1.0, Some(Duration::from_secs(2)));
+
+        // the messages are only sent after 1 second, when the first two are stale
+        let mut real = Time::<Real>::default();
+        real.advance_by(Duration::from_secs(1));
+        sender.update(&real, &LinkStats::default());
+        let (single, fragment) = sender.send_packet();
+        assert!(fragment.is_empty());
+        assert_eq!(single.len(), 2);
+        assert_eq!(single[0].expires_at, None);
+        assert_eq!(single[1].expires_at, Some(Duration::from_secs(2)));
+    }
 }
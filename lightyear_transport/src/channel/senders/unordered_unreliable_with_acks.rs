@@ -29,6 +29,7 @@ pub struct UnorderedUnreliableWithAcksSender {
     fragment_ack_receiver: FragmentAckReceiver,
     /// Internal timer to determine if the channel is ready to send messages
     timer: Option<Timer>,
+    current_time: Duration,
 }
 
 impl UnorderedUnreliableWithAcksSender {
@@ -45,12 +46,14 @@ impl UnorderedUnreliableWithAcksSender {
             fragment_sender: FragmentSender::new(),
             fragment_ack_receiver: FragmentAckReceiver::new(),
             timer,
+            current_time: Duration::default(),
         }
     }
 }
 
 impl ChannelSend for UnorderedUnreliableWithAcksSender {
     fn update(&mut self, real_time: &Time<Real>, _: &LinkStats) {
+        self.current_time = real_time.elapsed();
         self.fragment_ack_receiver
             .cleanup(real_time.elapsed().saturating_sub(DISCARD_AFTER));
         if let Some(timer) = &mut self.timer {
@@ -60,7 +63,13 @@ impl ChannelSend for UnorderedUnreliableWithAcksSender {
 
     /// Add a new message to the buffer of messages to be sent.
     /// This is a client-facing function, to be called when you want to send a message
-    fn buffer_send(&mut self, message: Bytes, priority: f32) -> Option<MessageId> {
+    fn buffer_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+        ttl: Option<Duration>,
+    ) -> Option<MessageId> {
+        let expires_at = ttl.map(|ttl| self.current_time + ttl);
         let message_id = self.next_send_message_id;
         if message.len() > self.fragment_sender.fragment_size {
             let fragments = self.fragment_sender.build_fragments(message_id, message);
@@ -70,6 +79,7 @@ impl ChannelSend for UnorderedUnreliableWithAcksSender {
                 self.fragmented_messages_to_send.push_back(SendMessage {
                     data: MessageData::Fragment(fragment),
                     priority,
+                    expires_at,
                 });
             }
         } else {
@@ -77,6 +87,7 @@ impl ChannelSend for UnorderedUnreliableWithAcksSender {
             self.single_messages_to_send.push_back(SendMessage {
                 data: MessageData::Single(single_data),
                 priority,
+                expires_at,
             });
         }
         self.next_send_message_id += 1;
@@ -88,6 +99,12 @@ impl ChannelSend for UnorderedUnreliableWithAcksSender {
         if self.timer.as_ref().is_some_and(|t| !t.finished()) {
             return (VecDeque::new(), VecDeque::new());
         }
+        // drop the messages that were not sent in time
+        let now = self.current_time;
+        self.single_messages_to_send
+            .retain(|message| !message.is_expired(now));
+        self.fragmented_messages_to_send
+            .retain(|message| !message.is_expired(now));
         (
             core::mem::take(&mut self.single_messages_to_send),
             core::mem::take(&mut self.fragmented_messages_to_send),
@@ -99,10 +116,43 @@ impl ChannelSend for UnorderedUnreliableWithAcksSender {
     }
 
     /// Notify any subscribers that a message was acked
-    fn receive_ack(&mut self, ack: &MessageAck) {
-        if let Some(fragment_index) = ack.fragment_id {
-            self.fragment_ack_receiver
-                .receive_fragment_ack(ack.message_id, fragment_index, None);
+    fn receive_ack(&mut self, ack: &MessageAck) -> bool {
+        match ack.fragment_id {
+            Some(fragment_index) => self.fragment_ack_receiver.receive_fragment_ack(
+                ack.message_id,
+                fragment_index,
+                None,
+            ),
+            None => true,
         }
     }
+
+    fn set_fragment_size(&mut self, fragment_size: usize) {
+        self.fragment_sender.fragment_size = fragment_size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unordered_unreliable_with_acks_sender_ttl() {
+        let mut sender = UnorderedUnreliableWithAcksSender::new(Duration::from_secs(1));
+        let fragmented = Bytes::from(vec![0; sender.fragment_sender.fragment_size + 1]);
+        sender.buffer_send(fragmented, 1.0, Some(Duration::from_millis(500)));
+        sender.buffer_send(Bytes::from("stale"), 1.0, Some(Duration::from_millis(500)));
+        sender.buffer_send(Bytes::from("hello"), 1.0, None);
+        sender.buffer_send(Bytes::from("world"), 1.0, Some(Duration::from_secs(2)));
+
+        // the messages are only sent after 1 second, when the first two are stale
+        let mut real = Time::<Real>::default();
+        real.advance_by(Duration::from_secs(1));
+        sender.update(&real, &LinkStats::default());
+        let (single, fragment) = sender.send_packet();
+        assert!(fragment.is_empty());
+        assert_eq!(single.len(), 2);
+        assert_eq!(single[0].expires_at, None);
+        assert_eq!(single[1].expires_at, Some(Duration::from_secs(2)));
+    }
 }
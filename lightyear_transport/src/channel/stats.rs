@@ -1,31 +1,157 @@
-pub(crate) mod send {
-    /// TODO: maybe this should be directly on the ChannelSender?
-    #[derive(Default, Copy, Clone, Debug, PartialEq)]
-    pub struct ChannelSendStats {
-        num_single_messages_sent: usize,
-        num_fragment_messages_sent: usize,
-        num_bytes_sent: usize,
-    }
-
-    impl ChannelSendStats {
-        pub fn new() -> Self {
-            Self::default()
-        }
+//! Statistics about the messages sent and received on each channel of a [`Transport`](crate::prelude::Transport)
+use crate::channel::{Channel, ChannelKind};
+#[cfg(feature = "metrics")]
+use bevy::ecs::entity::Entity;
+use bevy::prelude::Component;
+use lightyear_utils::collections::HashMap;
 
-        pub fn add_single_message_sent(&mut self, num: usize) {
-            self.num_single_messages_sent += num;
-        }
+/// Statistics about the messages sent on a channel
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct ChannelSendStats {
+    /// Number of single (non-fragmented) messages sent, including resends
+    pub messages_sent: u64,
+    /// Number of fragments sent, including resends
+    pub fragments_sent: u64,
+    /// Number of message bytes sent, including resends
+    pub bytes_sent: u64,
+    /// Number of messages or fragments that were sent again because they were not acked in time
+    pub resends: u64,
+    /// Number of messages or fragments that were dropped because they could not fit
+    /// in the bandwidth quota of the [`PriorityManager`](crate::prelude::PriorityManager)
+    pub dropped_by_bandwidth: u64,
+    /// Number of messages or fragments that were dropped because their ttl expired while they were
+    /// waiting in the [`PriorityManager`](crate::prelude::PriorityManager)
+    pub expired: u64,
+}
 
-        pub fn add_fragment_message_sent(&mut self, num: usize) {
-            self.num_fragment_messages_sent += num;
-        }
+impl ChannelSendStats {
+    pub(crate) fn add_single_message_sent(&mut self, num_bytes: usize) {
+        self.messages_sent += 1;
+        self.bytes_sent = self.bytes_sent.saturating_add(num_bytes as u64);
+    }
 
-        pub fn add_bytes_sent(&mut self, num_bytes: usize) {
-            self.num_bytes_sent = self.num_bytes_sent.saturating_add(num_bytes);
-        }
+    pub(crate) fn add_fragment_message_sent(&mut self, num_bytes: usize) {
+        self.fragments_sent += 1;
+        self.bytes_sent = self.bytes_sent.saturating_add(num_bytes as u64);
+    }
+}
+
+/// Statistics about the messages received on a channel
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct ChannelReceiveStats {
+    /// Number of single (non-fragmented) messages received, including duplicates
+    pub messages_received: u64,
+    /// Number of fragments received, including duplicates
+    pub fragments_received: u64,
+    /// Number of message bytes received, including duplicates
+    pub bytes_received: u64,
+    /// Number of messages currently held in the receive buffer of the channel
+    pub buffered_messages: usize,
+}
+
+impl ChannelReceiveStats {
+    pub(crate) fn add_single_message_received(&mut self, num_bytes: usize) {
+        self.messages_received += 1;
+        self.bytes_received = self.bytes_received.saturating_add(num_bytes as u64);
+    }
+
+    pub(crate) fn add_fragment_message_received(&mut self, num_bytes: usize) {
+        self.fragments_received += 1;
+        self.bytes_received = self.bytes_received.saturating_add(num_bytes as u64);
+    }
+}
+
+/// Statistics about a channel, in both directions
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub send: ChannelSendStats,
+    pub receive: ChannelReceiveStats,
+}
 
-        pub fn messages_sent(&self) -> usize {
-            self.num_single_messages_sent + self.num_fragment_messages_sent
+impl ChannelStats {
+    /// Export the counters as `metrics` gauges, labelled with the name of the channel and the
+    /// [`Transport`](crate::prelude::Transport) entity
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record_metrics(&self, channel: &'static str, entity: Entity) {
+        let entity = alloc::format!("{entity}");
+        let gauges = [
+            (
+                "transport::channel::messages_sent",
+                self.send.messages_sent as f64,
+            ),
+            (
+                "transport::channel::fragments_sent",
+                self.send.fragments_sent as f64,
+            ),
+            (
+                "transport::channel::bytes_sent",
+                self.send.bytes_sent as f64,
+            ),
+            ("transport::channel::resends", self.send.resends as f64),
+            (
+                "transport::channel::dropped_by_bandwidth",
+                self.send.dropped_by_bandwidth as f64,
+            ),
+            ("transport::channel::expired", self.send.expired as f64),
+            (
+                "transport::channel::messages_received",
+                self.receive.messages_received as f64,
+            ),
+            (
+                "transport::channel::fragments_received",
+                self.receive.fragments_received as f64,
+            ),
+            (
+                "transport::channel::bytes_received",
+                self.receive.bytes_received as f64,
+            ),
+            (
+                "transport::channel::buffered_messages",
+                self.receive.buffered_messages as f64,
+            ),
+        ];
+        for (name, value) in gauges {
+            metrics::gauge!(name, "channel" => channel, "entity" => entity.clone()).set(value);
         }
     }
+
+    /// Add the counters of `other` to these counters
+    pub fn merge(&mut self, other: &ChannelStats) {
+        self.send.messages_sent += other.send.messages_sent;
+        self.send.fragments_sent += other.send.fragments_sent;
+        self.send.bytes_sent += other.send.bytes_sent;
+        self.send.resends += other.send.resends;
+        self.send.dropped_by_bandwidth += other.send.dropped_by_bandwidth;
+        self.send.expired += other.send.expired;
+        self.receive.messages_received += other.receive.messages_received;
+        self.receive.fragments_received += other.receive.fragments_received;
+        self.receive.bytes_received += other.receive.bytes_received;
+        self.receive.buffered_messages += other.receive.buffered_messages;
+    }
+}
+
+/// Per-channel statistics of the [`Transport`](crate::prelude::Transport) on the same entity.
+///
+/// The counters are cumulative since the connection was established, and are updated every frame
+/// after the messages are sent.
+#[derive(Component, Default, Clone, Debug, PartialEq)]
+pub struct TransportStats {
+    pub channels: HashMap<ChannelKind, ChannelStats>,
+}
+
+impl TransportStats {
+    /// Statistics of the channel `C`
+    pub fn channel<C: Channel>(&self) -> Option<&ChannelStats> {
+        self.channels.get(&ChannelKind::of::<C>())
+    }
+
+    /// Statistics of the whole connection, summed over all channels
+    pub fn total(&self) -> ChannelStats {
+        self.channels
+            .values()
+            .fold(ChannelStats::default(), |mut total, stats| {
+                total.merge(stats);
+                total
+            })
+    }
 }
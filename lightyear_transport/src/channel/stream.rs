@@ -0,0 +1,757 @@
+/*! Streaming of large blobs of data over a [`ChannelMode::Stream`] channel.
+
+Sending a multi-megabyte payload as a single message splits it into fragments all at once,
+which floods the [`PriorityManager`](crate::packet::priority_manager::PriorityManager) and delays every other message.
+
+A stream instead splits the blob into chunks of [`StreamSettings::chunk_size`] bytes, and only keeps
+[`StreamSettings::window`] chunks in flight: a new chunk is only buffered when a previous chunk has been acked.
+
+Both peers get [`StreamProgress`], [`StreamCompleted`] and [`StreamCancelled`] triggers on the [`Transport`](crate::prelude::Transport) entity,
+and the receiver reads the data with a [`StreamReader`].
+
+Every chunk must be delivered for the stream to make progress, so the [`ReliableSettings`] of a
+stream channel cannot give up on a message, and the channel cannot have a ttl.
+The number of streams that are received at the same time on a channel is limited by [`StreamSettings::max_incoming_streams`],
+and the chunks that were received but not read yet count towards the [`ReceiveLimits::max_buffered_bytes`] of the connection.
+A stream that would exceed it is rejected.
+
+The receiver sends frames back to the sender to reject a stream, so a stream channel must be registered
+with [`NetworkDirection::Bidirectional`](lightyear_connection::direction::NetworkDirection::Bidirectional).
+*/
+use alloc::collections::{BTreeMap, VecDeque};
+use bevy::prelude::Event;
+use bytes::Bytes;
+use core::task::Poll;
+use lightyear_serde::reader::{ReadInteger, ReadVarInt, Reader};
+use lightyear_serde::varint::varint_len;
+use lightyear_serde::writer::{WriteInteger, Writer};
+use lightyear_serde::{SerializationError, ToBytes};
+use lightyear_utils::collections::HashMap;
+use tracing::{error, trace};
+
+use crate::channel::ChannelKind;
+use crate::channel::builder::{
+    ChannelMode, DEFAULT_MESSAGE_PRIORITY, ReliableSettings, SenderMetadata,
+};
+use crate::error::TransportError;
+use crate::limits::{LimitError, ReceiveLimits};
+use crate::packet::message::MessageId;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamSettings {
+    /// Settings used to reliably send the chunks of the streams.
+    ///
+    /// `max_retries` and `max_duration` must be `None`: a chunk that is never delivered would block the channel.
+    pub reliable: ReliableSettings,
+    /// Maximum number of bytes of the blob included in a single chunk
+    pub chunk_size: usize,
+    /// Maximum number of chunks of a stream that can be in flight (sent but not acked yet)
+    pub window: usize,
+    /// Maximum number of streams sent by the remote peer that we keep a [`StreamReader`] for.
+    ///
+    /// New streams are rejected once this limit is reached, until the existing readers are removed.
+    pub max_incoming_streams: usize,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            reliable: ReliableSettings::default(),
+            chunk_size: 16 * 1024,
+            window: 4,
+            max_incoming_streams: 8,
+        }
+    }
+}
+
+/// Identifier of a stream, unique among the streams sent by a [`Transport`](crate::prelude::Transport)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StreamId(pub u32);
+
+impl ToBytes for StreamId {
+    fn bytes_len(&self) -> usize {
+        varint_len(self.0 as u64)
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        buffer.write_varint(self.0 as u64)
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let id = buffer.read_varint()?;
+        Ok(Self(
+            u32::try_from(id).map_err(|_| SerializationError::InvalidValue)?,
+        ))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamDirection {
+    /// The stream is sent to the remote peer
+    Send,
+    /// The stream is received from the remote peer
+    Receive,
+}
+
+/// Triggered on the [`Transport`](crate::prelude::Transport) entity when more bytes of a stream
+/// were acked by the remote (for sent streams) or received (for received streams)
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct StreamProgress {
+    pub stream_id: StreamId,
+    pub direction: StreamDirection,
+    /// Number of bytes of the stream that were transferred so far
+    pub transferred: usize,
+    /// Total number of bytes of the stream
+    pub total: usize,
+}
+
+/// Triggered on the [`Transport`](crate::prelude::Transport) entity when all the bytes of a stream
+/// were acked by the remote (for sent streams) or received (for received streams)
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct StreamCompleted {
+    pub stream_id: StreamId,
+    pub direction: StreamDirection,
+}
+
+/// Triggered on the [`Transport`](crate::prelude::Transport) entity when a stream was cancelled
+/// by either peer, or rejected because the receiver has too many incoming streams
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct StreamCancelled {
+    pub stream_id: StreamId,
+    pub direction: StreamDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StreamEvent {
+    Progress(StreamProgress),
+    Completed(StreamCompleted),
+    Cancelled(StreamCancelled),
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum StreamError {
+    #[error("the stream was cancelled")]
+    Cancelled,
+}
+
+const CHUNK_TAG: u8 = 0;
+const CANCEL_TAG: u8 = 1;
+const REJECT_TAG: u8 = 2;
+
+#[derive(Debug, PartialEq)]
+enum StreamFrame {
+    /// Chunk of data of a stream
+    Chunk {
+        stream_id: StreamId,
+        total_len: u64,
+        data: Bytes,
+    },
+    /// The sender cancelled the stream
+    Cancel(StreamId),
+    /// The receiver cancelled the stream
+    Reject(StreamId),
+}
+
+impl ToBytes for StreamFrame {
+    fn bytes_len(&self) -> usize {
+        match self {
+            StreamFrame::Chunk {
+                stream_id,
+                total_len,
+                data,
+            } => 1 + stream_id.bytes_len() + varint_len(*total_len) + data.bytes_len(),
+            StreamFrame::Cancel(stream_id) | StreamFrame::Reject(stream_id) => {
+                1 + stream_id.bytes_len()
+            }
+        }
+    }
+
+    fn to_bytes(&self, buffer: &mut impl WriteInteger) -> Result<(), SerializationError> {
+        match self {
+            StreamFrame::Chunk {
+                stream_id,
+                total_len,
+                data,
+            } => {
+                buffer.write_u8(CHUNK_TAG)?;
+                stream_id.to_bytes(buffer)?;
+                buffer.write_varint(*total_len)?;
+                data.to_bytes(buffer)?;
+            }
+            StreamFrame::Cancel(stream_id) => {
+                buffer.write_u8(CANCEL_TAG)?;
+                stream_id.to_bytes(buffer)?;
+            }
+            StreamFrame::Reject(stream_id) => {
+                buffer.write_u8(REJECT_TAG)?;
+                stream_id.to_bytes(buffer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        match buffer.read_u8()? {
+            CHUNK_TAG => Ok(StreamFrame::Chunk {
+                stream_id: StreamId::from_bytes(buffer)?,
+                total_len: buffer.read_varint()?,
+                data: Bytes::from_bytes(buffer)?,
+            }),
+            CANCEL_TAG => Ok(StreamFrame::Cancel(StreamId::from_bytes(buffer)?)),
+            REJECT_TAG => Ok(StreamFrame::Reject(StreamId::from_bytes(buffer)?)),
+            _ => Err(SerializationError::InvalidValue),
+        }
+    }
+}
+
+impl StreamFrame {
+    fn serialize(&self) -> Bytes {
+        let mut writer = Writer::with_capacity(self.bytes_len());
+        // writing to a Writer cannot fail
+        self.to_bytes(&mut writer).unwrap();
+        writer.split()
+    }
+}
+
+#[derive(Debug)]
+struct OutgoingStream {
+    channel: ChannelKind,
+    data: Bytes,
+    /// Number of bytes of the blob that were buffered in the channel
+    sent: usize,
+    /// Number of bytes of the blob that were acked by the remote
+    acked: usize,
+    /// Chunks that were buffered but not acked yet, with their length
+    in_flight: HashMap<MessageId, usize>,
+    /// True once the first chunk was buffered. Needed to send empty blobs
+    started: bool,
+}
+
+/// Reads the data of a stream received from the remote peer.
+///
+/// The reader is kept in the [`Transport`](crate::prelude::Transport) until it is removed
+/// with [`Transport::remove_stream_reader`](crate::prelude::Transport::remove_stream_reader),
+/// or until it is evicted by the next update after the stream was cancelled or fully read.
+#[derive(Debug)]
+pub struct StreamReader {
+    channel: ChannelKind,
+    total_len: usize,
+    received: usize,
+    /// Chunks that were received but not read yet
+    chunks: VecDeque<Bytes>,
+    cancelled: bool,
+}
+
+impl StreamReader {
+    fn new(channel: ChannelKind, total_len: usize) -> Self {
+        Self {
+            channel,
+            total_len,
+            received: 0,
+            chunks: VecDeque::new(),
+            cancelled: false,
+        }
+    }
+
+    /// Total number of bytes of the stream
+    pub fn total_len(&self) -> usize {
+        self.total_len
+    }
+
+    /// Number of bytes of the stream received so far
+    pub fn received_len(&self) -> usize {
+        self.received
+    }
+
+    /// Returns true if all the bytes of the stream were received
+    pub fn is_complete(&self) -> bool {
+        !self.cancelled && self.received == self.total_len
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Number of bytes that were received but not read yet
+    pub fn buffered_len(&self) -> usize {
+        self.chunks.iter().map(Bytes::len).sum()
+    }
+
+    /// Read the received bytes into `buf`, similarly to `AsyncRead::poll_read`.
+    ///
+    /// Returns:
+    /// - `Poll::Ready(Ok(n))` with the number of bytes read, or 0 if the whole stream was read
+    /// - `Poll::Pending` if no bytes are available yet
+    /// - `Poll::Ready(Err(StreamError::Cancelled))` if the stream was cancelled
+    pub fn poll_read(&mut self, buf: &mut [u8]) -> Poll<Result<usize, StreamError>> {
+        if self.cancelled {
+            return Poll::Ready(Err(StreamError::Cancelled));
+        }
+        if self.chunks.is_empty() {
+            return if self.received == self.total_len {
+                Poll::Ready(Ok(0))
+            } else {
+                Poll::Pending
+            };
+        }
+        let mut read = 0;
+        while read < buf.len() {
+            let Some(chunk) = self.chunks.front_mut() else {
+                break;
+            };
+            let len = chunk.len().min(buf.len() - read);
+            buf[read..read + len].copy_from_slice(&chunk.split_to(len));
+            read += len;
+            if chunk.is_empty() {
+                self.chunks.pop_front();
+            }
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+/// Keeps track of the streams sent and received by a [`Transport`](crate::prelude::Transport)
+#[derive(Debug, Default)]
+pub struct StreamManager {
+    next_stream_id: u32,
+    outgoing: BTreeMap<StreamId, OutgoingStream>,
+    incoming: HashMap<StreamId, StreamReader>,
+    /// Most recent stream received on each channel. The stream ids of a peer are increasing and the
+    /// frames of a channel are ordered, so a lower id belongs to a stream that was already evicted or rejected.
+    last_incoming: HashMap<ChannelKind, StreamId>,
+    /// Cancel/Reject frames that must be buffered in the senders
+    pending_frames: Vec<(ChannelKind, Bytes)>,
+    events: Vec<StreamEvent>,
+}
+
+impl StreamManager {
+    /// Start sending the `data` blob on the stream channel `channel`
+    pub(crate) fn start(&mut self, channel: ChannelKind, data: Bytes) -> StreamId {
+        let stream_id = StreamId(self.next_stream_id);
+        self.next_stream_id = self.next_stream_id.wrapping_add(1);
+        self.outgoing.insert(
+            stream_id,
+            OutgoingStream {
+                channel,
+                data,
+                sent: 0,
+                acked: 0,
+                in_flight: HashMap::default(),
+                started: false,
+            },
+        );
+        stream_id
+    }
+
+    /// Stop sending a stream. Returns false if the stream was not being sent
+    pub(crate) fn cancel(&mut self, stream_id: StreamId) -> bool {
+        let Some(stream) = self.outgoing.remove(&stream_id) else {
+            return false;
+        };
+        self.pending_frames
+            .push((stream.channel, StreamFrame::Cancel(stream_id).serialize()));
+        self.events.push(StreamEvent::Cancelled(StreamCancelled {
+            stream_id,
+            direction: StreamDirection::Send,
+        }));
+        true
+    }
+
+    /// Ask the remote to stop sending a stream. Returns false if the stream is not being received
+    pub(crate) fn reject(&mut self, stream_id: StreamId) -> bool {
+        let Some(reader) = self.incoming.get_mut(&stream_id) else {
+            return false;
+        };
+        if reader.cancelled || reader.received == reader.total_len {
+            return false;
+        }
+        reader.cancelled = true;
+        reader.chunks.clear();
+        self.pending_frames
+            .push((reader.channel, StreamFrame::Reject(stream_id).serialize()));
+        self.events.push(StreamEvent::Cancelled(StreamCancelled {
+            stream_id,
+            direction: StreamDirection::Receive,
+        }));
+        true
+    }
+
+    pub(crate) fn reader_mut(&mut self, stream_id: StreamId) -> Option<&mut StreamReader> {
+        self.incoming.get_mut(&stream_id)
+    }
+
+    pub(crate) fn remove_reader(&mut self, stream_id: StreamId) -> Option<StreamReader> {
+        self.incoming.remove(&stream_id)
+    }
+
+    /// Number of bytes of the incoming streams that were received but not read yet
+    pub(crate) fn num_buffered_bytes(&self) -> usize {
+        self.incoming.values().map(StreamReader::buffered_len).sum()
+    }
+
+    /// Remove the readers of the streams that were cancelled or fully read
+    pub(crate) fn evict_readers(&mut self) {
+        self.incoming.retain(|stream_id, reader| {
+            let evict = reader.cancelled || (reader.is_complete() && reader.chunks.is_empty());
+            if evict {
+                trace!(?stream_id, "evicting stream reader");
+            }
+            !evict
+        });
+    }
+
+    /// Buffer new chunks of the outgoing streams in their channel, as long as the window allows it
+    pub(crate) fn send_chunks(&mut self, senders: &mut HashMap<ChannelKind, SenderMetadata>) {
+        self.pending_frames.drain(..).for_each(|(channel, bytes)| {
+            if let Some(sender_metadata) = senders.get_mut(&channel) {
+                sender_metadata.buffer_send(bytes, DEFAULT_MESSAGE_PRIORITY, None);
+            }
+        });
+        self.outgoing.iter_mut().for_each(|(stream_id, stream)| {
+            let Some(sender_metadata) = senders.get_mut(&stream.channel) else {
+                return;
+            };
+            let ChannelMode::Stream(settings) = sender_metadata.mode else {
+                return;
+            };
+            let total_len = stream.data.len();
+            while stream.in_flight.len() < settings.window.max(1)
+                && (stream.sent < total_len || !stream.started)
+            {
+                let end = total_len.min(stream.sent + settings.chunk_size.max(1));
+                let data = stream.data.slice(stream.sent..end);
+                let len = data.len();
+                let frame = StreamFrame::Chunk {
+                    stream_id: *stream_id,
+                    total_len: total_len as u64,
+                    data,
+                };
+                let Some(message_id) =
+                    sender_metadata.buffer_send(frame.serialize(), DEFAULT_MESSAGE_PRIORITY, None)
+                else {
+                    error!(?stream_id, "stream chunk was buffered without a MessageId");
+                    return;
+                };
+                trace!(?stream_id, ?message_id, offset = ?stream.sent, ?len, "buffered stream chunk");
+                stream.in_flight.insert(message_id, len);
+                stream.sent = end;
+                stream.started = true;
+            }
+        });
+    }
+
+    /// A message of a stream channel was acked by the remote
+    pub(crate) fn on_ack(&mut self, channel: ChannelKind, message_id: MessageId) {
+        let Some((stream_id, stream)) = self.outgoing.iter_mut().find(|(_, stream)| {
+            stream.channel == channel && stream.in_flight.contains_key(&message_id)
+        }) else {
+            return;
+        };
+        let stream_id = *stream_id;
+        let len = stream.in_flight.remove(&message_id).unwrap();
+        stream.acked += len;
+        self.events.push(StreamEvent::Progress(StreamProgress {
+            stream_id,
+            direction: StreamDirection::Send,
+            transferred: stream.acked,
+            total: stream.data.len(),
+        }));
+        if stream.started && stream.acked == stream.data.len() && stream.in_flight.is_empty() {
+            self.outgoing.remove(&stream_id);
+            self.events.push(StreamEvent::Completed(StreamCompleted {
+                stream_id,
+                direction: StreamDirection::Send,
+            }));
+        }
+    }
+
+    /// Handle a frame received on a stream channel
+    ///
+    /// A stream is rejected if buffering its chunk would exceed the [`ReceiveLimits::max_buffered_bytes`]
+    /// of the connection.
+    pub(crate) fn receive(
+        &mut self,
+        channel: ChannelKind,
+        settings: &StreamSettings,
+        limits: &ReceiveLimits,
+        bytes: Bytes,
+    ) -> Result<(), TransportError> {
+        match StreamFrame::from_bytes(&mut Reader::from(bytes))? {
+            StreamFrame::Chunk {
+                stream_id,
+                total_len,
+                data,
+            } => {
+                let total_len =
+                    usize::try_from(total_len).map_err(|_| SerializationError::InvalidValue)?;
+                if !self.incoming.contains_key(&stream_id) {
+                    // chunk of a stream that was already evicted or rejected
+                    if self
+                        .last_incoming
+                        .get(&channel)
+                        .is_some_and(|last| stream_id <= *last)
+                    {
+                        return Ok(());
+                    }
+                    self.last_incoming.insert(channel, stream_id);
+                    let num_streams = self
+                        .incoming
+                        .values()
+                        .filter(|reader| reader.channel == channel)
+                        .count();
+                    if num_streams >= settings.max_incoming_streams {
+                        error!(
+                            ?stream_id,
+                            ?channel,
+                            "Rejecting stream: too many incoming streams"
+                        );
+                        self.pending_frames
+                            .push((channel, StreamFrame::Reject(stream_id).serialize()));
+                        self.events.push(StreamEvent::Cancelled(StreamCancelled {
+                            stream_id,
+                            direction: StreamDirection::Receive,
+                        }));
+                        return Ok(());
+                    }
+                    self.incoming
+                        .insert(stream_id, StreamReader::new(channel, total_len));
+                }
+                let buffered_bytes = self.num_buffered_bytes() + data.len();
+                let reader = self.incoming.get_mut(&stream_id).unwrap();
+                // the remote might still send a few chunks after we rejected the stream
+                if reader.cancelled {
+                    return Ok(());
+                }
+                if reader.received + data.len() > reader.total_len {
+                    return Err(SerializationError::InvalidValue.into());
+                }
+                if buffered_bytes > limits.max_buffered_bytes {
+                    self.reject(stream_id);
+                    return Err(LimitError::ReceiveBufferFull {
+                        bytes: buffered_bytes,
+                        max: limits.max_buffered_bytes,
+                    }
+                    .into());
+                }
+                reader.received += data.len();
+                if !data.is_empty() {
+                    reader.chunks.push_back(data);
+                }
+                self.events.push(StreamEvent::Progress(StreamProgress {
+                    stream_id,
+                    direction: StreamDirection::Receive,
+                    transferred: reader.received,
+                    total: reader.total_len,
+                }));
+                if reader.received == reader.total_len {
+                    self.events.push(StreamEvent::Completed(StreamCompleted {
+                        stream_id,
+                        direction: StreamDirection::Receive,
+                    }));
+                }
+            }
+            StreamFrame::Cancel(stream_id) => {
+                if let Some(reader) = self.incoming.get_mut(&stream_id) {
+                    reader.cancelled = true;
+                    reader.chunks.clear();
+                    self.events.push(StreamEvent::Cancelled(StreamCancelled {
+                        stream_id,
+                        direction: StreamDirection::Receive,
+                    }));
+                }
+            }
+            StreamFrame::Reject(stream_id) => {
+                if self.outgoing.remove(&stream_id).is_some() {
+                    self.events.push(StreamEvent::Cancelled(StreamCancelled {
+                        stream_id,
+                        direction: StreamDirection::Send,
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<StreamEvent> {
+        core::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StreamChannel;
+
+    #[test]
+    fn test_stream_frame_serialization() {
+        let frames = [
+            StreamFrame::Chunk {
+                stream_id: StreamId(3),
+                total_len: 100_000,
+                data: Bytes::from("hello"),
+            },
+            StreamFrame::Cancel(StreamId(1000)),
+            StreamFrame::Reject(StreamId(0)),
+        ];
+        for frame in frames {
+            let bytes = frame.serialize();
+            assert_eq!(bytes.len(), frame.bytes_len());
+            assert_eq!(
+                StreamFrame::from_bytes(&mut Reader::from(bytes)).unwrap(),
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_reader() {
+        let channel = ChannelKind::of::<StreamChannel>();
+        let mut manager = StreamManager::default();
+        let chunk = |data: &'static str| {
+            StreamFrame::Chunk {
+                stream_id: StreamId(0),
+                total_len: 8,
+                data: Bytes::from(data),
+            }
+            .serialize()
+        };
+
+        manager
+            .receive(
+                channel,
+                &StreamSettings::default(),
+                &ReceiveLimits::default(),
+                chunk("hello"),
+            )
+            .unwrap();
+        let reader = manager.reader_mut(StreamId(0)).unwrap();
+        let mut buf = [0; 3];
+        assert_eq!(reader.poll_read(&mut buf), Poll::Ready(Ok(3)));
+        assert_eq!(&buf, b"hel");
+        assert_eq!(reader.poll_read(&mut buf), Poll::Ready(Ok(2)));
+        assert_eq!(&buf[..2], b"lo");
+        // the rest of the stream was not received yet
+        assert_eq!(reader.poll_read(&mut buf), Poll::Pending);
+
+        manager
+            .receive(
+                channel,
+                &StreamSettings::default(),
+                &ReceiveLimits::default(),
+                chunk("!!!"),
+            )
+            .unwrap();
+        let reader = manager.reader_mut(StreamId(0)).unwrap();
+        assert!(reader.is_complete());
+        assert_eq!(reader.poll_read(&mut buf), Poll::Ready(Ok(3)));
+        assert_eq!(reader.poll_read(&mut buf), Poll::Ready(Ok(0)));
+        assert_eq!(
+            manager.take_events().last(),
+            Some(&StreamEvent::Completed(StreamCompleted {
+                stream_id: StreamId(0),
+                direction: StreamDirection::Receive,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_max_incoming_streams() {
+        let channel = ChannelKind::of::<StreamChannel>();
+        let settings = StreamSettings {
+            max_incoming_streams: 1,
+            ..Default::default()
+        };
+        let mut manager = StreamManager::default();
+        let limits = ReceiveLimits::default();
+        let chunk = |stream_id: u32, data: &'static str| {
+            StreamFrame::Chunk {
+                stream_id: StreamId(stream_id),
+                total_len: 2,
+                data: Bytes::from(data),
+            }
+            .serialize()
+        };
+
+        manager
+            .receive(channel, &settings, &limits, chunk(0, "a"))
+            .unwrap();
+        // the second stream is rejected while the first one is being received
+        manager
+            .receive(channel, &settings, &limits, chunk(1, "a"))
+            .unwrap();
+        assert!(manager.reader_mut(StreamId(1)).is_none());
+        assert_eq!(
+            manager.pending_frames,
+            vec![(channel, StreamFrame::Reject(StreamId(1)).serialize())]
+        );
+        // the chunks of the rejected stream that are still in flight are ignored
+        manager
+            .receive(channel, &settings, &limits, chunk(1, "b"))
+            .unwrap();
+        assert!(manager.reader_mut(StreamId(1)).is_none());
+
+        // once the first stream is fully read, its reader is evicted and new streams are accepted
+        manager
+            .receive(channel, &settings, &limits, chunk(0, "b"))
+            .unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(
+            manager.reader_mut(StreamId(0)).unwrap().poll_read(&mut buf),
+            Poll::Ready(Ok(2))
+        );
+        manager.evict_readers();
+        assert!(manager.reader_mut(StreamId(0)).is_none());
+        manager
+            .receive(channel, &settings, &limits, chunk(2, "a"))
+            .unwrap();
+        assert!(manager.reader_mut(StreamId(2)).is_some());
+    }
+
+    #[test]
+    fn test_stream_buffer_limit() {
+        let channel = ChannelKind::of::<StreamChannel>();
+        let settings = StreamSettings::default();
+        let limits = ReceiveLimits {
+            max_buffered_bytes: 5,
+            ..Default::default()
+        };
+        let mut manager = StreamManager::default();
+        let chunk = |data: &'static str| {
+            StreamFrame::Chunk {
+                stream_id: StreamId(0),
+                // the total length is set by the remote, so it doesn't bound the buffered chunks
+                total_len: 1 << 30,
+                data: Bytes::from(data),
+            }
+            .serialize()
+        };
+
+        manager
+            .receive(channel, &settings, &limits, chunk("abc"))
+            .unwrap();
+        assert_eq!(manager.num_buffered_bytes(), 3);
+        // the chunks that were not read yet would exceed the limit: the stream is rejected
+        assert!(matches!(
+            manager.receive(channel, &settings, &limits, chunk("def")),
+            Err(TransportError::LimitExceeded(
+                LimitError::ReceiveBufferFull { bytes: 6, max: 5 }
+            ))
+        ));
+        assert!(manager.reader_mut(StreamId(0)).unwrap().is_cancelled());
+        assert_eq!(manager.num_buffered_bytes(), 0);
+        assert_eq!(
+            manager.pending_frames,
+            vec![(channel, StreamFrame::Reject(StreamId(0)).serialize())]
+        );
+    }
+}
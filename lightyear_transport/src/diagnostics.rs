@@ -0,0 +1,289 @@
+//! Compute Diagnostics based on the statistics of the channels of each [`Transport`](crate::prelude::Transport)
+
+use crate::channel::registry::{ChannelKind, ChannelRegistry};
+use crate::channel::stats::{ChannelStats, TransportStats};
+use crate::plugin::TransportSet;
+#[cfg(not(feature = "std"))]
+use alloc::{format, vec::Vec};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use core::time::Duration;
+use lightyear_utils::collections::HashMap;
+
+/// Plugin that exposes the statistics of the [`TransportStats`] components as diagnostics.
+///
+/// The counters are summed over all the [`Transport`](crate::prelude::Transport) entities,
+/// both over all channels and for each channel separately.
+pub struct TransportDiagnosticsPlugin {
+    pub history_len: usize,
+    pub flush_interval: Duration,
+}
+
+impl Default for TransportDiagnosticsPlugin {
+    fn default() -> Self {
+        Self {
+            history_len: 60,
+            flush_interval: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A counter of [`ChannelStats`] that is exposed as a diagnostic
+struct Counter {
+    name: &'static str,
+    path: DiagnosticPath,
+    suffix: &'static str,
+    value: fn(&ChannelStats) -> f64,
+}
+
+const COUNTERS: [Counter; 10] = [
+    Counter {
+        name: "messages_sent",
+        path: TransportDiagnosticsPlugin::MESSAGES_SENT,
+        suffix: "messages",
+        value: |stats| stats.send.messages_sent as f64,
+    },
+    Counter {
+        name: "fragments_sent",
+        path: TransportDiagnosticsPlugin::FRAGMENTS_SENT,
+        suffix: "fragments",
+        value: |stats| stats.send.fragments_sent as f64,
+    },
+    Counter {
+        name: "bytes_sent",
+        path: TransportDiagnosticsPlugin::BYTES_SENT,
+        suffix: "bytes",
+        value: |stats| stats.send.bytes_sent as f64,
+    },
+    Counter {
+        name: "resends",
+        path: TransportDiagnosticsPlugin::RESENDS,
+        suffix: "resends",
+        value: |stats| stats.send.resends as f64,
+    },
+    Counter {
+        name: "dropped_by_bandwidth",
+        path: TransportDiagnosticsPlugin::DROPPED_BY_BANDWIDTH,
+        suffix: "messages",
+        value: |stats| stats.send.dropped_by_bandwidth as f64,
+    },
+    Counter {
+        name: "expired",
+        path: TransportDiagnosticsPlugin::EXPIRED,
+        suffix: "messages",
+        value: |stats| stats.send.expired as f64,
+    },
+    Counter {
+        name: "messages_received",
+        path: TransportDiagnosticsPlugin::MESSAGES_RECEIVED,
+        suffix: "messages",
+        value: |stats| stats.receive.messages_received as f64,
+    },
+    Counter {
+        name: "fragments_received",
+        path: TransportDiagnosticsPlugin::FRAGMENTS_RECEIVED,
+        suffix: "fragments",
+        value: |stats| stats.receive.fragments_received as f64,
+    },
+    Counter {
+        name: "bytes_received",
+        path: TransportDiagnosticsPlugin::BYTES_RECEIVED,
+        suffix: "bytes",
+        value: |stats| stats.receive.bytes_received as f64,
+    },
+    Counter {
+        name: "buffered_messages",
+        path: TransportDiagnosticsPlugin::BUFFERED_MESSAGES,
+        suffix: "messages",
+        value: |stats| stats.receive.buffered_messages as f64,
+    },
+];
+
+/// Diagnostic paths of the counters of each channel, in the same order as [`COUNTERS`]
+#[derive(Resource, Default)]
+struct ChannelDiagnosticPaths(Vec<(ChannelKind, Vec<DiagnosticPath>)>);
+
+impl TransportDiagnosticsPlugin {
+    /// Number of single messages sent
+    pub const MESSAGES_SENT: DiagnosticPath = DiagnosticPath::const_new("transport.messages_sent");
+
+    /// Number of fragments sent
+    pub const FRAGMENTS_SENT: DiagnosticPath =
+        DiagnosticPath::const_new("transport.fragments_sent");
+
+    /// Number of message bytes sent
+    pub const BYTES_SENT: DiagnosticPath = DiagnosticPath::const_new("transport.bytes_sent");
+
+    /// Number of messages or fragments scheduled to be sent again because they were not acked in time
+    pub const RESENDS: DiagnosticPath = DiagnosticPath::const_new("transport.resends");
+
+    /// Number of messages dropped because they could not fit in the bandwidth quota
+    pub const DROPPED_BY_BANDWIDTH: DiagnosticPath =
+        DiagnosticPath::const_new("transport.dropped_by_bandwidth");
+
+    /// Number of messages dropped because their ttl expired before they could be sent
+    pub const EXPIRED: DiagnosticPath = DiagnosticPath::const_new("transport.expired");
+
+    /// Number of single messages received
+    pub const MESSAGES_RECEIVED: DiagnosticPath =
+        DiagnosticPath::const_new("transport.messages_received");
+
+    /// Number of fragments received
+    pub const FRAGMENTS_RECEIVED: DiagnosticPath =
+        DiagnosticPath::const_new("transport.fragments_received");
+
+    /// Number of message bytes received
+    pub const BYTES_RECEIVED: DiagnosticPath =
+        DiagnosticPath::const_new("transport.bytes_received");
+
+    /// Number of messages held in the receive buffers
+    pub const BUFFERED_MESSAGES: DiagnosticPath =
+        DiagnosticPath::const_new("transport.buffered_messages");
+
+    /// Path of the diagnostic for one counter of a channel, for example
+    /// `channel_path(core::any::type_name::<MyChannel>(), "messages_sent")`
+    pub fn channel_path(channel_name: &str, counter: &str) -> DiagnosticPath {
+        DiagnosticPath::new(format!("transport.{channel_name}.{counter}"))
+    }
+
+    fn flush_measurements(
+        query: Query<&TransportStats>,
+        paths: Res<ChannelDiagnosticPaths>,
+        mut diagnostics: Diagnostics,
+    ) {
+        let mut channels: HashMap<ChannelKind, ChannelStats> = HashMap::default();
+        query.iter().for_each(|transport_stats| {
+            transport_stats
+                .channels
+                .iter()
+                .for_each(|(channel_kind, stats)| {
+                    channels.entry(*channel_kind).or_default().merge(stats);
+                });
+        });
+        let total = channels
+            .values()
+            .fold(ChannelStats::default(), |mut total, stats| {
+                total.merge(stats);
+                total
+            });
+        COUNTERS.iter().for_each(|counter| {
+            diagnostics.add_measurement(&counter.path, || (counter.value)(&total));
+        });
+        paths.0.iter().for_each(|(channel_kind, paths)| {
+            let stats = channels.get(channel_kind).copied().unwrap_or_default();
+            COUNTERS.iter().zip(paths).for_each(|(counter, path)| {
+                diagnostics.add_measurement(path, || (counter.value)(&stats));
+            });
+        });
+    }
+}
+
+impl Plugin for TransportDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChannelDiagnosticPaths>();
+        app.add_systems(
+            PostUpdate,
+            Self::flush_measurements
+                .after(TransportSet::Send)
+                .run_if(on_timer(self.flush_interval)),
+        );
+        COUNTERS.iter().for_each(|counter| {
+            app.register_diagnostic(
+                Diagnostic::new(counter.path.clone())
+                    .with_suffix(counter.suffix)
+                    .with_max_history_length(self.history_len),
+            );
+        });
+    }
+
+    // the channels are registered once all plugins are built
+    fn finish(&self, app: &mut App) {
+        let Some(registry) = app.world().get_resource::<ChannelRegistry>() else {
+            return;
+        };
+        let channels: Vec<_> = registry.channels().collect();
+        let mut paths = Vec::new();
+        channels.into_iter().for_each(|(channel_kind, name)| {
+            let channel_paths = COUNTERS
+                .iter()
+                .map(|counter| {
+                    let path = Self::channel_path(name, counter.name);
+                    app.register_diagnostic(
+                        Diagnostic::new(path.clone())
+                            .with_suffix(counter.suffix)
+                            .with_max_history_length(self.history_len),
+                    );
+                    path
+                })
+                .collect();
+            paths.push((channel_kind, channel_paths));
+        });
+        app.insert_resource(ChannelDiagnosticPaths(paths));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::stats::{ChannelReceiveStats, ChannelSendStats};
+    use bevy::diagnostic::DiagnosticsStore;
+
+    struct C1;
+    struct C2;
+
+    #[test]
+    fn test_flush_measurements() {
+        let mut app = App::new();
+        app.init_resource::<Time>();
+        let mut registry = ChannelRegistry::default();
+        registry.add_channel::<C1>(default());
+        registry.add_channel::<C2>(default());
+        app.insert_resource(registry);
+        app.add_plugins(TransportDiagnosticsPlugin {
+            history_len: 10,
+            flush_interval: Duration::ZERO,
+        });
+        app.finish();
+
+        let stats = |messages_sent, buffered_messages| ChannelStats {
+            send: ChannelSendStats {
+                messages_sent,
+                ..default()
+            },
+            receive: ChannelReceiveStats {
+                buffered_messages,
+                ..default()
+            },
+        };
+        let mut transport_stats = TransportStats::default();
+        transport_stats
+            .channels
+            .insert(ChannelKind::of::<C1>(), stats(3, 1));
+        app.world_mut().spawn(transport_stats.clone());
+        transport_stats
+            .channels
+            .insert(ChannelKind::of::<C2>(), stats(2, 0));
+        app.world_mut().spawn(transport_stats);
+        app.update();
+
+        let store = app.world().resource::<DiagnosticsStore>();
+        let value = |path: &DiagnosticPath| store.get(path).unwrap().value().unwrap();
+        assert_eq!(value(&TransportDiagnosticsPlugin::MESSAGES_SENT), 8.0);
+        assert_eq!(value(&TransportDiagnosticsPlugin::BUFFERED_MESSAGES), 2.0);
+        assert_eq!(
+            value(&TransportDiagnosticsPlugin::channel_path(
+                core::any::type_name::<C1>(),
+                "messages_sent"
+            )),
+            6.0
+        );
+        assert_eq!(
+            value(&TransportDiagnosticsPlugin::channel_path(
+                core::any::type_name::<C2>(),
+                "messages_sent"
+            )),
+            2.0
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use crate::channel::ChannelKind;
 use crate::channel::receivers::error::ChannelReceiveError;
+use crate::limits::LimitError;
 use crate::packet::error::PacketError;
+use crate::packet::message::MessageHandle;
 use bytes::Bytes;
+use core::time::Duration;
 use crossbeam_channel::TrySendError;
 
 pub type Result<T> = core::result::Result<T, TransportError>;
@@ -13,8 +16,41 @@ pub enum TransportError {
     PacketError(#[from] PacketError),
     #[error("channel {0:?} was not found")]
     ChannelNotFound(ChannelKind),
+    #[error("channel {0:?} is not a stream channel")]
+    NotAStreamChannel(ChannelKind),
+    #[error("channel {0:?} is a stream channel and cannot send regular messages")]
+    StreamChannel(ChannelKind),
     #[error("receiver channel error: {0}")]
     ChannelReceiveError(#[from] ChannelReceiveError),
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitError),
     #[error("error sending data: {0}")]
-    ChannelSendError(#[from] TrySendError<(ChannelKind, Bytes, f32)>),
+    ChannelSendError(
+        #[from]
+        TrySendError<(
+            ChannelKind,
+            Bytes,
+            f32,
+            Option<Duration>,
+            Option<MessageHandle>,
+        )>,
+    ),
+}
+
+impl TransportError {
+    /// Returns the [`LimitError`] if this error was caused by the remote peer exceeding one of the
+    /// [`ReceiveLimits`](crate::limits::ReceiveLimits)
+    pub fn limit_exceeded(&self) -> Option<LimitError> {
+        match self {
+            Self::LimitExceeded(e)
+            | Self::ChannelReceiveError(ChannelReceiveError::LimitExceeded(e))
+            | Self::PacketError(PacketError::ChannelReceiveError(
+                ChannelReceiveError::LimitExceeded(e),
+            )) => Some(*e),
+            Self::SerializationError(e) | Self::PacketError(PacketError::Serialization(e)) => {
+                LimitError::from_serialization(e)
+            }
+            _ => None,
+        }
+    }
 }
@@ -9,8 +9,12 @@ extern crate alloc;
 
 pub mod channel;
 
+pub mod diagnostics;
+
 pub mod error;
 
+pub mod limits;
+
 #[cfg(feature = "client")]
 mod client;
 pub mod packet;
@@ -21,9 +25,24 @@ mod server;
 pub mod prelude {
     pub use crate::channel::Channel;
     pub use crate::channel::builder::{ChannelMode, ChannelSettings, ReliableSettings, Transport};
+    pub use crate::channel::compression::Compression;
+    pub use crate::channel::fec::ForwardErrorCorrection;
     pub use crate::channel::registry::AppChannelExt;
     pub use crate::channel::registry::ChannelRegistry;
+    pub use crate::channel::stats::{
+        ChannelReceiveStats, ChannelSendStats, ChannelStats, TransportStats,
+    };
+    pub use crate::channel::stream::{
+        StreamCancelled, StreamCompleted, StreamDirection, StreamError, StreamId, StreamProgress,
+        StreamReader, StreamSettings,
+    };
+    pub use crate::diagnostics::TransportDiagnosticsPlugin;
+    pub use crate::limits::{LimitError, ReceiveLimitExceeded, ReceiveLimits};
+    pub use crate::packet::congestion::CongestionConfig;
+    pub use crate::packet::message::{MessageHandle, MessageHandleAllocator};
+    pub use crate::packet::mtu::{LinkMtu, MtuProbeConfig};
     pub use crate::packet::priority_manager::{PriorityConfig, PriorityManager};
+    pub use crate::plugin::{MessageAbandoned, MessageAcked, MessageLost};
 
     pub use lightyear_macros::Channel;
 }
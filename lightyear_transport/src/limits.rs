@@ -0,0 +1,106 @@
+//! Limits on the data received from a remote peer
+//!
+//! A malicious peer could send payloads that make us allocate a lot of memory: a message with a huge
+//! collection length, a fragmented message with a huge number of fragments, or a lot of messages that
+//! are buffered but never read (for example by never filling the gaps in an ordered channel).
+//!
+//! The [`ReceiveLimits`] component bounds the memory that can be used by a single connection.
+//! When a limit is exceeded, the offending packet or message is dropped, a [`ReceiveLimitExceeded`] event is
+//! triggered on the entity, and the remote peer is disconnected if [`ReceiveLimits::disconnect_on_violation`] is set.
+use bevy::prelude::*;
+use lightyear_connection::client::disconnect_with_reason;
+use lightyear_serde::SerializationError;
+use lightyear_serde::reader::{DEFAULT_MAX_COLLECTION_LEN, Reader};
+use tracing::error;
+
+/// Limits applied to the data received on a connection.
+///
+/// Add this component on the entity holding the [`Transport`](crate::prelude::Transport) to override the default limits.
+/// On a server, the component must be added on each `ClientOf` entity.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct ReceiveLimits {
+    /// Maximum size in bytes of a single message, after its fragments have been reassembled and it
+    /// has been decompressed. This also bounds the number of bytes that bincode can allocate when
+    /// deserializing a message or a replicated component.
+    pub max_message_size: usize,
+    /// Maximum number of elements of a collection (`Vec`, `HashMap`, etc.) in a message or a replicated
+    /// component serialized with `ToBytes`
+    pub max_collection_len: usize,
+    /// Maximum number of fragments of messages that are being reassembled on a single channel
+    pub max_in_flight_fragments: usize,
+    /// Maximum number of bytes that can be buffered in the receivers of all the channels of the connection,
+    /// including the chunks of the incoming streams that were not read yet
+    pub max_buffered_bytes: usize,
+    /// If true, the remote peer is disconnected when one of the limits is exceeded
+    pub disconnect_on_violation: bool,
+}
+
+impl Default for ReceiveLimits {
+    fn default() -> Self {
+        Self {
+            max_message_size: 8 * 1024 * 1024,
+            max_collection_len: DEFAULT_MAX_COLLECTION_LEN,
+            max_in_flight_fragments: 16 * 1024,
+            max_buffered_bytes: 32 * 1024 * 1024,
+            disconnect_on_violation: false,
+        }
+    }
+}
+
+/// A [`ReceiveLimits`] was exceeded by the data sent by the remote peer
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum LimitError {
+    #[error("message of {size} bytes exceeds the maximum of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
+    #[error("collection length {len} exceeds the maximum of {max}")]
+    CollectionTooLong { len: u64, max: usize },
+    #[error("decoded value exceeds the maximum of {max} bytes")]
+    DecodeTooLarge { max: usize },
+    #[error("{fragments} fragments in flight exceed the maximum of {max}")]
+    TooManyFragments { fragments: usize, max: usize },
+    #[error("{bytes} buffered bytes exceed the maximum of {max} bytes")]
+    ReceiveBufferFull { bytes: usize, max: usize },
+}
+
+impl LimitError {
+    /// Returns the limit that was exceeded, if the error was caused by the [`ReceiveLimits::max_collection_len`]
+    /// or the [`ReceiveLimits::max_message_size`]
+    pub fn from_serialization(error: &SerializationError) -> Option<Self> {
+        match error {
+            SerializationError::CollectionTooLong { len, max } => Some(Self::CollectionTooLong {
+                len: *len,
+                max: *max,
+            }),
+            SerializationError::DecodeTooLarge { max } => Some(Self::DecodeTooLarge { max: *max }),
+            _ => None,
+        }
+    }
+}
+
+/// Triggered on the [`Transport`](crate::prelude::Transport) entity when the remote peer exceeded one of the [`ReceiveLimits`]
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ReceiveLimitExceeded {
+    pub error: LimitError,
+}
+
+impl ReceiveLimits {
+    /// Apply the limits to a [`Reader`] used to deserialize the data received from the remote peer
+    pub fn apply_to(&self, reader: Reader) -> Reader {
+        reader
+            .with_max_collection_len(self.max_collection_len)
+            .with_max_decode_size(self.max_message_size)
+    }
+
+    /// Notify that the remote peer connected to `entity` exceeded a limit, and disconnect it if needed.
+    pub fn on_exceeded(&self, commands: &mut Commands, entity: Entity, error: LimitError) {
+        error!(?entity, "Receive limit exceeded: {error}");
+        commands.trigger_targets(ReceiveLimitExceeded { error }, entity);
+        if self.disconnect_on_violation {
+            disconnect_with_reason(
+                commands,
+                entity,
+                alloc::format!("Receive limit exceeded: {error}"),
+            );
+        }
+    }
+}
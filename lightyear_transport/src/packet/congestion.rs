@@ -0,0 +1,346 @@
+//! Adaptive congestion control.
+//!
+//! With a fixed [`PriorityConfig::bandwidth_quota`](crate::packet::priority_manager::PriorityConfig),
+//! the transport either sends more than the link can handle (which increases latency and packet loss)
+//! or doesn't use all the available bandwidth.
+//!
+//! The [`CongestionController`] instead estimates the bandwidth of the link with an AIMD
+//! (additive increase, multiplicative decrease) algorithm:
+//! - every RTT during which the send budget was fully used and no congestion was detected, the
+//!   bandwidth estimate is increased by [`CongestionConfig::additive_increase`]
+//! - when a packet is lost, or when the RTT grows too far above the smallest RTT observed on the link,
+//!   the bandwidth estimate is multiplied by [`CongestionConfig::multiplicative_decrease`].
+//!   The estimate is decreased at most once per RTT, since all the packets that were in flight
+//!   were sent with the previous estimate.
+//!
+//! The estimate is then used as the refill rate of the token bucket that limits the number of bytes
+//! that the [`PriorityManager`](crate::packet::priority_manager::PriorityManager) can send.
+use core::time::Duration;
+use tracing::trace;
+
+/// RTT used to pace the adjustments until the link has an RTT estimate
+const DEFAULT_RTT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CongestionConfig {
+    /// Bandwidth estimate (in bytes per second) used when the connection starts
+    pub initial_bandwidth: u32,
+    /// The bandwidth estimate never goes below this number of bytes per second
+    pub min_bandwidth: u32,
+    /// The bandwidth estimate never goes above this number of bytes per second
+    pub max_bandwidth: u32,
+    /// Number of bytes per second added to the estimate for every RTT without congestion
+    pub additive_increase: u32,
+    /// Factor applied to the estimate when congestion is detected. Must be between 0.0 and 1.0
+    pub multiplicative_decrease: f32,
+    /// If set, congestion is also detected when the RTT is higher than the smallest RTT observed
+    /// on the link by more than this ratio (for example 0.5 means 50% higher).
+    ///
+    /// This lets the controller react to growing network queues before packets start getting dropped.
+    pub delay_threshold: Option<f32>,
+    /// Maximum duration of unused budget that can be accumulated and sent in a single burst
+    pub max_burst: Duration,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self {
+            initial_bandwidth: 32_000,
+            min_bandwidth: 4_000,
+            max_bandwidth: 1_000_000,
+            additive_increase: 4_000,
+            multiplicative_decrease: 0.75,
+            delay_threshold: Some(0.5),
+            max_burst: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Estimates the bandwidth of a link from the packet acks, packet losses and RTT,
+/// and limits the number of bytes sent accordingly.
+#[derive(Debug, Clone)]
+pub struct CongestionController {
+    config: CongestionConfig,
+    /// Current bandwidth estimate, in bytes per second
+    bandwidth: f32,
+    /// Number of bytes that can be sent right now. Can be negative if we sent more than the budget
+    tokens: f32,
+    /// Latest RTT estimate of the link
+    rtt: Duration,
+    /// Smallest RTT observed on the link
+    min_rtt: Option<Duration>,
+    last_update: Option<Duration>,
+    /// Start of the current adjustment period
+    period_start: Duration,
+    /// True if the send budget was fully used during the current period
+    budget_reached: bool,
+    /// True if at least one packet was acked during the current period
+    acked: bool,
+    last_decrease: Option<Duration>,
+}
+
+impl Default for CongestionController {
+    fn default() -> Self {
+        Self::new(CongestionConfig::default())
+    }
+}
+
+impl CongestionController {
+    pub fn new(config: CongestionConfig) -> Self {
+        let bandwidth = (config.initial_bandwidth as f32)
+            .clamp(config.min_bandwidth as f32, config.max_bandwidth as f32);
+        Self {
+            config,
+            bandwidth,
+            tokens: 0.0,
+            rtt: Duration::ZERO,
+            min_rtt: None,
+            last_update: None,
+            period_start: Duration::ZERO,
+            budget_reached: false,
+            acked: false,
+            last_decrease: None,
+        }
+    }
+
+    pub fn config(&self) -> &CongestionConfig {
+        &self.config
+    }
+
+    /// Current bandwidth estimate, in bytes per second
+    pub fn bandwidth(&self) -> f32 {
+        self.bandwidth
+    }
+
+    /// Number of bytes that can currently be sent
+    pub fn available_bytes(&self) -> f32 {
+        self.tokens.max(0.0)
+    }
+
+    fn rtt(&self) -> Duration {
+        if self.rtt.is_zero() {
+            DEFAULT_RTT
+        } else {
+            self.rtt
+        }
+    }
+
+    /// Refill the send budget and increase the bandwidth estimate if there was no congestion
+    /// during the last RTT.
+    pub(crate) fn update(&mut self, now: Duration, rtt: Duration) {
+        self.rtt = rtt;
+        if !rtt.is_zero() {
+            self.min_rtt = Some(self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt)));
+            if self.is_delay_congested() {
+                self.decrease(now);
+            }
+        }
+
+        let Some(last_update) = self.last_update.replace(now) else {
+            self.period_start = now;
+            // start with a full budget
+            self.tokens = self.max_tokens();
+            return;
+        };
+        let elapsed = now.saturating_sub(last_update).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * self.bandwidth).min(self.max_tokens());
+
+        if now.saturating_sub(self.period_start) >= self.rtt() {
+            // only probe for more bandwidth if we actually needed it; otherwise the estimate
+            // would keep growing on links that are mostly idle
+            if self.budget_reached && self.acked {
+                self.bandwidth = (self.bandwidth + self.config.additive_increase as f32)
+                    .min(self.config.max_bandwidth as f32);
+                trace!(bandwidth = ?self.bandwidth, "no congestion, increasing bandwidth estimate");
+            }
+            self.period_start = now;
+            self.budget_reached = false;
+            self.acked = false;
+        }
+    }
+
+    fn max_tokens(&self) -> f32 {
+        self.bandwidth * self.config.max_burst.as_secs_f32()
+    }
+
+    fn is_delay_congested(&self) -> bool {
+        let (Some(threshold), Some(min_rtt)) = (self.config.delay_threshold, self.min_rtt) else {
+            return false;
+        };
+        self.rtt > min_rtt.mul_f32(1.0 + threshold)
+    }
+
+    /// Returns true if a message of `bytes` bytes can be sent; in which case the bytes are
+    /// removed from the budget.
+    pub(crate) fn try_consume(&mut self, bytes: u32) -> bool {
+        if self.tokens <= 0.0 {
+            self.budget_reached = true;
+            return false;
+        }
+        // we allow the budget to become negative so that messages bigger than the burst size can be sent;
+        // the debt is paid back before the next message is allowed
+        self.tokens -= bytes as f32;
+        true
+    }
+
+    /// Remove bytes from the budget without checking if there is enough capacity.
+    ///
+    /// Used for messages that bypass the budget, and to account for the packet headers.
+    /// The debt is capped at one burst, so that a lot of bypassing messages cannot block
+    /// the other messages for a long time.
+    pub(crate) fn consume(&mut self, bytes: u32) {
+        self.tokens = (self.tokens - bytes as f32).max(-self.max_tokens());
+    }
+
+    /// Called when a packet was acked by the remote
+    pub(crate) fn on_packet_acked(&mut self) {
+        self.acked = true;
+    }
+
+    /// Called when a packet is considered lost
+    pub(crate) fn on_packet_lost(&mut self, now: Duration) {
+        self.decrease(now);
+    }
+
+    fn decrease(&mut self, now: Duration) {
+        if self
+            .last_decrease
+            .is_some_and(|last| now.saturating_sub(last) < self.rtt())
+        {
+            return;
+        }
+        self.last_decrease = Some(now);
+        self.bandwidth = (self.bandwidth * self.config.multiplicative_decrease)
+            .max(self.config.min_bandwidth as f32);
+        self.tokens = self.tokens.min(self.max_tokens());
+        // don't increase the estimate right after a decrease
+        self.period_start = now;
+        self.budget_reached = false;
+        self.acked = false;
+        trace!(bandwidth = ?self.bandwidth, "congestion detected, decreasing bandwidth estimate");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RTT: Duration = Duration::from_millis(50);
+
+    /// Use all the budget and get the packets acked for one RTT
+    fn saturate(controller: &mut CongestionController, now: &mut Duration) {
+        let end = *now + RTT;
+        while *now < end {
+            *now += Duration::from_millis(10);
+            controller.update(*now, RTT);
+            while controller.try_consume(100) {}
+            controller.on_packet_acked();
+        }
+    }
+
+    #[test]
+    fn test_additive_increase() {
+        let config = CongestionConfig::default();
+        let mut controller = CongestionController::new(config);
+        let mut now = Duration::ZERO;
+        controller.update(now, RTT);
+        saturate(&mut controller, &mut now);
+        saturate(&mut controller, &mut now);
+        assert!(controller.bandwidth() > config.initial_bandwidth as f32);
+
+        // the estimate does not grow if the budget is not used
+        let idle = |controller: &mut CongestionController, now: &mut Duration| {
+            for _ in 0..10 {
+                *now += Duration::from_millis(10);
+                controller.update(*now, RTT);
+                controller.on_packet_acked();
+            }
+        };
+        // finish the period that was started while saturating the link
+        idle(&mut controller, &mut now);
+        let bandwidth = controller.bandwidth();
+        idle(&mut controller, &mut now);
+        assert_eq!(controller.bandwidth(), bandwidth);
+    }
+
+    #[test]
+    fn test_multiplicative_decrease_once_per_rtt() {
+        let config = CongestionConfig::default();
+        let mut controller = CongestionController::new(config);
+        let mut now = Duration::ZERO;
+        controller.update(now, RTT);
+
+        controller.on_packet_lost(now);
+        controller.on_packet_lost(now + Duration::from_millis(10));
+        let expected = config.initial_bandwidth as f32 * config.multiplicative_decrease;
+        assert_eq!(controller.bandwidth(), expected);
+
+        now += RTT;
+        controller.update(now, RTT);
+        controller.on_packet_lost(now);
+        assert_eq!(
+            controller.bandwidth(),
+            expected * config.multiplicative_decrease
+        );
+
+        // the estimate never goes below the minimum
+        for _ in 0..100 {
+            now += RTT;
+            controller.on_packet_lost(now);
+        }
+        assert_eq!(controller.bandwidth(), config.min_bandwidth as f32);
+    }
+
+    #[test]
+    fn test_delay_based_decrease() {
+        let config = CongestionConfig::default();
+        let mut controller = CongestionController::new(config);
+        let mut now = Duration::ZERO;
+        controller.update(now, RTT);
+        now += Duration::from_millis(10);
+        controller.update(now, RTT * 2);
+        assert!(controller.bandwidth() < config.initial_bandwidth as f32);
+    }
+
+    #[test]
+    fn test_budget() {
+        let config = CongestionConfig {
+            initial_bandwidth: 10_000,
+            max_burst: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut controller = CongestionController::new(config);
+        controller.update(Duration::ZERO, RTT);
+        // 100ms of budget at 10KB/s
+        assert_eq!(controller.available_bytes(), 1000.0);
+        // a message bigger than the budget can still be sent
+        assert!(controller.try_consume(1500));
+        assert!(!controller.try_consume(1));
+
+        // the debt is paid back before more messages can be sent
+        controller.update(Duration::from_millis(40), RTT);
+        assert!(!controller.try_consume(1));
+        controller.update(Duration::from_millis(60), RTT);
+        assert!(controller.try_consume(1));
+    }
+
+    #[test]
+    fn test_bypass_debt_is_capped() {
+        let config = CongestionConfig {
+            initial_bandwidth: 10_000,
+            max_burst: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut controller = CongestionController::new(config);
+        controller.update(Duration::ZERO, RTT);
+        // messages that bypass the budget can send much more than the budget
+        controller.consume(1_000_000);
+        assert!(!controller.try_consume(1));
+
+        // but the debt is capped at one burst, so it is paid back after 100ms
+        controller.update(Duration::from_millis(50), RTT);
+        assert!(!controller.try_consume(1));
+        controller.update(Duration::from_millis(110), RTT);
+        assert!(controller.try_consume(1));
+    }
+}
@@ -0,0 +1,337 @@
+//! Decode raw packets (for example the ones recorded by a [`LinkCapture`](lightyear_link::capture::LinkCapture))
+//! into their header and the messages they contain, so that they can be inspected offline.
+use crate::channel::compression::ChannelCompressor;
+use crate::channel::receivers::error::ChannelReceiveError;
+use crate::channel::registry::{ChannelId, ChannelRegistry};
+use crate::limits::{LimitError, ReceiveLimits};
+use crate::packet::error::PacketError;
+use crate::packet::header::PacketHeader;
+use crate::packet::message::{FragmentData, MessageId, SingleData};
+use crate::packet::packet::PacketId;
+use crate::packet::packet_type::PacketType;
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+use bytes::{Bytes, BytesMut};
+use lightyear_core::tick::Tick;
+use lightyear_serde::reader::{ReadInteger, Reader};
+use lightyear_serde::{SerializationError, ToBytes};
+use lightyear_utils::collections::HashMap;
+
+/// A packet that was decoded by the [`PacketDecoder`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPacket {
+    /// Packet id from the sender's perspective
+    pub packet_id: PacketId,
+    /// Last packet id received by the sender
+    pub last_ack_packet_id: PacketId,
+    /// Bitfield of the 32 packet ids received before `last_ack_packet_id`
+    pub ack_bitfield: u32,
+    /// Tick of the sender when the packet was sent
+    pub tick: Tick,
+    /// True if the packet contains a fragment of a message that was too big to fit in a single packet
+    pub fragmented: bool,
+    pub messages: Vec<DecodedMessage>,
+}
+
+/// A message contained in a [`DecodedPacket`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedMessage {
+    pub channel_id: ChannelId,
+    /// Name of the channel, if it is registered in the [`ChannelRegistry`]
+    pub channel_name: Option<&'static str>,
+    pub message_id: Option<MessageId>,
+    /// `(fragment_index, num_fragments)` if the message is a fragment of a bigger message
+    pub fragment: Option<(u64, u64)>,
+    /// The bytes of the message.
+    ///
+    /// For fragments, this is only set once all the fragments of the message have been decoded,
+    /// and contains the bytes of the whole re-assembled message.
+    /// If the channel uses [`Compression`](crate::channel::compression::Compression), the bytes are decompressed.
+    pub bytes: Option<Bytes>,
+}
+
+/// Decodes raw packets into [`DecodedPacket`]s.
+///
+/// The decoder is stateful because it re-assembles fragmented messages; a separate decoder
+/// should be used for each direction of each link.
+///
+/// The number of fragments that are being re-assembled is bounded by
+/// [`ReceiveLimits::max_in_flight_fragments`], so that a corrupted capture cannot make the decoder
+/// allocate an arbitrary amount of memory.
+#[derive(Debug, Default)]
+pub struct PacketDecoder {
+    fragments: HashMap<(ChannelId, MessageId), Vec<Option<Bytes>>>,
+    compressors: HashMap<ChannelId, ChannelCompressor>,
+    limits: ReceiveLimits,
+}
+
+impl PacketDecoder {
+    /// Use the `limits` instead of the default [`ReceiveLimits`]
+    pub fn with_limits(mut self, limits: ReceiveLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn decode(
+        &mut self,
+        payload: Bytes,
+        registry: &ChannelRegistry,
+    ) -> Result<DecodedPacket, PacketError> {
+        let mut cursor = Reader::from(payload);
+        let header = PacketHeader::from_bytes(&mut cursor)?;
+        let fragmented = header.get_packet_type() == PacketType::DataFragment;
+        let mut messages = Vec::new();
+        if fragmented {
+            let channel_id = ChannelId::from_bytes(&mut cursor)?;
+            let fragment_data = FragmentData::from_bytes(&mut cursor)?;
+            messages.push(self.decode_fragment(channel_id, fragment_data, registry)?);
+        }
+        // the rest of an MTU probe is padding
+        let is_probe = header.get_packet_type() == PacketType::MtuProbe;
+        while !is_probe && cursor.has_remaining() {
+            let channel_id = ChannelId::from_bytes(&mut cursor)?;
+            let num_messages = cursor.read_u8().map_err(SerializationError::from)?;
+            for _ in 0..num_messages {
+                let single_data = SingleData::from_bytes(&mut cursor)?;
+                messages.push(DecodedMessage {
+                    channel_id,
+                    channel_name: registry.name(channel_id),
+                    message_id: single_data.id,
+                    fragment: None,
+                    bytes: Some(self.decompress(channel_id, single_data.bytes, registry)),
+                });
+            }
+        }
+        Ok(DecodedPacket {
+            packet_id: header.packet_id,
+            last_ack_packet_id: header.last_ack_packet_id,
+            ack_bitfield: header.ack_bitfield,
+            tick: header.tick,
+            fragmented,
+            messages,
+        })
+    }
+
+    fn decode_fragment(
+        &mut self,
+        channel_id: ChannelId,
+        data: FragmentData,
+        registry: &ChannelRegistry,
+    ) -> Result<DecodedMessage, PacketError> {
+        let (index, num_fragments) = (data.fragment_id.0, data.num_fragments.0);
+        if index >= num_fragments {
+            return Err(SerializationError::InvalidValue.into());
+        }
+        let key = (channel_id, data.message_id);
+        if !self.fragments.contains_key(&key) {
+            let max = self.limits.max_in_flight_fragments;
+            let in_flight = self.fragments.values().map(Vec::len).sum::<usize>();
+            let fragments = usize::try_from(num_fragments)
+                .unwrap_or(usize::MAX)
+                .saturating_add(in_flight);
+            if fragments > max {
+                return Err(ChannelReceiveError::from(LimitError::TooManyFragments {
+                    fragments,
+                    max,
+                })
+                .into());
+            }
+        }
+        let fragments = self
+            .fragments
+            .entry(key)
+            .or_insert_with(|| vec![None; num_fragments as usize]);
+        if fragments.len() as u64 != num_fragments {
+            return Err(SerializationError::InvalidValue.into());
+        }
+        // the same fragment can be received multiple times if it was re-sent
+        fragments[index as usize] = Some(data.bytes);
+        let bytes = if fragments.iter().all(Option::is_some) {
+            let mut bytes = BytesMut::new();
+            self.fragments
+                .remove(&(channel_id, data.message_id))
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .for_each(|fragment| bytes.extend_from_slice(&fragment));
+            Some(self.decompress(channel_id, bytes.freeze(), registry))
+        } else {
+            None
+        };
+        Ok(DecodedMessage {
+            channel_id,
+            channel_name: registry.name(channel_id),
+            message_id: Some(data.message_id),
+            fragment: Some((index, num_fragments)),
+            bytes,
+        })
+    }
+
+    /// Decompress a complete message; the raw bytes are kept if the message cannot be decompressed
+    fn decompress(
+        &mut self,
+        channel_id: ChannelId,
+        bytes: Bytes,
+        registry: &ChannelRegistry,
+    ) -> Bytes {
+        let Some(settings) = registry.settings_from_net_id(channel_id) else {
+            return bytes;
+        };
+        let max_message_size = self.limits.max_message_size;
+        self.compressors
+            .entry(channel_id)
+            .or_insert_with(|| {
+                let mut compressor = ChannelCompressor::from(settings.compression);
+                compressor.set_max_decompressed_size(max_message_size);
+                compressor
+            })
+            .decompress(bytes.clone())
+            .unwrap_or(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::builder::{ChannelMode, ChannelSettings};
+    use crate::packet::message::FragmentIndex;
+    use crate::packet::packet_builder::PacketBuilder;
+    use bevy::prelude::default;
+    use core::time::Duration;
+
+    struct Channel1;
+
+    #[test]
+    fn test_decode_packets() {
+        let mut registry = ChannelRegistry::default();
+        let (_, channel_id) = registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+
+        let mut builder = PacketBuilder::new(1.5);
+        let big_message = Bytes::from(vec![7u8; 2000]);
+        let fragments = vec![
+            FragmentData {
+                message_id: MessageId(3),
+                fragment_id: FragmentIndex(0),
+                num_fragments: FragmentIndex(2),
+                bytes: big_message.slice(..1000),
+            },
+            FragmentData {
+                message_id: MessageId(3),
+                fragment_id: FragmentIndex(1),
+                num_fragments: FragmentIndex(2),
+                bytes: big_message.slice(1000..),
+            },
+        ];
+        let single = SingleData::new(Some(MessageId(1)), Bytes::from_static(b"hello"));
+        let packets = builder
+            .build_packets(
+                Duration::default(),
+                Tick(5),
+                vec![(channel_id, vec![single].into())],
+                vec![(channel_id, fragments.into())],
+            )
+            .unwrap();
+
+        let mut decoder = PacketDecoder::default();
+        let decoded = packets
+            .into_iter()
+            .map(|packet| decoder.decode(packet.payload.into(), &registry).unwrap())
+            .collect::<Vec<_>>();
+        let messages = decoded
+            .iter()
+            .flat_map(|packet| packet.messages.iter())
+            .collect::<Vec<_>>();
+        assert!(decoded.iter().all(|packet| packet.tick == Tick(5)));
+        assert!(
+            messages
+                .iter()
+                .all(|m| m.channel_name == Some(core::any::type_name::<Channel1>()))
+        );
+
+        let single = messages.iter().find(|m| m.fragment.is_none()).unwrap();
+        assert_eq!(single.message_id, Some(MessageId(1)));
+        assert_eq!(single.bytes, Some(Bytes::from_static(b"hello")));
+
+        // only the last fragment contains the re-assembled message
+        let fragments = messages
+            .iter()
+            .filter(|m| m.fragment.is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(fragments.len(), 2);
+        assert_eq!(fragments[0].bytes, None);
+        assert_eq!(fragments[1].bytes, Some(big_message));
+    }
+
+    /// A corrupted fragment with a huge number of fragments is rejected instead of being allocated
+    #[test]
+    fn test_decode_too_many_fragments() {
+        let mut registry = ChannelRegistry::default();
+        let (_, channel_id) = registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+        let fragment = FragmentData {
+            message_id: MessageId(0),
+            fragment_id: FragmentIndex(0),
+            num_fragments: FragmentIndex(1 << 40),
+            bytes: Bytes::from_static(&[0; 10]),
+        };
+        let packets = PacketBuilder::new(1.5)
+            .build_packets(
+                Duration::default(),
+                Tick(0),
+                vec![],
+                vec![(channel_id, vec![fragment].into())],
+            )
+            .unwrap();
+
+        let mut decoder = PacketDecoder::default();
+        assert!(matches!(
+            decoder.decode(packets[0].payload.clone().into(), &registry),
+            Err(PacketError::ChannelReceiveError(
+                ChannelReceiveError::LimitExceeded(LimitError::TooManyFragments { .. })
+            ))
+        ));
+    }
+
+    /// A compressed message that would decompress to more than [`ReceiveLimits::max_message_size`] is not decompressed
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_decompression_limit() {
+        use crate::channel::compression::Compression;
+
+        let compression = Compression::Lz4 { dictionary: None };
+        let mut registry = ChannelRegistry::default();
+        let (_, channel_id) = registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            compression,
+            ..default()
+        });
+        let message = Bytes::from("hello world ".repeat(100));
+        let compressed = ChannelCompressor::new(compression).compress(message.clone());
+        let single = SingleData::new(Some(MessageId(0)), compressed.clone());
+        let packets = PacketBuilder::new(1.5)
+            .build_packets(
+                Duration::default(),
+                Tick(0),
+                vec![(channel_id, vec![single].into())],
+                vec![],
+            )
+            .unwrap();
+        let payload: Bytes = packets[0].payload.clone().into();
+
+        let mut decoder = PacketDecoder::default();
+        let decoded = decoder.decode(payload.clone(), &registry).unwrap();
+        assert_eq!(decoded.messages[0].bytes, Some(message));
+
+        let mut decoder = PacketDecoder::default().with_limits(ReceiveLimits {
+            max_message_size: 100,
+            ..default()
+        });
+        let decoded = decoder.decode(payload, &registry).unwrap();
+        assert_eq!(decoded.messages[0].bytes, Some(compressed));
+    }
+}
@@ -23,11 +23,11 @@ pub(crate) struct PacketHeader {
     /// Packet id from the sender's perspective
     pub(crate) packet_id: PacketId,
     /// Last ack-ed packet id received by the sender
-    last_ack_packet_id: PacketId,
+    pub(crate) last_ack_packet_id: PacketId,
     /// Bitfield of the last 32 packet ids before `ack_id`
     /// (this means that in total we send acks for 33 packet-ids)
     /// See more information at: [GafferOnGames](https://gafferongames.com/post/reliability_ordering_and_congestion_avoidance_over_udp/)
-    ack_bitfield: u32,
+    pub(crate) ack_bitfield: u32,
     /// Current tick
     pub(crate) tick: Tick,
 }
@@ -1,6 +1,9 @@
 /// Defines the [`Message`](message::Message) struct, which is a piece of serializable data
 use core::fmt::Debug;
+use core::time::Duration;
 
+use bevy::platform::sync::Arc;
+use bevy::platform::sync::atomic::{AtomicU64, Ordering};
 use bytes::Bytes;
 
 use lightyear_core::tick::Tick;
@@ -13,6 +16,27 @@ use lightyear_utils::wrapping_id;
 // Internal id that we assign to each message sent over the network
 wrapping_id!(MessageId);
 
+/// Handle returned when sending a message, to track its delivery with the
+/// [`MessageAcked`](crate::plugin::MessageAcked) and [`MessageLost`](crate::plugin::MessageLost) triggers.
+///
+/// Unlike the [`MessageId`], which is only assigned when the message is buffered in its channel,
+/// the handle is available as soon as the message is sent, and is unique across all channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MessageHandle(u64);
+
+/// Creates the [`MessageHandle`]s of a [`Transport`](crate::prelude::Transport).
+///
+/// Clones share the same counter, so that the handles are unique across all the senders of the `Transport`.
+#[derive(Clone, Debug, Default)]
+pub struct MessageHandleAllocator(Arc<AtomicU64>);
+
+impl MessageHandleAllocator {
+    /// Create a new unique handle
+    pub fn next(&self) -> MessageHandle {
+        MessageHandle(self.0.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 /// The index of a fragment in a fragmented message.
 ///
 /// It will be serialized as a varint, so it will take only 1 byte if there
@@ -44,6 +68,14 @@ pub struct MessageAck {
 pub struct SendMessage {
     pub(crate) data: MessageData,
     pub(crate) priority: f32,
+    /// Real time after which the message is not worth sending anymore
+    pub(crate) expires_at: Option<Duration>,
+}
+
+impl SendMessage {
+    pub(crate) fn is_expired(&self, now: Duration) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
 }
 
 #[derive(Debug, PartialEq)]
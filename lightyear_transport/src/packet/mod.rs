@@ -22,8 +22,17 @@ Packets that are over the maximum packet size will be fragmented into multiple [
 /// Manages the [`PacketHeader`](header::PacketHeader) which includes important packet information
 pub(crate) mod header;
 
+/// Decodes raw packets into their header and messages, to inspect them offline
+pub mod decode;
+
+/// Adaptive estimation of the bandwidth of a link
+pub mod congestion;
+
 pub mod message;
 
+/// Per-link MTU configuration and path MTU discovery
+pub mod mtu;
+
 // "module has the same name as its containing module" style nit.
 // clippy doesn't like this, but not much benefit to changing it now, so silence the warning.
 #[allow(clippy::module_inception)]
@@ -0,0 +1,259 @@
+//! Per-link MTU configuration and path MTU discovery.
+//!
+//! By default the [`Transport`](crate::channel::builder::Transport) builds packets of at most
+//! [`MAX_PACKET_SIZE`] bytes. Add a [`LinkMtu`] component on the entity to use a different packet
+//! size for that link, and optionally let the transport probe the link to discover the largest
+//! packet size that can reach the remote.
+//!
+//! Probing works by sending [`PacketType::MtuProbe`](crate::packet::packet_type::PacketType) packets
+//! padded to the candidate size. Probes use the regular packet ack system: if the probe gets acked
+//! the size is usable, and if it is lost too many times we consider that packets of that size are
+//! dropped on the path (for example by a VPN or tunnel that adds its own headers).
+//! The candidate sizes are chosen with a binary search between the current MTU and
+//! [`MtuProbeConfig::max_mtu`].
+use crate::packet::packet::PacketId;
+use crate::packet::packet_builder::MAX_PACKET_SIZE;
+use bevy::prelude::Component;
+use core::time::Duration;
+use tracing::{debug, info};
+
+/// Smallest packet size that can be used.
+///
+/// Every IPv4 host must be able to receive 576-byte datagrams, which leaves 508 bytes for the
+/// UDP payload.
+pub const MIN_MTU: usize = 508;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MtuProbeConfig {
+    /// Largest packet size that will be probed.
+    ///
+    /// Make sure that every layer below the transport can handle packets of that size: for example
+    /// the netcode connection layer only accepts packets of up to 1200 bytes.
+    ///
+    /// This defaults to [`MAX_PACKET_SIZE`], which is also the default MTU of a [`LinkMtu`], so
+    /// you must set it to a bigger value for the probing to do anything (for example 1472 bytes
+    /// for UDP over IPv4 without a connection layer).
+    pub max_mtu: usize,
+    /// Number of times a probe of a given size can be lost before we consider that packets of
+    /// that size cannot reach the remote
+    pub max_attempts: u8,
+    /// Minimum duration between two probes
+    pub probe_interval: Duration,
+    /// The search stops once the range of possible MTUs is smaller than this number of bytes
+    pub precision: usize,
+}
+
+impl Default for MtuProbeConfig {
+    fn default() -> Self {
+        Self {
+            max_mtu: MAX_PACKET_SIZE,
+            max_attempts: 3,
+            probe_interval: Duration::from_millis(200),
+            precision: 16,
+        }
+    }
+}
+
+/// State of the binary search
+#[derive(Debug, Clone)]
+struct MtuProbe {
+    config: MtuProbeConfig,
+    /// Largest size that we know can reach the remote
+    low: usize,
+    /// Largest size that could still reach the remote
+    high: usize,
+    /// The probe that is waiting to be acked
+    in_flight: Option<(PacketId, usize)>,
+    /// Number of times the current candidate size was lost
+    attempts: u8,
+    last_probe: Option<Duration>,
+}
+
+impl MtuProbe {
+    fn candidate(&self) -> usize {
+        self.low + (self.high - self.low).div_ceil(2)
+    }
+
+    fn is_done(&self) -> bool {
+        self.high.saturating_sub(self.low) < self.config.precision.max(1)
+    }
+}
+
+/// Maximum size (in bytes) of the packets that the [`Transport`](crate::channel::builder::Transport)
+/// of this link will build.
+///
+/// Messages that are bigger than what fits in a single packet get fragmented.
+/// Changing the MTU only affects messages that are sent afterwards.
+#[derive(Component, Debug, Clone)]
+pub struct LinkMtu {
+    mtu: usize,
+    probe: Option<MtuProbe>,
+}
+
+impl Default for LinkMtu {
+    fn default() -> Self {
+        Self::new(MAX_PACKET_SIZE)
+    }
+}
+
+impl LinkMtu {
+    /// Use packets of at most `mtu` bytes (at least [`MIN_MTU`])
+    pub fn new(mtu: usize) -> Self {
+        Self {
+            mtu: mtu.max(MIN_MTU),
+            probe: None,
+        }
+    }
+
+    /// Probe the link to find the largest packet size between the current MTU and
+    /// [`MtuProbeConfig::max_mtu`] that can reach the remote.
+    ///
+    /// The current MTU is assumed to be usable; it is increased every time a bigger probe is acked.
+    /// Probing is disabled if [`MtuProbeConfig::max_mtu`] is not bigger than the current MTU.
+    ///
+    /// Probes are only sent when they fit in the bandwidth budget of the
+    /// [`PriorityManager`](crate::packet::priority_manager::PriorityManager).
+    pub fn with_probing(mut self, config: MtuProbeConfig) -> Self {
+        let probe = MtuProbe {
+            config,
+            low: self.mtu,
+            high: config.max_mtu.max(self.mtu),
+            in_flight: None,
+            attempts: 0,
+            last_probe: None,
+        };
+        self.probe = (!probe.is_done()).then_some(probe);
+        self
+    }
+
+    /// Current maximum packet size
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Returns true if the path MTU discovery is still running
+    pub fn is_probing(&self) -> bool {
+        self.probe.is_some()
+    }
+
+    /// Returns the size of the probe packet that should be sent now, if any
+    pub(crate) fn poll_probe(&mut self, now: Duration) -> Option<usize> {
+        let probe = self.probe.as_mut()?;
+        if probe.in_flight.is_some()
+            || probe
+                .last_probe
+                .is_some_and(|last| now < last + probe.config.probe_interval)
+        {
+            return None;
+        }
+        probe.last_probe = Some(now);
+        Some(probe.candidate())
+    }
+
+    /// Register the probe packet that was sent
+    pub(crate) fn probe_sent(&mut self, packet_id: PacketId, size: usize) {
+        if let Some(probe) = self.probe.as_mut() {
+            probe.in_flight = Some((packet_id, size));
+        }
+    }
+
+    /// Called when a packet was acked by the remote
+    pub(crate) fn on_packet_acked(&mut self, packet_id: PacketId) {
+        let Some(probe) = self.probe.as_mut() else {
+            return;
+        };
+        let Some((_, size)) = probe.in_flight.take_if(|(id, _)| *id == packet_id) else {
+            return;
+        };
+        debug!(?size, "MTU probe acked");
+        probe.low = size;
+        probe.attempts = 0;
+        self.mtu = size;
+        self.finish_if_done();
+    }
+
+    /// Called when a packet is considered lost.
+    ///
+    /// Returns true if the packet was an MTU probe.
+    pub(crate) fn on_packet_lost(&mut self, packet_id: PacketId) -> bool {
+        let Some(probe) = self.probe.as_mut() else {
+            return false;
+        };
+        let Some((_, size)) = probe.in_flight.take_if(|(id, _)| *id == packet_id) else {
+            return false;
+        };
+        probe.attempts += 1;
+        debug!(?size, attempts = ?probe.attempts, "MTU probe lost");
+        if probe.attempts >= probe.config.max_attempts {
+            probe.high = size - 1;
+            probe.attempts = 0;
+        }
+        self.finish_if_done();
+        true
+    }
+
+    fn finish_if_done(&mut self) {
+        if self.probe.as_ref().is_some_and(MtuProbe::is_done) {
+            info!(mtu = ?self.mtu, "Path MTU discovery finished");
+            self.probe = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulate a path that drops every packet bigger than `path_mtu`
+    fn discover(mut link_mtu: LinkMtu, path_mtu: usize) -> LinkMtu {
+        let mut now = Duration::ZERO;
+        let mut packet_id = PacketId(0);
+        while link_mtu.is_probing() {
+            now += Duration::from_millis(100);
+            if let Some(size) = link_mtu.poll_probe(now) {
+                packet_id += 1;
+                link_mtu.probe_sent(packet_id, size);
+                if size <= path_mtu {
+                    link_mtu.on_packet_acked(packet_id);
+                } else {
+                    link_mtu.on_packet_lost(packet_id);
+                }
+            }
+        }
+        link_mtu
+    }
+
+    #[test]
+    fn test_probe_discovers_path_mtu() {
+        let config = MtuProbeConfig {
+            max_mtu: 1472,
+            ..Default::default()
+        };
+        let link_mtu = discover(LinkMtu::new(MIN_MTU).with_probing(config), 1400);
+        assert!(link_mtu.mtu() <= 1400);
+        assert!(link_mtu.mtu() > 1400 - config.precision);
+
+        // the path allows every size
+        let link_mtu = discover(LinkMtu::new(MIN_MTU).with_probing(config), 1500);
+        assert!(link_mtu.mtu() > 1472 - config.precision);
+
+        // the path doesn't allow anything bigger than the initial MTU
+        let link_mtu = discover(LinkMtu::new(1000).with_probing(config), 900);
+        assert_eq!(link_mtu.mtu(), 1000);
+    }
+
+    #[test]
+    fn test_probe_interval() {
+        let mut link_mtu = LinkMtu::new(MIN_MTU).with_probing(MtuProbeConfig::default());
+        let size = link_mtu.poll_probe(Duration::ZERO).unwrap();
+        link_mtu.probe_sent(PacketId(0), size);
+        // a probe is already in flight
+        assert_eq!(link_mtu.poll_probe(Duration::from_secs(1)), None);
+        assert!(!link_mtu.on_packet_lost(PacketId(1)));
+        assert!(link_mtu.on_packet_lost(PacketId(0)));
+        // too early
+        assert_eq!(link_mtu.poll_probe(Duration::from_millis(100)), None);
+        // the same size is probed again
+        assert_eq!(link_mtu.poll_probe(Duration::from_millis(200)), Some(size));
+    }
+}
@@ -1,7 +1,7 @@
 /// Defines the [`Packet`] struct
 use crate::channel::registry::ChannelId;
 use crate::packet::message::MessageAck;
-use crate::packet::packet_builder::Payload;
+use crate::packet::packet_builder::{MAX_PACKET_SIZE, Payload};
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 use lightyear_serde::ToBytes;
@@ -10,15 +10,19 @@ use lightyear_utils::wrapping_id;
 // Internal id that we assign to each packet sent over the network
 wrapping_id!(PacketId);
 
-const MAX_PACKET_SIZE: usize = 1200;
 /// Number of bytes to write the header
 const HEADER_BYTES: usize = 11;
 
-/// The maximum number of bytes for a message before it is fragmented
-/// MAX_PACKET_SIZE - HEADER_BYTES - 1 (channel_net_id) - 6 (message_id/fragment_id/num_fragments) - 2 (num bytes in fragment)
+/// The maximum number of bytes for a message before it is fragmented, for packets of size `mtu`
+/// mtu - HEADER_BYTES - 1 (channel_net_id) - 6 (message_id/fragment_id/num_fragments) - 2 (num bytes in fragment)
 // NOTE: this considers that we use 2 bytes for the fragment id and num_fragments, but in reality we are using
 //  varints so it could be more or less!
-pub(crate) const FRAGMENT_SIZE: usize = MAX_PACKET_SIZE - HEADER_BYTES - 9;
+pub(crate) const fn fragment_size(mtu: usize) -> usize {
+    mtu - HEADER_BYTES - 9
+}
+
+/// The maximum number of bytes for a message before it is fragmented, with the default [`MAX_PACKET_SIZE`]
+pub(crate) const FRAGMENT_SIZE: usize = fragment_size(MAX_PACKET_SIZE);
 
 /// Data structure that will help us write the packet
 #[derive(Debug)]
@@ -29,12 +33,14 @@ pub(crate) struct Packet {
     pub(crate) packet_id: PacketId,
     // How many bytes we know we are going to have to write in the packet, but haven't written yet
     pub(crate) prewritten_size: usize,
+    /// Maximum size of the packet
+    pub(crate) max_size: usize,
 }
 
 impl Packet {
     /// Check that we can still fit some data in the buffer
     pub(crate) fn can_fit(&self, size: usize) -> bool {
-        self.payload.len() + size + self.prewritten_size <= MAX_PACKET_SIZE
+        self.payload.len() + size + self.prewritten_size <= self.max_size
     }
 
     /// Check if we can write a channel_id + the number of messages in the packet.
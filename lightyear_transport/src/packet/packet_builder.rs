@@ -2,7 +2,7 @@
 use crate::channel::registry::ChannelId;
 use crate::packet::header::PacketHeaderManager;
 use crate::packet::message::{FragmentData, MessageAck, SingleData};
-use crate::packet::packet::{FRAGMENT_SIZE, Packet};
+use crate::packet::packet::Packet;
 use crate::packet::packet_type::PacketType;
 use alloc::collections::VecDeque;
 #[cfg(not(feature = "std"))]
@@ -16,6 +16,9 @@ use tracing::trace;
 #[cfg(feature = "trace")]
 use tracing::{Level, instrument};
 
+/// Default maximum size of the packets built by the [`PacketBuilder`].
+///
+/// It can be changed for each link with [`LinkMtu`](crate::packet::mtu::LinkMtu).
 pub const MAX_PACKET_SIZE: usize = 1200;
 
 pub type Payload = Vec<u8>;
@@ -66,6 +69,41 @@ impl PacketBuilder {
         }
     }
 
+    /// Max size of a single packet
+    pub(crate) fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    pub(crate) fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+
+    /// Build a [`PacketType::MtuProbe`] packet of exactly `size` bytes.
+    ///
+    /// The probe doesn't contain any messages; it is only used to check via the packet acks
+    /// if packets of that size can reach the remote.
+    pub(crate) fn build_mtu_probe(
+        &mut self,
+        real: Duration,
+        current_tick: Tick,
+        size: usize,
+    ) -> Result<Packet, SerializationError> {
+        let mut cursor = Vec::with_capacity(size);
+        let mut header = self
+            .header_manager
+            .prepare_send_packet_header(PacketType::MtuProbe, real);
+        header.tick = current_tick;
+        header.to_bytes(&mut cursor)?;
+        cursor.resize(size, 0);
+        Ok(Packet {
+            payload: cursor,
+            message_acks: vec![],
+            packet_id: header.packet_id,
+            prewritten_size: 0,
+            max_size: size,
+        })
+    }
+
     // TODO: get the vec from a pool of preallocated buffers
     fn get_new_buffer(&self) -> Payload {
         Vec::with_capacity(self.mtu)
@@ -92,6 +130,7 @@ impl PacketBuilder {
             message_acks: vec![],
             packet_id: header.packet_id,
             prewritten_size: 0,
+            max_size: self.mtu,
         });
         Ok(())
     }
@@ -125,6 +164,7 @@ impl PacketBuilder {
             )],
             packet_id: header.packet_id,
             prewritten_size: 0,
+            max_size: self.mtu,
         });
         Ok(())
 
@@ -186,7 +226,6 @@ impl PacketBuilder {
         // try to fill the packet with fragment messages first
         for (channel_id, mut fragment_messages) in fragment_data.into_iter() {
             while let Some(fragment_data) = fragment_messages.pop_front() {
-                debug_assert!(fragment_data.bytes.len() <= FRAGMENT_SIZE);
                 self.build_new_fragment_packet(real, channel_id, &fragment_data, current_tick)?;
                 if !fragment_data.is_last_fragment() {
                     // big fragment, write packet immediately
@@ -439,6 +478,7 @@ mod tests {
     use crate::channel::senders::fragment_sender::FragmentSender;
     use crate::packet::error::PacketError;
     use crate::packet::message::{FragmentIndex, MessageId};
+    use crate::packet::packet::FRAGMENT_SIZE;
     use bevy::prelude::{App, TypePath, default};
     use bytes::Bytes;
 
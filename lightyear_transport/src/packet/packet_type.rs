@@ -15,6 +15,10 @@ pub enum PacketType {
     /// - channel_id = 0 = indication of end of packet
     Data = 0,
     DataFragment = 1,
+    /// A packet used to discover the maximum packet size that can reach the remote.
+    ///
+    /// Contains only the header followed by padding, which is ignored by the receiver.
+    MtuProbe = 2,
 }
 
 impl From<PacketType> for u8 {
@@ -30,6 +34,7 @@ impl TryFrom<u8> for PacketType {
         match value {
             0 => Ok(PacketType::Data),
             1 => Ok(PacketType::DataFragment),
+            2 => Ok(PacketType::MtuProbe),
             _ => Err(lightyear_serde::SerializationError::InvalidPacketType),
         }
     }
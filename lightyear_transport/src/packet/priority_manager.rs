@@ -2,12 +2,17 @@ use alloc::collections::VecDeque;
 #[cfg(not(feature = "std"))]
 use alloc::{vec, vec::Vec};
 use core::num::NonZeroU32;
+use core::time::Duration;
 use lightyear_utils::collections::HashMap;
 
 use crate::channel::ChannelKind;
 use crate::channel::builder::SenderMetadata;
 use crate::channel::registry::{ChannelId, ChannelRegistry};
-use crate::packet::message::{FragmentData, MessageData, SendMessage, SingleData};
+use crate::channel::stats::ChannelSendStats;
+use crate::packet::congestion::{CongestionConfig, CongestionController};
+use crate::packet::message::{
+    FragmentData, FragmentIndex, MessageData, MessageId, SendMessage, SingleData,
+};
 use governor::{DefaultDirectRateLimiter, Quota};
 use lightyear_core::network::NetId;
 use nonzero_ext::*;
@@ -17,17 +22,70 @@ use tracing::{debug, error, trace};
 
 const BYPASS_QUOTA_PRIORITY: f32 = 100000.0;
 
+/// A message waiting in the [`PriorityManager`] to be sent
 #[derive(Debug)]
 pub struct BufferedMessage {
+    /// Accumulated priority: starts at `base_priority` and increases by `base_priority` every
+    /// frame where the message could not be sent
     priority: f32,
+    /// Priority of the message multiplied by the priority of its channel
+    base_priority: f32,
     channel_net_id: NetId,
     data: MessageData,
+    /// Real time after which the message is dropped instead of being sent
+    expires_at: Option<Duration>,
+}
+
+impl BufferedMessage {
+    /// Current accumulated priority of the message
+    pub fn priority(&self) -> f32 {
+        self.priority
+    }
+
+    /// Priority that is added to the accumulated priority every frame
+    pub fn base_priority(&self) -> f32 {
+        self.base_priority
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        self.channel_net_id
+    }
+
+    pub fn message_id(&self) -> Option<MessageId> {
+        self.data.message_id()
+    }
+
+    /// Real time after which the message is dropped instead of being sent
+    pub fn expires_at(&self) -> Option<Duration> {
+        self.expires_at
+    }
+
+    /// Identifies the message if it can be sent again by its channel (for example when a
+    /// reliable message is re-sent), so that it is not buffered twice
+    fn key(&self) -> Option<(NetId, MessageId, Option<FragmentIndex>)> {
+        match &self.data {
+            MessageData::Single(single) => single.id.map(|id| (self.channel_net_id, id, None)),
+            MessageData::Fragment(fragment) => Some((
+                self.channel_net_id,
+                fragment.message_id,
+                Some(fragment.fragment_id),
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PriorityConfig {
     /// Number of bytes per second that can be sent to each client
+    ///
+    /// Not used if `congestion_control` is set.
     pub bandwidth_quota: Quota,
+    /// If set, the number of bytes that can be sent is not fixed but adapts to the
+    /// conditions of each link (packet loss and RTT)
+    pub congestion_control: Option<CongestionConfig>,
+    /// Maximum number of messages that can stay buffered because they did not fit in the
+    /// bandwidth budget. When there are more, the messages with the lowest priority are dropped.
+    pub max_buffered_messages: usize,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
     pub enabled: bool,
 }
@@ -38,6 +96,8 @@ impl Default for PriorityConfig {
         Self {
             // 56 KB/s bandwidth cap
             bandwidth_quota: Quota::per_second(nonzero!(56000u32)),
+            congestion_control: None,
+            max_buffered_messages: 1024,
             enabled: false,
         }
     }
@@ -48,7 +108,18 @@ impl PriorityConfig {
         let cap = bytes_per_second_quota.try_into().unwrap();
         Self {
             bandwidth_quota: Quota::per_second(cap).allow_burst(cap),
+            congestion_control: None,
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// Adapt the number of bytes sent to each link with a [`CongestionController`]
+    pub fn adaptive(config: CongestionConfig) -> Self {
+        Self {
+            congestion_control: Some(config),
             enabled: true,
+            ..Default::default()
         }
     }
 }
@@ -58,11 +129,17 @@ pub struct PriorityManager {
     pub(crate) config: PriorityConfig,
     // TODO: can I do without this limiter?
     pub(crate) limiter: DefaultDirectRateLimiter,
+    /// Replaces the `limiter` if congestion control is enabled
+    pub(crate) congestion: Option<CongestionController>,
     // Internal buffer of data that we want to send
     // TODO: improve this
     data_to_send: Vec<(NetId, (VecDeque<SendMessage>, VecDeque<SendMessage>))>,
-    // Messages that could not be sent because of the bandwidth quota
-    // buffered_data: Vec<BufferedMessage>,
+    /// Messages that could not be sent because of the bandwidth quota.
+    ///
+    /// Their priority keeps accumulating until they are sent, so that low-priority messages
+    /// are not starved by higher-priority ones.
+    buffered_data: Vec<BufferedMessage>,
+    current_time: Duration,
 }
 
 impl Default for PriorityManager {
@@ -75,12 +152,74 @@ impl PriorityManager {
     pub fn new(config: PriorityConfig) -> Self {
         let quota = config.bandwidth_quota;
         Self {
+            congestion: config.congestion_control.map(CongestionController::new),
             config,
             data_to_send: Vec::new(),
             limiter: DefaultDirectRateLimiter::direct(quota),
-            // data_to_send: BTreeMap::new(),
-            // buffered_data: Vec::new(),
+            buffered_data: Vec::new(),
+            current_time: Duration::default(),
+        }
+    }
+
+    /// Current bandwidth estimate of the [`CongestionController`], in bytes per second
+    pub fn bandwidth_estimate(&self) -> Option<f32> {
+        self.congestion
+            .as_ref()
+            .map(CongestionController::bandwidth)
+    }
+
+    /// Messages that could not be sent yet because of the bandwidth quota, with their accumulated priority
+    pub fn buffered_messages(&self) -> &[BufferedMessage] {
+        &self.buffered_data
+    }
+
+    /// Update the current time, and refill the send budget of the [`CongestionController`]
+    pub(crate) fn update(&mut self, now: Duration, rtt: Duration) {
+        self.current_time = now;
+        if let Some(congestion) = self.congestion.as_mut() {
+            congestion.update(now, rtt);
+        }
+    }
+
+    /// Account for bytes that were sent without going through the priority filter
+    /// (for example the packet headers)
+    pub(crate) fn consume(&mut self, bytes: u32) {
+        if !self.config.enabled {
+            return;
+        }
+        if let Some(congestion) = self.congestion.as_mut() {
+            congestion.consume(bytes);
+        } else if let Ok(bytes) = bytes.try_into() {
+            let _ = self.limiter.check_n(bytes);
+        }
+    }
+
+    /// Returns true if `bytes` fit in the current send budget, and removes them from the budget.
+    ///
+    /// This is used for packets that are not built from messages, such as MTU probes.
+    pub(crate) fn try_consume(&mut self, bytes: u32) -> bool {
+        if !self.config.enabled || bytes == 0 {
+            return true;
+        }
+        self.check_budget(bytes, 0.0).unwrap_or(false)
+    }
+
+    /// Returns true if the message can be sent, and removes its bytes from the budget.
+    ///
+    /// Messages with a priority above [`BYPASS_QUOTA_PRIORITY`] are always sent.
+    /// Returns `None` if the message can never fit in the budget.
+    fn check_budget(&mut self, bytes: u32, priority: f32) -> Option<bool> {
+        let bypass = priority >= BYPASS_QUOTA_PRIORITY;
+        if let Some(congestion) = self.congestion.as_mut() {
+            if bypass {
+                congestion.consume(bytes);
+                return Some(true);
+            }
+            return Some(congestion.try_consume(bytes));
         }
+        let nonzero_bytes = NonZeroU32::try_from(bytes).unwrap();
+        let result = self.limiter.check_n(nonzero_bytes).ok()?;
+        Some(bypass || result.is_ok())
     }
 
     pub(crate) fn buffer_messages(
@@ -140,34 +279,72 @@ impl PriorityManager {
             return (single_data, fragment_data, 0);
         }
 
+        // the messages that could not be sent in the previous frames gain priority,
+        // unless they are not relevant anymore
+        let now = self.current_time;
+        let mut all_messages = core::mem::take(&mut self.buffered_data);
+        all_messages.retain(|buffered_message| {
+            let relevant = buffered_message
+                .expires_at
+                .is_none_or(|expires_at| now < expires_at);
+            if !relevant {
+                if let Some(stats) = Self::sender_stats(channel_registry, senders, buffered_message)
+                {
+                    stats.expired += 1;
+                }
+            }
+            relevant
+        });
+        all_messages.iter_mut().for_each(|buffered_message| {
+            buffered_message.priority += buffered_message.base_priority;
+        });
+        let mut buffered_keys: HashMap<_, usize> = all_messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, buffered_message)| buffered_message.key().map(|key| (key, i)))
+            .collect();
+
         // compute the priority of each new message
-        let mut all_messages = self
-            .data_to_send
-            .drain(..)
-            .flat_map(|(net_id, (single, fragment))| {
-                let channel_priority = channel_registry
-                    .settings_from_net_id(net_id)
-                    .unwrap()
-                    .priority;
-                trace!(?channel_priority, num_single=?single.len(), "channel priority");
-                single
-                    .into_iter()
-                    .map(move |single| BufferedMessage {
-                        priority: single.priority * channel_priority,
-                        channel_net_id: net_id,
-                        data: single.data,
-                    })
-                    .chain(fragment.into_iter().map(move |fragment| {
-                        // TODO (IMPORTANT): we should split fragments AFTER priority filtering
-                        //  because if we don't send one fragment, it's over..
-                        BufferedMessage {
-                            priority: fragment.priority * channel_priority,
-                            channel_net_id: net_id,
-                            data: fragment.data,
-                        }
-                    }))
-            })
-            .collect::<Vec<_>>();
+        for (net_id, (single, fragment)) in self.data_to_send.drain(..) {
+            let channel_priority = channel_registry
+                .settings_from_net_id(net_id)
+                .unwrap()
+                .priority;
+            trace!(?channel_priority, num_single=?single.len(), "channel priority");
+            // TODO (IMPORTANT): we should split fragments AFTER priority filtering
+            //  because if we don't send one fragment, it's over..
+            for message in single.into_iter().chain(fragment) {
+                if message.is_expired(now) {
+                    continue;
+                }
+                let base_priority = message.priority * channel_priority;
+                let buffered_message = BufferedMessage {
+                    priority: base_priority,
+                    base_priority,
+                    channel_net_id: net_id,
+                    data: message.data,
+                    expires_at: message.expires_at,
+                };
+                // a message that is still buffered was sent again by its channel (for example
+                // a reliable message that was not acked in time): keep the accumulated priority
+                if let Some(&i) = buffered_message
+                    .key()
+                    .as_ref()
+                    .and_then(|key| buffered_keys.get(key))
+                {
+                    let existing = &mut all_messages[i];
+                    existing.priority = existing.priority.max(buffered_message.priority);
+                    existing.base_priority = buffered_message.base_priority;
+                    existing.data = buffered_message.data;
+                    existing.expires_at = buffered_message.expires_at;
+                    continue;
+                }
+                if let Some(key) = buffered_message.key() {
+                    buffered_keys.insert(key, all_messages.len());
+                }
+                all_messages.push(buffered_message);
+            }
+        }
 
         // sort from highest priority to lower
         all_messages.sort_by(|a, b| a.priority.partial_cmp(&b.priority).unwrap());
@@ -184,18 +361,14 @@ impl PriorityManager {
             // we don't use the exact size of the message, but the size of the bytes
             // we will adjust for this later
             let message_bytes = buffered_message.data.bytes_len() as u32;
-            let nonzero_message_bytes = NonZeroU32::try_from(message_bytes).unwrap();
-            let Ok(result) = self.limiter.check_n(nonzero_message_bytes) else {
+            let Some(allowed) = self.check_budget(message_bytes, buffered_message.priority) else {
                 error!("the bandwidth does not have enough capacity for a message of this size!");
                 break;
             };
-
-            // above BYPASS_QUOTA_PRIORITY, we still send the message
-            if buffered_message.priority < BYPASS_QUOTA_PRIORITY {
-                let Ok(()) = result else {
-                    debug!("Bandwidth quota reached, no more messages can be sent this tick");
-                    break;
-                };
+            if !allowed {
+                debug!("Bandwidth quota reached, no more messages can be sent this tick");
+                all_messages.push(buffered_message);
+                break;
             }
             trace!(channel=?buffered_message.channel_net_id, "Sending message with priority {:?}", buffered_message.priority);
 
@@ -231,20 +404,32 @@ impl PriorityManager {
             }
         }
 
-        // all the other messages that don't make the cut, we just drop
+        // all the other messages that don't make the cut are kept for the next frames,
+        // where their priority will keep increasing until they get sent.
+        // If there are too many, we drop the ones with the lowest priority:
         // - unreliable messages: they are unreliable so it's ok
-        // - reliable messages: they will be retried later, maybe with higher priority?
-        // - unreliable entity updates: the replication sender keeps track for each entity of when we were able to send an update
-        //   - PROBLEM: we could have the entity action not get sent (bandwidth), and then the priority still drops because the entity update
-        //     was sent right after...
-        // - reliable entity actions:
+        // - reliable messages: they will be retried later by their channel
         let num_messages_sent = single_data.values().map(|data| data.len()).sum::<usize>()
             + fragment_data.values().map(|data| data.len()).sum::<usize>();
+        let num_messages_discarded = all_messages
+            .len()
+            .saturating_sub(self.config.max_buffered_messages);
+        all_messages
+            .drain(..num_messages_discarded)
+            .for_each(|buffered_message| {
+                if let Some(stats) =
+                    Self::sender_stats(channel_registry, senders, &buffered_message)
+                {
+                    stats.dropped_by_bandwidth += 1;
+                }
+            });
         debug!(
             bytes_sent = ?bytes_used,
             ?num_messages_sent,
-            num_messages_discarded = ?all_messages.len(),
+            num_messages_buffered = ?all_messages.len(),
+            ?num_messages_discarded,
             "priority filter done.");
+        self.buffered_data = all_messages;
 
         self.data_to_send.clear();
         (
@@ -253,4 +438,124 @@ impl PriorityManager {
             bytes_used,
         )
     }
+
+    /// Statistics of the channel of a buffered message, to count the messages that are dropped
+    fn sender_stats<'a>(
+        channel_registry: &ChannelRegistry,
+        senders: &'a mut HashMap<ChannelKind, SenderMetadata>,
+        buffered_message: &BufferedMessage,
+    ) -> Option<&'a mut ChannelSendStats> {
+        channel_registry
+            .get_kind_from_net_id(buffered_message.channel_net_id)
+            .and_then(|channel_kind| senders.get_mut(channel_kind))
+            .map(|sender_metadata| &mut sender_metadata.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::builder::{ChannelMode, ChannelSettings, Transport};
+    use bevy::prelude::default;
+    use bytes::Bytes;
+
+    struct Channel1;
+
+    fn message(priority: f32, expires_at: Option<Duration>) -> SendMessage {
+        SendMessage {
+            data: SingleData::new(None, Bytes::from(vec![0; 100])).into(),
+            priority,
+            expires_at,
+        }
+    }
+
+    /// Priority manager with a budget of one message per frame of 100ms
+    fn setup() -> (ChannelRegistry, ChannelId, PriorityManager) {
+        let mut registry = ChannelRegistry::default();
+        let (_, channel_id) = registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+        let manager = PriorityManager::new(PriorityConfig::adaptive(CongestionConfig {
+            initial_bandwidth: 1000,
+            min_bandwidth: 1000,
+            max_bandwidth: 1000,
+            delay_threshold: None,
+            max_burst: Duration::from_millis(100),
+            ..default()
+        }));
+        (registry, channel_id, manager)
+    }
+
+    /// Run the priority filter at time `now`, and return the number of messages sent
+    fn frame(
+        manager: &mut PriorityManager,
+        registry: &ChannelRegistry,
+        channel_id: ChannelId,
+        now: Duration,
+        messages: Vec<SendMessage>,
+    ) -> usize {
+        manager.update(now, Duration::ZERO);
+        manager.buffer_messages(channel_id, messages.into(), VecDeque::new());
+        let (single_data, _, _) = manager.priority_filter(registry, &mut HashMap::default());
+        single_data.into_iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Low priority messages that don't fit in the budget gain priority until they are sent
+    #[test]
+    fn test_priority_accumulation() {
+        let (registry, channel_id, mut manager) = setup();
+        let mut now = Duration::ZERO;
+        let mut frame = |manager: &mut PriorityManager, messages: Vec<SendMessage>| {
+            now += Duration::from_millis(100);
+            frame(manager, &registry, channel_id, now, messages)
+        };
+
+        assert_eq!(
+            frame(&mut manager, vec![message(2.5, None), message(1.0, None)]),
+            1
+        );
+        assert_eq!(manager.buffered_messages().len(), 1);
+        assert_eq!(manager.buffered_messages()[0].priority(), 1.0);
+
+        // the low priority message is not sent but its priority increases
+        assert_eq!(frame(&mut manager, vec![message(2.5, None)]), 1);
+        assert_eq!(manager.buffered_messages().len(), 1);
+        assert_eq!(manager.buffered_messages()[0].priority(), 2.0);
+
+        // the low priority message now has a higher priority than the new message
+        assert_eq!(frame(&mut manager, vec![message(2.5, None)]), 1);
+        assert_eq!(manager.buffered_messages().len(), 1);
+        assert_eq!(manager.buffered_messages()[0].priority(), 2.5);
+        assert_eq!(manager.buffered_messages()[0].base_priority(), 2.5);
+    }
+
+    /// Buffered messages are dropped once they expire, and counted separately from the bandwidth drops
+    #[test]
+    fn test_expired_messages_are_dropped() {
+        let (registry, channel_id, mut manager) = setup();
+        let mut transport = Transport::default();
+        transport.add_sender_from_registry::<Channel1>(&registry);
+        let mut frame = |manager: &mut PriorityManager,
+                         now: Duration,
+                         messages: Vec<SendMessage>| {
+            manager.update(now, Duration::ZERO);
+            manager.buffer_messages(channel_id, messages.into(), VecDeque::new());
+            let (single_data, _, _) = manager.priority_filter(&registry, &mut transport.senders);
+            single_data
+                .into_iter()
+                .map(|(_, data)| data.len())
+                .sum::<usize>()
+        };
+        let expires_at = Some(Duration::from_millis(150));
+        let messages = vec![message(2.0, expires_at), message(1.0, expires_at)];
+        assert_eq!(frame(&mut manager, Duration::ZERO, messages), 1);
+        assert_eq!(manager.buffered_messages().len(), 1);
+
+        assert_eq!(frame(&mut manager, Duration::from_millis(200), vec![]), 0);
+        assert!(manager.buffered_messages().is_empty());
+        let stats = transport.senders[&ChannelKind::of::<Channel1>()].stats;
+        assert_eq!(stats.expired, 1);
+        assert_eq!(stats.dropped_by_bandwidth, 0);
+    }
 }
@@ -1,18 +1,28 @@
-use crate::channel::builder::Transport;
+use crate::channel::ChannelKind;
+use crate::channel::builder::{ChannelMode, Transport};
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::registry::{ChannelId, ChannelRegistry};
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::TransportStats;
+use crate::channel::stream::StreamEvent;
 use crate::error::TransportError;
+use crate::limits::{LimitError, ReceiveLimits};
 use crate::packet::error::PacketError;
 use crate::packet::header::PacketHeader;
-use crate::packet::message::{FragmentData, ReceiveMessage, SingleData};
+use crate::packet::message::{FragmentData, MessageHandle, MessageId, ReceiveMessage, SingleData};
+use crate::packet::mtu::LinkMtu;
+use crate::packet::packet_type::PacketType;
 use bevy::app::App;
+use bevy::platform::time::Instant;
 use bevy::prelude::*;
 use bytes::Bytes;
+use core::time::Duration;
 #[cfg(any(feature = "client", feature = "server"))]
 use lightyear_connection::prelude::Disconnected;
 use lightyear_core::prelude::{LocalTimeline, NetworkTimeline};
 use lightyear_core::tick::Tick;
+#[cfg(feature = "std")]
+use lightyear_link::capture::LinkCapture;
 use lightyear_link::{Link, LinkPlugin, LinkSet, Linked};
 use lightyear_serde::reader::{ReadInteger, Reader};
 use lightyear_serde::{SerializationError, ToBytes};
@@ -34,6 +44,39 @@ pub struct PacketReceived {
     pub remote_tick: Tick,
 }
 
+/// Triggered on the [`Transport`] entity when a reliable message is abandoned because it was not acked
+/// after the maximum number of retries or the maximum duration of its [`ReliableSettings`](crate::prelude::ReliableSettings),
+/// or before its time-to-live expired
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct MessageAbandoned {
+    pub channel: ChannelKind,
+    pub message_id: MessageId,
+    /// Set if the delivery of the message was tracked
+    pub handle: Option<MessageHandle>,
+}
+
+/// Triggered on the [`Transport`] entity when a tracked message was received by the remote
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct MessageAcked {
+    pub channel: ChannelKind,
+    pub handle: MessageHandle,
+}
+
+/// Triggered on the [`Transport`] entity when a tracked message sent on an
+/// [`UnorderedUnreliableWithAcks`](crate::prelude::ChannelMode::UnorderedUnreliableWithAcks) channel
+/// is considered lost.
+///
+/// Messages that could not be sent at all (for example because of the bandwidth quota) are also
+/// considered lost after the [`Transport::tracking_timeout`].
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct MessageLost {
+    pub channel: ChannelKind,
+    pub handle: MessageHandle,
+}
+
+/// Default duration after which we stop waiting for the ack of a tracked unreliable message
+pub const DEFAULT_TRACKING_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub struct TransportPlugin;
 
 impl TransportPlugin {
@@ -43,37 +86,97 @@ impl TransportPlugin {
     fn buffer_receive(
         time: Res<Time<Real>>,
         par_commands: ParallelCommands,
-        mut query: Query<(Entity, &mut Link, &mut Transport), With<Linked>>,
+        mut query: Query<
+            (
+                Entity,
+                &mut Link,
+                &mut Transport,
+                &LocalTimeline,
+                Option<&mut LinkMtu>,
+                Option<&ReceiveLimits>,
+            ),
+            With<Linked>,
+        >,
     ) {
-        query
-            .par_iter_mut()
-            .for_each(|(entity, mut link, mut transport)| {
+        query.par_iter_mut().for_each(
+            |(entity, mut link, mut transport, timeline, mut link_mtu, limits)| {
+                let limits = limits.copied().unwrap_or_default();
                 // enable split borrows
                 let transport = &mut *transport;
                 // update with the latest time
-                transport.senders.values_mut().for_each(|sender_metadata| {
-                    sender_metadata.sender.update(&time, &link.stats);
-                    sender_metadata.message_acks.clear();
-                    sender_metadata.message_nacks.clear();
-                    sender_metadata.messages_sent.clear();
-                });
+                transport
+                    .senders
+                    .iter_mut()
+                    .for_each(|(channel_kind, sender_metadata)| {
+                        sender_metadata.sender.update(&time, &link.stats);
+                        sender_metadata.message_acks.clear();
+                        sender_metadata.message_nacks.clear();
+                        sender_metadata.messages_sent.clear();
+                        let abandoned = sender_metadata.sender.take_abandoned();
+                        let stale = sender_metadata.untrack_stale(
+                            time.elapsed().saturating_sub(transport.tracking_timeout),
+                        );
+                        if abandoned.is_empty() && stale.is_empty() {
+                            return;
+                        }
+                        par_commands.command_scope(|mut commands| {
+                            abandoned.into_iter().for_each(|message_id| {
+                                let handle = sender_metadata
+                                    .tracked_messages
+                                    .remove(&message_id)
+                                    .map(|(handle, _)| handle);
+                                commands.trigger_targets(
+                                    MessageAbandoned {
+                                        channel: *channel_kind,
+                                        message_id,
+                                        handle,
+                                    },
+                                    entity,
+                                );
+                            });
+                            stale.into_iter().for_each(|handle| {
+                                commands.trigger_targets(
+                                    MessageLost {
+                                        channel: *channel_kind,
+                                        handle,
+                                    },
+                                    entity,
+                                );
+                            });
+                        });
+                    });
                 transport
                     .receivers
                     .values_mut()
                     .for_each(|receiver_metadata| {
                         receiver_metadata.receiver.update(time.elapsed());
+                        receiver_metadata.receiver.update_tick(timeline.tick());
+                        receiver_metadata.receiver.update_limits(&limits);
+                        receiver_metadata
+                            .compressor
+                            .set_max_decompressed_size(limits.max_message_size);
                     });
                 // check which packets were lost
                 transport
                     .packet_manager
                     .header_manager
-                    .update(time.delta(), &link.stats);
+                    .update(time.elapsed(), &link.stats);
                 transport
                     .packet_manager
                     .header_manager
                     .lost_packets
                     .drain(..)
                     .try_for_each(|lost_packet| {
+                        let is_probe = link_mtu
+                            .as_mut()
+                            .is_some_and(|link_mtu| link_mtu.on_packet_lost(lost_packet));
+                        // a lost MTU probe means that the probe was too big, not that the link is congested
+                        if !is_probe {
+                            if let Some(congestion) = transport.priority_manager.congestion.as_mut()
+                            {
+                                congestion.on_packet_lost(time.elapsed());
+                            }
+                        }
                         if let Some(message_map) =
                             transport.packet_to_message_ack_map.remove(&lost_packet)
                         {
@@ -90,20 +193,57 @@ impl TransportPlugin {
                                     message_ack.message_id
                                 );
                                 sender_metadata.message_nacks.push(message_ack.message_id);
+                                // reliable messages will be resent
+                                if !sender_metadata.mode.is_reliable() {
+                                    if let Some((handle, _)) = sender_metadata
+                                        .tracked_messages
+                                        .remove(&message_ack.message_id)
+                                    {
+                                        par_commands.command_scope(|mut commands| {
+                                            commands.trigger_targets(
+                                                MessageLost {
+                                                    channel: channel_kind,
+                                                    handle,
+                                                },
+                                                entity,
+                                            );
+                                        });
+                                    }
+                                }
                             }
                         }
                         Ok::<(), TransportError>(())
                     })
                     .ok();
 
+                // bytes received from the remote that have not been read by the user yet
+                let mut buffered_bytes: usize = transport
+                    .receivers
+                    .values()
+                    .map(|receiver_metadata| receiver_metadata.receiver.num_buffered_bytes())
+                    .sum::<usize>()
+                    + transport.streams.num_buffered_bytes();
+                let now = Instant::now();
+                transport.packet_receive_times.clear();
                 link.recv
-                    .drain()
-                    .try_for_each(|packet| {
+                    .drain_with_elapsed()
+                    .try_for_each(|(packet, elapsed)| {
+                        buffered_bytes += packet.len();
+                        if buffered_bytes > limits.max_buffered_bytes {
+                            return Err(LimitError::ReceiveBufferFull {
+                                bytes: buffered_bytes,
+                                max: limits.max_buffered_bytes,
+                            }
+                            .into());
+                        }
                         let mut cursor = Reader::from(packet);
 
                         // Parse the packet
                         let header = PacketHeader::from_bytes(&mut cursor)?;
                         let tick = header.tick;
+                        transport
+                            .packet_receive_times
+                            .push((tick, now.checked_sub(elapsed).unwrap_or(now)));
 
                         // TODO: maybe switch to event buffer instead of triggers?
                         par_commands.command_scope(|mut commands| {
@@ -116,24 +256,29 @@ impl TransportPlugin {
                             .header_manager
                             .process_recv_packet_header(&header);
 
+                        // MTU probes don't contain any messages
+                        if header.get_packet_type() == PacketType::MtuProbe {
+                            return Ok(());
+                        }
+
                         // Parse the payload into messages, put them in the internal buffers for each channel
                         // we read directly from the packet and don't create intermediary datastructures to avoid allocations
                         // TODO: maybe do this in a helper function?
-                        if header.get_packet_type()
-                            == crate::packet::packet_type::PacketType::DataFragment
-                        {
+                        if header.get_packet_type() == PacketType::DataFragment {
                             // read the fragment data
                             let channel_id = ChannelId::from_bytes(&mut cursor)?;
                             let fragment_data = FragmentData::from_bytes(&mut cursor)?;
-                            transport
+                            let receiver_metadata = transport
                                 .receivers
                                 .get_mut(&channel_id)
-                                .ok_or(PacketError::ChannelNotFound)?
-                                .receiver
-                                .buffer_recv(ReceiveMessage {
-                                    data: fragment_data.into(),
-                                    remote_sent_tick: tick,
-                                })?;
+                                .ok_or(PacketError::ChannelNotFound)?;
+                            receiver_metadata
+                                .stats
+                                .add_fragment_message_received(fragment_data.bytes.len());
+                            receiver_metadata.receiver.buffer_recv(ReceiveMessage {
+                                data: fragment_data.into(),
+                                remote_sent_tick: tick,
+                            })?;
                         }
                         // read single message data
                         while cursor.has_remaining() {
@@ -143,20 +288,28 @@ impl TransportPlugin {
                             trace!(?channel_id, ?num_messages);
                             for _ in 0..num_messages {
                                 let single_data = SingleData::from_bytes(&mut cursor)?;
-                                transport
+                                let receiver_metadata = transport
                                     .receivers
                                     .get_mut(&channel_id)
-                                    .ok_or(PacketError::ChannelNotFound)?
-                                    .receiver
-                                    .buffer_recv(ReceiveMessage {
-                                        data: single_data.into(),
-                                        remote_sent_tick: tick,
-                                    })?;
+                                    .ok_or(PacketError::ChannelNotFound)?;
+                                receiver_metadata
+                                    .stats
+                                    .add_single_message_received(single_data.bytes.len());
+                                receiver_metadata.receiver.buffer_recv(ReceiveMessage {
+                                    data: single_data.into(),
+                                    remote_sent_tick: tick,
+                                })?;
                             }
                         }
                         Ok::<(), TransportError>(())
                     })
                     .inspect_err(|e| {
+                        if let Some(limit_error) = e.limit_exceeded() {
+                            par_commands.command_scope(|mut commands| {
+                                limits.on_exceeded(&mut commands, entity, limit_error);
+                            });
+                            return;
+                        }
                         error!("Error processing packet: {e:?}");
                     })
                     .ok();
@@ -169,6 +322,12 @@ impl TransportPlugin {
                     .drain(..)
                     .try_for_each(|acked_packet| {
                         trace!("Acked packet {:?}", acked_packet);
+                        if let Some(link_mtu) = link_mtu.as_mut() {
+                            link_mtu.on_packet_acked(acked_packet);
+                        }
+                        if let Some(congestion) = transport.priority_manager.congestion.as_mut() {
+                            congestion.on_packet_acked();
+                        }
                         if let Some(message_acks) =
                             transport.packet_to_message_ack_map.remove(&acked_packet)
                         {
@@ -182,13 +341,77 @@ impl TransportPlugin {
                                     sender_metadata.name, message_ack
                                 );
                                 sender_metadata.message_acks.push(message_ack.message_id);
-                                sender_metadata.sender.receive_ack(&message_ack);
+                                if sender_metadata.sender.receive_ack(&message_ack) {
+                                    if matches!(sender_metadata.mode, ChannelMode::Stream(_)) {
+                                        transport
+                                            .streams
+                                            .on_ack(channel_kind, message_ack.message_id);
+                                    }
+                                    if let Some((handle, _)) = sender_metadata
+                                        .tracked_messages
+                                        .remove(&message_ack.message_id)
+                                    {
+                                        par_commands.command_scope(|mut commands| {
+                                            commands.trigger_targets(
+                                                MessageAcked {
+                                                    channel: channel_kind,
+                                                    handle,
+                                                },
+                                                entity,
+                                            );
+                                        });
+                                    }
+                                }
                             }
                         }
                         Ok::<(), TransportError>(())
                     })
                     .ok();
-            })
+
+                // the frames of the stream channels are handled by the transport directly
+                transport.streams.evict_readers();
+                transport
+                    .receivers
+                    .values_mut()
+                    .for_each(|receiver_metadata| {
+                        let ChannelMode::Stream(settings) = receiver_metadata.mode else {
+                            return;
+                        };
+                        let channel_kind = receiver_metadata.channel_kind;
+                        while let Some((_, bytes, _)) = receiver_metadata.read_message() {
+                            transport
+                                .streams
+                                .receive(channel_kind, &settings, &limits, bytes)
+                                .inspect_err(|e| {
+                                    if let Some(limit_error) = e.limit_exceeded() {
+                                        par_commands.command_scope(|mut commands| {
+                                            limits.on_exceeded(&mut commands, entity, limit_error);
+                                        });
+                                        return;
+                                    }
+                                    error!(?channel_kind, "Error receiving stream frame: {e:?}")
+                                })
+                                .ok();
+                        }
+                    });
+                let stream_events = transport.streams.take_events();
+                if !stream_events.is_empty() {
+                    par_commands.command_scope(|mut commands| {
+                        stream_events.into_iter().for_each(|event| match event {
+                            StreamEvent::Progress(event) => {
+                                commands.trigger_targets(event, entity);
+                            }
+                            StreamEvent::Completed(event) => {
+                                commands.trigger_targets(event, entity);
+                            }
+                            StreamEvent::Cancelled(event) => {
+                                commands.trigger_targets(event, entity);
+                            }
+                        });
+                    });
+                }
+            },
+        )
     }
 
     // TODO: users will mostly interact only via the lightyear_message
@@ -203,22 +426,43 @@ impl TransportPlugin {
     /// Upload the packets to the [`Link`]
     fn buffer_send(
         real_time: Res<Time<Real>>,
-        mut query: Query<(&mut Link, &mut Transport, &LocalTimeline), With<Linked>>,
+        mut query: Query<
+            (
+                &mut Link,
+                &mut Transport,
+                &LocalTimeline,
+                Option<&mut LinkMtu>,
+            ),
+            With<Linked>,
+        >,
         channel_registry: Res<ChannelRegistry>,
     ) {
-        query.par_iter_mut().for_each(|(mut link, mut transport, timeline)| {
+        query.par_iter_mut().for_each(|(mut link, mut transport, timeline, mut link_mtu)| {
             let tick = timeline.tick();
             // allow split borrows
             let transport = &mut *transport;
 
+            // apply the MTU of the link (it can be updated by the path MTU discovery)
+            if let Some(link_mtu) = &link_mtu {
+                if transport.packet_manager.mtu() != link_mtu.mtu() {
+                    transport.set_mtu(link_mtu.mtu());
+                }
+            }
+
             // buffer all new messages in the Sender
-            transport.recv_channel.try_iter().try_for_each(|(channel_kind, bytes, priority)| {
+            transport.senders.values_mut().for_each(|sender_metadata| sender_metadata.current_tick = tick);
+            transport.recv_channel.try_iter().try_for_each(|(channel_kind, bytes, priority, ttl, handle)| {
                 let sender_metadata = transport.senders.get_mut(&channel_kind).ok_or(TransportError::ChannelNotFound(channel_kind))?;
-                // TODO: do we need the message_id?
-                sender_metadata.sender.buffer_send(bytes, priority);
+                let message_id = sender_metadata.buffer_send(bytes, priority, ttl);
+                if let Some(handle) = handle {
+                    sender_metadata.track(message_id, handle, real_time.elapsed());
+                }
                 Ok::<(), TransportError>(())
             }).inspect_err(|e| error!("error: {e:?}")).ok();
 
+            // buffer the next chunks of the streams that are being sent
+            transport.streams.send_chunks(&mut transport.senders);
+
             // flush messages from the Sender to the priority manager
             transport.senders.values_mut().for_each(|sender_metadata| {
                 let channel_id = sender_metadata.channel_id;
@@ -227,14 +471,31 @@ impl TransportPlugin {
                 if !single_data.is_empty() || !fragment_data.is_empty() {
                     trace!(?channel_id, "send message with channel_id");
                     transport.priority_manager.buffer_messages(channel_id, single_data, fragment_data);
+                    // the parity of the forward error correction group will be sent with the next messages
+                    sender_metadata.flush_parity();
                 }
             });
 
+            // update the current time of the priority manager and refill the send budget
+            transport.priority_manager.update(real_time.elapsed(), link.stats.rtt);
+
             // get the list of messages that we can send according to the bandwidth limiter
             let (single_data, fragment_data, num_bytes_added_to_limiter) = transport
                 .priority_manager
                 .priority_filter(&channel_registry, &mut transport.senders);
 
+            // keep track of the messages that are actually sent on each channel
+            single_data.iter().for_each(|(channel_id, messages)| {
+                if let Some(sender_metadata) = channel_registry.get_kind_from_net_id(*channel_id).and_then(|kind| transport.senders.get_mut(kind)) {
+                    messages.iter().for_each(|single| sender_metadata.stats.add_single_message_sent(single.bytes.len()));
+                }
+            });
+            fragment_data.iter().for_each(|(channel_id, fragments)| {
+                if let Some(sender_metadata) = channel_registry.get_kind_from_net_id(*channel_id).and_then(|kind| transport.senders.get_mut(kind)) {
+                    fragments.iter().for_each(|fragment| sender_metadata.stats.add_fragment_message_sent(fragment.bytes.len()));
+                }
+            });
+
             // build actual packets from these messages
             // TODO: swap to try_for_each when available
             let Ok(packets) =
@@ -281,20 +542,100 @@ impl TransportPlugin {
                 link.send.push(Bytes::from(packet.payload));
             }
 
-            // adjust the real amount of bytes that we sent through the limiter (to account for the actual packet size)
-            if transport.priority_manager.config.enabled {
-                if let Ok(remaining_bytes_to_add) =
-                    (total_bytes_sent - num_bytes_added_to_limiter).try_into()
+            // send a probe to discover the path MTU, if it fits in the bandwidth budget.
+            // Otherwise the probe is retried after the probe interval
+            if let Some(link_mtu) = link_mtu.as_mut() {
+                if let Some(size) = link_mtu
+                    .poll_probe(real_time.elapsed())
+                    .filter(|size| transport.priority_manager.try_consume(*size as u32))
                 {
-                    let _ = transport
-                        .priority_manager
-                        .limiter
-                        .check_n(remaining_bytes_to_add);
+                    match transport.packet_manager.build_mtu_probe(real_time.elapsed(), tick, size) {
+                        Ok(packet) => {
+                            trace!(packet_id = ?packet.packet_id, ?size, "sending MTU probe");
+                            link_mtu.probe_sent(packet.packet_id, size);
+                            link.send.push(Bytes::from(packet.payload));
+                        }
+                        Err(e) => error!("Failed to build MTU probe: {e:?}"),
+                    }
                 }
             }
+
+            // adjust the real amount of bytes that we sent through the limiter (to account for the actual packet size)
+            transport
+                .priority_manager
+                .consume(total_bytes_sent.saturating_sub(num_bytes_added_to_limiter));
         })
     }
 
+    /// Copy the statistics of each channel of the [`Transport`] to the [`TransportStats`] component
+    fn update_stats(mut query: Query<(&Transport, &mut TransportStats)>) {
+        query
+            .par_iter_mut()
+            .for_each(|(transport, mut transport_stats)| {
+                let transport_stats = &mut *transport_stats;
+                transport
+                    .senders
+                    .iter()
+                    .for_each(|(channel_kind, sender_metadata)| {
+                        let stats = &mut transport_stats
+                            .channels
+                            .entry(*channel_kind)
+                            .or_default()
+                            .send;
+                        *stats = sender_metadata.stats;
+                        stats.resends = sender_metadata.sender.num_resends();
+                    });
+                transport.receivers.values().for_each(|receiver_metadata| {
+                    let stats = &mut transport_stats
+                        .channels
+                        .entry(receiver_metadata.channel_kind)
+                        .or_default()
+                        .receive;
+                    *stats = receiver_metadata.stats;
+                    stats.buffered_messages = receiver_metadata.receiver.num_buffered_messages();
+                });
+            });
+    }
+
+    /// Export the statistics of each channel as `metrics` gauges
+    #[cfg(feature = "metrics")]
+    fn record_metrics(
+        query: Query<(Entity, &TransportStats)>,
+        channel_registry: Res<ChannelRegistry>,
+    ) {
+        query.iter().for_each(|(entity, transport_stats)| {
+            channel_registry
+                .channels()
+                .for_each(|(channel_kind, name)| {
+                    if let Some(stats) = transport_stats.channels.get(&channel_kind) {
+                        stats.record_metrics(name, entity);
+                    }
+                });
+        });
+    }
+
+    /// Record the packets received on the [`Link`] before they get processed by the [`Transport`]
+    #[cfg(feature = "std")]
+    fn capture_receive(mut query: Query<(&Link, &mut LinkCapture), With<Linked>>) {
+        query.par_iter_mut().for_each(|(link, mut capture)| {
+            capture
+                .record_recv(&link.recv)
+                .inspect_err(|e| error!("Error recording received packet: {e:?}"))
+                .ok();
+        });
+    }
+
+    /// Record the packets that the [`Transport`] just uploaded to the [`Link`]
+    #[cfg(feature = "std")]
+    fn capture_send(mut query: Query<(&Link, &mut LinkCapture), With<Linked>>) {
+        query.par_iter_mut().for_each(|(link, mut capture)| {
+            capture
+                .record_send(&link.send)
+                .inspect_err(|e| error!("Error recording sent packet: {e:?}"))
+                .ok();
+        });
+    }
+
     /// On disconnection, reset the Transport to its original state.
     #[cfg(any(feature = "client", feature = "server"))]
     fn handle_disconnection(
@@ -323,12 +664,42 @@ impl Plugin for TransportPlugin {
             app.world_mut().init_resource::<ChannelRegistry>();
         }
         app.configure_sets(PreUpdate, TransportSet::Receive.after(LinkSet::Receive));
-        app.configure_sets(PostUpdate, TransportSet::Send.before(LinkSet::Send));
+        app.configure_sets(
+            PostUpdate,
+            TransportSet::Send.before(LinkSet::ApplyConditioner),
+        );
         app.add_systems(
             PreUpdate,
             Self::buffer_receive.in_set(TransportSet::Receive),
         );
-        app.add_systems(PostUpdate, Self::buffer_send.in_set(TransportSet::Send));
+        app.add_systems(
+            PostUpdate,
+            (Self::buffer_send, Self::update_stats)
+                .chain()
+                .in_set(TransportSet::Send),
+        );
+        #[cfg(feature = "metrics")]
+        app.add_systems(
+            PostUpdate,
+            Self::record_metrics
+                .in_set(TransportSet::Send)
+                .after(Self::update_stats),
+        );
+        #[cfg(feature = "std")]
+        {
+            app.add_systems(
+                PreUpdate,
+                Self::capture_receive
+                    .in_set(TransportSet::Receive)
+                    .before(Self::buffer_receive),
+            );
+            app.add_systems(
+                PostUpdate,
+                Self::capture_send
+                    .in_set(TransportSet::Send)
+                    .after(Self::buffer_send),
+            );
+        }
     }
 }
 
@@ -0,0 +1,413 @@
+//! Linux fast path for the [`ServerUdpIo`](crate::server::ServerUdpIo).
+//!
+//! Instead of doing one syscall per packet, the datagrams are received with `recvmmsg` and sent
+//! with `sendmmsg`. When the kernel supports UDP Generic Segmentation Offload (GSO), consecutive
+//! packets of the same size sent to the same address are also merged into a single message, which
+//! the kernel (or the NIC) splits back into individual datagrams.
+use alloc::vec::Vec;
+use bevy::prelude::*;
+use bytes::Bytes;
+use core::mem::size_of;
+use core::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::io;
+use std::net::UdpSocket;
+use std::os::fd::AsRawFd;
+
+use crate::MTU;
+
+/// Maximum number of datagrams received with a single `recvmmsg` call
+pub(crate) const RECV_BATCH_SIZE: usize = 32;
+
+/// Maximum number of segments that can be sent in a single GSO message (`UDP_MAX_SEGMENTS`)
+const MAX_GSO_SEGMENTS: usize = 64;
+
+/// Maximum size of the payload of a single GSO message
+const MAX_GSO_SIZE: usize = 65507;
+
+/// Buffer used to store the control message that contains the GSO segment size.
+///
+/// `CMSG_SPACE(size_of::<u16>())` is 24 bytes on 64-bit targets; the buffer has to be aligned
+/// like a `cmsghdr`.
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(8))]
+struct CmsgBuffer([u8; 32]);
+
+/// Returns true if the socket supports UDP GSO
+pub(crate) fn gso_supported(socket: &UdpSocket) -> bool {
+    let mut value: libc::c_int = 0;
+    let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `value` and `len` are valid for the duration of the call
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_UDP,
+            libc::UDP_SEGMENT,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    ret == 0
+}
+
+/// Receive up to [`RECV_BATCH_SIZE`] datagrams with a single `recvmmsg` call.
+///
+/// `buffer` must contain at least `RECV_BATCH_SIZE * MTU` bytes. The received datagrams are
+/// appended to `datagrams`, and the number of datagrams read from the socket is returned.
+/// Datagrams that were larger than `MTU` are read but not appended, since they were truncated.
+pub(crate) fn recv_batch(
+    socket: &UdpSocket,
+    buffer: &mut [u8],
+    datagrams: &mut Vec<(SocketAddr, Bytes)>,
+) -> io::Result<usize> {
+    assert!(buffer.len() >= RECV_BATCH_SIZE * MTU);
+    // SAFETY: these are plain C structs for which all-zeroes is a valid value
+    let mut addrs: [libc::sockaddr_storage; RECV_BATCH_SIZE] = unsafe { core::mem::zeroed() };
+    let mut iovecs: [libc::iovec; RECV_BATCH_SIZE] = unsafe { core::mem::zeroed() };
+    let mut hdrs: [libc::mmsghdr; RECV_BATCH_SIZE] = unsafe { core::mem::zeroed() };
+    for i in 0..RECV_BATCH_SIZE {
+        iovecs[i] = libc::iovec {
+            // SAFETY: we checked that the buffer contains RECV_BATCH_SIZE chunks of MTU bytes
+            iov_base: unsafe { buffer.as_mut_ptr().add(i * MTU) } as *mut libc::c_void,
+            iov_len: MTU,
+        };
+        hdrs[i].msg_hdr.msg_name =
+            &mut addrs[i] as *mut libc::sockaddr_storage as *mut libc::c_void;
+        hdrs[i].msg_hdr.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        hdrs[i].msg_hdr.msg_iov = &mut iovecs[i];
+        hdrs[i].msg_hdr.msg_iovlen = 1;
+    }
+    // SAFETY: every header points to a valid address and to a distinct MTU-sized chunk of the buffer
+    let ret = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            hdrs.as_mut_ptr(),
+            RECV_BATCH_SIZE as _,
+            0,
+            core::ptr::null_mut(),
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let received = ret as usize;
+    for i in 0..received {
+        let len = hdrs[i].msg_len as usize;
+        // the datagram was larger than the buffer and only its start was copied
+        if hdrs[i].msg_hdr.msg_flags & libc::MSG_TRUNC != 0 {
+            warn!("Dropping truncated UDP packet larger than {MTU} bytes");
+            continue;
+        }
+        let Some(address) = from_sockaddr(&addrs[i]) else {
+            warn!("Received UDP packet from an address with an unsupported family");
+            continue;
+        };
+        let payload = Bytes::copy_from_slice(&buffer[i * MTU..i * MTU + len]);
+        datagrams.push((address, payload));
+    }
+    Ok(received)
+}
+
+/// A message that will be sent by `sendmmsg`: either a single datagram, or multiple datagrams
+/// merged into one GSO message
+struct Message {
+    address: SocketAddr,
+    /// Range of the packets contained in the message
+    start: usize,
+    end: usize,
+    /// Size of each segment, if the message contains multiple datagrams
+    segment_size: Option<u16>,
+}
+
+/// Send all the `packets` with as few `sendmmsg` calls as possible.
+///
+/// If `gso` is true, consecutive packets to the same address are merged into a single GSO message.
+/// If the kernel rejects a GSO message, `gso` is set to false and the datagrams of the message
+/// are sent individually.
+pub(crate) fn send_batch(socket: &UdpSocket, packets: &[(SocketAddr, Bytes)], gso: &mut bool) {
+    if packets.is_empty() {
+        return;
+    }
+    let messages = group_packets(packets, *gso);
+
+    // all these buffers must be fully built before we take pointers into them
+    let mut iovecs: Vec<libc::iovec> = packets
+        .iter()
+        .map(|(_, payload)| libc::iovec {
+            // the kernel doesn't write to the iovecs when sending
+            iov_base: payload.as_ptr() as *mut libc::c_void,
+            iov_len: payload.len(),
+        })
+        .collect();
+    let mut addrs: Vec<(libc::sockaddr_storage, libc::socklen_t)> = messages
+        .iter()
+        .map(|message| to_sockaddr(message.address))
+        .collect();
+    let mut cmsgs = vec![CmsgBuffer::default(); messages.len()];
+    let iovecs_ptr = iovecs.as_mut_ptr();
+    let mut hdrs: Vec<libc::mmsghdr> = Vec::with_capacity(messages.len());
+    for (i, message) in messages.iter().enumerate() {
+        // SAFETY: plain C struct for which all-zeroes is a valid value
+        let mut hdr: libc::mmsghdr = unsafe { core::mem::zeroed() };
+        hdr.msg_hdr.msg_name = &mut addrs[i].0 as *mut libc::sockaddr_storage as *mut libc::c_void;
+        hdr.msg_hdr.msg_namelen = addrs[i].1;
+        // SAFETY: `message.start` is a valid index in `iovecs`
+        hdr.msg_hdr.msg_iov = unsafe { iovecs_ptr.add(message.start) };
+        hdr.msg_hdr.msg_iovlen = (message.end - message.start) as _;
+        if let Some(segment_size) = message.segment_size {
+            hdr.msg_hdr.msg_control = cmsgs[i].0.as_mut_ptr() as *mut libc::c_void;
+            // SAFETY: CMSG_SPACE only computes a size
+            hdr.msg_hdr.msg_controllen =
+                unsafe { libc::CMSG_SPACE(size_of::<u16>() as libc::c_uint) } as _;
+            // SAFETY: the control buffer is large enough and correctly aligned for a cmsghdr
+            unsafe {
+                let cmsg = libc::CMSG_FIRSTHDR(&hdr.msg_hdr);
+                (*cmsg).cmsg_level = libc::SOL_UDP;
+                (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<u16>() as libc::c_uint) as _;
+                core::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment_size);
+            }
+        }
+        hdrs.push(hdr);
+    }
+
+    let mut sent = 0;
+    while sent < hdrs.len() {
+        // SAFETY: the headers point to buffers that stay alive until the end of the function
+        let ret = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                hdrs[sent..].as_mut_ptr(),
+                (hdrs.len() - sent) as _,
+                0,
+            )
+        };
+        if ret >= 0 {
+            sent += ret as usize;
+            continue;
+        }
+        // sendmmsg only returns an error if the first message could not be sent
+        let e = io::Error::last_os_error();
+        let message = &messages[sent];
+        if e.kind() == io::ErrorKind::WouldBlock {
+            error!(
+                "UDP socket send buffer is full, dropping {} packets",
+                packets.len() - message.start
+            );
+            return;
+        }
+        if message.segment_size.is_some() && e.raw_os_error() == Some(libc::EIO) {
+            // the network interface does not support GSO (for example because it does not
+            // support checksum offloading); stop using it and send the datagrams individually
+            info!("UDP GSO send failed with {e}, disabling segmentation offload");
+            *gso = false;
+            packets[message.start..message.end]
+                .iter()
+                .for_each(|(address, payload)| {
+                    socket
+                        .send_to(payload.as_ref(), *address)
+                        .inspect_err(|e| {
+                            error!("Error sending UDP packet to {}: {}", address, e);
+                        })
+                        .ok();
+                });
+        } else {
+            error!("Error sending UDP packet to {}: {}", message.address, e);
+        }
+        sent += 1;
+    }
+}
+
+/// Group the packets into messages.
+///
+/// With GSO, all the segments of a message must have the same size, except for the last one
+/// which can be smaller.
+fn group_packets(packets: &[(SocketAddr, Bytes)], gso: bool) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    while start < packets.len() {
+        let (address, first) = &packets[start];
+        let segment_size = first.len();
+        let mut end = start + 1;
+        let mut total_size = segment_size;
+        if gso && segment_size > 0 {
+            while end < packets.len() && end - start < MAX_GSO_SEGMENTS {
+                let (next_address, next) = &packets[end];
+                if next_address != address
+                    || next.is_empty()
+                    || next.len() > segment_size
+                    || total_size + next.len() > MAX_GSO_SIZE
+                {
+                    break;
+                }
+                total_size += next.len();
+                end += 1;
+                // a smaller segment must be the last one of the message
+                if next.len() < segment_size {
+                    break;
+                }
+            }
+        }
+        messages.push(Message {
+            address: *address,
+            start,
+            end,
+            segment_size: (end - start > 1).then_some(segment_size as u16),
+        });
+        start = end;
+    }
+    messages
+}
+
+fn to_sockaddr(address: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: plain C struct for which all-zeroes is a valid value
+    let mut storage: libc::sockaddr_storage = unsafe { core::mem::zeroed() };
+    let len = match address {
+        SocketAddr::V4(address) => {
+            // SAFETY: sockaddr_storage is large enough and aligned for any socket address type
+            let sin = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in)
+            };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = address.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from_ne_bytes(address.ip().octets()),
+            };
+            size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(address) => {
+            // SAFETY: sockaddr_storage is large enough and aligned for any socket address type
+            let sin6 = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6)
+            };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = address.port().to_be();
+            sin6.sin6_flowinfo = address.flowinfo();
+            sin6.sin6_addr = libc::in6_addr {
+                s6_addr: address.ip().octets(),
+            };
+            sin6.sin6_scope_id = address.scope_id();
+            size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
+
+fn from_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: the address family guarantees that this is a sockaddr_in
+            let sin =
+                unsafe { &*(storage as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes()),
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: the address family guarantees that this is a sockaddr_in6
+            let sin6 = unsafe {
+                &*(storage as *const libc::sockaddr_storage as *const libc::sockaddr_in6)
+            };
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_packets() {
+        let a: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+        let packet = |address, len| (address, Bytes::from(vec![0u8; len]));
+        let packets = vec![
+            packet(a, 100),
+            packet(a, 100),
+            packet(a, 50),
+            packet(a, 100),
+            packet(b, 100),
+            packet(b, 200),
+        ];
+        let messages = group_packets(&packets, true)
+            .into_iter()
+            .map(|m| (m.start, m.end, m.segment_size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![(0, 3, Some(100)), (3, 4, None), (4, 5, None), (5, 6, None)]
+        );
+        assert_eq!(group_packets(&packets, false).len(), packets.len());
+    }
+
+    #[test]
+    fn test_recv_batch_truncated() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_nonblocking(true).unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        client.send_to(&[1; MTU + 100], server_addr).unwrap();
+        client.send_to(&[2; 10], server_addr).unwrap();
+
+        let mut buffer = vec![0; RECV_BATCH_SIZE * MTU];
+        let mut datagrams = Vec::new();
+        let mut read = 0;
+        for _ in 0..100 {
+            match recv_batch(&server, &mut buffer, &mut datagrams) {
+                Ok(received) => read += received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{e}"),
+            }
+            if read == 2 {
+                break;
+            }
+            std::thread::sleep(core::time::Duration::from_millis(1));
+        }
+        // the oversized datagram is dropped instead of being delivered truncated
+        assert_eq!(read, 2);
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].1, Bytes::from_static(&[2; 10]));
+    }
+
+    #[test]
+    fn test_batch_roundtrip() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_nonblocking(true).unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let client_addr = client.local_addr().unwrap();
+
+        let packets = (0..10u8)
+            .map(|i| (server_addr, Bytes::from(vec![i; 10])))
+            .collect::<Vec<_>>();
+        let mut gso = gso_supported(&client);
+        send_batch(&client, &packets, &mut gso);
+
+        let mut buffer = vec![0; RECV_BATCH_SIZE * MTU];
+        let mut datagrams = Vec::new();
+        // the datagrams might not be available immediately
+        for _ in 0..100 {
+            match recv_batch(&server, &mut buffer, &mut datagrams) {
+                Ok(_) if datagrams.len() == packets.len() => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{e}"),
+            }
+            std::thread::sleep(core::time::Duration::from_millis(1));
+        }
+        assert_eq!(
+            datagrams,
+            packets
+                .into_iter()
+                .map(|(_, payload)| (client_addr, payload))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -22,12 +22,20 @@ use bevy::prelude::*;
 use bytes::{BufMut, BytesMut};
 use core::net::SocketAddr;
 use lightyear_link::{Link, LinkPlugin, LinkSet, LinkStart, Linked, Linking, Unlink, Unlinked};
+use thread::IoThread;
 
 /// Provides server-specific UDP IO functionalities.
 /// This module is only available when the "server" feature is enabled.
 #[cfg(feature = "server")]
 pub mod server;
 
+/// Dedicated IO threads that own the socket, used if `with_io_thread` is enabled.
+mod thread;
+
+/// Batched `recvmmsg`/`sendmmsg` IO used by the server on Linux.
+#[cfg(all(feature = "server", target_os = "linux"))]
+mod batch;
+
 /// Commonly used items for UDP transport in Lightyear.
 pub mod prelude {
     pub use crate::UdpIo;
@@ -50,6 +58,9 @@ pub(crate) const MTU: usize = 1472;
 ///
 /// The `UdpPlugin` is responsible for creating and managing the actual socket
 /// based on the `LinkState`.
+///
+/// With [`UdpIo::with_io_thread`], the socket is instead owned by dedicated IO threads
+/// that timestamp the packets as soon as they arrive.
 #[derive(Component)]
 #[require(Link)]
 pub struct UdpIo {
@@ -57,6 +68,8 @@ pub struct UdpIo {
     local_addr: SocketAddr,
     socket: Option<std::net::UdpSocket>,
     buffer: BytesMut,
+    use_io_thread: bool,
+    io_thread: Option<IoThread>,
 }
 
 // TODO: maybe We could have UdpIo<Unlinked> and UdpIo<Linked> and only UdpIo<Linked> has a std::net::UdpSocket?
@@ -68,8 +81,19 @@ impl UdpIo {
             local_addr,
             socket: None,
             buffer: BytesMut::with_capacity(MTU),
+            use_io_thread: false,
+            io_thread: None,
         })
     }
+
+    /// Read and write the socket on dedicated IO threads instead of in the `LinkSet` systems.
+    ///
+    /// The packets are timestamped when they arrive on the socket, so that the network timings
+    /// (for example the RTT) are not affected by the frame time.
+    pub fn with_io_thread(mut self, use_io_thread: bool) -> Self {
+        self.use_io_thread = use_io_thread;
+        self
+    }
 }
 
 /// Bevy plugin to integrate UDP-based IO with Lightyear.
@@ -93,8 +117,12 @@ impl UdpPlugin {
         if let Ok(mut udp_io) = query.get_mut(trigger.target()) {
             let socket = std::net::UdpSocket::bind(udp_io.local_addr)?;
             info!("UDP socket bound to {}", udp_io.local_addr);
-            socket.set_nonblocking(true)?;
-            udp_io.socket = Some(socket);
+            if udp_io.use_io_thread {
+                udp_io.io_thread = Some(IoThread::spawn(socket, "lightyear-udp")?);
+            } else {
+                socket.set_nonblocking(true)?;
+                udp_io.socket = Some(socket);
+            }
             commands.entity(trigger.target()).insert(Linked);
         }
         Ok(())
@@ -104,12 +132,19 @@ impl UdpPlugin {
         if let Ok(mut udp_io) = query.get_mut(trigger.target()) {
             info!("UDP socket closed");
             udp_io.socket = None;
+            udp_io.io_thread = None;
         }
     }
 
     fn send(mut query: Query<(&mut Link, &mut UdpIo), With<Linked>>) {
         query.par_iter_mut().for_each(|(mut link, mut udp_io)| {
             if let Some(remote_addr) = link.remote_addr {
+                if let Some(io_thread) = &udp_io.io_thread {
+                    link.send
+                        .drain()
+                        .for_each(|payload| io_thread.send(remote_addr, payload));
+                    return;
+                }
                 link.send.drain().for_each(|payload| {
                     udp_io
                         .socket
@@ -127,6 +162,12 @@ impl UdpPlugin {
         query.par_iter_mut().for_each(|(mut link, mut udp_io)| {
             // enable split borrows
             let udp_io = &mut *udp_io;
+            if let Some(io_thread) = &udp_io.io_thread {
+                io_thread
+                    .received()
+                    .for_each(|(_, payload, received_at)| link.recv.push(payload, received_at));
+                return;
+            }
             loop {
                 // TODO: this might cause Copy-on-Writes and re-allocations if we receive more than MTU bytes
                 //  in one frame. Solutions:
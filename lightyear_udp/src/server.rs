@@ -14,15 +14,31 @@ use bevy::platform::collections::HashMap;
 use bevy::platform::collections::hash_map::Entry;
 use bevy::platform::time::Instant;
 use bevy::prelude::*;
-use bytes::{BufMut, BytesMut};
+#[cfg(not(target_os = "linux"))]
+use bytes::BufMut;
+use bytes::{Bytes, BytesMut};
 use core::net::SocketAddr;
 use lightyear_link::prelude::{LinkOf, Server};
-use lightyear_link::{Link, LinkPlugin, LinkSet, LinkStart, Linked, Linking, Unlink, Unlinked};
+use lightyear_link::{
+    Link, LinkMigrated, LinkPlugin, LinkSet, LinkStart, Linked, Linking, Unlink, Unlinked,
+};
+
+#[cfg(target_os = "linux")]
+use crate::batch;
+use crate::thread::IoThread;
 
 /// Maximum transmission units; maximum size in bytes of a UDP packet
 /// See: <https://gafferongames.com/post/packet_fragmentation_and_reassembly/>
 pub(crate) const MTU: usize = 1472;
 
+/// Server-side UDP IO: a single socket shared by all the [`LinkOf`] entities of the server.
+///
+/// On Linux, the datagrams are received and sent in batches with `recvmmsg`/`sendmmsg` (and UDP
+/// GSO if the kernel supports it), so that the number of syscalls scales with the number of batches
+/// instead of the number of packets. Other platforms use one `recv_from`/`send_to` per packet.
+///
+/// With [`ServerUdpIo::with_io_thread`], the socket is instead owned by dedicated IO threads
+/// that timestamp the packets as soon as they arrive.
 #[derive(Component)]
 #[require(Server)]
 pub struct ServerUdpIo {
@@ -31,6 +47,23 @@ pub struct ServerUdpIo {
     socket: Option<std::net::UdpSocket>,
     buffer: BytesMut,
     connected_addresses: HashMap<SocketAddr, Option<Entity>>,
+    /// Packets to send, gathered from all the links
+    send_buffer: Vec<(SocketAddr, Bytes)>,
+    #[cfg(target_os = "linux")]
+    batch: BatchState,
+    use_io_thread: bool,
+    io_thread: Option<IoThread>,
+}
+
+/// State used by the batched IO fast path
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+struct BatchState {
+    /// Buffer that can hold `RECV_BATCH_SIZE` datagrams of `MTU` bytes
+    recv_buffer: Vec<u8>,
+    datagrams: Vec<(SocketAddr, Bytes)>,
+    /// True if the socket supports UDP GSO
+    gso: bool,
 }
 
 impl ServerUdpIo {
@@ -40,8 +73,22 @@ impl ServerUdpIo {
             socket: None,
             buffer: BytesMut::with_capacity(MTU),
             connected_addresses: HashMap::with_capacity(1),
+            send_buffer: Vec::new(),
+            #[cfg(target_os = "linux")]
+            batch: BatchState::default(),
+            use_io_thread: false,
+            io_thread: None,
         }
     }
+
+    /// Read and write the socket on dedicated IO threads instead of in the `LinkSet` systems.
+    ///
+    /// The packets are timestamped when they arrive on the socket, so that the network timings
+    /// (for example the RTT) are not affected by the frame time.
+    pub fn with_io_thread(mut self, use_io_thread: bool) -> Self {
+        self.use_io_thread = use_io_thread;
+        self
+    }
 }
 
 pub struct ServerUdpPlugin;
@@ -56,8 +103,17 @@ impl ServerUdpPlugin {
         if let Ok(mut udp_io) = query.get_mut(trigger.target()) {
             info!("Server UDP socket bound to {}", udp_io.local_addr);
             let socket = std::net::UdpSocket::bind(udp_io.local_addr)?;
-            socket.set_nonblocking(true)?;
-            udp_io.socket = Some(socket);
+            if udp_io.use_io_thread {
+                udp_io.io_thread = Some(IoThread::spawn(socket, "lightyear-udp-server")?);
+            } else {
+                socket.set_nonblocking(true)?;
+                #[cfg(target_os = "linux")]
+                {
+                    udp_io.batch.recv_buffer = vec![0; batch::RECV_BATCH_SIZE * MTU];
+                    udp_io.batch.gso = batch::gso_supported(&socket);
+                }
+                udp_io.socket = Some(socket);
+            }
             commands.entity(trigger.target()).insert(Linked);
         }
         Ok(())
@@ -67,7 +123,32 @@ impl ServerUdpPlugin {
         if let Ok(mut udp_io) = query.get_mut(trigger.target()) {
             info!("Server UDP socket closed");
             udp_io.socket = None;
+            udp_io.io_thread = None;
+        }
+    }
+
+    /// Route the packets from the new address of a migrated [`LinkOf`] to its entity
+    fn migrate(
+        trigger: Trigger<LinkMigrated>,
+        link_query: Query<&LinkOf>,
+        mut server_query: Query<&mut ServerUdpIo>,
+    ) {
+        let entity = trigger.target();
+        let Ok(link_of) = link_query.get(entity) else {
+            return;
+        };
+        let Ok(mut udp_io) = server_query.get_mut(link_of.server) else {
+            return;
+        };
+        if let Some(old_addr) = trigger.old_addr {
+            if udp_io.connected_addresses.get(&old_addr) == Some(&Some(entity)) {
+                udp_io.connected_addresses.remove(&old_addr);
+            }
         }
+        info!(?entity, new_addr = ?trigger.new_addr, "UDP link migrated to a new address");
+        udp_io
+            .connected_addresses
+            .insert(trigger.new_addr, Some(entity));
     }
 
     fn send(
@@ -78,6 +159,8 @@ impl ServerUdpPlugin {
         server_query
             .iter_mut()
             .for_each(|(mut server_udp_io, server)| {
+                // enable split borrows
+                let server_udp_io = &mut *server_udp_io;
                 server.collection().iter().for_each(|client_entity| {
                     let Some(mut link) = link_query.get_mut(*client_entity).ok() else {
                         error!("Client entity {} not found in link query", client_entity);
@@ -87,18 +170,44 @@ impl ServerUdpPlugin {
                         error!("Client entity {} has no remote address", client_entity);
                         return;
                     };
-                    link.send.drain().for_each(|send_payload| {
-                        server_udp_io
-                            .socket
-                            .as_mut()
-                            .unwrap()
-                            .send_to(send_payload.as_ref(), remote_addr)
+                    server_udp_io.send_buffer.extend(
+                        link.send
+                            .drain()
+                            .map(|send_payload| (remote_addr, send_payload)),
+                    );
+                });
+                if let Some(io_thread) = &server_udp_io.io_thread {
+                    server_udp_io
+                        .send_buffer
+                        .drain(..)
+                        .for_each(|(remote_addr, send_payload)| {
+                            io_thread.send(remote_addr, send_payload);
+                        });
+                    return;
+                }
+                let socket = server_udp_io.socket.as_ref().unwrap();
+
+                #[cfg(target_os = "linux")]
+                batch::send_batch(
+                    socket,
+                    &server_udp_io.send_buffer,
+                    &mut server_udp_io.batch.gso,
+                );
+
+                #[cfg(not(target_os = "linux"))]
+                server_udp_io
+                    .send_buffer
+                    .iter()
+                    .for_each(|(remote_addr, send_payload)| {
+                        socket
+                            .send_to(send_payload.as_ref(), *remote_addr)
                             .inspect_err(|e| {
                                 error!("Error sending UDP packet to {}: {}", remote_addr, e);
                             })
                             .ok();
                     });
-                });
+
+                server_udp_io.send_buffer.clear();
             });
     }
 
@@ -124,6 +233,58 @@ impl ServerUdpPlugin {
                 // enable split borrows
                 let server_udp_io = &mut *server_udp_io;
 
+                if let Some(io_thread) = &server_udp_io.io_thread {
+                    io_thread
+                        .received()
+                        .for_each(|(address, payload, received_at)| {
+                            Self::handle_payload(
+                                server_entity,
+                                &mut server_udp_io.connected_addresses,
+                                &mut link_query,
+                                &commands,
+                                address,
+                                payload,
+                                received_at,
+                            );
+                        });
+                    return;
+                }
+
+                #[cfg(target_os = "linux")]
+                loop {
+                    let batch = &mut server_udp_io.batch;
+                    let result = batch::recv_batch(
+                        server_udp_io.socket.as_ref().unwrap(),
+                        &mut batch.recv_buffer,
+                        &mut batch.datagrams,
+                    );
+                    // handle the datagrams that were read before checking for errors, so that
+                    // none of them are lost if the socket stops being readable mid-batch
+                    let received_at = Instant::now();
+                    batch.datagrams.drain(..).for_each(|(address, payload)| {
+                        Self::handle_payload(
+                            server_entity,
+                            &mut server_udp_io.connected_addresses,
+                            &mut link_query,
+                            &commands,
+                            address,
+                            payload,
+                            received_at,
+                        );
+                    });
+                    match result {
+                        // the socket has no more datagrams available
+                        Ok(received) if received < batch::RECV_BATCH_SIZE => return,
+                        Ok(_) => {}
+                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => return,
+                        Err(e) => {
+                            error!("Error receiving UDP packet: {}", e);
+                            return;
+                        }
+                    }
+                }
+
+                #[cfg(not(target_os = "linux"))]
                 loop {
                     // reserve additional space in the buffer
                     // this tries to reclaim space at the start of the buffer if possible
@@ -149,52 +310,15 @@ impl ServerUdpPlugin {
                                 server_udp_io.buffer.advance_mut(recv_len);
                             }
                             let payload = server_udp_io.buffer.split_to(recv_len).freeze();
-                            match server_udp_io.connected_addresses.entry(address) {
-                                Entry::Occupied(entry) => {
-                                    let entity = *entry.get();
-                                    if let Some(entity) = entity {
-                                        match link_query.get_mut(entity) {
-                                            Ok(mut link) => {
-                                                link.recv.push(payload, Instant::now());
-                                            }
-                                            Err(_) => {
-                                                error!(
-                                                    "Received UDP packet for unknown entity: {}",
-                                                    entity
-                                                );
-                                                // this might because the remote entity has disconnected and is trying to reconnect.
-                                                // Remove the entry so that the next packet can be processed
-                                                entry.remove();
-                                                continue;
-                                            }
-                                        }
-                                    } else {
-                                        // this means that we have received multiple packets from the same address
-                                        // before we had time to spawn the entity! These extra packets will be dropped
-                                    }
-                                }
-                                Entry::Vacant(vacant) => {
-                                    // we are spawning a new entity but the initial packets will be dropped
-                                    let mut link = Link::new(address, None);
-                                    info!("Received UDP packet from new address: {}", address);
-                                    link.recv.push(payload, Instant::now());
-                                    let vacant = vacant.insert(None);
-                                    commands.command_scope(|mut c| {
-                                        let entity = c
-                                            .spawn((
-                                                LinkOf {
-                                                    server: server_entity,
-                                                },
-                                                link,
-                                                Linked,
-                                            ))
-                                            .id();
-                                        info!(?entity, ?server_entity, "Spawn new LinkOf");
-                                        *vacant = Some(entity);
-                                    });
-                                    continue;
-                                }
-                            };
+                            Self::handle_payload(
+                                server_entity,
+                                &mut server_udp_io.connected_addresses,
+                                &mut link_query,
+                                &commands,
+                                address,
+                                payload,
+                                Instant::now(),
+                            );
                         }
                         Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => return,
                         Err(e) => {
@@ -205,6 +329,60 @@ impl ServerUdpPlugin {
                 }
             });
     }
+
+    /// Buffer a payload received from `address` at `received_at` in the corresponding [`Link`],
+    /// or spawn a new [`LinkOf`] entity if the address is not known yet.
+    fn handle_payload(
+        server_entity: Entity,
+        connected_addresses: &mut HashMap<SocketAddr, Option<Entity>>,
+        link_query: &mut Query<&mut Link>,
+        commands: &ParallelCommands,
+        address: SocketAddr,
+        payload: Bytes,
+        received_at: Instant,
+    ) {
+        match connected_addresses.entry(address) {
+            Entry::Occupied(entry) => {
+                let entity = *entry.get();
+                if let Some(entity) = entity {
+                    match link_query.get_mut(entity) {
+                        Ok(mut link) => {
+                            link.recv.push(payload, received_at);
+                        }
+                        Err(_) => {
+                            error!("Received UDP packet for unknown entity: {}", entity);
+                            // this might because the remote entity has disconnected and is trying to reconnect.
+                            // Remove the entry so that the next packet can be processed
+                            entry.remove();
+                        }
+                    }
+                } else {
+                    // this means that we have received multiple packets from the same address
+                    // before we had time to spawn the entity! These extra packets will be dropped
+                }
+            }
+            Entry::Vacant(vacant) => {
+                // we are spawning a new entity but the initial packets will be dropped
+                let mut link = Link::new(address, None);
+                info!("Received UDP packet from new address: {}", address);
+                link.recv.push(payload, received_at);
+                let vacant = vacant.insert(None);
+                commands.command_scope(|mut c| {
+                    let entity = c
+                        .spawn((
+                            LinkOf {
+                                server: server_entity,
+                            },
+                            link,
+                            Linked,
+                        ))
+                        .id();
+                    info!(?entity, ?server_entity, "Spawn new LinkOf");
+                    *vacant = Some(entity);
+                });
+            }
+        }
+    }
 }
 
 impl Plugin for ServerUdpPlugin {
@@ -214,6 +392,7 @@ impl Plugin for ServerUdpPlugin {
         }
         app.add_observer(Self::link);
         app.add_observer(Self::unlink);
+        app.add_observer(Self::migrate);
         app.add_systems(PreUpdate, Self::receive.in_set(LinkSet::Receive));
         app.add_systems(PostUpdate, Self::send.in_set(LinkSet::Send));
     }
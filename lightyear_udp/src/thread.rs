@@ -0,0 +1,174 @@
+//! Off-thread network IO for [`UdpIo`](crate::UdpIo) and [`ServerUdpIo`](crate::server::ServerUdpIo).
+//!
+//! When enabled, the socket is owned by dedicated IO threads instead of being read and written
+//! inside the `LinkSet` systems:
+//! - the receive thread blocks on the socket and timestamps each datagram as soon as it arrives
+//! - the send thread writes the datagrams to the socket as soon as they are flushed by the `Link`
+//!
+//! The payloads are exchanged with the systems via bounded lock-free channels, so that a slow frame
+//! does not delay the receipt of packets (and does not inflate the RTT measured by the
+//! `PingManager`). If a channel is full, the datagram is dropped as if it was lost on the network.
+use alloc::sync::Arc;
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use bytes::Bytes;
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryIter, TrySendError};
+use std::net::UdpSocket;
+use std::thread::JoinHandle;
+
+use crate::MTU;
+
+/// How often the IO threads check if they should shut down when they are idle
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Maximum number of datagrams buffered in each direction between the IO threads and the systems
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// A datagram received by the IO thread, with the time at which it was received
+pub(crate) type Received = (SocketAddr, Bytes, Instant);
+
+/// Handle to the IO threads that own a UDP socket.
+///
+/// The threads are stopped and joined when the handle is dropped, so that the socket is closed
+/// once the handle is gone.
+pub(crate) struct IoThread {
+    recv: Receiver<Received>,
+    send: Sender<(SocketAddr, Bytes)>,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl IoThread {
+    /// Spawn the IO threads. The socket must be in blocking mode.
+    pub(crate) fn spawn(socket: UdpSocket, name: &str) -> std::io::Result<Self> {
+        socket.set_nonblocking(false)?;
+        socket.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
+        let send_socket = socket.try_clone()?;
+        let (recv_tx, recv_rx) = crossbeam_channel::bounded(CHANNEL_CAPACITY);
+        let (send_tx, send_rx) = crossbeam_channel::bounded(CHANNEL_CAPACITY);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let recv_shutdown = shutdown.clone();
+        let recv_thread = std::thread::Builder::new()
+            .name(format!("{name}-recv"))
+            .spawn(move || Self::recv_loop(socket, recv_tx, recv_shutdown))?;
+        let send_shutdown = shutdown.clone();
+        let send_thread = std::thread::Builder::new()
+            .name(format!("{name}-send"))
+            .spawn(move || Self::send_loop(send_socket, send_rx, send_shutdown))?;
+        Ok(Self {
+            recv: recv_rx,
+            send: send_tx,
+            shutdown,
+            threads: vec![recv_thread, send_thread],
+        })
+    }
+
+    /// Iterate through the datagrams received by the IO thread since the last call
+    pub(crate) fn received(&self) -> TryIter<'_, Received> {
+        self.recv.try_iter()
+    }
+
+    /// Queue a datagram to be sent by the IO thread.
+    ///
+    /// The datagram is dropped if the send thread cannot keep up.
+    pub(crate) fn send(&self, address: SocketAddr, payload: Bytes) {
+        if let Err(TrySendError::Full(_)) = self.send.try_send((address, payload)) {
+            error!("UDP send queue is full, dropping packet to {}", address);
+        }
+    }
+
+    fn recv_loop(socket: UdpSocket, sender: Sender<Received>, shutdown: Arc<AtomicBool>) {
+        let mut buffer = [0; MTU];
+        while !shutdown.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buffer) {
+                Ok((recv_len, address)) => {
+                    let received_at = Instant::now();
+                    let payload = Bytes::copy_from_slice(&buffer[..recv_len]);
+                    match sender.try_send((address, payload, received_at)) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            warn!(
+                                "UDP receive queue is full, dropping packet from {}",
+                                address
+                            );
+                        }
+                        Err(TrySendError::Disconnected(_)) => return,
+                    }
+                }
+                Err(ref e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => {
+                    error!("Error receiving UDP packet: {}", e);
+                }
+            }
+        }
+    }
+
+    fn send_loop(
+        socket: UdpSocket,
+        receiver: Receiver<(SocketAddr, Bytes)>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        while !shutdown.load(Ordering::Relaxed) {
+            match receiver.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                Ok((address, payload)) => {
+                    socket
+                        .send_to(payload.as_ref(), address)
+                        .inspect_err(|e| error!("Error sending UDP packet to {}: {}", address, e))
+                        .ok();
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+impl Drop for IoThread {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        // wait for the threads to release the socket, so that the address can be reused
+        // immediately
+        self.threads.drain(..).for_each(|thread| {
+            if thread.join().is_err() {
+                error!("UDP IO thread panicked");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_thread() {
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (a_addr, b_addr) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        let a = IoThread::spawn(a, "a").unwrap();
+        let b = IoThread::spawn(b, "b").unwrap();
+
+        let before_send = Instant::now();
+        a.send(b_addr, Bytes::from_static(b"hello"));
+        let (address, payload, received_at) = b.recv.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(address, a_addr);
+        assert_eq!(payload, Bytes::from_static(b"hello"));
+        assert!(received_at >= before_send);
+    }
+
+    #[test]
+    fn test_io_thread_drop_releases_socket() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let io_thread = IoThread::spawn(socket, "drop").unwrap();
+        drop(io_thread);
+        // the threads are joined on drop, so the address is free again
+        UdpSocket::bind(addr).unwrap();
+    }
+}
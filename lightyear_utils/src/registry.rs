@@ -50,4 +50,10 @@ impl<K: TypeKind> TypeMapper<K> {
     pub fn net_id(&self, kind: &K) -> Option<&NetId> {
         self.kind_map.get(kind)
     }
+
+    /// Iterate through the registered types, in the order of their network ids
+    pub fn iter(&self) -> impl Iterator<Item = (NetId, &K)> {
+        (0..self.next_net_id)
+            .filter_map(|net_id| self.id_map.get(&net_id).map(|kind| (net_id, kind)))
+    }
 }
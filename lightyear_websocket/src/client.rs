@@ -0,0 +1,68 @@
+use aeronet_websocket::client::{ClientConfig, WebSocketClient};
+use alloc::format;
+use bevy::prelude::*;
+use core::net::SocketAddr;
+use lightyear_aeronet::{AeronetLinkOf, AeronetPlugin};
+use lightyear_link::{Link, LinkStart, Linked, Linking};
+
+pub struct WebSocketClientPlugin;
+
+impl Plugin for WebSocketClientPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AeronetPlugin>() {
+            app.add_plugins(AeronetPlugin);
+        }
+        app.add_plugins(aeronet_websocket::client::WebSocketClientPlugin);
+        app.add_observer(Self::link);
+    }
+}
+
+/// WebSocket session implementation which acts as a dedicated client,
+/// connecting to a target endpoint.
+///
+/// The connection is started when the [`LinkStart`] event is triggered on the entity.
+#[derive(Debug, Component)]
+#[require(Link)]
+pub struct WebSocketClientIo {
+    pub server_addr: SocketAddr,
+    /// If true, connect using TLS (`wss://`), otherwise connect without encryption (`ws://`).
+    ///
+    /// The server must be configured accordingly. On native clients, the server certificate is
+    /// validated against the platform's root certificates, so a server using a self-signed
+    /// certificate must have it added to the platform's certificate store.
+    pub secure: bool,
+}
+
+impl WebSocketClientPlugin {
+    fn link(
+        trigger: Trigger<LinkStart>,
+        query: Query<(Entity, &WebSocketClientIo), (Without<Linking>, Without<Linked>)>,
+        mut commands: Commands,
+    ) {
+        if let Ok((entity, client)) = query.get(trigger.target()) {
+            let scheme = if client.secure { "wss" } else { "ws" };
+            let server_url = format!("{scheme}://{}", client.server_addr);
+            let config = Self::client_config(client.secure);
+            commands.queue(move |world: &mut World| {
+                info!("Connecting to WebSocket server at {server_url}");
+                let entity_mut =
+                    world.spawn((AeronetLinkOf(entity), Name::from("WebSocketClient")));
+                WebSocketClient::connect(config, server_url).apply(entity_mut);
+            });
+        }
+    }
+
+    #[cfg(target_family = "wasm")]
+    fn client_config(_secure: bool) -> ClientConfig {
+        ClientConfig::default()
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn client_config(secure: bool) -> ClientConfig {
+        if secure {
+            ClientConfig::default()
+        } else {
+            ClientConfig::builder().with_no_encryption()
+        }
+    }
+}
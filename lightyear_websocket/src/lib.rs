@@ -0,0 +1,102 @@
+//! # Lightyear WebSocket
+//!
+//! WebSocket IO layer for lightyear, built on top of [`aeronet_websocket`].
+//!
+//! WebSockets run over TCP, so they are less efficient than UDP or WebTransport (a lost packet
+//! blocks all the following packets), but they can be used in environments where both UDP and
+//! WebTransport are blocked, for example on some corporate networks or browsers.
+//!
+//! The native server and clients, and the wasm client, plug into the [`Link`](lightyear_link::Link)
+//! the same way as the other aeronet-based IO layers, via the [`AeronetPlugin`](lightyear_aeronet::AeronetPlugin).
+#![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+
+extern crate alloc;
+
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+pub mod server;
+
+pub mod prelude {
+    #[cfg(all(feature = "server", not(target_family = "wasm")))]
+    pub use aeronet_websocket::server::Identity;
+
+    #[cfg(feature = "client")]
+    pub mod client {
+        pub use crate::client::WebSocketClientIo;
+    }
+
+    #[cfg(all(feature = "server", not(target_family = "wasm")))]
+    pub mod server {
+        pub use crate::server::WebSocketServerIo;
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "client",
+    feature = "server",
+    not(target_family = "wasm")
+))]
+mod tests {
+    use crate::client::{WebSocketClientIo, WebSocketClientPlugin};
+    use crate::server::{WebSocketServerIo, WebSocketServerPlugin};
+    use bevy::prelude::*;
+    use core::net::SocketAddr;
+    use core::time::Duration;
+    use lightyear_link::prelude::LinkOf;
+    use lightyear_link::{LinkStart, Linked};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    /// Runs the app until `condition` is true, or panics after a timeout
+    fn update_until(app: &mut App, mut condition: impl FnMut(&mut World) -> bool) {
+        let start = Instant::now();
+        while !condition(app.world_mut()) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "timed out waiting for the WebSocket connection"
+            );
+            app.update();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_loopback_connect() {
+        // find a free port for the server
+        let server_addr: SocketAddr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, WebSocketServerPlugin, WebSocketClientPlugin));
+
+        let server = app
+            .world_mut()
+            .spawn(WebSocketServerIo {
+                server_addr,
+                identity: None,
+            })
+            .id();
+        app.world_mut().trigger_targets(LinkStart, server);
+        update_until(&mut app, |world| world.get::<Linked>(server).is_some());
+
+        let client = app
+            .world_mut()
+            .spawn(WebSocketClientIo {
+                server_addr,
+                secure: false,
+            })
+            .id();
+        app.world_mut().trigger_targets(LinkStart, client);
+        update_until(&mut app, |world| {
+            let client_of_linked = world
+                .query_filtered::<&LinkOf, With<Linked>>()
+                .iter(world)
+                .any(|link_of| link_of.server == server);
+            world.get::<Linked>(client).is_some() && client_of_linked
+        });
+    }
+}
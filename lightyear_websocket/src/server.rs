@@ -0,0 +1,94 @@
+use aeronet_io::Session;
+use aeronet_io::connection::PeerAddr;
+use aeronet_websocket::server::{Identity, ServerConfig, WebSocketServer, WebSocketServerClient};
+use bevy::prelude::*;
+use core::net::SocketAddr;
+use lightyear_aeronet::server::ServerAeronetPlugin;
+use lightyear_aeronet::{AeronetLinkOf, AeronetPlugin};
+use lightyear_link::prelude::LinkOf;
+use lightyear_link::server::Server;
+use lightyear_link::{Link, LinkStart, Linked, Linking};
+
+/// Allows using [`WebSocketServer`].
+pub struct WebSocketServerPlugin;
+
+impl Plugin for WebSocketServerPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AeronetPlugin>() {
+            app.add_plugins(AeronetPlugin);
+        }
+        if !app.is_plugin_added::<ServerAeronetPlugin>() {
+            app.add_plugins(ServerAeronetPlugin);
+        }
+        app.add_plugins(aeronet_websocket::server::WebSocketServerPlugin);
+
+        app.add_observer(Self::link);
+        app.add_observer(Self::on_connection);
+    }
+}
+
+/// WebSocket server implementation which listens for client connections,
+/// and coordinates messaging between multiple clients.
+///
+/// The server is opened when the [`LinkStart`] event is triggered on the entity.
+/// Unlike WebTransport, every client that opens a WebSocket connection is accepted.
+#[derive(Component)]
+#[require(Server)]
+pub struct WebSocketServerIo {
+    pub server_addr: SocketAddr,
+    /// TLS identity of the server.
+    ///
+    /// If `None`, the server accepts unencrypted (`ws://`) connections.
+    pub identity: Option<Identity>,
+}
+
+impl WebSocketServerPlugin {
+    fn link(
+        trigger: Trigger<LinkStart>,
+        query: Query<(Entity, &WebSocketServerIo), (Without<Linking>, Without<Linked>)>,
+        mut commands: Commands,
+    ) {
+        if let Ok((entity, io)) = query.get(trigger.target()) {
+            let addr = io.server_addr;
+            // `Identity` is not `Clone`, so rebuild it from its parts to keep the component intact
+            // in case the server is restarted
+            let identity = io.identity.as_ref().map(|identity| {
+                Identity::new(identity.cert_chain.clone(), identity.key_der.clone_key())
+            });
+            commands.queue(move |world: &mut World| {
+                let config = ServerConfig::builder().with_bind_address(addr);
+                let config = match identity {
+                    Some(identity) => config.with_identity(identity),
+                    None => config.with_no_encryption(),
+                };
+                info!("Server WebSocket starting at {}", addr);
+                let child = world.spawn((AeronetLinkOf(entity), Name::from("WebSocketServer")));
+                WebSocketServer::open(config).apply(child);
+            });
+        }
+    }
+
+    fn on_connection(
+        trigger: Trigger<OnAdd, Session>,
+        query: Query<&AeronetLinkOf>,
+        child_query: Query<(&ChildOf, &PeerAddr), With<WebSocketServerClient>>,
+        mut commands: Commands,
+    ) {
+        if let Ok((child_of, peer_addr)) = child_query.get(trigger.target()) {
+            if let Ok(server_link) = query.get(child_of.parent()) {
+                let link = Link::new(peer_addr.0, None);
+                let link_entity = commands
+                    .spawn((
+                        LinkOf {
+                            server: server_link.0,
+                        },
+                        link,
+                    ))
+                    .id();
+                commands
+                    .entity(trigger.target())
+                    .insert((AeronetLinkOf(link_entity), Name::from("WebSocketClientOf")));
+            }
+        }
+    }
+}